@@ -1,53 +1,118 @@
-use crate::{mutator::Mutator, units::MEBIBYTES};
-use anyhow::Result;
-use voxell_timer::time_fn;
-
-pub mod arcode;
-pub mod bsc;
-pub mod bwt;
-pub mod huffman;
-pub mod mtf;
-pub mod pipeline;
-pub mod re_pair;
-pub mod serializing_algorithm;
-pub mod imgdecode;
-
-#[derive(Clone, Copy, Debug)]
-pub struct DynMutator {
-    pub(crate) drive_mutation: fn(data: &[u8], buf: &mut Vec<u8>) -> Result<()>,
-    pub(crate) revert_mutation: fn(data: &[u8], buf: &mut Vec<u8>) -> Result<()>,
-}
-
-impl Mutator for DynMutator {
-    fn drive_mutation(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
-        if_tracing! {{
-            tracing::info!("data_len:MB" = data.len() as f64 / MEBIBYTES as f64, "dyn drive_mutation started");
-            let (res, d) = time_fn(|| (self.drive_mutation)(data, buf));
-            tracing::info!(
-                out_len = buf.len(),
-                ratio = data.len() as f64 / buf.len() as f64,
-                "dyn drive_mutation finished in {:.1?}", d
-            );
-            res
-        }}
-        if_not_tracing! {
-            (self.drive_mutation)(data, buf)
-        }
-    }
-
-    fn revert_mutation(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
-        if_tracing! {{
-            tracing::info!("data_len:MB" = data.len() as f64 / MEBIBYTES as f64, "dyn drive_mutation started");
-            let (res, d) = time_fn(|| (self.revert_mutation)(data, buf));
-            tracing::info!(
-                out_len = buf.len(),
-                ratio = data.len() as f64 / buf.len() as f64,
-                "dyn revert_mutation finished in {:.1?}", d
-            );
-            res
-        }}
-        if_not_tracing! {
-            (self.revert_mutation)(data, buf)
-        }
-    }
-}
+use std::fmt::{self, Display};
+
+use crate::{algorithms::params::StageParams, mutator::Mutator, units::MEBIBYTES};
+use anyhow::Result;
+use voxell_timer::time_fn;
+
+pub mod alphabet;
+pub mod arcode;
+pub mod bpe;
+pub mod bsc;
+pub mod bwt;
+pub mod dict;
+pub mod heuristics;
+pub mod huffman;
+pub mod lint;
+pub mod lz4;
+pub mod mtf;
+pub mod params;
+pub mod passthrough;
+pub mod pipeline;
+pub mod re_pair;
+pub mod rle1;
+pub mod sais;
+pub mod serializing_algorithm;
+pub mod util;
+pub mod imgdecode;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DynMutator {
+    pub(crate) drive_mutation: fn(data: &[u8], buf: &mut Vec<u8>) -> Result<()>,
+    pub(crate) revert_mutation: fn(data: &[u8], buf: &mut Vec<u8>, context: &RevertContext) -> Result<()>,
+}
+
+/// Like [`DynMutator`], but for algorithms that take [`StageParams`], written as `name(key=value, ...)` in pipelines.
+#[derive(Clone, Copy, Debug)]
+pub struct ParamMutator {
+    pub(crate) drive_mutation: fn(data: &[u8], buf: &mut Vec<u8>, params: &StageParams) -> Result<()>,
+    pub(crate) revert_mutation: fn(data: &[u8], buf: &mut Vec<u8>, params: &StageParams, context: &RevertContext) -> Result<()>,
+    /// Rejects unknown keys and malformed values when the pipeline is built, instead of halfway through encoding.
+    pub(crate) validate: fn(params: &StageParams) -> Result<()>,
+}
+
+/// What a stage decodes under, handed to the decoder of every built-in stage.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RevertContext {
+    /// Most bytes the stage may produce, see [`pipeline::CompressionPipeline::set_max_output_size`]. decoders that can
+    /// expand their input a lot check it as they write, so they stop as soon as they cross it instead of building all
+    /// of their output first.
+    pub max_output_size: Option<usize>,
+}
+
+impl RevertContext {
+    pub const fn new() -> Self {
+        RevertContext { max_output_size: None }
+    }
+
+    /// Fails with [`OutputLimitExceeded`] once `len` bytes of output are over the limit.
+    pub fn check_output(&self, len: usize) -> Result<()> {
+        match self.max_output_size {
+            Some(limit) if len > limit => Err(OutputLimitExceeded { limit }.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// How many bytes to read from a decoder that writes as much as it is asked for, one more than the limit so
+    /// [`RevertContext::check_output`] can tell that it was crossed.
+    pub fn read_limit(&self) -> u64 {
+        self.max_output_size.map_or(u64::MAX, |limit| (limit as u64).saturating_add(1))
+    }
+}
+
+/// A stage decoded past [`RevertContext::max_output_size`]. the pipeline names the stage when it reports it.
+#[derive(Debug)]
+pub struct OutputLimitExceeded {
+    pub limit: usize,
+}
+
+impl Display for OutputLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "output passed the --max-output-size limit of {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for OutputLimitExceeded {}
+
+impl Mutator for DynMutator {
+    fn drive_mutation(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+        if_tracing! {{
+            tracing::info!("data_len:MB" = data.len() as f64 / MEBIBYTES as f64, "dyn drive_mutation started");
+            let (res, d) = time_fn(|| (self.drive_mutation)(data, buf));
+            tracing::info!(
+                out_len = buf.len(),
+                ratio = data.len() as f64 / buf.len() as f64,
+                "dyn drive_mutation finished in {:.1?}", d
+            );
+            res
+        }}
+        if_not_tracing! {
+            (self.drive_mutation)(data, buf)
+        }
+    }
+
+    fn revert_mutation(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+        if_tracing! {{
+            tracing::info!("data_len:MB" = data.len() as f64 / MEBIBYTES as f64, "dyn drive_mutation started");
+            let (res, d) = time_fn(|| (self.revert_mutation)(data, buf, &RevertContext::new()));
+            tracing::info!(
+                out_len = buf.len(),
+                ratio = data.len() as f64 / buf.len() as f64,
+                "dyn revert_mutation finished in {:.1?}", d
+            );
+            res
+        }}
+        if_not_tracing! {
+            (self.revert_mutation)(data, buf, &RevertContext::new())
+        }
+    }
+}
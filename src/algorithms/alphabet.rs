@@ -0,0 +1,180 @@
+//! alphabet compaction, which maps the byte values the input uses onto the smallest ones, `0..n` for `n` distinct
+//! values, in the same order. data over a small alphabet such as dna, hex or base32 dumps then reaches the stages
+//! after it as dense symbols: mtf ranks and entropy coder models stay small, and a bwt sorts the same as before.
+//! with `pack=on` the symbols are also packed into as few bits as the alphabet needs, e.g. four dna bases to a byte,
+//! which suits a final stage or one before lz4 better than before a bwt, since packing hides the symbol boundaries.
+//!
+//! the output is
+//!
+//! | field           | notes                                                                  |
+//! |-----------------|------------------------------------------------------------------------|
+//! | original length | varint                                                                 |
+//! | symbol count    | varint, how many distinct values the input uses                        |
+//! | symbols         | the values the input uses, ascending, one byte each                    |
+//! | bits            | u8, bits per packed symbol, 0 when the symbols aren't packed           |
+//! | data            | the symbol index of every input byte, one per byte or packed msb first |
+use anyhow::{Result, anyhow, bail};
+
+use crate::{
+    algorithms::{
+        ParamMutator, RevertContext,
+        heuristics::MemoryCost,
+        params::{ParamDescriptor, ParamKind, StageParams},
+    },
+    registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo},
+    varint,
+};
+
+pub const Alphabet: RegisteredCompressor = RegisteredCompressor::new_param(
+    ParamMutator {
+        drive_mutation: alphabet_encode,
+        revert_mutation: alphabet_decode,
+        validate: validate_params,
+    },
+    "alphabet",
+    Some(DESCRIPTION),
+)
+.with_info(StageInfo {
+    category: StageCategory::Transform,
+    aliases: &["compact"],
+    params: PARAMS,
+    memory: MemoryCost::PerByte(2),
+    explanation: StageExplanation {
+        entropy: "leaves the information as it is, but maps the byte values the input uses onto the smallest ones, so \
+            the models of the stages after it stay small. with pack=on the symbols are packed into as few bits as the \
+            alphabet needs, which hides their boundaries from a bwt",
+        size: "adds a table of the values used, and with pack=on shrinks to the bits the alphabet needs, e.g. a \
+            quarter for dna",
+        decoding: "unpacks the symbols if they were packed and maps them back through the stored table",
+    },
+});
+const PARAMS: &[ParamDescriptor] = &[ParamDescriptor {
+    name: "pack",
+    kind: ParamKind::Choice { values: &["off", "on"] },
+    default: Some("off"),
+    description: "Packs the symbols into as few bits as the alphabet needs.",
+}];
+const DESCRIPTION: &str = "Alphabet compaction, mapping the byte values the input uses onto 0..n so small alphabets \
+such as dna or hex become dense symbols. Parameters: pack=on also packs them into as few bits as they need.";
+
+fn packs(params: &StageParams) -> Result<bool> {
+    match params.get("pack") {
+        None | Some("off") => Ok(false),
+        Some("on") => Ok(true),
+        Some(raw) => bail!("alphabet pack must be on or off, got {}", raw),
+    }
+}
+
+fn validate_params(params: &StageParams) -> Result<()> {
+    packs(params).map(|_| ())
+}
+
+/// Bits a symbol of an alphabet of `symbols` values takes, at least one.
+fn bits_for(symbols: usize) -> u8 {
+    (usize::BITS - symbols.saturating_sub(1).leading_zeros()).max(1) as u8
+}
+
+fn alphabet_encode(data: &[u8], buf: &mut Vec<u8>, params: &StageParams) -> Result<()> {
+    if_tracing! {{
+        tracing::debug!(target = "alphabet", input_len = data.len(), "alphabet encode start");
+    }}
+    let mut used = [false; 256];
+    for &byte in data {
+        used[byte as usize] = true;
+    }
+    let symbols = (0..=255u8).filter(|&byte| used[byte as usize]).collect::<Vec<_>>();
+    let mut codes = [0u8; 256];
+    for (code, &symbol) in symbols.iter().enumerate() {
+        codes[symbol as usize] = code as u8;
+    }
+    // packing into all 8 bits would only copy the codes
+    let bits = match bits_for(symbols.len()) {
+        8 => 0,
+        bits if packs(params)? => bits,
+        _ => 0,
+    };
+
+    buf.clear();
+    varint::write_u64(buf, data.len() as u64);
+    varint::write_u64(buf, symbols.len() as u64);
+    buf.extend_from_slice(&symbols);
+    buf.push(bits);
+    if bits == 0 {
+        buf.extend(data.iter().map(|&byte| codes[byte as usize]));
+    } else {
+        let mut acc = 0u32;
+        let mut filled = 0;
+        for &byte in data {
+            acc = acc << bits | u32::from(codes[byte as usize]);
+            filled += bits;
+            if filled >= 8 {
+                filled -= 8;
+                buf.push((acc >> filled) as u8);
+            }
+        }
+        if filled > 0 {
+            buf.push((acc << (8 - filled)) as u8);
+        }
+    }
+    if_tracing! {{
+        tracing::info!(target = "alphabet", input_len = data.len(), output_len = buf.len(), symbols = symbols.len(), bits = bits, "alphabet encode complete");
+    }}
+    Ok(())
+}
+
+fn alphabet_decode(mut data: &[u8], buf: &mut Vec<u8>, _params: &StageParams, _context: &RevertContext) -> Result<()> {
+    if_tracing! {{
+        tracing::debug!(target = "alphabet", input_len = data.len(), "alphabet decode start");
+    }}
+    let original_len =
+        usize::try_from(varint::read_u64(&mut data)?).map_err(|_| anyhow!("alphabet original length overflows"))?;
+    let symbol_count = varint::read_u64(&mut data)?;
+    if symbol_count > 256 {
+        bail!("alphabet has {} symbols, a byte has only 256 values", symbol_count);
+    }
+    let (symbols, rest) = data
+        .split_at_checked(symbol_count as usize)
+        .ok_or_else(|| anyhow!("alphabet symbol table truncated"))?;
+    let (&bits, codes) = rest.split_first().ok_or_else(|| anyhow!("alphabet data truncated"))?;
+    if bits > 7 {
+        bail!("alphabet symbols are packed into {} bits, at most 7 are", bits);
+    }
+    let symbol = |code: u8| {
+        symbols
+            .get(code as usize)
+            .copied()
+            .ok_or_else(|| anyhow!("alphabet code {} is outside the {} symbol alphabet", code, symbols.len()))
+    };
+
+    buf.clear();
+    buf.reserve(original_len);
+    if bits == 0 {
+        if codes.len() != original_len {
+            bail!("alphabet data holds {} symbols, expected {}", codes.len(), original_len);
+        }
+        for &code in codes {
+            buf.push(symbol(code)?);
+        }
+    } else {
+        let needed = (original_len as u128 * u128::from(bits)).div_ceil(8);
+        if codes.len() as u128 != needed {
+            bail!("alphabet data is {} bytes, {} symbols of {} bits take {}", codes.len(), original_len, bits, needed);
+        }
+        let mask = (1u32 << bits) - 1;
+        let mut acc = 0u32;
+        let mut filled = 0;
+        let mut bytes = codes.iter();
+        while buf.len() < original_len {
+            if filled < bits {
+                acc = acc << 8 | u32::from(*bytes.next().expect("the length was checked above"));
+                filled += 8;
+            }
+            filled -= bits;
+            buf.push(symbol(((acc >> filled) & mask) as u8)?);
+        }
+    }
+    if_tracing! {{
+        tracing::info!(target = "alphabet", output_len = buf.len(), "alphabet decode complete");
+    }}
+    Ok(())
+}
@@ -1,32 +1,97 @@
 use std::io::Cursor;
 
-use anyhow::{Result, anyhow};
+use anyhow::{Result, anyhow, bail};
 use arcode::{
     ArithmeticDecoder, ArithmeticEncoder, Model,
     bitbit::{BitReader, BitWriter, MSB},
 };
 
-use crate::{algorithms::DynMutator, registered::RegisteredCompressor};
+use crate::{
+    algorithms::{
+        ParamMutator, RevertContext,
+        heuristics::MemoryCost,
+        params::{ParamDescriptor, ParamKind, StageParams},
+    },
+    registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo},
+};
 
-pub const ArithmeticCoding: RegisteredCompressor = RegisteredCompressor::new_dyn(
-    DynMutator {
-        drive_mutation: arith_encode,
-        revert_mutation: arith_decode,
+pub const ArithmeticCoding: RegisteredCompressor = RegisteredCompressor::new_param(
+    ParamMutator {
+        drive_mutation: arith_encode_stage,
+        revert_mutation: arith_decode_stage,
+        validate: validate_params,
     },
     "arcode",
     Some(DESCRIPTION),
-);
-const DESCRIPTION: &str = "Arithmetic coding";
+)
+.with_info(StageInfo {
+    category: StageCategory::EntropyCoder,
+    aliases: &["arithmetic"],
+    params: PARAMS,
+    memory: MemoryCost::PerByte(2),
+    explanation: StageExplanation {
+        entropy: "codes the data close to its order-0 entropy with a model that adapts as it goes, the output looks \
+            random",
+        size: "shrinks to about the order-0 entropy of its input",
+        decoding: "rebuilds the same model and reads every symbol back out of the code",
+    },
+});
+const PARAMS: &[ParamDescriptor] = &[ParamDescriptor {
+    name: "precision",
+    kind: ParamKind::Integer { min: 16, max: 62 },
+    default: Some("48"),
+    description: "Bits of precision of the coder's range, lower is faster but limits the input size.",
+}];
+const DESCRIPTION: &str = "Arithmetic coding. Parameters: precision=<16..62> sets the bits of precision of the range \
+(default 48), an input can be at most 2^(precision-2) bytes.";
 
 fn get_model() -> Model {
     Model::builder().num_symbols(256).eof(arcode::EOFKind::EndAddOne).build()
 }
 
 const ARCODE_PRECISION: u64 = 48;
-fn arith_encode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+
+/// Reads the precision of parameters already checked against [`PARAMS`].
+fn precision(params: &StageParams) -> Result<u64> {
+    match params.get("precision") {
+        Some(raw) => match raw.parse::<u64>() {
+            Ok(precision) if (16..=62).contains(&precision) => Ok(precision),
+            _ => bail!("arcode precision must be between 16 and 62, got {}", raw),
+        },
+        None => Ok(ARCODE_PRECISION),
+    }
+}
+
+fn validate_params(params: &StageParams) -> Result<()> {
+    precision(params).map(|_| ())
+}
+
+fn arith_encode_stage(data: &[u8], buf: &mut Vec<u8>, params: &StageParams) -> Result<()> {
+    arith_encode_with_precision(data, buf, precision(params)?)
+}
+
+fn arith_decode_stage(data: &[u8], buf: &mut Vec<u8>, params: &StageParams, context: &RevertContext) -> Result<()> {
+    arith_decode_with_precision(data, buf, precision(params)?, context)
+}
+
+/// Arithmetic codes `data` with the default precision.
+pub(crate) fn arith_encode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+    arith_encode_with_precision(data, buf, ARCODE_PRECISION)
+}
+
+fn arith_encode_with_precision(data: &[u8], buf: &mut Vec<u8>, precision: u64) -> Result<()> {
     if_tracing! {{
-        tracing::debug!(target = "arcode", input_len = data.len(), precision = ARCODE_PRECISION, "arcode encode start");
+        tracing::debug!(target = "arcode", input_len = data.len(), precision = precision, "arcode encode start");
     }}
+    // every symbol needs a slice of at least one of the range's smallest quarter, and the counts grow with the input
+    if data.len() as u64 + 257 > 1 << (precision - 2) {
+        bail!(
+            "arcode precision {} is too low for {} bytes, it codes at most {} bytes",
+            precision,
+            data.len(),
+            (1u64 << (precision - 2)) - 257
+        );
+    }
 
     // Ensure the output buffer is empty so writing via a Cursor will not leave
     // trailing bytes from a previous contents. The BitWriter writes into the
@@ -35,7 +100,7 @@ fn arith_encode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
     buf.clear();
 
     let mut model = get_model();
-    let encode_result = encode_data_with_model(data, &mut model, buf, ARCODE_PRECISION);
+    let encode_result = encode_data_with_model(data, &mut model, buf, precision);
     if_tracing! {{
         if let Err(ref err) = encode_result {
             tracing::error!(target = "arcode", error = %err, "arcode encode failed");
@@ -50,7 +115,7 @@ fn arith_encode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
     });
 
     if_tracing! {{
-        tracing::info!(target = "arcode", input_len = data.len(), output_len = buf.len(), precision = ARCODE_PRECISION, "arcode encode complete");
+        tracing::info!(target = "arcode", input_len = data.len(), output_len = buf.len(), precision = precision, "arcode encode complete");
     }}
     Ok(())
 }
@@ -108,9 +173,14 @@ fn encode_data_with_model(data: &[u8], model: &mut Model, buf: &mut Vec<u8>, pre
     Ok(())
 }
 
-fn arith_decode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+/// Decodes what [`arith_encode`] coded, stopping once the output crosses the limit of `context`.
+pub(crate) fn arith_decode(data: &[u8], buf: &mut Vec<u8>, context: &RevertContext) -> Result<()> {
+    arith_decode_with_precision(data, buf, ARCODE_PRECISION, context)
+}
+
+fn arith_decode_with_precision(data: &[u8], buf: &mut Vec<u8>, precision: u64, context: &RevertContext) -> Result<()> {
     if_tracing! {{
-        tracing::debug!(target = "arcode", input_len = data.len(), precision = ARCODE_PRECISION, "arcode decode start");
+        tracing::debug!(target = "arcode", input_len = data.len(), precision = precision, "arcode decode start");
     }}
 
     if data.is_empty() {
@@ -121,7 +191,7 @@ fn arith_decode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
     }
 
     let mut model = get_model();
-    let decode_result = decode_data_with_model(data, &mut model, buf, ARCODE_PRECISION);
+    let decode_result = decode_data_with_model(data, &mut model, buf, precision, context.max_output_size);
 
     if_tracing! {
         if let Err(ref err) = decode_result {
@@ -133,15 +203,16 @@ fn arith_decode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
 
     if_tracing! {{
         if mapped.is_ok() {
-            tracing::info!(target = "arcode", input_len = data.len(), output_len = buf.len(), precision = ARCODE_PRECISION, "arcode decode complete");
+            tracing::info!(target = "arcode", input_len = data.len(), output_len = buf.len(), precision = precision, "arcode decode complete");
         }
     }}
 
-    #[allow(clippy::let_and_return)]
-    mapped
+    mapped?;
+    context.check_output(buf.len())
 }
 
-fn decode_data_with_model(data: &[u8], model: &mut Model, buf: &mut Vec<u8>, precision: u64) -> Result<(), String> {
+/// Stops early once more than `max_len` symbols are decoded, leaving those in `buf` for the caller to report.
+fn decode_data_with_model(data: &[u8], model: &mut Model, buf: &mut Vec<u8>, precision: u64, max_len: Option<usize>) -> Result<(), String> {
     let mut input_reader = BitReader::<_, MSB>::new(data);
     let mut decoder = ArithmeticDecoder::new(precision);
     buf.clear();
@@ -152,6 +223,10 @@ fn decode_data_with_model(data: &[u8], model: &mut Model, buf: &mut Vec<u8>, pre
             .map_err(|_| "Error decoding symbol".to_string())?;
         model.update_symbol(sym);
         buf.push(sym as u8);
+        // one more than the limit and the eof marker, which may be the last symbol
+        if max_len.is_some_and(|max_len| buf.len() > max_len.saturating_add(1)) {
+            break;
+        }
     }
 
     // remove EOF marker
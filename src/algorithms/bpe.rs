@@ -0,0 +1,180 @@
+//! byte pair encoding in its original form, which replaces the most frequent pair of adjacent bytes with a byte value
+//! the data doesn't use, over and over until no unused values or no frequent pairs are left. the output is still
+//! bytes, so it can go on to an entropy coder, and the merge table is a few bytes per merge. it finds less than
+//! [`re_pair`](crate::algorithms::re_pair) does but costs a lot less, which makes it a fit for text.
+//!
+//! the output is
+//!
+//! | field           | notes                                                         |
+//! |-----------------|---------------------------------------------------------------|
+//! | original length | varint                                                        |
+//! | merge count     | u8                                                            |
+//! | merges          | `[token: u8][left: u8][right: u8]` each, in the order applied |
+//! | data            | the input with every merge applied                            |
+//!
+//! a merge may use the tokens of earlier merges as its left or right byte.
+use anyhow::{Result, anyhow, bail};
+
+use crate::{
+    algorithms::{
+        ParamMutator, RevertContext,
+        heuristics::MemoryCost,
+        params::{ParamDescriptor, ParamKind, StageParams},
+    },
+    registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo},
+    varint,
+};
+
+pub const Bpe: RegisteredCompressor = RegisteredCompressor::new_param(
+    ParamMutator {
+        drive_mutation: bpe_encode,
+        revert_mutation: bpe_decode,
+        validate: validate_params,
+    },
+    "bpe",
+    Some(DESCRIPTION),
+)
+.with_info(StageInfo {
+    category: StageCategory::Dictionary,
+    aliases: &["byte-pair"],
+    params: PARAMS,
+    // a working copy of the input is rewritten pair by pair
+    memory: MemoryCost::PerByte(3),
+    explanation: StageExplanation {
+        entropy: "replaces the most frequent pairs of adjacent bytes with byte values the data doesn't use, which \
+            takes out short repeats",
+        size: "shrinks data with frequent pairs, the merge table takes three bytes per merge",
+        decoding: "expands the merges from the stored table, the last one first",
+    },
+});
+const PARAMS: &[ParamDescriptor] = &[ParamDescriptor {
+    name: "merges",
+    kind: ParamKind::Integer { min: 0, max: 255 },
+    default: Some("255"),
+    description: "Most pairs to replace, each takes one byte value the input doesn't use.",
+}];
+const DESCRIPTION: &str = "Byte pair encoding, replacing frequent byte pairs with byte values the input doesn't use. \
+A lighter alternative to re_pair for text. Parameters: merges=<0..255> limits the number of pairs replaced.";
+/// A merge costs three bytes in the table and saves one byte per replaced pair, pairs seen less often don't pay off.
+const MIN_PAIR_COUNT: u32 = 4;
+
+fn max_merges(params: &StageParams) -> Result<usize> {
+    match params.get("merges") {
+        Some(raw) => match raw.parse::<u8>() {
+            Ok(merges) => Ok(merges as usize),
+            Err(_) => bail!("bpe merge count must be between 0 and 255, got {}", raw),
+        },
+        None => Ok(255),
+    }
+}
+
+fn validate_params(params: &StageParams) -> Result<()> {
+    max_merges(params).map(|_| ())
+}
+
+/// The most frequent pair of adjacent bytes in `data` and how often it occurs, the smallest pair on ties.
+fn most_frequent_pair(data: &[u8], counts: &mut [u32]) -> Option<(u8, u8, u32)> {
+    counts.fill(0);
+    for pair in data.windows(2) {
+        counts[(pair[0] as usize) << 8 | pair[1] as usize] += 1;
+    }
+    let (pair, &count) = counts.iter().enumerate().rev().max_by_key(|&(_, count)| count)?;
+    Some(((pair >> 8) as u8, pair as u8, count))
+}
+
+/// Replaces the non-overlapping occurrences of `left` `right` in `data` with `token`, from left to right.
+fn replace_pair(data: &mut Vec<u8>, left: u8, right: u8, token: u8) {
+    let mut read = 0;
+    let mut write = 0;
+    while read < data.len() {
+        if read + 1 < data.len() && data[read] == left && data[read + 1] == right {
+            data[write] = token;
+            read += 2;
+        } else {
+            data[write] = data[read];
+            read += 1;
+        }
+        write += 1;
+    }
+    data.truncate(write);
+}
+
+fn bpe_encode(data: &[u8], buf: &mut Vec<u8>, params: &StageParams) -> Result<()> {
+    if_tracing! {{
+        tracing::debug!(target = "bpe", input_len = data.len(), "bpe encode start");
+    }}
+    let max_merges = max_merges(params)?;
+    let mut used = [false; 256];
+    for &byte in data {
+        used[byte as usize] = true;
+    }
+    let mut unused = (0..=255u8).filter(|&byte| !used[byte as usize]);
+
+    let mut work = data.to_vec();
+    let mut merges = Vec::new();
+    let mut counts = vec![0u32; 1 << 16];
+    while merges.len() < max_merges {
+        let Some((left, right, count)) = most_frequent_pair(&work, &mut counts) else {
+            break;
+        };
+        if count < MIN_PAIR_COUNT {
+            break;
+        }
+        let Some(token) = unused.next() else {
+            break;
+        };
+        replace_pair(&mut work, left, right, token);
+        merges.push([token, left, right]);
+    }
+
+    buf.clear();
+    varint::write_u64(buf, data.len() as u64);
+    buf.push(merges.len() as u8);
+    buf.extend(merges.iter().flatten());
+    buf.extend_from_slice(&work);
+    if_tracing! {{
+        tracing::info!(target = "bpe", input_len = data.len(), output_len = buf.len(), merges = merges.len(), "bpe encode complete");
+    }}
+    Ok(())
+}
+
+fn bpe_decode(mut data: &[u8], buf: &mut Vec<u8>, _params: &StageParams, _context: &RevertContext) -> Result<()> {
+    if_tracing! {{
+        tracing::debug!(target = "bpe", input_len = data.len(), "bpe decode start");
+    }}
+    let original_len = usize::try_from(varint::read_u64(&mut data)?).map_err(|_| anyhow!("bpe original length overflows"))?;
+    let (&merge_count, rest) = data.split_first().ok_or_else(|| anyhow!("bpe data truncated"))?;
+    let (merges, tokens) = rest
+        .split_at_checked(merge_count as usize * 3)
+        .ok_or_else(|| anyhow!("bpe merge table truncated"))?;
+
+    // every byte stands for itself until a merge makes it a token
+    let mut expansions = (0..=255u8).map(|byte| vec![byte]).collect::<Vec<_>>();
+    for merge in merges.chunks_exact(3) {
+        let [token, left, right] = [merge[0], merge[1], merge[2]].map(usize::from);
+        // a crafted table could double the expansion with every merge, nothing real expands past the whole output
+        let len = expansions[left].len() + expansions[right].len();
+        if len > original_len {
+            bail!(
+                "bpe merge expands to {} bytes, more than the {} bytes of the original",
+                len,
+                original_len
+            );
+        }
+        expansions[token] = [expansions[left].as_slice(), expansions[right].as_slice()].concat();
+    }
+
+    let decoded_len = tokens.iter().map(|&token| expansions[token as usize].len()).sum::<usize>();
+    if decoded_len != original_len {
+        bail!("bpe data expands to {} bytes, expected {}", decoded_len, original_len);
+    }
+    buf.clear();
+    buf.reserve(original_len);
+    for &token in tokens {
+        buf.extend_from_slice(&expansions[token as usize]);
+    }
+    if_tracing! {{
+        tracing::info!(target = "bpe", output_len = buf.len(), "bpe decode complete");
+    }}
+    Ok(())
+}
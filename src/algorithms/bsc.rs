@@ -1,8 +1,17 @@
 use core::ffi::c_int;
 
-use crate::{algorithms::DynMutator, registered::RegisteredCompressor};
-use anyhow::{Result, anyhow};
-use bsc_m03_sys::{libbsc_compress_memory_block_u8, libbsc_decompress_memory_block_c};
+use crate::{
+    algorithms::{
+        ParamMutator, RevertContext,
+        heuristics::{BlockCost, MemoryCost, plan_blocks},
+        params::{ParamDescriptor, ParamKind, StageParams},
+    },
+    registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo},
+    units::parse_byte_size,
+    varint,
+};
+use anyhow::{Result, anyhow, bail};
+use bsc_m03_sys::{libbsc_compress_memory_block_u8, libbsc_compress_memory_block_u16, libbsc_decompress_memory_block_c};
 use core::mem::size_of;
 
 macro_rules! cold {
@@ -15,59 +24,189 @@ macro_rules! cold {
     }};
 }
 
-pub const Bsc: RegisteredCompressor = RegisteredCompressor::new_dyn(
-    DynMutator {
+pub const Bsc: RegisteredCompressor = RegisteredCompressor::new_param(
+    ParamMutator {
         drive_mutation: bsc_encode,
         revert_mutation: bsc_decode,
+        validate: validate_params,
     },
     "bsc",
     Some(DESCRIPTION),
-);
-const DESCRIPTION: &str = "bsc-m03 general purpose compressor by Ilya Grebnov.";
+)
+.with_info(StageInfo {
+    category: StageCategory::Compressor,
+    aliases: &[],
+    params: PARAMS,
+    memory: MemoryCost::Blocks(BSC_COST),
+    explanation: StageExplanation {
+        entropy: "models the data on its own with a bwt and context mixing, the output looks random",
+        size: "shrinks, by the most of the built-in stages",
+        decoding: "checks the crc32 of every chunk and runs the bsc-m03 decoder on it",
+    },
+});
+const PARAMS: &[ParamDescriptor] = &[
+    ParamDescriptor {
+        name: "block",
+        kind: ParamKind::Size {
+            min: 2,
+            max: MAX_CHUNK_SIZE as u64,
+        },
+        default: None,
+        description: "Block size, chosen from the input size and available memory when left out.",
+    },
+    ParamDescriptor {
+        name: "width",
+        kind: ParamKind::Choice { values: &["8", "16"] },
+        default: Some("8"),
+        description: "Sorts and models the input as 8 or 16 bit symbols.",
+    },
+];
+const DESCRIPTION: &str = "bsc-m03 general purpose compressor by Ilya Grebnov. \
+Parameters: block=<size> sets the block size (chosen from input size and memory by default), \
+width=8|16 sorts and models the input as 8 or 16 bit symbols (default 8).";
+/// bsc-m03 keeps the block, its suffix array and the context model tables alive at once.
+const BSC_COST: BlockCost = BlockCost {
+    memory_per_byte: 10,
+    max_block_size: usize::MAX,
+};
+/// bsc-m03 takes `c_int` sizes, so blocks are compressed in chunks of at most this many bytes.
+const MAX_CHUNK_SIZE: usize = i32::MAX as usize - 16384;
+/// Starts the current framing. Read as the little endian `i32` block size of the legacy framing it is negative,
+/// which the legacy framing never produces.
+const FRAMING_MAGIC: [u8; 4] = [0xb5, 0xc0, 0x03, 0xf0];
 
-fn bsc_encode(mut data: &[u8], output: &mut Vec<u8>) -> Result<()> {
+/// Tuning knobs of bsc-m03, parsed from the stage parameters.
+#[derive(Debug, Clone, Copy)]
+struct BscOptions {
+    /// Overrides the block size picked by [`plan_blocks`].
+    block_size: Option<usize>,
+    /// Symbol width in bytes, 1 or 2.
+    symbol_width: usize,
+}
+
+impl BscOptions {
+    /// Reads parameters already checked against [`PARAMS`].
+    fn from_params(params: &StageParams) -> Result<Self> {
+        let block_size = match params.get("block") {
+            Some(raw) => Some(parse_byte_size(raw).map_err(|e| anyhow!(e))? as usize),
+            None => None,
+        };
+        let symbol_width = match params.get("width") {
+            None | Some("8") => 1,
+            Some("16") => 2,
+            Some(other) => bail!("bsc symbol width must be 8 or 16, got {}", other),
+        };
+        Ok(BscOptions { block_size, symbol_width })
+    }
+}
+
+fn validate_params(params: &StageParams) -> Result<()> {
+    BscOptions::from_params(params).map(|_| ())
+}
+
+/// The output is [`FRAMING_MAGIC`] followed by one frame per chunk:
+/// `[chunk size: varint][compressed size: varint][crc32 of the compressed chunk: u32][compressed chunk]`.
+/// The checksum covers the compressed bytes so corruption is caught before it reaches bsc-m03, which doesn't
+/// validate its input.
+fn bsc_encode(data: &[u8], output: &mut Vec<u8>, params: &StageParams) -> Result<()> {
     if_tracing! {{
         tracing::debug!(target = "bsc", data.len = data.len(), "enter bsc encode");
     }};
     output.clear();
-    let mut remaining_size: i64 = data.len() as i64;
-    let mut buffer_size = remaining_size.min(i32::MAX as i64) + 16384;
-    buffer_size += buffer_size / 16;
-    let mut buffer: Vec<u8> = Vec::with_capacity(buffer_size as usize);
-    while remaining_size > 0 {
-        // fits in i32 guaranteed, as max_block_size is i32 and we're doing a min
-        let block_size: i32 = remaining_size.min(i32::MAX as i64) as i32;
+    let options = BscOptions::from_params(params)?;
+    let block_size = options.block_size.unwrap_or_else(|| plan_blocks(data.len(), BSC_COST).block_size);
+    // 16 bit symbols need chunks of whole symbols, only the final chunk may end on a stray byte.
+    let chunk_size = block_size.min(MAX_CHUNK_SIZE) / options.symbol_width * options.symbol_width;
+    if_tracing! {{
+        tracing::debug!(target = "bsc", block_size, chunk_size, symbol_width = options.symbol_width, "bsc encode chose block size");
+    }};
+
+    output.extend_from_slice(&FRAMING_MAGIC);
+    // bsc-m03 keeps its symbol frequencies in a global, so chunks are compressed one at a time whatever `--threads`
+    // allows
+    let mut buffer: Vec<u8> = Vec::with_capacity(chunk_size.min(data.len()) + 16384);
+    for chunk in data.chunks(chunk_size.max(1)) {
         buffer.clear();
-        let (block, rest) = data
-            .split_at_checked(block_size as usize)
-            .ok_or_else(|| cold!({ anyhow!("input too short") } -> anyhow::Error))?;
-        buffer.extend_from_slice(block);
-        data = rest;
-        let compressed_size: i32 = unsafe { libbsc_compress_memory_block_u8(buffer.as_mut_ptr(), block_size as c_int) as i32 };
-        if compressed_size <= 0 || compressed_size > block_size {
+        buffer.extend_from_slice(chunk);
+        // fits in c_int guaranteed, as chunks are capped at MAX_CHUNK_SIZE
+        let compressed_size: i32 = unsafe {
+            if options.symbol_width == 2 && chunk.len() % 2 == 0 {
+                libbsc_compress_memory_block_u16(buffer.as_mut_ptr(), chunk.len() as c_int) as i32
+            } else {
+                libbsc_compress_memory_block_u8(buffer.as_mut_ptr(), chunk.len() as c_int) as i32
+            }
+        };
+        if compressed_size <= 0 || compressed_size as usize > chunk.len() {
             return cold!({Err(anyhow!(
                 "compression failed: internal error, please contact Ilya Grebnov, the author of bsc-m03 and libsais."
             ))} -> Result<()>);
         }
+        let compressed = &buffer[..compressed_size as usize];
+
+        varint::write_u64(output, chunk.len() as u64);
+        varint::write_u64(output, compressed.len() as u64);
+        output.extend_from_slice(&crc32fast::hash(compressed).to_le_bytes());
+        output.extend_from_slice(compressed);
+    }
+    Ok(())
+}
+
+/// The symbol width and block size are recorded in the stream, so decoding ignores the stage parameters.
+fn bsc_decode(data: &[u8], output: &mut Vec<u8>, _params: &StageParams, _context: &RevertContext) -> Result<()> {
+    match data.strip_prefix(&FRAMING_MAGIC) {
+        Some(frames) => bsc_decode_frames(frames, output),
+        None => bsc_decode_legacy(data, output),
+    }
+}
+
+fn bsc_decode_frames(mut data: &[u8], output: &mut Vec<u8>) -> Result<()> {
+    output.clear();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut chunk_index = 0usize;
+
+    while !data.is_empty() {
+        let chunk_size = varint::read_u64(&mut data)?;
+        let compressed_size = varint::read_u64(&mut data)?;
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE as u64 || compressed_size == 0 || compressed_size > chunk_size {
+            return cold!({ Err(anyhow!("corrupted input")) } -> Result<()>);
+        }
+        let (chunk_size, compressed_size) = (chunk_size as usize, compressed_size as usize);
+        let (crc_bytes, rest) = data
+            .split_at_checked(4)
+            .ok_or_else(|| cold!({ anyhow!("input too short") } -> anyhow::Error))?;
+        let expected_crc = u32::from_le_bytes(crc_bytes.try_into().unwrap());
+        let (compressed_slice, rest) = rest
+            .split_at_checked(compressed_size)
+            .ok_or_else(|| cold!({ anyhow!("input too short") } -> anyhow::Error))?;
+        data = rest;
+        if crc32fast::hash(compressed_slice) != expected_crc {
+            return Err(anyhow!("bsc chunk {} failed its checksum", chunk_index));
+        }
+
+        buffer.clear();
+        buffer.reserve(chunk_size);
+        buffer.extend_from_slice(compressed_slice);
+        let decompressed_size = if compressed_size < chunk_size {
+            unsafe { libbsc_decompress_memory_block_c(buffer.as_mut_ptr(), compressed_size as c_int, chunk_size as c_int) as i32 }
+        } else {
+            chunk_size as i32
+        };
+        if decompressed_size != chunk_size as i32 {
+            return cold!({ Err(anyhow!("corrupted input")) } -> Result<()>);
+        }
         unsafe {
-            buffer.set_len(compressed_size as usize);
+            buffer.set_len(chunk_size);
         };
-        output.reserve((size_of::<i32>() as i32 + size_of::<i32>() as i32 + compressed_size) as usize);
-        output.extend_from_slice(&block_size.to_le_bytes());
-        output.extend_from_slice(&compressed_size.to_le_bytes());
-        let s = &buffer[..compressed_size as usize];
-        output.extend_from_slice(s);
-        remaining_size -= block_size as i64;
-    }
-    if remaining_size != 0 {
-        return cold!({Err(anyhow!(
-            "internal error: remaining size after processing is not zero"
-        ))} -> Result<()>);
+        output.extend_from_slice(&buffer[..chunk_size]);
+        chunk_index += 1;
     }
+
     Ok(())
 }
 
-fn bsc_decode(mut data: &[u8], output: &mut Vec<u8>) -> Result<()> {
+/// Decodes the framing used before [`FRAMING_MAGIC`] was introduced:
+/// `[block size: i32][compressed size: i32][compressed block]` per block.
+fn bsc_decode_legacy(mut data: &[u8], output: &mut Vec<u8>) -> Result<()> {
     #[inline]
     fn read_i32(data: &mut &[u8]) -> Result<i32> {
         let (block, rest) = (*data)
@@ -82,6 +221,20 @@ fn bsc_decode(mut data: &[u8], output: &mut Vec<u8>) -> Result<()> {
         return Ok(());
     }
 
+    // libbsc aborts on some malformed blocks instead of failing, and this framing has no checksums, so the blocks
+    // have to add up to the input before any of them is handed to it
+    let mut rest = data;
+    while !rest.is_empty() {
+        let block_size = read_i32(&mut rest)?;
+        let compressed_size = read_i32(&mut rest)?;
+        if block_size <= 0 || compressed_size <= 0 || compressed_size > block_size {
+            return cold!({ Err(anyhow!("corrupted input")) } -> Result<()>);
+        }
+        rest = rest
+            .get(compressed_size as usize..)
+            .ok_or_else(|| cold!({ anyhow!("input too short") } -> anyhow::Error))?;
+    }
+
     let mut buffer: Vec<u8> = Vec::new();
     let mut remaining_size: i64 = data.len() as i64;
 
@@ -1,102 +1,335 @@
-use crate::{algorithms::DynMutator, registered::RegisteredCompressor};
-use anyhow::{Result, anyhow};
+//! the burrows-wheeler transform, with the suffix array behind it built by one of three backends:
+//!
+//! | backend     | blocks up to      | notes                                                        |
+//! |-------------|-------------------|--------------------------------------------------------------|
+//! | `libsais`   | `i32::MAX` bytes  | libsais with a 32-bit suffix array, the fastest              |
+//! | `libsais64` | `u32::MAX - 1`    | libsais with a 64-bit suffix array, twice the memory         |
+//! | `sais`      | `u32::MAX - 1`    | pure rust SA-IS ([`crate::algorithms::sais`]), the slowest   |
+//!
+//! `backend=auto`, the default, picks `libsais64` for blocks that `libsais` can't index and `libsais` for the rest.
+//! builds without the `libsais` feature only have `sais`, which auto picks there. every backend writes the same
+//! transform, so decoding doesn't depend on the one used to encode.
+use crate::{
+    algorithms::{
+        ParamMutator, RevertContext,
+        heuristics::{BlockCost, BlockPlan, MemoryCost, plan_blocks},
+        params::{ParamDescriptor, ParamKind, StageParams},
+        sais,
+    },
+    registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo},
+    units::parse_byte_size,
+};
+use anyhow::{Result, anyhow, bail};
+#[cfg(feature = "libsais")]
 use libsais::{BwtConstruction, ThreadCount, bwt::Bwt as LibsaisBwt, suffix_array::ExtraSpace, typestate::OwnedBuffer};
 
-pub const Bwt: RegisteredCompressor = RegisteredCompressor::new_dyn(
-    DynMutator {
+pub const Bwt: RegisteredCompressor = RegisteredCompressor::new_param(
+    ParamMutator {
         drive_mutation: bwt_encode,
         revert_mutation: bwt_decode,
+        validate: validate_params,
     },
     "bwt",
     Some(DESCRIPTION),
-);
-const DESCRIPTION: &str = "Burrows-wheeler transform provided by the libsais library by Ilya Grebnov.";
+)
+.with_info(StageInfo {
+    category: StageCategory::Transform,
+    aliases: &["burrows-wheeler"],
+    params: PARAMS,
+    memory: MemoryCost::Blocks(BWT_COST),
+    explanation: StageExplanation {
+        entropy: "leaves the information as it is, but sorts every byte by what follows it, so bytes from alike \
+            contexts end up next to each other in runs",
+        size: "keeps the size, plus the position of the original rotation for every block",
+        decoding: "rebuilds the original order of every block from the sorted bytes and the stored position",
+    },
+});
+const PARAMS: &[ParamDescriptor] = &[
+    ParamDescriptor {
+        name: "block",
+        kind: ParamKind::Size {
+            min: 1,
+            max: MAX_BLOCK_SIZE as u64,
+        },
+        default: None,
+        description: "Block size, chosen from the input size and available memory when left out.",
+    },
+    ParamDescriptor {
+        name: "backend",
+        kind: ParamKind::Choice {
+            values: &["auto", "libsais", "libsais64", "sais"],
+        },
+        default: Some("auto"),
+        description: "Suffix array construction, auto picks libsais64 for blocks libsais can't index.",
+    },
+];
+const DESCRIPTION: &str = "Burrows-wheeler transform, with the suffix array built by the libsais library by Ilya \
+Grebnov or a pure rust SA-IS. Parameters: block=<size> sets the block size (chosen from input size and memory by \
+default), backend=auto|libsais|libsais64|sais picks the suffix array construction.";
+
+/// Marks the multi-block framing. Single block framing starts with a primary index, which is always smaller.
+const BLOCKED_MARKER: u32 = u32::MAX;
+/// Largest block the framing can hold, its primary index has to stay below [`BLOCKED_MARKER`].
+const MAX_BLOCK_SIZE: usize = BLOCKED_MARKER as usize - 1;
+/// Input, output and a 32-bit suffix array temporary for every byte. libsais32 can't index blocks past `i32::MAX`.
+const BWT_COST: BlockCost = BlockCost {
+    memory_per_byte: 6,
+    max_block_size: i32::MAX as usize,
+};
+/// Input, output and a 64-bit suffix array temporary for every byte.
+const LIBSAIS64_COST: BlockCost = BlockCost {
+    memory_per_byte: 10,
+    max_block_size: MAX_BLOCK_SIZE,
+};
+/// Input, output, the 32-bit suffix array and the lms bookkeeping of [`sais`] for every byte.
+const SAIS_COST: BlockCost = BlockCost {
+    memory_per_byte: 16,
+    max_block_size: MAX_BLOCK_SIZE,
+};
+
+/// Suffix array construction a block's bwt is computed with, see the module docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Libsais,
+    Libsais64,
+    Sais,
+}
 
-fn bwt_encode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
-    let use_fixed_threads = data.len() > 1_000_000;
+impl Backend {
+    fn cost(self) -> BlockCost {
+        match self {
+            Backend::Libsais => BWT_COST,
+            Backend::Libsais64 => LIBSAIS64_COST,
+            Backend::Sais => SAIS_COST,
+        }
+    }
+}
+
+/// Reads the backend of parameters already checked against [`PARAMS`], `None` when it is left to [`plan`].
+fn backend(params: &StageParams) -> Result<Option<Backend>> {
+    let backend = match params.get("backend") {
+        None | Some("auto") => return Ok(None),
+        Some("libsais") => Backend::Libsais,
+        Some("libsais64") => Backend::Libsais64,
+        Some("sais") => return Ok(Some(Backend::Sais)),
+        Some(raw) => bail!("bwt backend must be auto, libsais, libsais64 or sais, got {}", raw),
+    };
+    if !cfg!(feature = "libsais") {
+        bail!("bwt backend {:?} needs libsais, which this build of stackpack leaves out", params.get("backend").unwrap());
+    }
+    Ok(Some(backend))
+}
+
+/// Reads the block size of parameters already checked against [`PARAMS`], `None` when it is left to [`plan_blocks`].
+fn block_size(params: &StageParams) -> Result<Option<usize>> {
+    match params.get("block") {
+        Some(raw) => Ok(Some(parse_byte_size(raw).map_err(|e| anyhow!(e))? as usize)),
+        None => Ok(None),
+    }
+}
+
+fn validate_params(params: &StageParams) -> Result<()> {
+    match (block_size(params)?, backend(params)?) {
+        (Some(block_size), Some(Backend::Libsais)) if block_size > BWT_COST.max_block_size => bail!(
+            "bwt backend libsais can't index blocks of {} bytes, at most {}, libsais64 can",
+            block_size,
+            BWT_COST.max_block_size
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// The backend and block plan for `input_len` bytes: the block size given or one fitting the backend's memory needs,
+/// and with `backend=auto` the cheapest backend that can index such blocks.
+fn plan(params: &StageParams, input_len: usize) -> Result<(Backend, BlockPlan)> {
+    let backend = match backend(params)? {
+        Some(backend) => backend,
+        None if !cfg!(feature = "libsais") => Backend::Sais,
+        None => match block_size(params)? {
+            Some(block_size) if block_size > BWT_COST.max_block_size => Backend::Libsais64,
+            Some(_) => Backend::Libsais,
+            None if plan_blocks(input_len, LIBSAIS64_COST).block_size > BWT_COST.max_block_size => Backend::Libsais64,
+            None => Backend::Libsais,
+        },
+    };
+    let plan = match block_size(params)? {
+        Some(block_size) => BlockPlan {
+            block_size,
+            threads: plan_blocks(block_size.min(input_len), backend.cost()).threads,
+        },
+        None => plan_blocks(input_len, backend.cost()),
+    };
+    Ok((backend, plan))
+}
+
+#[cfg(feature = "libsais")]
+fn thread_count(threads: usize) -> ThreadCount {
+    ThreadCount::fixed(threads.clamp(1, u16::MAX as usize) as u16)
+}
+
+/// Single block inputs are framed as `[primary index: u32][bwt]`.
+/// Larger inputs are framed as `[BLOCKED_MARKER: u32][block size: u64]` followed by single block frames.
+/// The block size is recorded, so decoding ignores the stage parameters.
+fn bwt_encode(data: &[u8], buf: &mut Vec<u8>, params: &StageParams) -> Result<()> {
+    let (backend, plan) = plan(params, data.len())?;
     if_tracing! {{
-        tracing::debug!(target = "bwt", input_len = data.len(), use_fixed_threads, "bwt encode selecting thread strategy");
+        tracing::debug!(target = "bwt", input_len = data.len(), backend = ?backend, block_size = plan.block_size, threads = plan.threads, "bwt encode chose block plan");
     }}
-    let res = BwtConstruction::for_text(data)
-        .with_owned_temporary_array_buffer_and_extra_space32(ExtraSpace::Recommended)
-        .multi_threaded(if use_fixed_threads {
-            ThreadCount::fixed(12)
-        } else {
-            ThreadCount::openmp_default()
-        })
-        .run()
-        .unwrap();
 
     buf.clear();
-    let primary_index = res.primary_index();
-    let primary_index = u32::try_from(primary_index).expect("primary index must fit into u32");
-    let bwt_slice = res.bwt();
+    if data.len() <= plan.block_size {
+        return bwt_encode_block(data, backend, plan.threads, buf);
+    }
+
+    buf.extend_from_slice(&BLOCKED_MARKER.to_le_bytes());
+    buf.extend_from_slice(&(plan.block_size as u64).to_le_bytes());
+    for block in data.chunks(plan.block_size) {
+        bwt_encode_block(block, backend, plan.threads, buf)?;
+    }
+    Ok(())
+}
+
+/// Appends `[primary index: u32][bwt]` of `block` to `buf`.
+fn bwt_encode_block(block: &[u8], backend: Backend, threads: usize, buf: &mut Vec<u8>) -> Result<()> {
+    let index_at = buf.len();
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    let primary_index = match backend {
+        #[cfg(feature = "libsais")]
+        Backend::Libsais => libsais_bwt(block, threads, false, buf)?,
+        #[cfg(feature = "libsais")]
+        Backend::Libsais64 => libsais_bwt(block, threads, true, buf)?,
+        #[cfg(not(feature = "libsais"))]
+        Backend::Libsais | Backend::Libsais64 => unreachable!("libsais backends are refused without libsais"),
+        Backend::Sais => sais::bwt(block, buf),
+    };
+    // only the libsais backends run on several threads
+    #[cfg(not(feature = "libsais"))]
+    let _ = threads;
     if_tracing! {{
-        tracing::debug!(target = "bwt", primary_index, bwt_len = bwt_slice.len(), "bwt encode libsais complete");
+        tracing::debug!(target = "bwt", primary_index, bwt_len = block.len(), threads, "bwt encode block complete");
     }}
-    buf.extend_from_slice(&primary_index.to_le_bytes());
-    buf.extend_from_slice(bwt_slice);
+    buf[index_at..index_at + 4].copy_from_slice(&primary_index.to_le_bytes());
 
     Ok(())
 }
 
-fn bwt_decode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+/// Appends the bwt of `block` to `buf` and returns its primary index, with a 64-bit suffix array if `wide`.
+#[cfg(feature = "libsais")]
+fn libsais_bwt(block: &[u8], threads: usize, wide: bool, buf: &mut Vec<u8>) -> Result<u32> {
+    let construction = BwtConstruction::for_text(block);
+    let primary_index = if wide {
+        let res = construction
+            .with_owned_temporary_array_buffer_and_extra_space64(ExtraSpace::Recommended)
+            .multi_threaded(thread_count(threads))
+            .run()
+            .map_err(|err| anyhow!("libsais64 bwt failed: {:?}", err))?;
+        buf.extend_from_slice(res.bwt());
+        res.primary_index()
+    } else {
+        let res = construction
+            .with_owned_temporary_array_buffer_and_extra_space32(ExtraSpace::Recommended)
+            .multi_threaded(thread_count(threads))
+            .run()
+            .map_err(|err| anyhow!("libsais bwt failed: {:?}", err))?;
+        buf.extend_from_slice(res.bwt());
+        res.primary_index()
+    };
+    u32::try_from(primary_index).map_err(|_| anyhow!("primary index must fit into u32"))
+}
+
+fn bwt_decode(data: &[u8], buf: &mut Vec<u8>, _params: &StageParams, _context: &RevertContext) -> Result<()> {
     if_tracing! {{
         tracing::debug!(target = "bwt", input_len = data.len(), "bwt decode start");
     }}
 
+    buf.clear();
     if data.len() < 4 {
-        buf.clear();
         buf.extend_from_slice(data);
         return Ok(());
     }
 
+    let threads = plan_blocks(data.len(), BWT_COST).threads;
+    if data[..4] != BLOCKED_MARKER.to_le_bytes() {
+        return bwt_decode_block(data, threads, buf);
+    }
+
+    let (size_bytes, mut rest) = data[4..]
+        .split_at_checked(8)
+        .ok_or_else(|| anyhow!("bwt framing truncated: missing block size"))?;
+    let block_size = u64::from_le_bytes(size_bytes.try_into().unwrap());
+    let block_size = usize::try_from(block_size).map_err(|_| anyhow!("bwt block size {} too large", block_size))?;
+    if block_size == 0 {
+        return Err(anyhow!("bwt framing corrupt: zero block size"));
+    }
+    if_tracing! {{
+        tracing::debug!(target = "bwt", block_size, "bwt decode parsed block framing");
+    }}
+
+    while !rest.is_empty() {
+        if rest.len() <= 4 {
+            return Err(anyhow!("bwt framing truncated: block header without payload"));
+        }
+        let frame_len = 4 + block_size.min(rest.len() - 4);
+        let (frame, tail) = rest.split_at(frame_len);
+        bwt_decode_block(frame, threads, buf)?;
+        rest = tail;
+    }
+
+    Ok(())
+}
+
+/// Decodes one `[primary index: u32][bwt]` frame, appending the original bytes to `buf`.
+fn bwt_decode_block(frame: &[u8], threads: usize, buf: &mut Vec<u8>) -> Result<()> {
     let mut index_bytes = [0u8; 4];
-    index_bytes.copy_from_slice(&data[..4]);
+    index_bytes.copy_from_slice(&frame[..4]);
     let primary_index = u32::from_le_bytes(index_bytes) as usize;
-    let bwt_payload = &data[4..];
+    let bwt_payload = &frame[4..];
 
     if bwt_payload.is_empty() {
-        buf.clear();
         return Ok(());
     }
 
-    if primary_index >= bwt_payload.len() {
+    if primary_index > bwt_payload.len() {
         return Err(anyhow!("Invalid primary index: {} (bwt length: {})", primary_index, bwt_payload.len()));
     }
 
     if_tracing! {{
-        tracing::debug!(target = "bwt", primary_index, payload_len = bwt_payload.len(), "bwt decode parsed header");
+        tracing::debug!(target = "bwt", primary_index, payload_len = bwt_payload.len(), threads, "bwt decode parsed header");
     }}
 
-    buf.clear();
-    buf.resize(bwt_payload.len(), 0);
-    let bwt_owned = bwt_payload.to_vec();
+    unbwt(bwt_payload, primary_index, threads, buf)?;
+
+    if_tracing! {{
+        tracing::info!(target = "bwt", output_len = buf.len(), "bwt decode complete");
+    }}
+
+    Ok(())
+}
+
+/// Appends the block whose bwt is `bwt` to `buf`, with a 64-bit temporary for blocks libsais32 can't index.
+#[cfg(feature = "libsais")]
+fn unbwt(bwt: &[u8], primary_index: usize, threads: usize, buf: &mut Vec<u8>) -> Result<()> {
+    let start = buf.len();
+    buf.resize(start + bwt.len(), 0);
+    let bwt_owned = bwt.to_vec();
 
     // SAFETY: the primary index has been validated against the BWT payload, so they hopefully
     // follow the libsais BWT conventions or this is UB.
-    let builder = unsafe { LibsaisBwt::<u8, OwnedBuffer>::from_parts(bwt_owned, primary_index) }
+    let unbwt = unsafe { LibsaisBwt::<u8, OwnedBuffer>::from_parts(bwt_owned, primary_index) }
         .unbwt()
-        .in_borrowed_text_buffer(buf.as_mut_slice())
-        .with_owned_temporary_array_buffer32();
-
-    let use_fixed_threads = bwt_payload.len() > 1_000_000;
-    if_tracing! {{
-        tracing::debug!(target = "bwt", payload_len = bwt_payload.len(), use_fixed_threads, "bwt decode selecting thread strategy");
-    }}
-    let result = if use_fixed_threads {
-        builder.multi_threaded(ThreadCount::fixed(12)).run()
+        .in_borrowed_text_buffer(&mut buf[start..]);
+    let result = if bwt.len() > BWT_COST.max_block_size {
+        unbwt.with_owned_temporary_array_buffer64().multi_threaded(thread_count(threads)).run().map(|_| ())
     } else {
-        builder.multi_threaded(ThreadCount::openmp_default()).run()
+        unbwt.with_owned_temporary_array_buffer32().multi_threaded(thread_count(threads)).run().map(|_| ())
     };
+    result.map_err(|err| anyhow!("libsais unbwt failed: {:?}", err))
+}
 
-    result.map_err(|err| anyhow!("libsais unbwt failed: {:?}", err))?;
-
-    if_tracing! {{
-        tracing::info!(target = "bwt", output_len = buf.len(), "bwt decode complete");
-    }}
-
-    Ok(())
+/// Appends the block whose bwt is `bwt` to `buf`, by the inverse of [`sais`] in builds without libsais.
+#[cfg(not(feature = "libsais"))]
+fn unbwt(bwt: &[u8], primary_index: usize, _threads: usize, buf: &mut Vec<u8>) -> Result<()> {
+    sais::unbwt(bwt, primary_index, buf)
 }
 
 // /// Build a circular suffix array using the doubling algorithm.
@@ -284,3 +517,68 @@ fn bwt_decode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
 //     }
 //     Ok(())
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Blocks covering the edge cases of suffix sorting: empty and tiny ones, runs, periodic text and random bytes
+    /// over alphabets of several sizes.
+    fn samples() -> Vec<Vec<u8>> {
+        let mut samples = vec![
+            Vec::new(),
+            b"a".to_vec(),
+            b"ab".to_vec(),
+            b"ba".to_vec(),
+            b"banana".to_vec(),
+            b"mississippi".to_vec(),
+            vec![0; 1000],
+            vec![255; 1000],
+            b"abc".repeat(500),
+            b"abcabcabd".repeat(300),
+            (0..=255).collect(),
+            (0..=255).rev().collect(),
+        ];
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        for (len, alphabet) in [(17, 2), (100, 3), (1000, 4), (5000, 26), (20000, 256), (65537, 2)] {
+            let block = (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    (state % alphabet) as u8
+                })
+                .collect();
+            samples.push(block);
+        }
+        samples
+    }
+
+    #[cfg(feature = "libsais")]
+    #[test]
+    fn sais_matches_libsais() {
+        for block in samples() {
+            let (mut sais_bwt, mut libsais, mut libsais64) = (Vec::new(), Vec::new(), Vec::new());
+            let sais_index = sais::bwt(&block, &mut sais_bwt);
+            if block.is_empty() {
+                assert!(sais_bwt.is_empty());
+                continue;
+            }
+            let libsais_index = libsais_bwt(&block, 1, false, &mut libsais).unwrap();
+            let libsais64_index = libsais_bwt(&block, 1, true, &mut libsais64).unwrap();
+            assert_eq!((sais_index, &sais_bwt), (libsais_index, &libsais), "block of {} bytes", block.len());
+            assert_eq!((sais_index, &sais_bwt), (libsais64_index, &libsais64), "block of {} bytes", block.len());
+        }
+    }
+
+    #[test]
+    fn sais_round_trips() {
+        for block in samples() {
+            let mut transformed = Vec::new();
+            let primary_index = sais::bwt(&block, &mut transformed);
+            let mut restored = Vec::new();
+            sais::unbwt(&transformed, primary_index as usize, &mut restored).unwrap();
+            assert_eq!(restored, block);
+        }
+    }
+}
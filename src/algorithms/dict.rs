@@ -0,0 +1,94 @@
+//! static dictionary preloading. `prepend_dict(dict=<path>)` puts the contents of a shared dictionary file in front
+//! of the data, so that a context using stage after it, like `bwt` or `bsc`, already knows the strings the data is
+//! likely to contain. decoding strips the dictionary off again. this is meant for many small files that look alike,
+//! e.g. json records or config files, which are too short for the stages to learn much from on their own:
+//!
+//! > `$exename enc record.json --using "prepend_dict(dict=records.dict) -> bsc"`
+//!
+//! the output is
+//!
+//! | field           | notes                      |
+//! |-----------------|----------------------------|
+//! | dictionary size | varint                     |
+//! | dictionary      | the contents of `dict`     |
+//! | data            | the input, unchanged       |
+//!
+//! the dictionary goes through the rest of the pipeline along with the data, so keep it small: what it saves on the
+//! data has to make up for its own compressed size. as its size is recorded, decoding doesn't need the file.
+use std::fs;
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    algorithms::{
+        ParamMutator, RevertContext,
+        heuristics::MemoryCost,
+        params::{ParamDescriptor, ParamKind, StageParams},
+    },
+    registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo},
+    varint,
+};
+
+pub const PrependDict: RegisteredCompressor = RegisteredCompressor::new_param(
+    ParamMutator {
+        drive_mutation: prepend,
+        revert_mutation: strip,
+        validate: validate_params,
+    },
+    "prepend_dict",
+    Some(DESCRIPTION),
+)
+.with_info(StageInfo {
+    category: StageCategory::Transform,
+    aliases: &["dict"],
+    params: PARAMS,
+    memory: MemoryCost::PerByte(2),
+    explanation: StageExplanation {
+        entropy: "adds the dictionary in front of the data, so the context using stages after it already know the \
+            strings the data is likely to contain",
+        size: "grows by the size of the dictionary",
+        decoding: "strips the dictionary off again, the dictionary file isn't needed",
+    },
+});
+const PARAMS: &[ParamDescriptor] = &[ParamDescriptor {
+    name: "dict",
+    kind: ParamKind::Path,
+    default: None,
+    description: "The dictionary file, only read when encoding.",
+}];
+const DESCRIPTION: &str = "Prepends a shared dictionary file to the data, so that bwt or bsc after it do better on \
+small similar files, and strips it after decoding. Parameters: dict=<path> is the dictionary, required.";
+
+/// Reads the dictionary path of parameters already checked against [`PARAMS`].
+fn dict_path(params: &StageParams) -> Result<&str> {
+    params
+        .get("dict")
+        .ok_or_else(|| anyhow!("prepend_dict needs the dictionary file, as prepend_dict(dict=<path>)"))
+}
+
+fn validate_params(params: &StageParams) -> Result<()> {
+    dict_path(params).map(|_| ())
+}
+
+fn prepend(data: &[u8], buf: &mut Vec<u8>, params: &StageParams) -> Result<()> {
+    let path = dict_path(params)?;
+    let dict = fs::read(path).map_err(|e| anyhow!("failed to read the dictionary {}: {}", path, e))?;
+    buf.clear();
+    buf.reserve(dict.len() + data.len() + 10);
+    varint::write_u64(buf, dict.len() as u64);
+    buf.extend_from_slice(&dict);
+    buf.extend_from_slice(data);
+    Ok(())
+}
+
+/// The dictionary size is recorded, so decoding ignores the stage parameters.
+fn strip(mut data: &[u8], buf: &mut Vec<u8>, _params: &StageParams, _context: &RevertContext) -> Result<()> {
+    let dict_size = varint::read_u64(&mut data)?;
+    let data = usize::try_from(dict_size)
+        .ok()
+        .and_then(|dict_size| data.get(dict_size..))
+        .ok_or_else(|| anyhow!("input too short for its {} byte dictionary", dict_size))?;
+    buf.clear();
+    buf.extend_from_slice(data);
+    Ok(())
+}
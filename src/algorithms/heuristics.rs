@@ -0,0 +1,137 @@
+//! heuristics picking block sizes and thread counts for the block based algorithms.
+//!
+//! the thread count is shared by all stages. it is what the machine can run in parallel unless the global `--threads`
+//! option asked for another number, see [`set_threads`].
+//!
+//! the smallest block and the least work worth a thread of their own are [`Thresholds`], built-in defaults unless
+//! `calibrate` measured them on the machine and saved them to the user config, see [`set_thresholds`].
+use std::{
+    fs,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::units::{GIBIBYTES, MEBIBYTES};
+
+/// Memory budget assumed when the amount of available memory can't be determined.
+const FALLBACK_MEMORY_BUDGET: u64 = 2 * GIBIBYTES as u64;
+const DEFAULT_MIN_BLOCK_SIZE: usize = MEBIBYTES;
+const DEFAULT_MIN_BYTES_PER_THREAD: usize = 256 * 1024;
+
+/// The machine specific numbers [`plan_blocks`] goes by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Thresholds {
+    /// Blocks smaller than this are never chosen, as per-block overhead starts to dominate.
+    pub min_block_size: usize,
+    /// Inputs smaller than this are processed on a single thread, spawning threads costs more than it saves.
+    pub min_bytes_per_thread: usize,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            min_block_size: DEFAULT_MIN_BLOCK_SIZE,
+            min_bytes_per_thread: DEFAULT_MIN_BYTES_PER_THREAD,
+        }
+    }
+}
+
+/// Block size and thread count chosen for one invocation of an algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockPlan {
+    pub block_size: usize,
+    pub threads: usize,
+}
+
+/// Per-algorithm inputs to [`plan_blocks`].
+#[derive(Debug, Clone, Copy)]
+pub struct BlockCost {
+    /// Peak bytes of memory needed per byte of block.
+    pub memory_per_byte: u64,
+    /// Largest block the algorithm can process.
+    pub max_block_size: usize,
+}
+
+/// How the memory a stage needs grows with its input, see [`stage_memory`].
+#[derive(Debug, Clone, Copy)]
+pub enum MemoryCost {
+    /// Peak bytes of memory needed per byte of input, counting the input and the output.
+    PerByte(u64),
+    /// Works through blocks picked by [`plan_blocks`] with this cost, holding the whole input and output besides.
+    Blocks(BlockCost),
+}
+
+/// Threads asked for with `--threads`, zero when the machine decides.
+static THREADS: AtomicUsize = AtomicUsize::new(0);
+static MIN_BLOCK_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MIN_BLOCK_SIZE);
+static MIN_BYTES_PER_THREAD: AtomicUsize = AtomicUsize::new(DEFAULT_MIN_BYTES_PER_THREAD);
+
+/// Limits every stage to `threads` threads.
+pub fn set_threads(threads: usize) {
+    THREADS.store(threads, Ordering::Relaxed);
+}
+
+/// Number of threads stages may use, the one asked for with `--threads` or what the machine can run in parallel.
+pub fn available_threads() -> usize {
+    match THREADS.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        threads => threads,
+    }
+}
+
+/// Makes every stage go by `thresholds` instead of the built-in defaults.
+pub fn set_thresholds(thresholds: Thresholds) {
+    MIN_BLOCK_SIZE.store(thresholds.min_block_size.max(1), Ordering::Relaxed);
+    MIN_BYTES_PER_THREAD.store(thresholds.min_bytes_per_thread.max(1), Ordering::Relaxed);
+}
+
+/// The thresholds stages go by, see [`set_thresholds`].
+pub fn thresholds() -> Thresholds {
+    Thresholds {
+        min_block_size: MIN_BLOCK_SIZE.load(Ordering::Relaxed),
+        min_bytes_per_thread: MIN_BYTES_PER_THREAD.load(Ordering::Relaxed),
+    }
+}
+
+/// Bytes of memory currently available, if the platform exposes it.
+pub fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemAvailable:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Peak bytes of memory a stage of `cost` needs for `input_len` bytes, assuming its output is no larger than its input.
+pub fn stage_memory(cost: MemoryCost, input_len: usize) -> u64 {
+    let len = input_len as u64;
+    match cost {
+        MemoryCost::PerByte(per_byte) => per_byte.saturating_mul(len),
+        MemoryCost::Blocks(cost) => {
+            let block_size = plan_blocks(input_len, cost).block_size as u64;
+            // the block cost counts the block's share of the input and output too
+            (2 * len).saturating_add(cost.memory_per_byte.saturating_sub(2).saturating_mul(block_size))
+        }
+    }
+}
+
+/// Picks a block size that keeps one block within half of the available memory, and a thread count that
+/// gives every thread a meaningful amount of work.
+pub fn plan_blocks(input_len: usize, cost: BlockCost) -> BlockPlan {
+    let budget = available_memory().map(|m| m / 2).unwrap_or(FALLBACK_MEMORY_BUDGET);
+    let memory_bound = usize::try_from(budget / cost.memory_per_byte.max(1)).unwrap_or(usize::MAX);
+    let thresholds = thresholds();
+    let block_size = memory_bound
+        .clamp(thresholds.min_block_size, cost.max_block_size.max(thresholds.min_block_size))
+        .min(cost.max_block_size)
+        .min(input_len.max(1));
+
+    let threads = (block_size / thresholds.min_bytes_per_thread).clamp(1, available_threads());
+
+    let plan = BlockPlan { block_size, threads };
+    if_tracing! {{
+        tracing::debug!(target = "heuristics", input_len, budget, block_size = plan.block_size, threads = plan.threads, "chose block plan");
+    }}
+    plan
+}
@@ -1,10 +1,11 @@
 #![allow(unused)]
+#![allow(clippy::ptr_arg)]
 use std::fmt::Display;
 
 //todo
 use anyhow::{Result, anyhow};
 
-use crate::algorithms::DynMutator;
+use crate::algorithms::{DynMutator, RevertContext};
 pub const Huffman: DynMutator = DynMutator {
     drive_mutation: huffman_encode,
     revert_mutation: huffman_decode,
@@ -16,6 +17,6 @@ pub fn huffman_encode(_data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
     todo!("Huffman coding is currently unimplemented")
 }
 
-pub fn huffman_decode(_data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+pub fn huffman_decode(_data: &[u8], buf: &mut Vec<u8>, _context: &RevertContext) -> Result<()> {
     todo!("Huffman coding is currently unimplemented")
 }
@@ -1,6 +1,11 @@
+#![allow(unused)] //todo
+#![allow(clippy::ptr_arg)]
 use anyhow::{Result, anyhow};
 
-use crate::{algorithms::DynMutator, registered::RegisteredCompressor};
+use crate::{
+    algorithms::{DynMutator, RevertContext, heuristics::MemoryCost},
+    registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo},
+};
 
 pub const ImgDecoder: RegisteredCompressor = RegisteredCompressor::new_dyn(
     DynMutator {
@@ -9,7 +14,20 @@ pub const ImgDecoder: RegisteredCompressor = RegisteredCompressor::new_dyn(
     },
     "img_decode",
     Some(DESCRIPTION),
-);
+)
+.with_info(StageInfo {
+    category: StageCategory::Media,
+    aliases: &[],
+    params: &[],
+    // decoded pixels take many times the space of a compressed image
+    memory: MemoryCost::PerByte(16),
+    explanation: StageExplanation {
+        entropy: "would decode an image into its raw pixels, which expose the structure the image codec hid. not \
+            implemented yet",
+        size: "would grow, raw pixels take more room than the compressed image",
+        decoding: "would encode the pixels back into the original image format. not implemented yet",
+    },
+});
 const DESCRIPTION: &str = "General Image Decoding";
 
 const ARCODE_PRECISION: u64 = 48;
@@ -102,7 +120,7 @@ fn img_encode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
     Err(anyhow!("image decoder cannot be used to encode images yet"))
 }
 
-fn img_decode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+fn img_decode(data: &[u8], buf: &mut Vec<u8>, _context: &RevertContext) -> Result<()> {
     if_tracing! {{
         tracing::debug!(target = "img_decode", input_len = data.len(), "image decode start");
     }}
@@ -0,0 +1,89 @@
+//! heuristics flagging stage orderings that are known to compress badly.
+use core::fmt::{self, Display};
+
+use crate::algorithms::pipeline::CompressionPipeline;
+
+/// The role a stage plays, as far as the lints are concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StageRole {
+    /// Burrows-Wheeler transform.
+    Bwt,
+    /// Move-to-front transform.
+    Mtf,
+    /// Output looks like random bytes, nothing after it finds structure to exploit.
+    EntropyCoder,
+    /// Reshapes the data for later stages without reducing entropy itself.
+    Filter,
+    /// Plugins and anything else the lints know nothing about.
+    Unknown,
+}
+
+fn role(name: &str) -> StageRole {
+    match name {
+        "bwt" => StageRole::Bwt,
+        "mtf" => StageRole::Mtf,
+        "arcode" | "bsc" => StageRole::EntropyCoder,
+        "re_pair" | "bpe" | "img_decode" | "alphabet" | "rle1" => StageRole::Filter,
+        _ => StageRole::Unknown,
+    }
+}
+
+/// A questionable stage, with an explanation of why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    /// Index of the offending stage, in encoding order.
+    pub stage: usize,
+    pub message: String,
+    pub explanation: &'static str,
+}
+
+impl Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stage {}: {}\n    {}", self.stage, self.message, self.explanation)
+    }
+}
+
+/// Checks the stage order of `pipeline`. An encryption stage would get a rule against running before compression,
+/// but there is none yet.
+pub fn lint(pipeline: &CompressionPipeline) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    let mut entropy_coder: Option<(usize, &str)> = None;
+    let mut seen_bwt = false;
+
+    for (index, stage) in pipeline.stages().iter().enumerate() {
+        let role = role(stage.name);
+        match (role, entropy_coder) {
+            (StageRole::Bwt, Some((coder, name))) => warnings.push(LintWarning {
+                stage: index,
+                message: format!("bwt runs after the entropy coder {} (stage {})", name, coder),
+                explanation: "the bwt groups similar contexts so an entropy coder can exploit them, it belongs before the coder.",
+            }),
+            (StageRole::EntropyCoder, Some((coder, name))) => warnings.push(LintWarning {
+                stage: index,
+                message: format!("{} runs after the entropy coder {} (stage {})", stage.name, name, coder),
+                explanation: "entropy coded data is close to random, coding it a second time costs time and usually grows it.",
+            }),
+            (StageRole::Mtf | StageRole::Filter, Some((coder, name))) => warnings.push(LintWarning {
+                stage: index,
+                message: format!("{} runs after the entropy coder {} (stage {})", stage.name, name, coder),
+                explanation: "transforms prepare data for the entropy coder, after it there is no structure left for them to expose.",
+            }),
+            _ => {}
+        }
+        if role == StageRole::Mtf && !seen_bwt {
+            warnings.push(LintWarning {
+                stage: index,
+                message: "mtf runs without a bwt before it".to_string(),
+                explanation: "mtf turns the runs a bwt produces into small numbers, on untransformed data it mostly scrambles the statistics.",
+            });
+        }
+
+        match role {
+            StageRole::Bwt => seen_bwt = true,
+            StageRole::EntropyCoder if entropy_coder.is_none() => entropy_coder = Some((index, stage.name)),
+            _ => {}
+        }
+    }
+
+    warnings
+}
@@ -0,0 +1,247 @@
+use std::io::{Read, Write};
+
+use anyhow::{Result, anyhow, bail};
+use lz4_flex::{
+    block,
+    frame::{FrameDecoder, FrameEncoder},
+};
+
+use crate::{
+    algorithms::{
+        OutputLimitExceeded, ParamMutator, RevertContext,
+        arcode::{arith_decode, arith_encode},
+        heuristics::MemoryCost,
+        params::{ParamDescriptor, ParamKind, StageParams},
+    },
+    registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo},
+    varint,
+};
+
+pub const Lz4: RegisteredCompressor = RegisteredCompressor::new_param(
+    ParamMutator {
+        drive_mutation: lz4_encode,
+        revert_mutation: lz4_decode,
+        validate: validate_params,
+    },
+    "lz4",
+    Some(DESCRIPTION),
+)
+.with_info(StageInfo {
+    category: StageCategory::Compressor,
+    aliases: &[],
+    params: PARAMS,
+    // the split layout holds the literal, length and offset streams before joining them
+    memory: MemoryCost::PerByte(3),
+    explanation: StageExplanation {
+        entropy: "replaces repeats with references back into the data, which order-0 statistics can't see. with \
+            streams=split the literals, lengths and offsets are arithmetic coded each on their own",
+        size: "shrinks data with repeats, by less than the other compressors but very fast",
+        decoding: "copies the literals and matches back out, the layout is recognized from the data",
+    },
+});
+const PARAMS: &[ParamDescriptor] = &[ParamDescriptor {
+    name: "streams",
+    kind: ParamKind::Choice {
+        values: &["frame", "split"],
+    },
+    default: Some("frame"),
+    description: "Writes a standard .lz4 frame, or arithmetic codes literals, lengths and offsets apart.",
+}];
+const DESCRIPTION: &str = "LZ4 frame format, trading ratio for very fast encoding and decoding. \
+The output is a standard .lz4 file that `lz4 -d` reads. \
+Parameters: streams=split splits the tokens into literal, length and offset streams and arithmetic codes each of \
+them on its own, which compresses much better but is no longer .lz4 (default frame).";
+/// Starts the output of `streams=split`. the lz4 frame magic is `04 22 4d 18`, so decoding tells the two apart.
+const SPLIT_MAGIC: [u8; 4] = [0x5a, 0x4c, 0x53, 0x01];
+/// The shortest match lz4 encodes, match lengths are stored less this.
+const MIN_MATCH: usize = 4;
+
+/// Reads the stream layout of parameters already checked against [`PARAMS`].
+fn split_streams(params: &StageParams) -> Result<bool> {
+    match params.get("streams") {
+        None | Some("frame") => Ok(false),
+        Some("split") => Ok(true),
+        Some(other) => bail!("lz4 streams must be frame or split, got {}", other),
+    }
+}
+
+fn validate_params(params: &StageParams) -> Result<()> {
+    split_streams(params).map(|_| ())
+}
+
+fn lz4_encode(data: &[u8], buf: &mut Vec<u8>, params: &StageParams) -> Result<()> {
+    if_tracing! {{
+        tracing::debug!(target = "lz4", input_len = data.len(), "lz4 encode start");
+    }}
+    buf.clear();
+    if split_streams(params)? {
+        return split_encode(data, buf);
+    }
+    let mut encoder = FrameEncoder::new(&mut *buf);
+    encoder.write_all(data)?;
+    encoder.finish().map_err(|e| anyhow!("lz4 encode failed: {}", e))?;
+    if_tracing! {{
+        tracing::info!(target = "lz4", input_len = data.len(), output_len = buf.len(), "lz4 encode complete");
+    }}
+    Ok(())
+}
+
+/// The stream layout is recognized from the data, so decoding ignores the stage parameters.
+fn lz4_decode(data: &[u8], buf: &mut Vec<u8>, _params: &StageParams, context: &RevertContext) -> Result<()> {
+    if_tracing! {{
+        tracing::debug!(target = "lz4", input_len = data.len(), "lz4 decode start");
+    }}
+    buf.clear();
+    if let Some(streams) = data.strip_prefix(&SPLIT_MAGIC) {
+        return split_decode(streams, buf, context);
+    }
+    // reading a byte past the limit is enough to tell it was crossed, the rest of the frame is never decoded
+    FrameDecoder::new(data)
+        .take(context.read_limit())
+        .read_to_end(buf)
+        .map_err(|e| anyhow!("lz4 decode failed: {}", e))?;
+    context.check_output(buf.len())?;
+    if_tracing! {{
+        tracing::info!(target = "lz4", input_len = data.len(), output_len = buf.len(), "lz4 decode complete");
+    }}
+    Ok(())
+}
+
+/// The tokens of an lz4 block, pulled apart into streams that each hold one kind of value.
+#[derive(Debug, Default)]
+struct Streams {
+    /// Every literal byte, in order.
+    literals: Vec<u8>,
+    /// The literal length of every sequence followed by its match length less [`MIN_MATCH`], as varints. the last
+    /// sequence has no match.
+    lengths: Vec<u8>,
+    /// The offset of every match, as little endian `u16`s.
+    offsets: Vec<u8>,
+}
+
+/// Reads the length in the low or high nibble of a token, followed by 255s and a final byte when it is 15.
+fn read_length(nibble: u8, block: &mut &[u8]) -> Result<usize> {
+    let mut length = nibble as usize;
+    if nibble == 15 {
+        loop {
+            let (&byte, rest) = block.split_first().ok_or_else(|| anyhow!("lz4 block ends inside a length"))?;
+            *block = rest;
+            length += byte as usize;
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+    Ok(length)
+}
+
+impl Streams {
+    /// Splits a block as [`block::compress`] writes it.
+    fn split(mut block: &[u8]) -> Result<Self> {
+        let mut streams = Streams::default();
+        while let Some((&token, rest)) = block.split_first() {
+            block = rest;
+            let literal_length = read_length(token >> 4, &mut block)?;
+            let (literals, rest) = block
+                .split_at_checked(literal_length)
+                .ok_or_else(|| anyhow!("lz4 block ends inside literals"))?;
+            block = rest;
+            streams.literals.extend_from_slice(literals);
+            varint::write_u64(&mut streams.lengths, literal_length as u64);
+            if block.is_empty() {
+                break;
+            }
+            let (offset, rest) = block
+                .split_at_checked(2)
+                .ok_or_else(|| anyhow!("lz4 block ends inside an offset"))?;
+            block = rest;
+            streams.offsets.extend_from_slice(offset);
+            let match_length = read_length(token & 15, &mut block)?;
+            varint::write_u64(&mut streams.lengths, match_length as u64);
+        }
+        Ok(streams)
+    }
+
+    /// Replays the sequences into `buf` until it holds `size` bytes.
+    fn join(&self, size: usize, buf: &mut Vec<u8>) -> Result<()> {
+        let (mut literals, mut lengths, mut offsets) = (&self.literals[..], &self.lengths[..], &self.offsets[..]);
+        loop {
+            let literal_length = varint::read_u64(&mut lengths)? as usize;
+            let (run, rest) = literals
+                .split_at_checked(literal_length)
+                .ok_or_else(|| anyhow!("lz4 literal stream is too short"))?;
+            literals = rest;
+            buf.extend_from_slice(run);
+            if buf.len() >= size {
+                break;
+            }
+            let (offset, rest) = offsets
+                .split_at_checked(2)
+                .ok_or_else(|| anyhow!("lz4 offset stream is too short"))?;
+            offsets = rest;
+            let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+            let match_length = varint::read_u64(&mut lengths)? as usize + MIN_MATCH;
+            if offset == 0 || offset > buf.len() || buf.len() + match_length > size {
+                bail!("lz4 match at {} is out of bounds", buf.len());
+            }
+            // matches may overlap what they copy, so they are copied a byte at a time
+            let start = buf.len() - offset;
+            for index in start..start + match_length {
+                buf.push(buf[index]);
+            }
+        }
+        if buf.len() != size || !literals.is_empty() || !lengths.is_empty() || !offsets.is_empty() {
+            bail!("lz4 streams don't add up to {} bytes", size);
+        }
+        Ok(())
+    }
+}
+
+/// The output is [`SPLIT_MAGIC`], the size of the input as a varint, then the literal, length and offset streams as
+/// `[arithmetic coded size: varint][arithmetic coded stream]`.
+fn split_encode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+    let streams = Streams::split(&block::compress(data))?;
+    buf.extend_from_slice(&SPLIT_MAGIC);
+    varint::write_u64(buf, data.len() as u64);
+    let mut coded = Vec::new();
+    for stream in [&streams.literals, &streams.lengths, &streams.offsets] {
+        arith_encode(stream, &mut coded)?;
+        varint::write_u64(buf, coded.len() as u64);
+        buf.extend_from_slice(&coded);
+    }
+    if_tracing! {{
+        tracing::info!(target = "lz4", input_len = data.len(), literals = streams.literals.len(), lengths = streams.lengths.len(), offsets = streams.offsets.len(), output_len = buf.len(), "lz4 split encode complete");
+    }}
+    Ok(())
+}
+
+fn split_decode(mut data: &[u8], buf: &mut Vec<u8>, context: &RevertContext) -> Result<()> {
+    let size = usize::try_from(varint::read_u64(&mut data)?).map_err(|_| anyhow!("lz4 size doesn't fit in memory"))?;
+    // the size is known up front, so an input claiming too much is refused before any stream is decoded
+    context.check_output(size)?;
+    // no stream of an intact input holds more than a byte over the output, so under a limit the streams are stopped
+    // there too instead of growing past it
+    let streams_context = RevertContext {
+        max_output_size: context.max_output_size.map(|_| size.saturating_add(1)),
+    };
+    let mut decoded: [Vec<u8>; 3] = Default::default();
+    for stream in &mut decoded {
+        let coded_size = varint::read_u64(&mut data)? as usize;
+        let (coded, rest) = data.split_at_checked(coded_size).ok_or_else(|| anyhow!("input too short"))?;
+        data = rest;
+        arith_decode(coded, stream, &streams_context).map_err(|e| match e.is::<OutputLimitExceeded>() {
+            true => anyhow!("lz4 streams hold more than the {} bytes they decode to", size),
+            false => e,
+        })?;
+    }
+    if !data.is_empty() {
+        bail!("lz4 streams are followed by {} stray bytes", data.len());
+    }
+    let [literals, lengths, offsets] = decoded;
+    Streams {
+        literals,
+        lengths,
+        offsets,
+    }
+    .join(size, buf)
+}
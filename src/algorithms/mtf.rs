@@ -1,4 +1,8 @@
-use crate::{algorithms::DynMutator, mutator::Result, registered::RegisteredCompressor};
+use crate::{
+    algorithms::{DynMutator, RevertContext, heuristics::MemoryCost},
+    mutator::Result,
+    registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo},
+};
 
 pub const Mtf: RegisteredCompressor = RegisteredCompressor::new_dyn(
     DynMutator {
@@ -7,7 +11,19 @@ pub const Mtf: RegisteredCompressor = RegisteredCompressor::new_dyn(
     },
     "mtf",
     Some(DESCRIPTION),
-);
+)
+.with_info(StageInfo {
+    category: StageCategory::Transform,
+    aliases: &["move-to-front"],
+    params: &[],
+    memory: MemoryCost::PerByte(2),
+    explanation: StageExplanation {
+        entropy: "leaves the information as it is, but turns bytes seen recently into small ranks, so the output of a \
+            bwt becomes mostly zeros and ones",
+        size: "keeps the size",
+        decoding: "replays the ranks against the same starting list of byte values",
+    },
+});
 const DESCRIPTION: &str = "Move-to-front transform. Useful after Burrows-Wheeler transform";
 
 macro_rules! iota {
@@ -69,7 +85,7 @@ pub fn mtf_encode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
     Ok(())
 }
 
-pub fn mtf_decode(encoded: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+pub fn mtf_decode(encoded: &[u8], buf: &mut Vec<u8>, _context: &RevertContext) -> Result<()> {
     if_tracing! {{
         tracing::debug!(target = "mtf", input_len = encoded.len(), "mtf decode start");
     }}
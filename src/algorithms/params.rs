@@ -0,0 +1,186 @@
+//! `key=value` parameters attached to a single pipeline stage, as in `bsc(block=64m, width=16)`.
+use core::fmt::{self, Display};
+
+use anyhow::{Result, anyhow, bail};
+use serde::Serialize;
+
+use crate::units::{ByteSize, parse_byte_size};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StageParams {
+    entries: Vec<(String, String)>,
+}
+
+impl StageParams {
+    pub const fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Parses the text between the parentheses of a stage, e.g. `block=64m, width=16`.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let mut params = StageParams::new();
+        for entry in raw.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (key, value) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("stage parameter {:?} is not of the form key=value", entry))?;
+            let (key, value) = (key.trim(), value.trim());
+            if key.is_empty() || value.is_empty() {
+                bail!("stage parameter {:?} is not of the form key=value", entry);
+            }
+            if params.get(key).is_some() {
+                bail!("stage parameter {:?} is given more than once", key);
+            }
+            params.entries.push((key.to_string(), value.to_string()));
+        }
+        Ok(params)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(k, _)| k.as_str())
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Sets `key` to `value`, unless it is set already.
+    pub fn set_default(&mut self, key: &str, value: &str) {
+        if self.get(key).is_none() {
+            self.entries.push((key.to_string(), value.to_string()));
+        }
+    }
+}
+
+/// The values a stage parameter accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ParamKind {
+    /// A byte count with an optional suffix such as `64m`, between `min` and `max` bytes.
+    Size { min: u64, max: u64 },
+    /// A whole number between `min` and `max`.
+    Integer { min: u64, max: u64 },
+    /// One of a fixed set of values.
+    Choice { values: &'static [&'static str] },
+    /// A file path. it can't contain `,` or `->`, which separate parameters and stages.
+    Path,
+}
+
+impl ParamKind {
+    /// Checks a raw value, describing what was expected when it doesn't fit.
+    pub fn check(&self, raw: &str) -> core::result::Result<(), String> {
+        match *self {
+            ParamKind::Size { min, max } => match parse_byte_size(raw) {
+                Ok(size) if (min..=max).contains(&size) => Ok(()),
+                _ => Err(format!("a size between {} and {}", ByteSize(min), ByteSize(max))),
+            },
+            ParamKind::Integer { min, max } => match raw.parse::<u64>() {
+                Ok(value) if (min..=max).contains(&value) => Ok(()),
+                _ => Err(format!("a whole number between {} and {}", min, max)),
+            },
+            ParamKind::Choice { values } if values.contains(&raw) => Ok(()),
+            ParamKind::Choice { values } => Err(format!("one of {}", values.join(", "))),
+            ParamKind::Path => Ok(()),
+        }
+    }
+}
+
+/// Formats the kind as it is shown in help output, e.g. `<size>` or `8|16`.
+impl Display for ParamKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamKind::Size { .. } => f.write_str("<size>"),
+            ParamKind::Integer { min, max } => write!(f, "<{}..{}>", min, max),
+            ParamKind::Choice { values } => f.write_str(&values.join("|")),
+            ParamKind::Path => f.write_str("<path>"),
+        }
+    }
+}
+
+/// A parameter a stage accepts, written as `name(key=value, ...)` in pipelines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ParamDescriptor {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    /// The value used when the parameter is left out, `None` when it is picked at runtime.
+    pub default: Option<&'static str>,
+    pub description: &'static str,
+}
+
+impl StageParams {
+    /// Checks every parameter against the ones `stage` declares in `schema`.
+    pub fn check_schema(&self, stage: &str, schema: &[ParamDescriptor]) -> Result<()> {
+        for (key, value) in &self.entries {
+            let Some(descriptor) = schema.iter().find(|descriptor| descriptor.name == key) else {
+                let names = schema.iter().map(|descriptor| descriptor.name);
+                bail!(
+                    "{}: unknown parameter '{}', {}",
+                    stage,
+                    key,
+                    match closest(key, names.clone()) {
+                        Some(name) => format!("did you mean '{}'?", name),
+                        None if schema.is_empty() => format!("{} takes no parameters", stage),
+                        None => format!("expected one of {}", names.collect::<Vec<_>>().join(", ")),
+                    }
+                );
+            };
+            if let Err(expected) = descriptor.kind.check(value) {
+                bail!("{}: parameter '{}' must be {}, got '{}'", stage, key, expected, value);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The candidate within two edits of `key`, if there is one.
+fn closest<'a>(key: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|&(distance, _)| distance <= 2)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+impl FromIterator<(String, String)> for StageParams {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        StageParams {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Formats the parameters as `key=value, key=value`, the form accepted by [`StageParams::parse`].
+impl Display for StageParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (key, value)) in self.entries.iter().enumerate() {
+            if index != 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+//! header and trailer regions that a stage passes through untouched, asked for with the `skip_head=<size>` and
+//! `skip_tail=<size>` parameters every stage accepts, as in `bwt(skip_head=44)`. the stage only sees the data in
+//! between, so a known header such as the one of a WAV or BMP file stays readable at the start of the output, or is
+//! left to a stage that understands it.
+//!
+//! the output is
+//!
+//! | field       | notes                                          |
+//! |-------------|------------------------------------------------|
+//! | head        | the first bytes of the input, as they are      |
+//! | body        | the rest of the input, run through the stage   |
+//! | tail        | the last bytes of the input, as they are       |
+//! | head length | u64, little endian                             |
+//! | tail length | u64, little endian                             |
+//!
+//! inputs shorter than both regions together pass through whole, head first.
+//!
+//! `on_error=skip` passes through the whole input of a stage that fails on it instead of failing the pipeline, e.g.
+//! `img_decode(on_error=skip)` in an archive that has images along with other files. every output of such a stage
+//! starts with a byte telling whether the stage ran on it, [`STAGE_RAN`] or [`STAGE_SKIPPED`], so decoding skips the
+//! stage for the same blocks and frames. `enc --skip-failing-stages` sets it on every stage.
+use anyhow::{Result, anyhow, bail};
+
+use crate::{
+    algorithms::params::{ParamDescriptor, ParamKind, StageParams},
+    events,
+    units::parse_byte_size,
+};
+
+/// Parameters every stage accepts on top of its own.
+pub const PASSTHROUGH_PARAMS: &[ParamDescriptor] = &[
+    ParamDescriptor {
+        name: "skip_head",
+        kind: ParamKind::Size { min: 0, max: u64::MAX },
+        default: Some("0"),
+        description: "Bytes at the start of the input passed through without running the stage on them.",
+    },
+    ParamDescriptor {
+        name: "skip_tail",
+        kind: ParamKind::Size { min: 0, max: u64::MAX },
+        default: Some("0"),
+        description: "Bytes at the end of the input passed through without running the stage on them.",
+    },
+    ParamDescriptor {
+        name: "on_error",
+        kind: ParamKind::Choice { values: &["fail", "skip"] },
+        default: Some("fail"),
+        description: "Whether an input the stage fails on fails the pipeline, or is passed through and flagged.",
+    },
+];
+/// Starts the output of an `on_error=skip` stage that ran.
+pub const STAGE_RAN: u8 = 0;
+/// Starts the output of an `on_error=skip` stage that failed, followed by its input as it was.
+pub const STAGE_SKIPPED: u8 = 1;
+/// The two region lengths at the end of the output.
+const FOOTER_LEN: usize = 16;
+
+/// The regions of the input a stage passes through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Passthrough {
+    pub head: usize,
+    pub tail: usize,
+}
+
+impl Passthrough {
+    /// Reads the regions from parameters already checked against [`PASSTHROUGH_PARAMS`], `None` when the stage
+    /// passes nothing through.
+    pub fn from_params(params: &StageParams) -> Option<Self> {
+        let size = |key| {
+            params
+                .get(key)
+                .and_then(|raw| parse_byte_size(raw).ok())
+                .map_or(0, |size| usize::try_from(size).unwrap_or(usize::MAX))
+        };
+        let passthrough = Passthrough {
+            head: size("skip_head"),
+            tail: size("skip_tail"),
+        };
+        (passthrough.head != 0 || passthrough.tail != 0).then_some(passthrough)
+    }
+
+    /// Runs `stage` on everything of `data` but the regions.
+    pub fn drive(self, data: &[u8], buf: &mut Vec<u8>, stage: impl FnOnce(&[u8], &mut Vec<u8>) -> Result<()>) -> Result<()> {
+        let head = self.head.min(data.len());
+        let tail = self.tail.min(data.len() - head);
+        let (head, rest) = data.split_at(head);
+        let (body, tail) = rest.split_at(rest.len() - tail);
+
+        let mut encoded = Vec::new();
+        stage(body, &mut encoded)?;
+        buf.clear();
+        buf.reserve(head.len() + encoded.len() + tail.len() + FOOTER_LEN);
+        buf.extend_from_slice(head);
+        buf.extend_from_slice(&encoded);
+        buf.extend_from_slice(tail);
+        buf.extend_from_slice(&(head.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&(tail.len() as u64).to_le_bytes());
+        Ok(())
+    }
+
+    /// Undoes [`Passthrough::drive`], running `stage` backwards on the body between the regions.
+    pub fn revert(data: &[u8], buf: &mut Vec<u8>, stage: impl FnOnce(&[u8], &mut Vec<u8>) -> Result<()>) -> Result<()> {
+        let (framed, footer) = data
+            .len()
+            .checked_sub(FOOTER_LEN)
+            .map(|at| data.split_at(at))
+            .ok_or_else(|| anyhow!("passthrough footer truncated"))?;
+        let length = |bytes: &[u8]| usize::try_from(u64::from_le_bytes(bytes.try_into().expect("8 bytes"))).unwrap_or(usize::MAX);
+        let (head, tail) = (length(&footer[..8]), length(&footer[8..]));
+        if head.saturating_add(tail) > framed.len() {
+            bail!(
+                "passthrough regions of {} and {} bytes don't fit in {} bytes",
+                head,
+                tail,
+                framed.len()
+            );
+        }
+        let (head, rest) = framed.split_at(head);
+        let (body, tail) = rest.split_at(rest.len() - tail);
+
+        let mut decoded = Vec::new();
+        stage(body, &mut decoded)?;
+        buf.clear();
+        buf.reserve(head.len() + decoded.len() + tail.len());
+        buf.extend_from_slice(head);
+        buf.extend_from_slice(&decoded);
+        buf.extend_from_slice(tail);
+        Ok(())
+    }
+}
+
+/// Whether the stage passes the inputs it fails on through, asked for with `on_error=skip`.
+pub fn skips_failures(params: &StageParams) -> bool {
+    params.get("on_error") == Some("skip")
+}
+
+/// Runs `stage` on `data`, passing `data` through when the stage fails on it. see the module docs for the output.
+pub fn drive_or_skip(name: &str, data: &[u8], buf: &mut Vec<u8>, stage: impl FnOnce(&[u8], &mut Vec<u8>) -> Result<()>) -> Result<()> {
+    let mut encoded = Vec::new();
+    let result = stage(data, &mut encoded);
+    buf.clear();
+    match result {
+        Ok(()) => {
+            buf.reserve(encoded.len() + 1);
+            buf.push(STAGE_RAN);
+            buf.extend_from_slice(&encoded);
+        }
+        Err(e) => {
+            if_tracing! {{
+                tracing::warn!(event = "stage_skipped", stage = name, input_len = data.len(), error = %e, "stage failed, passing its input through");
+            }}
+            events::warn(format_args!(
+                "{} failed on {} bytes, passing them through (on_error=skip): {}",
+                name,
+                data.len(),
+                e
+            ));
+            buf.reserve(data.len() + 1);
+            buf.push(STAGE_SKIPPED);
+            buf.extend_from_slice(data);
+        }
+    }
+    Ok(())
+}
+
+/// Undoes [`drive_or_skip`], running `stage` backwards unless it was skipped.
+pub fn revert_or_skip(data: &[u8], buf: &mut Vec<u8>, stage: impl FnOnce(&[u8], &mut Vec<u8>) -> Result<()>) -> Result<()> {
+    match data.split_first() {
+        Some((&STAGE_RAN, encoded)) => stage(encoded, buf),
+        Some((&STAGE_SKIPPED, passed)) => {
+            buf.clear();
+            buf.extend_from_slice(passed);
+            Ok(())
+        }
+        Some((&flag, _)) => bail!("unknown on_error=skip flag {}", flag),
+        None => bail!("on_error=skip flag missing"),
+    }
+}
@@ -1,20 +1,331 @@
 use crate::{
-    algorithms::{DynMutator, arcode::ArithmeticCoding, bsc::Bsc, bwt::Bwt, mtf::Mtf},
+    algorithms::{
+        OutputLimitExceeded, RevertContext, arcode::ArithmeticCoding, bsc::Bsc, bwt::Bwt, heuristics, lz4::Lz4, mtf::Mtf,
+        params::StageParams, rle1::Rle1,
+    },
+    interrupt,
     mutator::{Mutator, Result},
-    registered::{ALL_COMPRESSORS, RegisteredCompressor},
+    progress,
+    registered::{self, RegisteredCompressor},
+    units::Elapsed,
+    verbosity,
 };
+use anyhow::{anyhow, bail};
 use core::mem;
-use core::{fmt::Debug, str};
+use core::{
+    fmt::{self, Debug, Display},
+    str,
+    time::Duration,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, panic,
+    path::PathBuf,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Instant,
+};
 use voxell_timer::time_fn;
 
-#[derive(Debug)]
+/// Version of the JSON pipeline format written by [`CompressionPipeline::to_json`]. files of a newer version are
+/// refused, since their stages may be described in ways this build doesn't know.
+pub const PIPELINE_FORMAT_VERSION: u32 = 1;
+
+/// JSON representation of a pipeline, as stored in pipeline files and sidecars:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "stages": [
+///     { "name": "bwt", "params": { "block": "16MiB" } },
+///     { "name": "arcode" }
+///   ]
+/// }
+/// ```
+///
+/// files written before the format had a version have no `version` and list the stages as strings in the `--using`
+/// form, e.g. `"bwt(block=16MiB)"`. they are still read, and so are such strings in place of a stage object.
+#[derive(Debug, Serialize, Deserialize)]
+struct PipelineDescription {
+    #[serde(default)]
+    version: Option<u32>,
+    stages: Vec<StageDescription>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum StageDescription {
+    Stage {
+        name: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        params: BTreeMap<String, ParamValue>,
+    },
+    Inline(String),
+}
+
+/// A parameter value, written as a string but also read as a number, e.g. `"precision": 32`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum ParamValue {
+    Text(String),
+    Number(serde_json::Number),
+}
+
+impl StageDescription {
+    /// The stage in the `--using` form, e.g. `bwt(block=16MiB)`, for diagnostics.
+    fn label(&self) -> String {
+        match self {
+            StageDescription::Stage { name, params } if params.is_empty() => name.clone(),
+            StageDescription::Stage { name, params } => {
+                let params = params
+                    .iter()
+                    .map(|(key, value)| match value {
+                        ParamValue::Text(text) => format!("{}={}", key, text),
+                        ParamValue::Number(number) => format!("{}={}", key, number),
+                    })
+                    .collect::<Vec<_>>();
+                format!("{}({})", name, params.join(", "))
+            }
+            StageDescription::Inline(stage) => stage.clone(),
+        }
+    }
+
+    fn to_stage(&self) -> Result<RegisteredCompressor> {
+        match self {
+            StageDescription::Stage { name, params } => {
+                let params = params
+                    .iter()
+                    .map(|(key, value)| match value {
+                        ParamValue::Text(text) => (key.clone(), text.clone()),
+                        ParamValue::Number(number) => (key.clone(), number.to_string()),
+                    })
+                    .collect();
+                registered::resolve(name)?.with_params(params)
+            }
+            StageDescription::Inline(stage) => parse_stage(stage),
+        }
+    }
+}
+
+/// Wall-clock budgets of a pipeline, see [`CompressionPipeline::set_time_limits`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeLimits {
+    /// Longest any one stage may run.
+    pub stage: Option<Duration>,
+    /// Longest the whole run may take, and when that is up.
+    pub run: Option<(Duration, Instant)>,
+}
+
+impl TimeLimits {
+    /// Limits of a run starting now.
+    pub fn starting_now(stage: Option<Duration>, run: Option<Duration>) -> Self {
+        TimeLimits {
+            stage,
+            run: run.map(|run| (run, Instant::now() + run)),
+        }
+    }
+
+    /// Whether the run took longer than it may.
+    pub fn run_is_over(&self) -> bool {
+        self.run.is_some_and(|(_, deadline)| Instant::now() >= deadline)
+    }
+
+    /// How long the next stage may run, and whether that is up to the stage limit rather than the time left to the run.
+    fn budget(&self) -> Option<(Duration, bool)> {
+        let left = self.run.map(|(_, deadline)| deadline.saturating_duration_since(Instant::now()));
+        match (self.stage, left) {
+            (Some(stage), Some(left)) if stage <= left => Some((stage, true)),
+            (Some(stage), None) => Some((stage, true)),
+            (_, Some(left)) => Some((left, false)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Encodes or decodes with a stage, [`Mutator::drive_mutation`] or [`Mutator::revert_mutation`].
+pub type StageRun = fn(&mut RegisteredCompressor, &[u8], &mut Vec<u8>) -> Result<()>;
+
+/// Runs `stage` on `data` on a thread of its own, giving up on it once it takes longer than `budget`. `None` when it
+/// did. a stage can't be stopped from the outside, so one that overran is left running until the process exits.
+pub fn run_within(stage: &RegisteredCompressor, run: StageRun, data: &[u8], budget: Duration) -> Option<Result<Vec<u8>>> {
+    let mut stage = stage.clone();
+    let input = data.to_vec();
+    let (sender, receiver) = mpsc::channel();
+    let worker = thread::Builder::new().name(format!("stage {}", stage.name)).spawn(move || {
+        let mut output = Vec::new();
+        let result = run(&mut stage, &input, &mut output);
+        // nobody is listening anymore once the stage overran
+        let _ = sender.send(result.map(|()| output));
+    });
+    let worker = match worker {
+        Ok(worker) => worker,
+        Err(e) => return Some(Err(anyhow!("failed to start a thread for the stage: {}", e))),
+    };
+    match receiver.recv_timeout(budget) {
+        Ok(result) => Some(result),
+        Err(RecvTimeoutError::Timeout) => None,
+        // the stage panicked, which goes on as if it had run on this thread
+        Err(RecvTimeoutError::Disconnected) => panic::resume_unwind(worker.join().expect_err("the stage hung up without a result")),
+    }
+}
+
+/// Where the output of every stage is written, see [`CompressionPipeline::set_dump_dir`].
+#[derive(Debug, Clone)]
+struct StageDump {
+    dir: PathBuf,
+    /// How many times the pipeline encoded and decoded so far.
+    encodes: usize,
+    decodes: usize,
+}
+
+#[derive(Debug, Clone)]
 pub struct CompressionPipeline {
     pipeline: Vec<RegisteredCompressor>,
+    /// Most bytes decoding may produce, see [`CompressionPipeline::set_max_output_size`].
+    max_output_size: Option<usize>,
+    time_limits: TimeLimits,
+    dump: Option<StageDump>,
 }
 
 impl CompressionPipeline {
     pub const fn new() -> Self {
-        Self { pipeline: vec![] }
+        Self {
+            pipeline: vec![],
+            max_output_size: None,
+            time_limits: TimeLimits { stage: None, run: None },
+            dump: None,
+        }
+    }
+
+    /// Writes the output of every stage to `dir` as it finishes, to find the stage that mangles the data. encoding
+    /// writes `00-bwt.bin`, `01-mtf.bin` and so on, decoding `01-mtf.rev.bin`, `00-bwt.rev.bin` and so on, so
+    /// `01-mtf.rev.bin` should match `00-bwt.bin`. every run after the first, as for the blocks of block mode or the
+    /// frames of an archive, adds its number, e.g. `00-bwt.3.bin`. `dir` has to exist.
+    pub fn set_dump_dir(&mut self, dir: Option<PathBuf>) {
+        self.dump = dir.map(|dir| StageDump {
+            dir,
+            encodes: 0,
+            decodes: 0,
+        });
+    }
+
+    /// The number of this encoding or decoding run, to name its dumps by.
+    fn start_dump_run(&mut self, decoding: bool) -> usize {
+        let Some(dump) = &mut self.dump else {
+            return 0;
+        };
+        let runs = if decoding { &mut dump.decodes } else { &mut dump.encodes };
+        *runs += 1;
+        *runs - 1
+    }
+
+    /// Writes the output of stage `index` in run `run`, see [`CompressionPipeline::set_dump_dir`].
+    fn dump_stage(&self, decoding: bool, run: usize, index: usize, output: &[u8]) -> Result<()> {
+        let Some(dump) = &self.dump else {
+            return Ok(());
+        };
+        let mut name = format!("{:02}-{}", index, self.pipeline[index].name);
+        if run != 0 {
+            name += &format!(".{}", run);
+        }
+        if decoding {
+            name += ".rev";
+        }
+        let path = dump.dir.join(name + ".bin");
+        fs::write(&path, output).map_err(|e| anyhow!("failed to dump the output of stage {} to {}: {}", index, path.display(), e))
+    }
+
+    /// Stops a stage that runs longer than `limits` allow with an error naming it. stages are run on a thread of their
+    /// own to be timed, see [`run_within`], so without limits they run as they are.
+    pub fn set_time_limits(&mut self, limits: TimeLimits) {
+        self.time_limits = limits;
+    }
+
+    /// Runs stage `index` within the limits set with [`CompressionPipeline::set_time_limits`].
+    fn run_stage(&mut self, index: usize, run: StageRun, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+        self.pipeline[index].revert_context = RevertContext {
+            max_output_size: self.max_output_size,
+        };
+        let Some((budget, stage_limit)) = self.time_limits.budget() else {
+            return run(&mut self.pipeline[index], data, buf);
+        };
+        let overran = || match (stage_limit, self.time_limits.stage, self.time_limits.run) {
+            (true, Some(limit), _) => anyhow!(
+                "stage {} ({}) ran longer than the --max-stage-time of {}",
+                index,
+                self.pipeline[index],
+                Elapsed(limit)
+            ),
+            (_, _, Some((limit, _))) => anyhow!(
+                "stage {} ({}) ran past the --max-time of {} for the whole run",
+                index,
+                self.pipeline[index],
+                Elapsed(limit)
+            ),
+            _ => unreachable!("a budget comes from a limit"),
+        };
+        if budget.is_zero() {
+            return Err(overran());
+        }
+        match run_within(&self.pipeline[index], run, data, budget) {
+            Some(result) => {
+                *buf = result?;
+                Ok(())
+            }
+            None => Err(overran()),
+        }
+    }
+
+    /// Caps how many bytes decoding may produce. the decoders of stages that can expand their input a lot, `rle1`,
+    /// `arcode` and `lz4`, get the limit in their [`RevertContext`] and stop as soon as their output crosses it, so a
+    /// corrupt or malicious input fails at the stage that blows up without it building all of its output first. the
+    /// output of every other stage, plugin stages included, is checked as soon as the stage finishes.
+    pub fn set_max_output_size(&mut self, limit: Option<usize>) {
+        self.max_output_size = limit;
+    }
+
+    pub fn max_output_size(&self) -> Option<usize> {
+        self.max_output_size
+    }
+
+    /// Fails if `len` bytes of output are over the limit set with [`CompressionPipeline::set_max_output_size`].
+    /// block and archive decoding use this to bound the output of all blocks together.
+    pub fn check_output_size(&self, len: usize) -> Result<()> {
+        match self.max_output_size {
+            Some(limit) if len > limit => Err(anyhow!(
+                "output is {} bytes, over the --max-output-size limit of {} bytes",
+                len,
+                limit
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Names the stage in an [`OutputLimitExceeded`] its decoder stopped with, other errors are left as they are.
+    fn name_limit_error(&self, stage: usize, e: anyhow::Error) -> anyhow::Error {
+        match e.downcast_ref::<OutputLimitExceeded>() {
+            Some(exceeded) => anyhow!(
+                "stage {} ({}) was stopped after decoding past the --max-output-size limit of {} bytes",
+                stage,
+                self.pipeline[stage],
+                exceeded.limit
+            ),
+            None => e,
+        }
+    }
+
+    fn check_stage_output(&self, stage: usize, len: usize) -> Result<()> {
+        match self.max_output_size {
+            Some(limit) if len > limit => Err(anyhow!(
+                "stage {} ({}) produced {} bytes, over the --max-output-size limit of {} bytes",
+                stage,
+                self.pipeline[stage],
+                len,
+                limit
+            )),
+            _ => Ok(()),
+        }
     }
 
     pub fn try_from_bytes(bytes: &[u8]) -> Option<Self> {
@@ -23,19 +334,21 @@ impl CompressionPipeline {
         let mut pipeline = CompressionPipeline::new();
         let mut start = 0;
         let mut index = 0;
+        // commas inside a stage's parentheses separate its parameters, not stages
+        let mut depth = 0usize;
         while index < bytes.len() {
             let c = bytes[index];
             match c {
-                END_OF_ALGORITHM_NAME => {
-                    let name = str::from_utf8(&bytes[start..index]).ok()?;
-                    let algo = get_specific_compressor_from_name(name)?;
-                    pipeline.push_algorithm(algo.clone());
+                b'(' => depth += 1,
+                b')' => depth = depth.checked_sub(1)?,
+                END_OF_ALGORITHM_NAME if depth == 0 => {
+                    let stage = str::from_utf8(&bytes[start..index]).ok()?;
+                    pipeline.push_algorithm(parse_stage(stage).ok()?);
                     start = index + 1;
                 }
                 END_OF_PIPELINE => {
-                    let name = str::from_utf8(&bytes[start..index]).ok()?;
-                    let algo = get_specific_compressor_from_name(name)?;
-                    pipeline.push_algorithm(algo.clone());
+                    let stage = str::from_utf8(&bytes[start..index]).ok()?;
+                    pipeline.push_algorithm(parse_stage(stage).ok()?);
                     return Some(pipeline);
                 }
                 _ => {}
@@ -46,10 +359,114 @@ impl CompressionPipeline {
         None
     }
 
+    /// Serializes the pipeline into the format read by [`CompressionPipeline::try_from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let stages = self.pipeline.iter().map(|algo| algo.to_string()).collect::<Vec<_>>();
+        let mut bytes = stages.join(",").into_bytes();
+        bytes.push(b'\0');
+        bytes
+    }
+
+    /// Serializes the stages of this pipeline as a JSON document, see [`PipelineDescription`].
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.description()).expect("pipeline description is always serializable")
+    }
+
+    /// Like [`CompressionPipeline::to_json`], with the fields of `extra` after the stages. readers of pipeline files
+    /// ignore fields they don't know, so this is how sidecars record more than the pipeline.
+    pub fn to_json_with(&self, extra: &impl Serialize) -> String {
+        #[derive(Serialize)]
+        struct Extended<'a, T> {
+            #[serde(flatten)]
+            description: PipelineDescription,
+            #[serde(flatten)]
+            extra: &'a T,
+        }
+        let document = Extended {
+            description: self.description(),
+            extra,
+        };
+        serde_json::to_string_pretty(&document).expect("pipeline description is always serializable")
+    }
+
+    fn description(&self) -> PipelineDescription {
+        PipelineDescription {
+            version: Some(PIPELINE_FORMAT_VERSION),
+            stages: self
+                .pipeline
+                .iter()
+                .map(|algo| StageDescription::Stage {
+                    name: algo.name.to_string(),
+                    params: algo
+                        .params
+                        .entries()
+                        .map(|(key, value)| (key.to_string(), ParamValue::Text(value.to_string())))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Parses a JSON document written by [`CompressionPipeline::to_json`], or by a build from before the format had
+    /// a version.
+    pub fn try_from_json(json: &str) -> Result<Self> {
+        let mut pipeline = CompressionPipeline::new();
+        for (index, (_, stage)) in Self::try_stages_from_json(json)?.into_iter().enumerate() {
+            pipeline.push_algorithm(stage.map_err(|e| e.context(format!("invalid stage {}", index)))?);
+        }
+        Ok(pipeline)
+    }
+
+    /// Parses the stages of a JSON document like [`CompressionPipeline::try_from_json`], but every one on its own, so
+    /// a stage that doesn't parse doesn't hide the problems of those after it. each comes with its `--using` form.
+    pub fn try_stages_from_json(json: &str) -> Result<Vec<(String, Result<RegisteredCompressor>)>> {
+        let description: PipelineDescription = serde_json::from_str(json)?;
+        if let Some(version) = description.version.filter(|&version| version > PIPELINE_FORMAT_VERSION) {
+            bail!(
+                "the pipeline is in format version {}, which needs a newer stackpack (this one reads up to version {})",
+                version,
+                PIPELINE_FORMAT_VERSION
+            );
+        }
+        Ok(description.stages.iter().map(|stage| (stage.label(), stage.to_stage())).collect())
+    }
+
+    /// A crc32 of the pipeline's `"a -> b -> c"` form, the same for equal pipelines across runs and builds.
+    pub fn fingerprint(&self) -> u32 {
+        crc32fast::hash(self.to_string().as_bytes())
+    }
+
     pub fn push_algorithm(&mut self, algorithm: RegisteredCompressor) {
         self.pipeline.push(algorithm);
     }
 
+    /// The stages of this pipeline, in encoding order.
+    pub fn stages(&self) -> &[RegisteredCompressor] {
+        &self.pipeline
+    }
+
+    /// Estimated peak memory of encoding `input_len` bytes: the most any one stage needs (see
+    /// [`heuristics::stage_memory`]) plus the input of the pipeline, which stays alive while the stages after the first run.
+    pub fn estimate_memory(&self, input_len: usize) -> u64 {
+        self.pipeline
+            .iter()
+            .enumerate()
+            .map(|(index, stage)| {
+                let held = if index == 0 { 0 } else { input_len as u64 };
+                held.saturating_add(heuristics::stage_memory(stage.info.memory, input_len))
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Makes every stage that doesn't say otherwise pass the blocks it fails on through, see
+    /// [`crate::algorithms::passthrough`].
+    pub fn skip_failing_stages(&mut self) {
+        for stage in &mut self.pipeline {
+            stage.params.set_default("on_error", "skip");
+        }
+    }
+
     /// Chain this method to add multiple algorithms in a shorter way.
     pub fn with_algorithm(mut self, algorithm: RegisteredCompressor) -> Self {
         self.pipeline.push(algorithm);
@@ -57,20 +474,54 @@ impl CompressionPipeline {
     }
 }
 
+impl Default for CompressionPipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Formats the pipeline in the canonical `"a -> b -> c"` form accepted by `--using`.
+impl Display for CompressionPipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, algo) in self.pipeline.iter().enumerate() {
+            if index != 0 {
+                f.write_str(" -> ")?;
+            }
+            write!(f, "{}", algo)?;
+        }
+        Ok(())
+    }
+}
+
 impl Mutator for CompressionPipeline {
     fn drive_mutation(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
         if_tracing! {
             let pipeline_span = tracing::span!(tracing::Level::INFO, "compression_pipeline", stages = self.pipeline.len());
             let _enter = pipeline_span.enter();
         }
+        let run = self.start_dump_run(false);
         match self.pipeline.len() {
             0 => Ok(()),
-            1 => self.pipeline[0].drive_mutation(data, buf),
+            1 => {
+                interrupt::checkpoint();
+                progress::stage(0, 1, self.pipeline[0].name);
+                let (res, d) = time_fn(|| self.run_stage(0, Mutator::drive_mutation, data, buf));
+                res?;
+                self.dump_stage(false, run, 0, buf)?;
+                progress::advance(data.len());
+                verbosity::stage("encode", 0, 1, self.pipeline[0].name, data.len(), buf.len(), d);
+                Ok(())
+            }
             n => {
                 let mut intermediate: Vec<u8> = vec![];
                 // first algorithm compresses from data to buf
-                let (res, d) = time_fn(|| self.pipeline[0].drive_mutation(data, buf));
+                interrupt::checkpoint();
+                progress::stage(0, n, self.pipeline[0].name);
+                let (res, d) = time_fn(|| self.run_stage(0, Mutator::drive_mutation, data, buf));
                 res?;
+                self.dump_stage(false, run, 0, buf)?;
+                progress::advance(data.len());
+                verbosity::stage("encode", 0, n, self.pipeline[0].name, data.len(), buf.len(), d);
                 if_tracing! {{
                     tracing::info!(stage = 0, elapsed = ?d, out_len = buf.len(), "stage complete");
                 }}
@@ -79,9 +530,14 @@ impl Mutator for CompressionPipeline {
                     let mut ref1 = &mut *buf;
                     let mut ref2 = &mut intermediate;
 
-                    for algo in self.pipeline.iter_mut().skip(1) {
-                        let (res, d) = time_fn(|| algo.drive_mutation(ref1, ref2));
+                    for stage in 1..n {
+                        interrupt::checkpoint();
+                        progress::stage(stage, n, self.pipeline[stage].name);
+                        let (res, d) = time_fn(|| self.run_stage(stage, Mutator::drive_mutation, ref1, ref2));
                         res?;
+                        self.dump_stage(false, run, stage, ref2)?;
+                        progress::advance(ref1.len());
+                        verbosity::stage("encode", stage, n, self.pipeline[stage].name, ref1.len(), ref2.len(), d);
                         if_tracing! {{
                             tracing::info!(elapsed = ?d, out_len = ref2.len(), "stage complete");
                         }}
@@ -106,16 +562,33 @@ impl Mutator for CompressionPipeline {
             let pipeline_span = tracing::span!(tracing::Level::INFO, "decompression_pipeline", stages = self.pipeline.len());
             let _enter = pipeline_span.enter();
         }
+        let run = self.start_dump_run(true);
 
         match self.pipeline.len() {
             0 => Ok(()),
-            1 => self.pipeline[0].revert_mutation(data, buf),
+            1 => {
+                interrupt::checkpoint();
+                progress::stage(0, 1, self.pipeline[0].name);
+                let (res, dur) = time_fn(|| self.run_stage(0, Mutator::revert_mutation, data, buf));
+                res.map_err(|e| self.name_limit_error(0, e))?;
+                self.dump_stage(true, run, 0, buf)?;
+                self.check_stage_output(0, buf.len())?;
+                progress::advance(buf.len());
+                verbosity::stage("decode", 0, 1, self.pipeline[0].name, data.len(), buf.len(), dur);
+                Ok(())
+            }
             n => {
                 let mut intermediate: Vec<u8> = vec![];
 
                 // first algorithm decompresses from data to buf
-                let (res, dur) = time_fn(|| self.pipeline[n - 1].revert_mutation(data, buf));
-                res?;
+                interrupt::checkpoint();
+                progress::stage(n - 1, n, self.pipeline[n - 1].name);
+                let (res, dur) = time_fn(|| self.run_stage(n - 1, Mutator::revert_mutation, data, buf));
+                res.map_err(|e| self.name_limit_error(n - 1, e))?;
+                self.dump_stage(true, run, n - 1, buf)?;
+                self.check_stage_output(n - 1, buf.len())?;
+                progress::advance(buf.len());
+                verbosity::stage("decode", n - 1, n, self.pipeline[n - 1].name, data.len(), buf.len(), dur);
                 if_tracing! {{
                     tracing::info!(stage = n - 1, elapsed_ms = ?dur, out_len = buf.len(), "stage complete");
                 }}
@@ -124,9 +597,15 @@ impl Mutator for CompressionPipeline {
                     let mut ref1 = &mut *buf;
                     let mut ref2 = &mut intermediate;
 
-                    for algo in self.pipeline.iter_mut().rev().skip(1) {
-                        let (res, dur) = time_fn(|| algo.revert_mutation(ref1, ref2));
-                        res?;
+                    for stage in (0..n - 1).rev() {
+                        interrupt::checkpoint();
+                        progress::stage(stage, n, self.pipeline[stage].name);
+                        let (res, dur) = time_fn(|| self.run_stage(stage, Mutator::revert_mutation, ref1, ref2));
+                        res.map_err(|e| self.name_limit_error(stage, e))?;
+                        self.dump_stage(true, run, stage, ref2)?;
+                        self.check_stage_output(stage, ref2.len())?;
+                        progress::advance(ref2.len());
+                        verbosity::stage("decode", stage, n, self.pipeline[stage].name, ref1.len(), ref2.len(), dur);
                         if_tracing! {{
                             tracing::info!(elapsed_ms = ?dur, out_len = ref2.len(), "stage complete");
                         }}
@@ -148,7 +627,22 @@ impl Mutator for CompressionPipeline {
 }
 
 pub fn get_specific_compressor_from_name(s: &str) -> Option<RegisteredCompressor> {
-    ALL_COMPRESSORS.lock().iter().find(|&comp| comp.name == s).cloned()
+    registered::lookup(s)
+}
+
+/// Parses a single stage, either a bare algorithm name or `name(key=value, ...)`.
+pub fn parse_stage(stage: &str) -> Result<RegisteredCompressor> {
+    let stage = stage.trim();
+    let (name, params) = match stage.split_once('(') {
+        Some((name, rest)) => {
+            let inner = rest
+                .strip_suffix(')')
+                .ok_or_else(|| anyhow!("stage {:?} is missing its closing parenthesis", stage))?;
+            (name.trim(), StageParams::parse(inner)?)
+        }
+        None => (stage, StageParams::new()),
+    };
+    registered::resolve(name)?.with_params(params)
 }
 
 pub fn default_pipeline() -> CompressionPipeline {
@@ -165,10 +659,81 @@ pub fn bsc() -> CompressionPipeline {
     CompressionPipeline::new().with_algorithm(Bsc)
 }
 
+/// Fastest preset, for when throughput matters more than ratio.
+pub fn lz4() -> CompressionPipeline {
+    CompressionPipeline::new().with_algorithm(Lz4)
+}
+
+/// The stages of bzip2: its initial run length encoding, a bwt over blocks of 900000 bytes as with `bzip2 -9`, mtf
+/// and an entropy coder. only the stages are bzip2's, the output is a stackpack payload and not a `.bz2` file.
+pub fn bzip2() -> CompressionPipeline {
+    CompressionPipeline::new()
+        .with_algorithm(Rle1)
+        .with_algorithm(parse_stage("bwt(block=900000)").expect("the bzip2 preset only uses built-in stages"))
+        .with_algorithm(Mtf)
+        .with_algorithm(ArithmeticCoding)
+}
+
+/// Names accepted by [`get_preset`].
+pub const PRESET_NAMES: &[&str] = &["default", "bsc", "lz4", "bzip2"];
+
 pub fn get_preset(s: &str) -> Option<fn() -> CompressionPipeline> {
     Some(match s {
         "default" => default_pipeline,
         "bsc" => bsc,
+        "lz4" => lz4,
+        "bzip2" => bzip2,
         _ => None?,
     })
 }
+
+/// Pipelines behind `--level 1..9`, from fastest to smallest output. the low levels stay with lz4 and cheap
+/// entropy coding, the middle ones use the default bwt pipeline, and the high ones bsc with ever larger blocks, up to
+/// the largest the available memory allows at 9.
+pub const LEVELS: [&str; 9] = [
+    "lz4",
+    "lz4 -> arcode",
+    "bpe(merges=32) -> lz4 -> arcode",
+    "bwt -> mtf -> arcode",
+    "bpe -> bwt -> mtf -> arcode",
+    "bsc(block=1m)",
+    "bsc(block=8m)",
+    "bsc(block=64m)",
+    "bsc",
+];
+
+/// The pipeline of compression level `level`, `None` outside of `1..=9`.
+pub fn get_level(level: u8) -> Option<CompressionPipeline> {
+    let description = LEVELS.get(usize::from(level).checked_sub(1)?)?;
+    let mut pipeline = CompressionPipeline::new();
+    for stage in description.split("->") {
+        pipeline.push_algorithm(parse_stage(stage.trim()).expect("level pipelines only use built-in stages"));
+    }
+    Some(pipeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expanding_decoders_stop_at_the_output_limit() {
+        let data = vec![0u8; 1 << 20];
+        for stage in ["rle1", "arcode", "lz4", "lz4(streams=split)"] {
+            let mut pipeline = CompressionPipeline::new().with_algorithm(parse_stage(stage).unwrap());
+            let mut encoded = Vec::new();
+            pipeline.drive_mutation(&data, &mut encoded).unwrap();
+
+            pipeline.set_max_output_size(Some(1000));
+            let mut decoded = Vec::new();
+            let error = pipeline.revert_mutation(&encoded, &mut decoded).unwrap_err().to_string();
+            assert!(error.contains("was stopped after decoding past"), "{}: {}", stage, error);
+            // the decoder gave up right after the limit instead of producing all of the data
+            assert!(decoded.len() < data.len() / 2, "{} decoded {} bytes", stage, decoded.len());
+
+            pipeline.set_max_output_size(Some(data.len()));
+            pipeline.revert_mutation(&encoded, &mut decoded).unwrap();
+            assert_eq!(decoded, data, "{}", stage);
+        }
+    }
+}
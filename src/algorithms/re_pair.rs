@@ -1,4 +1,5 @@
 #![allow(unused)] //todo
+#![allow(clippy::ptr_arg)]
 use core::fmt;
 use core::fmt::{Debug, Display};
 use std::{
@@ -8,8 +9,8 @@ use std::{
 
 use anyhow::Result;
 
-use crate::algorithms::DynMutator;
-use crate::registered::RegisteredCompressor;
+use crate::algorithms::{DynMutator, RevertContext, heuristics::MemoryCost};
+use crate::registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo};
 
 pub const RePair: RegisteredCompressor = RegisteredCompressor::new_dyn(
     DynMutator {
@@ -18,7 +19,20 @@ pub const RePair: RegisteredCompressor = RegisteredCompressor::new_dyn(
     },
     "re_pair",
     Some(DESCRIPTION),
-);
+)
+.with_info(StageInfo {
+    category: StageCategory::Dictionary,
+    aliases: &["repair"],
+    params: &[],
+    // a 16 byte symbol and a pair count per input byte, and the hash map around them
+    memory: MemoryCost::PerByte(64),
+    explanation: StageExplanation {
+        entropy: "would build a grammar of the maximal repeats in the data, which takes out repeats of any length. \
+            not implemented yet",
+        size: "would shrink data with long repeats, storing the rules of the grammar",
+        decoding: "would expand every rule of the grammar back into the bytes it stands for. not implemented yet",
+    },
+});
 pub const DESCRIPTION: &str = "MR-RePair byte-pair encoding algorithm.
 Based on the paper MR-RePair: Grammar Compression based on Maximal Repeats
 https://arxiv.org/abs/1811.04596";
@@ -67,6 +81,6 @@ pub fn repair_encode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
     todo!()
 }
 
-pub fn repair_decode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+pub fn repair_decode(data: &[u8], buf: &mut Vec<u8>, _context: &RevertContext) -> Result<()> {
     todo!("{:?}", data.to_vec());
 }
@@ -0,0 +1,102 @@
+//! the initial run length encoding of bzip2, run before its bwt: every run of 4 to 255 equal bytes becomes the first
+//! 4 of them followed by a byte counting the other 0 to 251, and longer runs are split into runs of at most 255.
+//! it was added to bzip2 to keep long runs from sending its block sort into its worst case, and does the same for the
+//! suffix array construction of `bwt`, which also has less to sort afterwards. shorter runs stay as they are, so data
+//! without long runs grows by at most one byte in every five, on runs of exactly 4.
+use anyhow::bail;
+
+use crate::{
+    algorithms::{DynMutator, RevertContext, heuristics::MemoryCost},
+    mutator::Result,
+    registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo},
+};
+
+pub const Rle1: RegisteredCompressor = RegisteredCompressor::new_dyn(
+    DynMutator {
+        drive_mutation: rle1_encode,
+        revert_mutation: rle1_decode,
+    },
+    "rle1",
+    Some(DESCRIPTION),
+)
+.with_info(StageInfo {
+    category: StageCategory::Transform,
+    aliases: &["initial-rle"],
+    params: &[],
+    memory: MemoryCost::PerByte(3),
+    explanation: StageExplanation {
+        entropy: "none, every run of 4 to 255 equal bytes becomes the first 4 of them and a count of the others",
+        size: "shrinks data with long runs, grows by at most one byte in every five when runs are exactly 4 long",
+        decoding: "expands every count after 4 equal bytes into that many more of them",
+    },
+});
+const DESCRIPTION: &str = "bzip2's initial run length encoding, capping runs of equal bytes before a bwt so long runs \
+can't slow its sort down.";
+
+/// Equal bytes in a row after which a count of further repeats follows.
+const RUN_START: usize = 4;
+/// Longest run a single count covers, the [`RUN_START`] bytes and a count of at most 251.
+const MAX_RUN: usize = 255;
+
+pub fn rle1_encode(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+    if_tracing! {{
+        tracing::debug!(target = "rle1", input_len = data.len(), "rle1 encode start");
+    }}
+    buf.clear();
+    buf.reserve(data.len() + data.len() / 4);
+    let mut index = 0;
+    while index < data.len() {
+        let byte = data[index];
+        let run = data[index..].iter().take(MAX_RUN).take_while(|&&next| next == byte).count();
+        if run >= RUN_START {
+            buf.extend_from_slice(&[byte; RUN_START]);
+            buf.push((run - RUN_START) as u8);
+        } else {
+            buf.extend_from_slice(&data[index..index + run]);
+        }
+        index += run;
+    }
+    if_tracing! {{
+        tracing::info!(target = "rle1", input_len = data.len(), output_len = buf.len(), "rle1 encode complete");
+    }}
+    Ok(())
+}
+
+pub fn rle1_decode(data: &[u8], buf: &mut Vec<u8>, context: &RevertContext) -> Result<()> {
+    if_tracing! {{
+        tracing::debug!(target = "rle1", input_len = data.len(), "rle1 decode start");
+    }}
+    buf.clear();
+    buf.reserve(data.len());
+    let mut bytes = data.iter().copied();
+    let mut last = None;
+    let mut run = 0;
+    while let Some(byte) = bytes.next() {
+        if last == Some(byte) {
+            run += 1;
+        } else {
+            last = Some(byte);
+            run = 1;
+        }
+        buf.push(byte);
+        if run == RUN_START {
+            let Some(count) = bytes.next() else {
+                bail!("rle1 input ends after a run of {} bytes, without its count", RUN_START);
+            };
+            if count as usize > MAX_RUN - RUN_START {
+                bail!("rle1 run count is {}, at most {} are written", count, MAX_RUN - RUN_START);
+            }
+            buf.resize(buf.len() + count as usize, byte);
+            // the next byte starts a new run, even if it is the same byte again
+            last = None;
+            run = 0;
+            // every count writes up to 251 bytes, so a corrupt input is stopped right after the one that crosses
+            context.check_output(buf.len())?;
+        }
+    }
+    context.check_output(buf.len())?;
+    if_tracing! {{
+        tracing::info!(target = "rle1", output_len = buf.len(), "rle1 decode complete");
+    }}
+    Ok(())
+}
@@ -0,0 +1,183 @@
+//! suffix array construction by induced sorting (SA-IS, Nong, Zhang and Chan 2009) and the bwt built from it, in pure
+//! rust. it is the `sais` backend of [`crate::algorithms::bwt`], slower than libsais but needing no c library, and
+//! writes the same transform libsais does: the bwt of the block followed by a virtual sentinel smaller than every
+//! byte, with the sentinel left out and its row recorded as the primary index.
+//!
+//! suffix array entries are `u32`, so blocks have to be shorter than `u32::MAX` bytes.
+use anyhow::{Result, bail};
+
+/// Marks an empty slot of a suffix array under construction.
+const EMPTY: u32 = u32::MAX;
+
+/// The suffixes of `text` in sorted order, a suffix that is a prefix of another sorting first.
+pub fn suffix_array(text: &[u8]) -> Vec<u32> {
+    assert!(text.len() < EMPTY as usize, "sais suffix arrays index fewer than u32::MAX bytes");
+    sa_is(text, u8::MAX as usize)
+}
+
+/// Appends the bwt of `block` to `out` and returns its primary index, as `libsais_bwt` does.
+pub fn bwt(block: &[u8], out: &mut Vec<u8>) -> u32 {
+    let Some(&last) = block.last() else {
+        return 0;
+    };
+    let sa = suffix_array(block);
+    // the sentinel sorts first, and the byte before it is the last one of the block
+    out.reserve(block.len());
+    out.push(last);
+    let mut primary_index = 0;
+    for (row, &suffix) in sa.iter().enumerate() {
+        match suffix {
+            0 => primary_index = row as u32 + 1,
+            suffix => out.push(block[suffix as usize - 1]),
+        }
+    }
+    primary_index
+}
+
+/// Appends the block whose bwt is `bwt` to `out`, the inverse of [`bwt`].
+pub fn unbwt(bwt: &[u8], primary_index: usize, out: &mut Vec<u8>) -> Result<()> {
+    let n = bwt.len();
+    if n == 0 {
+        return Ok(());
+    }
+    if primary_index == 0 || primary_index > n {
+        bail!("Invalid primary index: {} (bwt length: {})", primary_index, n);
+    }
+    // rows of the full bwt, which has the sentinel at the primary index, before every byte in the first column
+    let mut starts = [0u32; 256];
+    for &byte in bwt {
+        starts[byte as usize] += 1;
+    }
+    let mut sum = 1;
+    for start in &mut starts {
+        (*start, sum) = (sum, sum + *start);
+    }
+    let next_row = bwt
+        .iter()
+        .map(|&byte| {
+            let row = starts[byte as usize];
+            starts[byte as usize] += 1;
+            row
+        })
+        .collect::<Vec<_>>();
+
+    let start = out.len();
+    out.resize(start + n, 0);
+    let mut row = 0;
+    for index in (0..n).rev() {
+        let column = if row < primary_index { row } else { row - 1 };
+        out[start + index] = bwt[column];
+        row = next_row[column] as usize;
+    }
+    Ok(())
+}
+
+/// The suffix array of `s`, whose symbols are at most `upper`.
+fn sa_is<T: Copy + Into<u32>>(s: &[T], upper: usize) -> Vec<u32> {
+    let n = s.len();
+    let sym = |index: usize| Into::<u32>::into(s[index]) as usize;
+    match n {
+        0 => return Vec::new(),
+        1 => return vec![0],
+        2 => return if sym(0) < sym(1) { vec![0, 1] } else { vec![1, 0] },
+        _ => {}
+    }
+
+    // whether every suffix is smaller than the one after it (s-type) or larger (l-type)
+    let mut smaller = vec![false; n];
+    for index in (0..n - 1).rev() {
+        smaller[index] = match sym(index).cmp(&sym(index + 1)) {
+            core::cmp::Ordering::Equal => smaller[index + 1],
+            order => order.is_lt(),
+        };
+    }
+    // where the l-type and s-type suffixes of every symbol start in the suffix array
+    let mut l_starts = vec![0u32; upper + 1];
+    let mut s_starts = vec![0u32; upper + 1];
+    for index in 0..n {
+        match smaller[index] {
+            false => s_starts[sym(index)] += 1,
+            true => l_starts[sym(index) + 1] += 1,
+        }
+    }
+    for symbol in 0..=upper {
+        s_starts[symbol] += l_starts[symbol];
+        if symbol < upper {
+            l_starts[symbol + 1] += s_starts[symbol];
+        }
+    }
+
+    let is_lms = |index: usize| index > 0 && !smaller[index - 1] && smaller[index];
+    let induce = |lms: &[u32], sa: &mut [u32]| {
+        sa.fill(EMPTY);
+        let mut heads = s_starts.clone();
+        for &suffix in lms {
+            let symbol = sym(suffix as usize);
+            sa[heads[symbol] as usize] = suffix;
+            heads[symbol] += 1;
+        }
+        heads.copy_from_slice(&l_starts);
+        sa[heads[sym(n - 1)] as usize] = n as u32 - 1;
+        heads[sym(n - 1)] += 1;
+        for row in 0..n {
+            let suffix = sa[row];
+            if suffix != EMPTY && suffix >= 1 && !smaller[suffix as usize - 1] {
+                let symbol = sym(suffix as usize - 1);
+                sa[heads[symbol] as usize] = suffix - 1;
+                heads[symbol] += 1;
+            }
+        }
+        heads.copy_from_slice(&l_starts);
+        for row in (0..n).rev() {
+            let suffix = sa[row];
+            if suffix != EMPTY && suffix >= 1 && smaller[suffix as usize - 1] {
+                let symbol = sym(suffix as usize - 1) + 1;
+                heads[symbol] -= 1;
+                sa[heads[symbol] as usize] = suffix - 1;
+            }
+        }
+    };
+
+    let mut lms_rank = vec![EMPTY; n];
+    let lms = (1..n).filter(|&index| is_lms(index)).map(|index| index as u32).collect::<Vec<_>>();
+    for (rank, &suffix) in lms.iter().enumerate() {
+        lms_rank[suffix as usize] = rank as u32;
+    }
+    let mut sa = vec![EMPTY; n];
+    induce(&lms, &mut sa);
+    if lms.is_empty() {
+        return sa;
+    }
+
+    // name the lms substrings in sorted order, equal substrings getting the same name, and sort their suffixes by
+    // sorting the string of names
+    let mut sorted_lms = sa.iter().copied().filter(|&suffix| lms_rank[suffix as usize] != EMPTY).collect::<Vec<_>>();
+    let substring_end = |suffix: u32| match lms.get(lms_rank[suffix as usize] as usize + 1) {
+        Some(&next) => next as usize,
+        None => n,
+    };
+    let mut names = vec![0u32; lms.len()];
+    let mut name = 0;
+    for pair in sorted_lms.windows(2) {
+        let (mut left, mut right) = (pair[0] as usize, pair[1] as usize);
+        let (left_end, right_end) = (substring_end(pair[0]), substring_end(pair[1]));
+        let mut same = left_end - left == right_end - right;
+        if same {
+            while left < left_end && sym(left) == sym(right) {
+                left += 1;
+                right += 1;
+            }
+            same = left != n && right != n && sym(left) == sym(right);
+        }
+        if !same {
+            name += 1;
+        }
+        names[lms_rank[pair[1] as usize] as usize] = name;
+    }
+    let lms_sa = sa_is(&names, name as usize);
+    for (slot, &rank) in sorted_lms.iter_mut().zip(&lms_sa) {
+        *slot = lms[rank as usize];
+    }
+    induce(&sorted_lms, &mut sa);
+    sa
+}
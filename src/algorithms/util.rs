@@ -0,0 +1,112 @@
+//! trivial stages for measuring and testing pipelines rather than compressing anything. `identity` passes its input
+//! through, so a pipeline of it measures the overhead of the pipeline itself. `reverse` and `xor` are cheap,
+//! obviously correct transforms that change every byte of the data, for checking that brute-force search and
+//! plugins put stages back together in the right order.
+use anyhow::{Result, anyhow};
+
+use crate::{
+    algorithms::{
+        DynMutator, ParamMutator,
+        heuristics::MemoryCost,
+        params::{ParamDescriptor, ParamKind, StageParams},
+    },
+    registered::{RegisteredCompressor, StageCategory, StageExplanation, StageInfo},
+};
+
+pub const Identity: RegisteredCompressor = RegisteredCompressor::new_dyn(
+    DynMutator {
+        drive_mutation: copy,
+        revert_mutation: |data, buf, _| copy(data, buf),
+    },
+    "identity",
+    Some("Passes the data through unchanged, for measuring the overhead of a pipeline."),
+)
+.with_info(StageInfo {
+    category: StageCategory::Util,
+    aliases: &["null"],
+    params: &[],
+    memory: MemoryCost::PerByte(2),
+    explanation: StageExplanation {
+        entropy: "none, the data passes through as it is",
+        size: "keeps the size",
+        decoding: "passes the data through again",
+    },
+});
+
+pub const Reverse: RegisteredCompressor = RegisteredCompressor::new_dyn(
+    DynMutator {
+        drive_mutation: reverse,
+        revert_mutation: |data, buf, _| reverse(data, buf),
+    },
+    "reverse",
+    Some("Reverses the order of the bytes, for testing stage ordering."),
+)
+.with_info(StageInfo {
+    category: StageCategory::Util,
+    aliases: &[],
+    params: &[],
+    memory: MemoryCost::PerByte(2),
+    explanation: StageExplanation {
+        entropy: "none, only the order of the bytes changes",
+        size: "keeps the size",
+        decoding: "reverses the bytes again",
+    },
+});
+
+pub const Xor: RegisteredCompressor = RegisteredCompressor::new_param(
+    ParamMutator {
+        drive_mutation: xor,
+        revert_mutation: |data, buf, params, _| xor(data, buf, params),
+        validate: validate_xor,
+    },
+    "xor",
+    Some("XORs every byte with key=<0..255> (default 255), for testing stage ordering and parameters."),
+)
+.with_info(StageInfo {
+    category: StageCategory::Util,
+    aliases: &[],
+    params: XOR_PARAMS,
+    memory: MemoryCost::PerByte(2),
+    explanation: StageExplanation {
+        entropy: "none, every byte is XORed with the key",
+        size: "keeps the size",
+        decoding: "XORs every byte with the same key again",
+    },
+});
+const XOR_PARAMS: &[ParamDescriptor] = &[ParamDescriptor {
+    name: "key",
+    kind: ParamKind::Integer { min: 0, max: 255 },
+    default: Some("255"),
+    description: "The byte every input byte is XORed with.",
+}];
+
+fn copy(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+    buf.clear();
+    buf.extend_from_slice(data);
+    Ok(())
+}
+
+fn reverse(data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+    buf.clear();
+    buf.extend(data.iter().rev());
+    Ok(())
+}
+
+/// Reads the key of parameters already checked against [`XOR_PARAMS`].
+fn xor_key(params: &StageParams) -> Result<u8> {
+    match params.get("key") {
+        Some(raw) => raw.parse().map_err(|_| anyhow!("xor key must be between 0 and 255, got {}", raw)),
+        None => Ok(0xff),
+    }
+}
+
+fn validate_xor(params: &StageParams) -> Result<()> {
+    xor_key(params).map(|_| ())
+}
+
+fn xor(data: &[u8], buf: &mut Vec<u8>, params: &StageParams) -> Result<()> {
+    let key = xor_key(params)?;
+    buf.clear();
+    buf.extend(data.iter().map(|byte| byte ^ key));
+    Ok(())
+}
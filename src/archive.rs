@@ -0,0 +1,988 @@
+//! directory archives: many files stored in one container.
+//!
+//! the files are grouped into frames, and every frame runs through the pipeline on its own. files smaller than the
+//! small-file threshold share frames, so tiny files don't each pay for a pipeline run and its framing, while larger
+//! files get a frame each and can be extracted without decoding their neighbours. the payload is the encoded
+//! frames back to back, and the [`ArchiveTable`] in the container header records where each frame and member is.
+//!
+//! the table is encoded as:
+//!
+//! | field         | notes                                                                       |
+//! |---------------|-----------------------------------------------------------------------------|
+//! | frame count   | varint                                                                      |
+//! | frames        | `[encoded length: varint][decoded length: varint]` per frame                |
+//! | member count  | varint                                                                      |
+//! | members       | `[path length: varint][path][kind: u8][frame: varint][offset: varint][size: varint]` |
+//!
+//! paths are relative to the archived directory, use `/` as the separator and are stored in walk order, so
+//! directories come before their contents.
+//!
+//! the crc32 of every file member is stored in a header field of its own (see
+//! [`crate::container::TAG_MEMBER_CHECKSUMS`]), so builds that don't know it still read the table. it is encoded as
+//! `[member index: varint][crc32: u32 le]` per file member that has one, which members archived by an older build and
+//! appended to since don't. [`verify_members`] checks every member against it on its own, and reports exactly which
+//! members are damaged rather than a single verdict for the whole archive. the content type of every file member is
+//! stored the same way, see [`crate::content`].
+//!
+//! # extraction
+//!
+//! archives are input like any other, so a crafted one can hold member paths meant to land outside the directory it
+//! is extracted to ("zip slip"). with the default [`PathPolicy::Confined`], member paths are normalized, dropping
+//! empty and `.` parts and reading `\` as a separator too, and members that are absolute, start with a drive prefix
+//! such as `C:`, climb out with `..` or hold a NUL are refused before anything is written. every file and directory
+//! is also checked to resolve below the output directory once its existing parts are canonicalized, so symlinks
+//! already in the output directory can't lead out of it either. `--unsafe-paths` selects [`PathPolicy::Unchecked`],
+//! which writes members where their paths say, for archives that are trusted and meant to do so.
+use std::{
+    collections::{BTreeMap, HashSet, btree_map},
+    fs,
+    io::{self, Read, Seek, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::{
+    algorithms::pipeline::CompressionPipeline,
+    container::ContainerReader,
+    content::ContentType,
+    events::{self, Event},
+    io::{IoPolicy, read_file, write_file},
+    mutator::Mutator,
+    progress,
+    units::MEBIBYTES,
+    varint,
+    walk::PathFilter,
+};
+
+/// Shared frames are closed once they hold this much data.
+const SHARED_FRAME_SIZE: usize = 8 * MEBIBYTES;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberKind {
+    File,
+    Directory,
+}
+
+/// A file or directory stored in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    /// Path relative to the archived directory, with `/` separators.
+    pub path: String,
+    pub kind: MemberKind,
+    /// Frame holding the contents, meaningless for directories.
+    pub frame: usize,
+    /// Offset of the contents within the decoded frame.
+    pub offset: u64,
+    pub size: u64,
+    /// Crc32 of the contents, absent for directories and for files archived by a build that didn't record it.
+    pub crc32: Option<u32>,
+    /// What kind of content the file holds, absent for directories and for files archived by a build that didn't
+    /// detect it.
+    pub content: Option<ContentType>,
+}
+
+/// One independently encoded run of the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub encoded_len: u64,
+    pub decoded_len: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveTable {
+    pub frames: Vec<Frame>,
+    pub members: Vec<Member>,
+}
+
+impl ArchiveTable {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint::write_u64(&mut out, self.frames.len() as u64);
+        for frame in &self.frames {
+            varint::write_u64(&mut out, frame.encoded_len);
+            varint::write_u64(&mut out, frame.decoded_len);
+        }
+        varint::write_u64(&mut out, self.members.len() as u64);
+        for member in &self.members {
+            varint::write_u64(&mut out, member.path.len() as u64);
+            out.extend_from_slice(member.path.as_bytes());
+            out.push(match member.kind {
+                MemberKind::File => 0,
+                MemberKind::Directory => 1,
+            });
+            varint::write_u64(&mut out, member.frame as u64);
+            varint::write_u64(&mut out, member.offset);
+            varint::write_u64(&mut out, member.size);
+        }
+        out
+    }
+
+    pub fn parse(mut bytes: &[u8]) -> Result<Self> {
+        let truncated = || anyhow!("archive table truncated");
+        let frame_count = varint::read_u64(&mut bytes)?;
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            let encoded_len = varint::read_u64(&mut bytes)?;
+            let decoded_len = varint::read_u64(&mut bytes)?;
+            frames.push(Frame { encoded_len, decoded_len });
+        }
+        // frame offsets are sums of these, which can't overflow past here
+        if frames.iter().try_fold(0u64, |total, frame| total.checked_add(frame.encoded_len)).is_none() {
+            bail!("archive frame lengths add up to more than a payload can hold");
+        }
+
+        let member_count = varint::read_u64(&mut bytes)?;
+        let mut members = Vec::new();
+        for _ in 0..member_count {
+            let path_len = usize::try_from(varint::read_u64(&mut bytes)?).map_err(|_| truncated())?;
+            let (path, rest) = bytes.split_at_checked(path_len).ok_or_else(truncated)?;
+            let path = String::from_utf8(path.to_vec()).map_err(|_| anyhow!("archive member path is not utf-8"))?;
+            let (&kind, mut rest) = rest.split_first().ok_or_else(truncated)?;
+            let kind = match kind {
+                0 => MemberKind::File,
+                1 => MemberKind::Directory,
+                other => bail!("archive member {:?} has unknown kind {}", path, other),
+            };
+            let frame = usize::try_from(varint::read_u64(&mut rest)?).map_err(|_| truncated())?;
+            let offset = varint::read_u64(&mut rest)?;
+            let size = varint::read_u64(&mut rest)?;
+            bytes = rest;
+            if kind == MemberKind::File
+                && frames
+                    .get(frame)
+                    .is_none_or(|f| offset.checked_add(size).is_none_or(|end| end > f.decoded_len))
+            {
+                bail!("archive member {:?} points outside its frame", path);
+            }
+            members.push(Member {
+                path,
+                kind,
+                frame,
+                offset,
+                size,
+                crc32: None,
+                content: None,
+            });
+        }
+        if !bytes.is_empty() {
+            bail!("archive table has trailing bytes");
+        }
+        Ok(ArchiveTable { frames, members })
+    }
+
+    /// The crc32 of every file member that has one, in the format described in the module docs. `None` when no member
+    /// has one, so the header field is left out.
+    pub fn member_checksums(&self) -> Option<Vec<u8>> {
+        let mut out = Vec::new();
+        for (index, member) in self.members.iter().enumerate() {
+            if let Some(crc32) = member.crc32 {
+                varint::write_u64(&mut out, index as u64);
+                out.extend_from_slice(&crc32.to_le_bytes());
+            }
+        }
+        (!out.is_empty()).then_some(out)
+    }
+
+    /// Sets the crc32 of the members from what [`ArchiveTable::member_checksums`] wrote.
+    pub fn parse_member_checksums(&mut self, mut bytes: &[u8]) -> Result<()> {
+        while !bytes.is_empty() {
+            let index = varint::read_u64(&mut bytes)?;
+            let (crc32, rest) = bytes
+                .split_first_chunk::<4>()
+                .ok_or_else(|| anyhow!("archive member checksums truncated"))?;
+            bytes = rest;
+            let member = usize::try_from(index)
+                .ok()
+                .and_then(|index| self.members.get_mut(index))
+                .filter(|member| member.kind == MemberKind::File)
+                .ok_or_else(|| anyhow!("archive member checksum {} doesn't belong to a file member", index))?;
+            member.crc32 = Some(u32::from_le_bytes(*crc32));
+        }
+        Ok(())
+    }
+
+    /// The encoded bytes of frame `index` within `payload`.
+    pub fn frame<'a>(&self, payload: &'a [u8], index: usize) -> Result<&'a [u8]> {
+        let start = usize::try_from(self.frame_offset(index)).ok();
+        let len = usize::try_from(self.frames[index].encoded_len).ok();
+        start
+            .zip(len)
+            .and_then(|(start, len)| payload.get(start..start.checked_add(len)?))
+            .ok_or_else(|| anyhow!("archive frame {} lies outside the payload", index))
+    }
+
+    /// Where frame `index` starts in the payload.
+    pub fn frame_offset(&self, index: usize) -> u64 {
+        self.frames[..index].iter().map(|frame| frame.encoded_len).sum()
+    }
+}
+
+/// Builds frames as files are added, packing small files together.
+struct ArchiveBuilder<'p> {
+    pipeline: &'p mut CompressionPipeline,
+    table: ArchiveTable,
+    payload: Vec<u8>,
+    /// Everything fed to the pipeline, in frame order, for the container checksum.
+    original: Vec<u8>,
+    shared: Vec<u8>,
+    /// Members waiting in `shared`, their frame index is only known once it is flushed.
+    shared_members: Vec<usize>,
+    /// Paths of all members so far, to refuse adding one twice.
+    paths: HashSet<String>,
+}
+
+impl<'p> ArchiveBuilder<'p> {
+    /// Continues an archive, new frames are added after the ones it already has.
+    fn resume(pipeline: &'p mut CompressionPipeline, archive: EncodedArchive) -> Self {
+        ArchiveBuilder {
+            pipeline,
+            paths: archive.table.members.iter().map(|member| member.path.clone()).collect(),
+            table: archive.table,
+            payload: archive.payload,
+            original: archive.original,
+            shared: Vec::new(),
+            shared_members: Vec::new(),
+        }
+    }
+
+    fn finish(mut self) -> Result<EncodedArchive> {
+        self.flush_shared()?;
+        Ok(EncodedArchive {
+            table: self.table,
+            payload: self.payload,
+            original: self.original,
+        })
+    }
+
+    /// Adds everything `walk` yields, stored under its path relative to `base`.
+    fn add_tree(
+        &mut self,
+        walk: impl Iterator<Item = walkdir::Result<DirEntry>>,
+        base: &Path,
+        small_file_threshold: u64,
+        policy: IoPolicy,
+    ) -> Result<()> {
+        for entry in walk {
+            let entry = entry.with_context(|| format!("failed to walk {}", base.display()))?;
+            let relative = entry.path().strip_prefix(base).expect("walkdir yields paths below its root");
+            let path = relative_to_archive_path(relative)?;
+            if !self.paths.insert(path.clone()) {
+                bail!("the archive already holds {:?}", path);
+            }
+            if_tracing! {
+                let _span = tracing::debug_span!("member", id = self.table.members.len(), path = %path).entered();
+            }
+            if entry.file_type().is_dir() {
+                self.table.members.push(Member {
+                    path,
+                    kind: MemberKind::Directory,
+                    frame: 0,
+                    offset: 0,
+                    size: 0,
+                    crc32: None,
+                    content: None,
+                });
+            } else if entry.file_type().is_file() {
+                let data = read_file(entry.path(), policy).with_context(|| format!("failed to read {}", entry.path().display()))?;
+                self.add_file(path, &data, small_file_threshold)?;
+            } else {
+                events::warn(format_args!(
+                    "skipping {}, only files and directories are archived.",
+                    entry.path().display()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn push_frame(&mut self, data: &[u8]) -> Result<usize> {
+        if_tracing! {
+            let _span = tracing::debug_span!("frame", id = self.table.frames.len(), len = data.len()).entered();
+        }
+        let mut encoded = Vec::new();
+        self.pipeline.drive_mutation(data, &mut encoded)?;
+        events::emit(Event::UnitFinished {
+            unit: "frame",
+            index: self.table.frames.len(),
+            count: None,
+            output_size: encoded.len() as u64,
+        });
+        self.table.frames.push(Frame {
+            encoded_len: encoded.len() as u64,
+            decoded_len: data.len() as u64,
+        });
+        self.payload.extend_from_slice(&encoded);
+        self.original.extend_from_slice(data);
+        Ok(self.table.frames.len() - 1)
+    }
+
+    fn flush_shared(&mut self) -> Result<()> {
+        if self.shared_members.is_empty() {
+            return Ok(());
+        }
+        let shared = std::mem::take(&mut self.shared);
+        let frame = self.push_frame(&shared)?;
+        for member in self.shared_members.drain(..) {
+            self.table.members[member].frame = frame;
+        }
+        Ok(())
+    }
+
+    fn add_file(&mut self, path: String, data: &[u8], small_file_threshold: u64) -> Result<()> {
+        let size = data.len() as u64;
+        let crc32 = Some(crc32fast::hash(data));
+        let content = Some(ContentType::detect(data));
+        if size < small_file_threshold {
+            self.shared_members.push(self.table.members.len());
+            self.table.members.push(Member {
+                path,
+                kind: MemberKind::File,
+                frame: 0,
+                offset: self.shared.len() as u64,
+                size,
+                crc32,
+                content,
+            });
+            self.shared.extend_from_slice(data);
+            if self.shared.len() >= SHARED_FRAME_SIZE {
+                self.flush_shared()?;
+            }
+        } else {
+            let frame = self.push_frame(data)?;
+            self.table.members.push(Member {
+                path,
+                kind: MemberKind::File,
+                frame,
+                offset: 0,
+                size,
+                crc32,
+                content,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// An encoded directory, ready to be written into a container.
+#[derive(Default)]
+pub struct EncodedArchive {
+    pub table: ArchiveTable,
+    pub payload: Vec<u8>,
+    /// The data the frames were made from, which the container checksum covers.
+    pub original: Vec<u8>,
+}
+
+/// Walks `root` and encodes every file in it that `filter` keeps.
+pub fn encode_directory(
+    pipeline: &mut CompressionPipeline,
+    root: &Path,
+    filter: &PathFilter,
+    small_file_threshold: u64,
+    policy: IoPolicy,
+) -> Result<EncodedArchive> {
+    let mut builder = ArchiveBuilder::resume(pipeline, EncodedArchive::default());
+    builder.add_tree(filter.walk(WalkDir::new(root).min_depth(1)), root, small_file_threshold, policy)?;
+    builder.finish()
+}
+
+/// Adds `inputs`, files or directories with everything below them, to an existing archive under their own names.
+/// The frames already in the archive are kept as they are and the new members go into frames after them, so only
+/// the new data runs through the pipeline. `archive.original` has to hold the decoded frames of the existing archive,
+/// an empty [`EncodedArchive`] starts a new one. `filter` chooses what is added from directory inputs.
+pub fn append_to_archive(
+    pipeline: &mut CompressionPipeline,
+    archive: EncodedArchive,
+    inputs: &[PathBuf],
+    filter: &PathFilter,
+    small_file_threshold: u64,
+    policy: IoPolicy,
+) -> Result<EncodedArchive> {
+    let mut builder = ArchiveBuilder::resume(pipeline, archive);
+    for input in inputs {
+        if input.file_name().is_none() {
+            bail!("{} has no name to store it under", input.display());
+        }
+        let parent = input.parent().unwrap_or(Path::new(""));
+        builder.add_tree(filter.walk(WalkDir::new(input)), parent, small_file_threshold, policy)?;
+    }
+    builder.finish()
+}
+
+fn relative_to_archive_path(relative: &Path) -> Result<String> {
+    let mut parts = Vec::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => parts.push(
+                part.to_str()
+                    .ok_or_else(|| anyhow!("{} is not valid utf-8", relative.display()))?
+                    .to_string(),
+            ),
+            _ => bail!("unexpected path component in {}", relative.display()),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+/// How member paths are mapped onto the file system when an archive is extracted, see the module docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathPolicy {
+    /// Refuses members whose paths would land outside the output directory.
+    #[default]
+    Confined,
+    /// Writes members wherever their paths point, `..` and absolute paths included.
+    Unchecked,
+}
+
+impl PathPolicy {
+    pub fn new(unsafe_paths: bool) -> Self {
+        match unsafe_paths {
+            true => PathPolicy::Unchecked,
+            false => PathPolicy::Confined,
+        }
+    }
+}
+
+/// The parts of `member_path` that lead below the directory it is extracted to, refusing the paths
+/// [`PathPolicy::Confined`] refuses.
+pub fn member_path_parts(member_path: &str) -> Result<Vec<&str>> {
+    let refuse = |why: &str| {
+        anyhow!(
+            "archive member path {:?} {}, pass --unsafe-paths to extract it anyway",
+            member_path,
+            why
+        )
+    };
+    if member_path.contains('\0') {
+        return Err(refuse("holds a NUL"));
+    }
+    if member_path.starts_with(['/', '\\']) {
+        return Err(refuse("is absolute"));
+    }
+    if let [drive, b':', ..] = member_path.as_bytes()
+        && drive.is_ascii_alphabetic()
+    {
+        return Err(refuse("starts with a drive prefix"));
+    }
+    let mut parts = Vec::new();
+    for part in member_path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return Err(refuse("climbs out of the output directory")),
+            // whatever else the platform reads as more than one plain name
+            part if !matches!(Path::new(part).components().collect::<Vec<_>>().as_slice(), [Component::Normal(_)]) => {
+                return Err(refuse("is not a plain relative path"));
+            }
+            part => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return Err(refuse("names no file"));
+    }
+    Ok(parts)
+}
+
+/// Resolves a member path below `root`, refusing anything that would land outside of it unless `paths` is
+/// [`PathPolicy::Unchecked`].
+pub fn member_output_path(root: &Path, member_path: &str, paths: PathPolicy) -> Result<PathBuf> {
+    match paths {
+        PathPolicy::Confined => Ok(member_path_parts(member_path)?.into_iter().fold(root.to_path_buf(), |path, part| path.join(part))),
+        PathPolicy::Unchecked => Ok(root.join(member_path)),
+    }
+}
+
+/// Fails if `path` resolves outside of `root`, as far as it exists, e.g. through a symlink in the output directory.
+fn check_confined(root: &Path, path: &Path, paths: PathPolicy) -> Result<()> {
+    if paths == PathPolicy::Unchecked {
+        return Ok(());
+    }
+    let root = fs::canonicalize(root).with_context(|| format!("failed to resolve {}", root.display()))?;
+    // symlink_metadata so a dangling symlink counts as existing, canonicalizing it fails below
+    let existing = path
+        .ancestors()
+        .find(|ancestor| fs::symlink_metadata(ancestor).is_ok())
+        .unwrap_or(path);
+    let resolved = fs::canonicalize(existing).with_context(|| format!("failed to resolve {}", existing.display()))?;
+    if !resolved.starts_with(&root) {
+        bail!(
+            "{} resolves to {}, outside of {}, pass --unsafe-paths to extract it anyway",
+            path.display(),
+            resolved.display(),
+            root.display()
+        );
+    }
+    Ok(())
+}
+
+/// Decodes every frame of an archive, in order.
+pub fn decode_frames(pipeline: &mut CompressionPipeline, table: &ArchiveTable, payload: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut frames = Vec::with_capacity(table.frames.len());
+    let mut total = 0;
+    for index in 0..table.frames.len() {
+        progress::unit("frame", index, table.frames.len());
+        let frame = decode_frame(pipeline, table, payload, index)?;
+        events::emit(Event::UnitFinished {
+            unit: "frame",
+            index,
+            count: Some(table.frames.len()),
+            output_size: frame.len() as u64,
+        });
+        total += frame.len();
+        pipeline.check_output_size(total)?;
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+/// Decodes frame `index` of an archive on its own.
+pub fn decode_frame(pipeline: &mut CompressionPipeline, table: &ArchiveTable, payload: &[u8], index: usize) -> Result<Vec<u8>> {
+    decode_encoded_frame(pipeline, table, index, table.frame(payload, index)?)
+}
+
+fn decode_encoded_frame(pipeline: &mut CompressionPipeline, table: &ArchiveTable, index: usize, encoded: &[u8]) -> Result<Vec<u8>> {
+    if_tracing! {
+        let _span = tracing::debug_span!("frame", id = index).entered();
+    }
+    let frame = table.frames[index];
+    let mut decoded = Vec::new();
+    pipeline
+        .revert_mutation(encoded, &mut decoded)
+        .map_err(|e| anyhow!("frame {} failed to decode: {}", index, e))?;
+    if decoded.len() as u64 != frame.decoded_len {
+        bail!(
+            "frame {} decoded to {} bytes instead of {}",
+            index,
+            decoded.len(),
+            frame.decoded_len
+        );
+    }
+    Ok(decoded)
+}
+
+/// What [`verify_members`] found out about a file member.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemberCheck {
+    /// The member decoded to the data it was archived with.
+    Ok,
+    /// The archive records no crc32 of the member, so it only decoded without errors.
+    Unchecked,
+    /// The member decoded, but not to the data it was archived with.
+    Damaged { expected: u32, actual: u32 },
+    /// The frame holding the member failed to decode.
+    Undecodable(String),
+}
+
+/// Checks every file member of an archive against its crc32, returning the index of every file member in the table
+/// with what was found. frames are decoded one at a time, so only one is held in memory, and a frame that fails to
+/// decode, or makes a stage panic, fails the members in it and no others.
+pub fn verify_members(pipeline: &mut CompressionPipeline, table: &ArchiveTable, payload: &[u8]) -> Vec<(usize, MemberCheck)> {
+    let mut by_frame = BTreeMap::<usize, Vec<usize>>::new();
+    for (index, member) in table.members.iter().enumerate() {
+        if member.kind == MemberKind::File {
+            by_frame.entry(member.frame).or_default().push(index);
+        }
+    }
+    let mut checks = Vec::new();
+    for (frame_index, members) in by_frame {
+        progress::unit("frame", frame_index, table.frames.len());
+        let decoded = panic::catch_unwind(AssertUnwindSafe(|| decode_frame(pipeline, table, payload, frame_index)))
+            .unwrap_or_else(|_| Err(anyhow!("frame {} failed to decode: a stage crashed on malformed input", frame_index)));
+        for index in members {
+            let member = &table.members[index];
+            let check = match (&decoded, member.crc32) {
+                (Err(e), _) => MemberCheck::Undecodable(e.to_string()),
+                (Ok(_), None) => MemberCheck::Unchecked,
+                (Ok(frame), Some(expected)) => {
+                    // the table and the decoded length were checked, so the member lies inside the frame
+                    let start = member.offset as usize;
+                    let actual = crc32fast::hash(&frame[start..start + member.size as usize]);
+                    match actual == expected {
+                        true => MemberCheck::Ok,
+                        false => MemberCheck::Damaged { expected, actual },
+                    }
+                }
+            };
+            checks.push((index, check));
+        }
+    }
+    checks.sort_by_key(|&(index, _)| index);
+    checks
+}
+
+/// Decodes the contents of the file member at `entry`, decoding only the frame that holds it.
+pub fn read_entry(pipeline: &mut CompressionPipeline, table: &ArchiveTable, payload: &[u8], entry: &str) -> Result<Vec<u8>> {
+    let member = file_member(table, entry)?;
+    let frame = decode_frame(pipeline, table, payload, member.frame)?;
+    Ok(member_data(member, frame))
+}
+
+/// Like [`read_entry`], for an archive read through a [`ContainerReader`]. only the frame holding the entry is read
+/// from the source.
+pub fn read_entry_from<R: Read + Seek>(
+    pipeline: &mut CompressionPipeline,
+    table: &ArchiveTable,
+    reader: &mut ContainerReader<R>,
+    entry: &str,
+) -> Result<Vec<u8>> {
+    let member = file_member(table, entry)?;
+    let encoded = reader.read_payload_range(table.frame_offset(member.frame), table.frames[member.frame].encoded_len)?;
+    let frame = decode_encoded_frame(pipeline, table, member.frame, &encoded)?;
+    Ok(member_data(member, frame))
+}
+
+fn file_member<'t>(table: &'t ArchiveTable, entry: &str) -> Result<&'t Member> {
+    let entry = entry.trim_matches('/');
+    let member = table
+        .members
+        .iter()
+        .find(|member| member.path == entry)
+        .ok_or_else(|| anyhow!("the archive has no entry {:?}", entry))?;
+    if member.kind == MemberKind::Directory {
+        bail!("{:?} is a directory", entry);
+    }
+    Ok(member)
+}
+
+/// Cuts the contents of `member` out of its decoded frame.
+fn member_data(member: &Member, mut frame: Vec<u8>) -> Vec<u8> {
+    let start = member.offset as usize;
+    frame.truncate(start + member.size as usize);
+    frame.drain(..start);
+    frame
+}
+
+/// Extracts the member at `entry` to `output`, along with everything below it when it is a directory. Only the
+/// frames holding those members are decoded, so the container checksum, which covers every frame, can't be checked.
+/// Returns how many members were written.
+pub fn extract_entry(
+    pipeline: &mut CompressionPipeline,
+    table: &ArchiveTable,
+    payload: &[u8],
+    entry: &str,
+    output: &Path,
+    policy: IoPolicy,
+    paths: PathPolicy,
+) -> Result<usize> {
+    let entry = entry.trim_matches('/');
+    if !table.members.iter().any(|member| member.path == entry) {
+        bail!("the archive has no entry {:?}", entry);
+    }
+    let selected = table.members.iter().filter_map(|member| {
+        if member.path == entry {
+            Some((member, None))
+        } else {
+            member
+                .path
+                .strip_prefix(entry)
+                .and_then(|rest| rest.strip_prefix('/'))
+                .map(|relative| (member, Some(relative)))
+        }
+    });
+
+    // every path is checked before any frame is decoded or anything written
+    let selected = selected
+        .map(|(member, relative)| match relative {
+            Some(relative) => Ok((member, Some(member_output_path(output, relative, paths)?))),
+            None => Ok((member, None)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut frames = BTreeMap::new();
+    let mut written = 0;
+    for (member, below) in selected {
+        let path = match &below {
+            Some(path) => {
+                check_confined(output, path, paths)?;
+                path.clone()
+            }
+            None => output.to_path_buf(),
+        };
+        match member.kind {
+            MemberKind::Directory => fs::create_dir_all(&path).with_context(|| format!("failed to create {}", path.display()))?,
+            MemberKind::File => {
+                let frame = match frames.entry(member.frame) {
+                    btree_map::Entry::Occupied(frame) => frame.into_mut(),
+                    btree_map::Entry::Vacant(slot) => slot.insert(decode_frame(pipeline, table, payload, member.frame)?),
+                };
+                let start = member.offset as usize;
+                let data = &frame[start..start + member.size as usize];
+                if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                    fs::create_dir_all(parent).with_context(|| format!("failed to create {}", parent.display()))?;
+                }
+                write_file(&path, data, policy).with_context(|| format!("failed to write {}", path.display()))?;
+            }
+        }
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Writes the archived tree from decoded frames into `out` as a tar stream, with the members in the order they are
+/// stored. archives don't record permissions or times, so files get mode 0644, directories 0755, and all of them
+/// `mtime`.
+pub fn write_tar(table: &ArchiveTable, frames: &[Vec<u8>], mtime: u64, out: impl Write) -> Result<()> {
+    let mut builder = tar::Builder::new(out);
+    for member in &table.members {
+        let mut header = tar::Header::new_gnu();
+        header.set_mtime(mtime);
+        let appended = match member.kind {
+            MemberKind::Directory => {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                builder.append_data(&mut header, format!("{}/", member.path), io::empty())
+            }
+            MemberKind::File => {
+                let start = member.offset as usize;
+                let data = frames
+                    .get(member.frame)
+                    .and_then(|frame| frame.get(start..start + member.size as usize))
+                    .ok_or_else(|| anyhow!("{:?} lies outside of its frame", member.path))?;
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_mode(0o644);
+                header.set_size(member.size);
+                builder.append_data(&mut header, &member.path, data)
+            }
+        };
+        appended.with_context(|| format!("failed to add {} to the tar stream", member.path))?;
+    }
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+/// Recreates the archived tree below `root` from decoded frames.
+pub fn extract_all(table: &ArchiveTable, frames: &[Vec<u8>], root: &Path, policy: IoPolicy, paths: PathPolicy) -> Result<()> {
+    // every path is checked before anything is written
+    let outputs = table
+        .members
+        .iter()
+        .map(|member| member_output_path(root, &member.path, paths))
+        .collect::<Result<Vec<_>>>()?;
+    fs::create_dir_all(root).with_context(|| format!("failed to create {}", root.display()))?;
+    for (member, path) in table.members.iter().zip(outputs) {
+        if_tracing! {
+            let _span = tracing::debug_span!("member", path = %member.path).entered();
+        }
+        check_confined(root, &path, paths)?;
+        match member.kind {
+            MemberKind::Directory => fs::create_dir_all(&path).with_context(|| format!("failed to create {}", path.display()))?,
+            MemberKind::File => {
+                let start = member.offset as usize;
+                let data = &frames[member.frame][start..start + member.size as usize];
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).with_context(|| format!("failed to create {}", parent.display()))?;
+                }
+                write_file(&path, data, policy).with_context(|| format!("failed to write {}", path.display()))?;
+                events::emit(Event::MemberWritten {
+                    path: &path,
+                    size: member.size,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    fn refused(member_path: &str) -> bool {
+        member_path_parts(member_path).is_err()
+    }
+
+    /// A fresh directory under the system temp directory, removed again when the test is done with it.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("stackpack-archive-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// An archive of one file member at `path` holding `x`.
+    fn single_file(path: &str) -> (ArchiveTable, Vec<Vec<u8>>) {
+        let table = ArchiveTable {
+            frames: vec![Frame {
+                encoded_len: 1,
+                decoded_len: 1,
+            }],
+            members: vec![Member {
+                path: path.to_string(),
+                kind: MemberKind::File,
+                frame: 0,
+                offset: 0,
+                size: 1,
+                crc32: None,
+                content: None,
+            }],
+        };
+        (table, vec![b"x".to_vec()])
+    }
+
+    #[test]
+    fn plain_paths_are_normalized() {
+        assert_eq!(member_path_parts("a/b").unwrap(), ["a", "b"]);
+        assert_eq!(member_path_parts("a//./b/").unwrap(), ["a", "b"]);
+        assert_eq!(member_path_parts("./a").unwrap(), ["a"]);
+        assert_eq!(member_path_parts("12:30.log").unwrap(), ["12:30.log"]);
+    }
+
+    #[test]
+    fn parent_dirs_are_refused() {
+        assert!(refused(".."));
+        assert!(refused("../a"));
+        assert!(refused("a/../../b"));
+        assert!(refused("a/.."));
+    }
+
+    #[test]
+    fn absolute_paths_are_refused() {
+        assert!(refused("/etc/passwd"));
+        assert!(refused("/"));
+        assert!(refused("\\windows\\system32"));
+    }
+
+    #[test]
+    fn backslashes_separate() {
+        assert_eq!(member_path_parts("a\\b").unwrap(), ["a", "b"]);
+        assert!(refused("..\\a"));
+        assert!(refused("a\\..\\..\\b"));
+    }
+
+    #[test]
+    fn drive_and_unc_prefixes_are_refused() {
+        assert!(refused("C:/Windows"));
+        assert!(refused("c:relative"));
+        assert!(refused("C:\\Windows"));
+        assert!(refused("\\\\server\\share\\a"));
+        assert!(refused("//server/share/a"));
+        assert!(refused("\\\\?\\C:\\a"));
+    }
+
+    #[test]
+    fn nul_bytes_and_empty_paths_are_refused() {
+        assert!(refused("a\0b"));
+        assert!(refused(""));
+        assert!(refused("."));
+        assert!(refused("./"));
+    }
+
+    #[test]
+    fn unchecked_paths_are_taken_as_they_are() {
+        let root = Path::new("out");
+        assert_eq!(member_output_path(root, "../a", PathPolicy::Unchecked).unwrap(), root.join("../a"));
+        assert!(member_output_path(root, "../a", PathPolicy::Confined).is_err());
+    }
+
+    #[test]
+    fn extracts_below_the_root() {
+        let dir = TempDir::new("plain");
+        let (table, frames) = single_file("a/b");
+        extract_all(&table, &frames, &dir.0, IoPolicy::default(), PathPolicy::Confined).unwrap();
+        assert_eq!(fs::read(dir.0.join("a/b")).unwrap(), b"x");
+    }
+
+    #[test]
+    fn refused_paths_write_nothing() {
+        let dir = TempDir::new("refused");
+        let root = dir.0.join("root");
+        let (table, frames) = single_file("../escaped");
+        assert!(extract_all(&table, &frames, &root, IoPolicy::default(), PathPolicy::Confined).is_err());
+        assert!(!root.exists());
+        assert!(!dir.0.join("escaped").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_root_are_refused() {
+        let dir = TempDir::new("symlink");
+        let (root, outside) = (dir.0.join("root"), dir.0.join("outside"));
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        let (table, frames) = single_file("link/f");
+
+        assert!(extract_all(&table, &frames, &root, IoPolicy::default(), PathPolicy::Confined).is_err());
+        assert!(!outside.join("f").exists());
+        extract_all(&table, &frames, &root, IoPolicy::default(), PathPolicy::Unchecked).unwrap();
+        assert_eq!(fs::read(outside.join("f")).unwrap(), b"x");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlinks_are_refused() {
+        let dir = TempDir::new("dangling");
+        let root = dir.0.join("root");
+        fs::create_dir_all(&root).unwrap();
+        std::os::unix::fs::symlink(dir.0.join("missing"), root.join("f")).unwrap();
+        let (table, frames) = single_file("f");
+        let policy = IoPolicy::default().forced();
+        assert!(extract_all(&table, &frames, &root, policy, PathPolicy::Confined).is_err());
+        assert!(!dir.0.join("missing").exists());
+    }
+
+    /// An identity pipeline encodes the data as it is, so damage to the payload decodes to damaged data.
+    fn identity_archive(files: &[&[u8]]) -> (CompressionPipeline, EncodedArchive) {
+        let mut pipeline = CompressionPipeline::new().with_algorithm(crate::algorithms::util::Identity);
+        let mut builder = ArchiveBuilder::resume(&mut pipeline, EncodedArchive::default());
+        for (index, data) in files.iter().enumerate() {
+            builder.add_file(format!("f{}", index), data, 4).unwrap();
+        }
+        let archive = builder.finish().unwrap();
+        (pipeline, archive)
+    }
+
+    #[test]
+    fn member_checksums_round_trip() {
+        let (_, archive) = identity_archive(&[b"a", b"long enough"]);
+        let mut table = ArchiveTable::parse(&archive.table.to_bytes()).unwrap();
+        assert!(table.members.iter().all(|member| member.crc32.is_none()));
+        table.parse_member_checksums(&archive.table.member_checksums().unwrap()).unwrap();
+        crate::content::parse(&mut table, &crate::content::to_bytes(&archive.table).unwrap()).unwrap();
+        assert_eq!(table, archive.table);
+        assert_eq!(table.members[1].crc32, Some(crc32fast::hash(b"long enough")));
+    }
+
+    #[test]
+    fn content_types_round_trip() {
+        let (_, archive) = identity_archive(&[b"some text", b"\x7fELF\x02\x01\x01"]);
+        assert_eq!(archive.table.members[0].content, Some(ContentType::Text));
+        assert_eq!(archive.table.members[1].content, Some(ContentType::Executable));
+        let mut table = ArchiveTable::parse(&archive.table.to_bytes()).unwrap();
+        assert!(table.members.iter().all(|member| member.content.is_none()));
+        crate::content::parse(&mut table, &crate::content::to_bytes(&archive.table).unwrap()).unwrap();
+        assert_eq!(
+            table.members.iter().map(|member| member.content).collect::<Vec<_>>(),
+            archive.table.members.iter().map(|member| member.content).collect::<Vec<_>>()
+        );
+        assert_eq!(crate::content::composition(&table), crate::content::composition(&archive.table));
+    }
+
+    #[test]
+    fn damaged_members_are_named() {
+        let (mut pipeline, mut archive) = identity_archive(&[b"a", b"first file", b"second file"]);
+        let checks = verify_members(&mut pipeline, &archive.table, &archive.payload);
+        assert!(checks.iter().all(|(_, check)| *check == MemberCheck::Ok));
+
+        let second = archive.table.frame_offset(archive.table.members[2].frame) as usize;
+        archive.payload[second] ^= 1;
+        let checks = verify_members(&mut pipeline, &archive.table, &archive.payload);
+        assert_eq!(checks[0], (0, MemberCheck::Ok));
+        assert_eq!(checks[1], (1, MemberCheck::Ok));
+        assert!(matches!(checks[2], (2, MemberCheck::Damaged { .. })));
+    }
+}
@@ -0,0 +1,198 @@
+//! block mode payloads, where the input is split into fixed-size blocks that each run through the pipeline on
+//! their own, so single blocks can be located and decoded without touching the rest of the file.
+//!
+//! the payload is laid out as:
+//!
+//! | field        | size        | notes                                                   |
+//! |--------------|-------------|---------------------------------------------------------|
+//! | blocks       | variable    | every block encoded by the pipeline, back to back       |
+//! | offsets      | 8 per block | little endian `u64` offset of each block in the payload |
+//! | block count  | 8           | little endian `u64`                                     |
+//! | index magic  | 4           | [`INDEX_MAGIC`]                                         |
+//!
+//! every block decodes to the container's block size, except the last one which may be shorter.
+use anyhow::{Result, anyhow, bail};
+
+use crate::{
+    algorithms::pipeline::CompressionPipeline,
+    events::{self, Event},
+    mutator::Mutator,
+    progress,
+};
+
+pub const INDEX_MAGIC: [u8; 4] = *b"STBI";
+const FOOTER_LEN: usize = 8 + INDEX_MAGIC.len();
+
+/// Encodes `data` in blocks of `block_size` bytes and appends the block index.
+pub fn encode_blocks(pipeline: &mut CompressionPipeline, data: &[u8], block_size: usize, out: &mut Vec<u8>) -> Result<()> {
+    if block_size == 0 {
+        bail!("block size must not be zero");
+    }
+    out.clear();
+    let mut offsets = Vec::with_capacity(data.len().div_ceil(block_size));
+    let mut encoded = Vec::new();
+    let count = data.len().div_ceil(block_size);
+    for (index, block) in data.chunks(block_size).enumerate() {
+        progress::unit("block", index, count);
+        if_tracing! {
+            let _span = tracing::debug_span!("block", id = index, len = block.len()).entered();
+        }
+        pipeline.drive_mutation(block, &mut encoded)?;
+        events::emit(Event::UnitFinished {
+            unit: "block",
+            index,
+            count: Some(count),
+            output_size: encoded.len() as u64,
+        });
+        offsets.push(out.len() as u64);
+        out.extend_from_slice(&encoded);
+    }
+
+    for offset in &offsets {
+        out.extend_from_slice(&offset.to_le_bytes());
+    }
+    out.extend_from_slice(&(offsets.len() as u64).to_le_bytes());
+    out.extend_from_slice(&INDEX_MAGIC);
+    Ok(())
+}
+
+/// A slice of the decoded data, given as `offset..len` on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// The block index at the end of a block mode payload.
+#[derive(Debug)]
+pub struct BlockIndex<'a> {
+    blocks: &'a [u8],
+    offsets: Vec<usize>,
+}
+
+impl<'a> BlockIndex<'a> {
+    pub fn parse(payload: &'a [u8]) -> Result<Self> {
+        let truncated = || anyhow!("block index truncated");
+        let footer_start = payload.len().checked_sub(FOOTER_LEN).ok_or_else(truncated)?;
+        let (rest, footer) = payload.split_at(footer_start);
+        let (count, magic) = footer.split_at(8);
+        if magic != INDEX_MAGIC {
+            bail!("block index magic bytes missing");
+        }
+        let count = usize::try_from(u64::from_le_bytes(count.try_into().unwrap())).map_err(|_| truncated())?;
+        let index_start = count
+            .checked_mul(8)
+            .and_then(|len| rest.len().checked_sub(len))
+            .ok_or_else(truncated)?;
+        let (blocks, index) = rest.split_at(index_start);
+
+        let offsets = index
+            .chunks_exact(8)
+            .map(|offset| usize::try_from(u64::from_le_bytes(offset.try_into().unwrap())).unwrap_or(usize::MAX))
+            .collect::<Vec<_>>();
+        let mut previous = 0;
+        for (block, &offset) in offsets.iter().enumerate() {
+            if offset < previous || offset > blocks.len() || (block == 0 && offset != 0) {
+                bail!("block index entry {} points outside the payload", block);
+            }
+            previous = offset;
+        }
+        Ok(BlockIndex { blocks, offsets })
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The encoded bytes of block `index`.
+    pub fn block(&self, index: usize) -> &'a [u8] {
+        let end = self.offsets.get(index + 1).copied().unwrap_or(self.blocks.len());
+        &self.blocks[self.offsets[index]..end]
+    }
+}
+
+/// Decodes every block of a block mode payload into `out`.
+pub fn decode_blocks(pipeline: &mut CompressionPipeline, payload: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    out.clear();
+    decode_blocks_with(pipeline, payload, |block| out.extend_from_slice(block))
+}
+
+/// Decodes every block in order and hands each one to `sink` as soon as it is decoded, so callers that only look at
+/// the data once, like `dec --checksum-only`, don't need room for all of it.
+pub fn decode_blocks_with(
+    pipeline: &mut CompressionPipeline,
+    payload: &[u8],
+    mut sink: impl FnMut(&[u8]),
+) -> Result<()> {
+    let index = BlockIndex::parse(payload)?;
+    let mut decoded = Vec::new();
+    let mut total = 0;
+    for block in 0..index.len() {
+        progress::unit("block", block, index.len());
+        if_tracing! {
+            let _span = tracing::debug_span!("block", id = block).entered();
+        }
+        pipeline
+            .revert_mutation(index.block(block), &mut decoded)
+            .map_err(|e| anyhow!("block {} failed to decode: {}", block, e))?;
+        events::emit(Event::UnitFinished {
+            unit: "block",
+            index: block,
+            count: Some(index.len()),
+            output_size: decoded.len() as u64,
+        });
+        total += decoded.len();
+        pipeline.check_output_size(total)?;
+        sink(&decoded);
+    }
+    Ok(())
+}
+
+/// Decodes only the blocks overlapping `range` and writes the requested bytes into `out`.
+/// Bytes of the range past the end of the data are left out, like with `dec --head`.
+pub fn decode_range(
+    pipeline: &mut CompressionPipeline,
+    payload: &[u8],
+    block_size: u64,
+    range: ByteRange,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    let index = BlockIndex::parse(payload)?;
+    out.clear();
+    if range.len == 0 || block_size == 0 || index.is_empty() {
+        return Ok(());
+    }
+    let first = usize::try_from(range.offset / block_size).unwrap_or(usize::MAX);
+    let last = usize::try_from(range.offset.saturating_add(range.len - 1) / block_size).unwrap_or(usize::MAX);
+
+    let mut decoded = Vec::new();
+    let last = last.min(index.len().saturating_sub(1));
+    for block in first..=last {
+        progress::unit("block", block - first, last - first + 1);
+        if_tracing! {
+            let _span = tracing::debug_span!("block", id = block).entered();
+        }
+        pipeline
+            .revert_mutation(index.block(block), &mut decoded)
+            .map_err(|e| anyhow!("block {} failed to decode: {}", block, e))?;
+        events::emit(Event::UnitFinished {
+            unit: "block",
+            index: block - first,
+            count: Some(last - first + 1),
+            output_size: decoded.len() as u64,
+        });
+        out.extend_from_slice(&decoded);
+        pipeline.check_output_size(out.len())?;
+    }
+
+    let skip = usize::try_from(range.offset - first as u64 * block_size)
+        .unwrap_or(usize::MAX)
+        .min(out.len());
+    out.drain(..skip);
+    out.truncate(usize::try_from(range.len).unwrap_or(usize::MAX));
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use core::fmt::{self, Display};
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use crc32fast::Hasher;
+use xxhash_rust::xxh3::{Xxh3, xxh3_64};
+
+/// A sink that hashes everything written into it instead of storing it.
+#[derive(Default)]
+pub struct ChecksumWriter {
+    hasher: Hasher,
+    len: u64,
+}
+
+impl ChecksumWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the crc32 and the number of bytes written so far.
+    pub fn finish(self) -> (u32, u64) {
+        (self.hasher.finalize(), self.len)
+    }
+}
+
+impl Write for ChecksumWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.hasher.update(buf);
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash algorithms a container can use to check the integrity of the original data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ChecksumAlgorithm {
+    /// crc32 (IEEE), the default.
+    #[default]
+    Crc32,
+    /// crc32c (Castagnoli), hardware accelerated on most cpus.
+    Crc32c,
+    /// 64 bit xxh3, the fastest option for large inputs.
+    Xxh3,
+    /// 256 bit blake3, a cryptographic hash for when tampering is a concern.
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    /// The identifier stored in container headers.
+    pub const fn id(self) -> u8 {
+        match self {
+            ChecksumAlgorithm::Crc32 => 0,
+            ChecksumAlgorithm::Crc32c => 1,
+            ChecksumAlgorithm::Xxh3 => 2,
+            ChecksumAlgorithm::Blake3 => 3,
+        }
+    }
+
+    pub const fn from_id(id: u8) -> Option<Self> {
+        Some(match id {
+            0 => ChecksumAlgorithm::Crc32,
+            1 => ChecksumAlgorithm::Crc32c,
+            2 => ChecksumAlgorithm::Xxh3,
+            3 => ChecksumAlgorithm::Blake3,
+            _ => return None,
+        })
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "crc32",
+            ChecksumAlgorithm::Crc32c => "crc32c",
+            ChecksumAlgorithm::Xxh3 => "xxh3",
+            ChecksumAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Hashes `data`, integers are stored big endian so the digest prints like the usual hex form.
+    pub fn digest(self, data: &[u8]) -> Checksum {
+        let digest = match self {
+            ChecksumAlgorithm::Crc32 => crc32fast::hash(data).to_be_bytes().to_vec(),
+            ChecksumAlgorithm::Crc32c => crc32c::crc32c(data).to_be_bytes().to_vec(),
+            ChecksumAlgorithm::Xxh3 => xxh3_64(data).to_be_bytes().to_vec(),
+            ChecksumAlgorithm::Blake3 => blake3::hash(data).as_bytes().to_vec(),
+        };
+        Checksum { algorithm: self, digest }
+    }
+
+    /// A hasher that takes the data in pieces and ends up with the same digest as [`ChecksumAlgorithm::digest`].
+    pub fn hasher(self) -> DigestHasher {
+        DigestHasher(match self {
+            ChecksumAlgorithm::Crc32 => HasherState::Crc32(Hasher::new()),
+            ChecksumAlgorithm::Crc32c => HasherState::Crc32c(0),
+            ChecksumAlgorithm::Xxh3 => HasherState::Xxh3(Box::default()),
+            ChecksumAlgorithm::Blake3 => HasherState::Blake3(Box::default()),
+        })
+    }
+}
+
+/// Computes a [`Checksum`] of data that is decoded a block at a time, without keeping all of it around.
+pub struct DigestHasher(HasherState);
+
+enum HasherState {
+    Crc32(Hasher),
+    Crc32c(u32),
+    Xxh3(Box<Xxh3>),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl DigestHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.0 {
+            HasherState::Crc32(hasher) => hasher.update(data),
+            HasherState::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+            HasherState::Xxh3(hasher) => hasher.update(data),
+            HasherState::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    pub fn finish(self) -> Checksum {
+        let (algorithm, digest) = match self.0 {
+            HasherState::Crc32(hasher) => (ChecksumAlgorithm::Crc32, hasher.finalize().to_be_bytes().to_vec()),
+            HasherState::Crc32c(crc) => (ChecksumAlgorithm::Crc32c, crc.to_be_bytes().to_vec()),
+            HasherState::Xxh3(hasher) => (ChecksumAlgorithm::Xxh3, hasher.digest().to_be_bytes().to_vec()),
+            HasherState::Blake3(hasher) => (ChecksumAlgorithm::Blake3, hasher.finalize().as_bytes().to_vec()),
+        };
+        Checksum { algorithm, digest }
+    }
+}
+
+/// A digest together with the algorithm that produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+/// Formats the checksum as `algorithm hexdigest`, e.g. `crc32 904ecd7d`.
+impl Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.algorithm.name())?;
+        for byte in &self.digest {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
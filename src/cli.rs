@@ -8,18 +8,104 @@
 //! whatever the user renamed the file to. `<description>` denotes a required argument, while
 //! `[description]` denotes an optional argument.
 //!
-//! > `$exename enc <path to file or folder> <output path>
+//! > `$exename enc <path to file or folder> [output path]
 //! >   [--using <pipeline name>]
 //! >   [--from_file <path to pipeline file>]
 //! >   [--embed_to_file]
 //! >   [--preset <preset id>]
 //! >   [--raw]`
 //!
+//! without an output path, `enc bigfile.bin` writes `bigfile.bin.stpk` next to the input. when the output path is an
+//! existing directory, the artifact gets the same name inside it.
+//!
+//! `enc a.txt b.txt c.txt -o out/` encodes several inputs with the same pipeline, which is built and checked once.
+//! each input gets its own artifact inside `out/`, which is created if needed, or with `--archive` they all go into
+//! one directory archive at the `-o` path, stored under their own names. without `-o`, a second path is the output of
+//! the first as above, so several inputs always need it.
+//!
+//! the global `--verbose` (`-v`) and `--quiet` (`-q`) flags set how much `enc`, `dec`, `test` and `corpus` report
+//! on stderr in any build, from only failures up to the sizes and timing of every stage (see
+//! [`crate::verbosity`]).
+//!
+//! `enc`, `dec`, `test` and `corpus` also take `--format json`, which prints their results as a json document on
+//! stdout: sizes, ratios, timings, per-stage stats and whether each run passed, for scripts and CI (see
+//! [`summary`]).
+//!
+//! `enc --stdout-stats` prints a single line to stdout once it succeeds, e.g. `input_bytes=4096 output_bytes=1210
+//! ratio=0.2954 pipeline_fingerprint=5d1c3a7e elapsed_ms=3.4`, so shell scripts can pick the results up without
+//! parsing the reports on stderr.
+//!
+//! `enc`, `dec` and `test` take `--stats` to print a table of what every stage did once they are done: its input and
+//! output size, ratio, time and throughput, summed over all inputs, blocks and frames. it goes to stderr, or into the
+//! `--format json` document as `stats` (see [`stats`]).
+//!
+//! the global `--threads <N>` option limits the threads stages use, e.g. for the suffix sorting of `bwt`. by default
+//! they use what the machine can run in parallel (see [`crate::algorithms::heuristics`]).
+//!
+//! `STACKPACK_PIPELINE`, `STACKPACK_PRESET`, `STACKPACK_THREADS` and `STACKPACK_PLUGIN_DIRS` set what a command
+//! falls back on when the matching flag isn't given, ahead of the user config (see [`crate::settings`]), e.g. to
+//! run a whole script with one pipeline.
+//!
+//! `enc --dry-run` resolves the pipeline and checks every stage of it, then encodes the first MiB of every input to
+//! estimate the size of the output and how long encoding takes, and prints that along with where the output would
+//! go, without writing anything. it is worth a look before a long run on a huge file.
+//!
+//! before encoding a file, `enc` estimates the peak memory of the pipeline from the input size and what every stage
+//! needs per byte of it, and warns when that is more than the machine has available, as such a run tends to die in
+//! its last stage after hours of work. `--strict-memory` refuses to start instead, and `--dry-run` prints the
+//! estimate. `--block-size` keeps the estimate down for the stages that aren't block based already.
+//!
+//! the global `--events ndjson` reports every stage, block, archive frame and extracted file as it goes, along with
+//! the warnings and errors, as one json object per line for GUIs and wrappers to draw their own progress from. they
+//! go to stderr, or to the file descriptor given with `--events-fd` (see [`crate::events`]).
+//!
+//! `--max-stage-time <duration>` gives every stage of `enc` and `dec` a wall-clock budget, and `--max-time
+//! <duration>` the whole run, e.g. for a stage that takes forever on an input it wasn't made for. a stage over
+//! budget fails the run with an error naming it, and `dec --try-brute` stops searching once the run is out of time.
+//! stages are run on a thread of their own to be timed, so they are only timed when asked for.
+//!
+//! `--dump-stages <dir>` makes `enc` and `dec` write the output of every stage to `dir`, `00-bwt.bin`, `01-mtf.bin`
+//! and so on when encoding, and `01-mtf.rev.bin`, `00-bwt.rev.bin` and so on when decoding, so a stage that corrupts
+//! the data shows up as the first `.rev.bin` that doesn't match the encoder's file before it. block mode and archives
+//! run the pipeline once per block or frame, and every run after the first adds its number, e.g. `00-bwt.3.bin`
+//! (see [`crate::algorithms::pipeline::CompressionPipeline::set_dump_dir`]).
+//!
+//! `enc --skip-failing-stages` keeps a stage that fails on a block, e.g. `img_decode` on a file of an archive that
+//! isn't an image, from failing the whole run: the block goes on through the rest of the pipeline as it was, flagged
+//! so `dec` skips the stage for it too. it sets `on_error=skip` on every stage, which can also be given per stage
+//! (see [`crate::algorithms::passthrough`]).
+//!
+//! Ctrl-C stops a command between two stages, blocks or archive frames and removes the outputs it was writing, then
+//! exits with code 130. outputs already written are kept, and a second Ctrl-C exits right away (see
+//! [`crate::interrupt`]).
+//!
+//! when `enc` or `dec` fail, the exit code says why: 2 for flags that don't fit the input, 3 for a file that can't be
+//! read or written, 4 for a broken pipeline, 5 for corrupt or foreign data and 6 for a stage that fails on its input.
+//! the full table is in [`error`].
+//!
+//! directory inputs can be narrowed down with `--include <glob>` and `--exclude <glob>`, e.g.
+//! `enc project/ --exclude target --exclude .git` archives a source tree without its build output. `test` and
+//! `corpus` take the same options for the files they run (see [`crate::walk`]).
+//!
 //! the first option passes the pipeline as a cli flag with custom parsing. this comes with two caveats:
 //!     1. the decompressor must either remember the pipeline or manually store it elsewhere
 //!     2. the decompressor has no way of inferring the pipeline used to encode the file, and thus cannot decompress it
 //! > `$exename enc <path> <output path> --using "bwt -> rle -> arcode"`
 //!
+//! stages that take parameters accept them in parentheses, e.g. `--using "bsc(block=64m, width=16)"`.
+//! `--using "bwt(block=16MiB) -> arcode(precision=32)"` sets the block size of the bwt and the precision of the
+//! arithmetic coder.
+//! parameters are stored along with the pipeline wherever it is persisted. each stage declares the parameters it takes
+//! along with their type and range, and a pipeline with an unknown or out of range parameter is refused when it is
+//! parsed, suggesting the closest parameter name for typos.
+//! stages can also be named by one of their aliases, e.g. `move-to-front` for `mtf`, and are stored under their own
+//! name. `pipeline list-compressors --detailed` shows the aliases, category and parameters of every stage, and
+//! `--json` (or `--format json`) prints the same for other tools (see [`crate::registered::stage_descriptors`]).
+//!
+//! every stage also takes `skip_head=<size>` and `skip_tail=<size>`, which pass that many bytes at the start or end
+//! of its input through as they are, e.g. `bwt(skip_head=44)` to keep a WAV header readable (see
+//! [`crate::algorithms::passthrough`]).
+//!
 //! the next option reads the pipeline from a file, which allows the user to remember what pipeline was used to compress a file
 //! in a readable format, allows for fine-grained experimenting with the pipeline for optimal compression,
 //! and allows for sharing the pipeline with other users.
@@ -28,6 +114,24 @@
 //! another option is to use preset pipelines which can be invoked with a much shorter command.
 //! > `$exename enc <path> <output path> --preset o1`
 //!
+//! the `lz4` preset (and stage) gives up ratio for speed, and writes a standard `.lz4` frame when used with `--raw`.
+//!
+//! the `bzip2` preset runs the stages of bzip2, `rle1 -> bwt(block=900000) -> mtf -> arcode`. `rle1` is bzip2's
+//! initial run length encoding, which caps runs of equal bytes so they can't send the bwt's sort into its worst case
+//! (see [`crate::algorithms::rle1`]). the output is a stackpack file like any other, not one `bzip2` can read.
+//!
+//! `--level 1..9` (`-l`) picks a pipeline gzip style, trading speed for ratio: 1 is plain lz4, 4 the default bwt
+//! pipeline and 9 bsc with the largest blocks memory allows (see [`crate::algorithms::pipeline::LEVELS`]).
+//!
+//! the `util` stages `identity`, `reverse` and `xor(key=<0..255>)` don't compress anything. `--using identity`
+//! measures what the pipeline itself costs, and the other two are easy to check transforms for testing stage
+//! ordering, plugins and brute-force search (see [`crate::algorithms::util`]).
+//!
+//! stages loaded from plugins (with `--unsafe`) are named `<plugin>.<stage>`, e.g. `fast.rle` for the `rle` stage of
+//! `libfast.so`, and can be called plain `rle` as long as no built-in stage and no other plugin goes by that name. a
+//! plugin can't take over the name of a built-in stage, and `pipeline list-plugins` shows which names each one got
+//! (see [`crate::registered`]).
+//!
 //! another option is to use a default pipeline, which will be stabilized at some point and used if no other options are provided.
 //! > `$exename enc <path> <output path>`
 //!
@@ -35,18 +139,116 @@
 //! the first option uses a file format dedicated for this use case and embeds the pipeline information in the file itself.
 //! this makes it much easier for the decompressor to decompress the resulting file, at the expense of being unreadable by the user,
 //! and not allowing the raw bytes of the file to be passed into the decompressor.
+//! the embedded format is the `.stpk` container described in [`crate::container`]: magic bytes, a format version,
+//! extensible header fields holding the pipeline and metadata, and finally the payload. `dec` detects it and reads the
+//! pipeline from it, and refuses files that need a newer stackpack with an error saying so.
+//! > `$exename enc <path> <output path> --embed_to_file`
+//!
+//! `dec --range <offset>..<len>` writes only `len` bytes starting at `offset`. for block mode containers only the
+//! blocks covering the range are decoded, which skips the check against the checksum of the whole original data.
+//!
+//! `--block-size <SIZE>` splits the input into blocks of that size which run through the pipeline independently,
+//! and stores an index of their offsets at the end of the container (see [`crate::blocks`]). this costs some
+//! compression ratio but lets single blocks be found and decoded on their own.
+//!
+//! `enc --record-host` records the stackpack version and cargo features, the OS and architecture, and the plugin
+//! stages the pipeline uses along with a hash of their libraries in the container, so `info` can tell what it takes to
+//! decode a file made on another machine. `info` finds the record even when the pipeline holds stages this build
+//! doesn't know (see [`crate::host`]). like `--histogram`, it is refused for a single file without `--embed_to_file`.
+//!
+//! `enc --histogram` records how often every byte value occurs in the input in the container, a few hundred bytes at
+//! most. a single file only gets a container with `--embed_to_file`, so without it the flag is refused. `info` then says how many distinct bytes the original data has and its entropy, and `analyze` lists its most
+//! common bytes, both without decoding anything (see [`crate::histogram`]).
+//!
+//! `enc --sign key.pem` signs the container header, which includes the checksum of the original data, and the payload
+//! with an ed25519 key. `dec --verify-sig pub.pem` refuses to decode anything that isn't signed by the matching key.
+//! keys are the PEM files `openssl genpkey -algorithm ed25519` and `openssl pkey -pubout` produce.
+//!
+//! the container also records the input's file name and modification time. when the output path given to `dec`
+//! is a directory, the file is written there under its original name, and `--restore-mtime` sets its modification
+//! time back to the recorded one.
+//!
+//! the container stores a checksum of the original data which `dec` verifies. `--checksum crc32|crc32c|xxh3|blake3`
+//! picks the hash, crc32 by default. blake3 is the choice when tampering matters more than speed.
+//!
 //! the second option does not store this information at all, which allows the user to use the raw bytes of the file as they see fit
 //! and to use the file with other programs, but the decompressor will be unable to decompress the file if the pipeline used to compress it is not remembered.
 //! > `$exename enc <path> <output path> --raw`
 //!
-//! the third option outputs a `{file stem}.pipeline.json` file along with the compressed file,
-//! which contains the pipeline in json format.
+//! since raw outputs are easy to lose track of, `enc --raw` prints a warning, and `dec` refuses inputs that have neither
+//! an embedded pipeline nor a sidecar unless the pipeline is given explicitly.
+//!
+//! `convert <raw file> <output> --using <pipeline>` turns such an output into a container after the fact. the payload
+//! is copied as it is, not recompressed. it is decoded once to check that the pipeline is the right one and to record
+//! the checksum and size of the original data. sidecar-mode outputs convert without `--using`, their sidecar is read.
+//!
+//! the third option, used when neither of the above is requested, outputs a `{file stem}.pipeline.json` file along with
+//! the compressed file, which contains the pipeline in json format. when `dec` is not given a pipeline, it looks for
+//! this sidecar next to its input. the sidecar can also be passed explicitly with `--from_file`. with `--sidecar-stats`
+//! the sidecar also records the ratio, total time and per-stage sizes and times of the encode that wrote it, so a
+//! directory of outputs doubles as a record of the experiments that made them (see [`crate::sidecar`]).
+//!
+//! when the input is a directory, `enc` archives every file and directory below it into a container, whatever
+//! persistence mode was asked for, and `dec` recreates the tree inside its output directory (see [`crate::archive`]).
+//! files smaller than `--small-file-threshold` (64KiB by default) are packed into shared frames with their offsets
+//! recorded in the member table, so that tiny files don't each pay for their own pipeline run and framing.
+//! `dec <archive> --to-tar -` writes the tree as a tar stream to stdout instead, e.g. `| tar -x -C out/` or into
+//! any other tool that reads tar, and `--to-tar <path>` writes it to a `.tar` file.
+//!
+//! in tracing builds every block, archive frame and archive member is processed inside a `block`, `frame` or
+//! `member` span carrying its id, so their logs can be told apart. `STACKPACK_SPAN_EVENTS=close` additionally logs
+//! each span's busy and idle time when it closes, which is enough to see where an encode spends its time.
+//!
+//! when writing to network mounts or unreliable disks, `--durability flush` flushes userspace buffers and
+//! `--durability fsync` writes to a temporary file, fsyncs it, renames it over the output and fsyncs the parent
+//! directory before reporting success. the default, `none`, leaves this to the operating system.
+//!
+//! `--split-size <SIZE>` writes the output as `{output}.001`, `{output}.002`, ... volumes of at most that size,
+//! for media or services with a file size limit (see [`crate::volumes`]). `dec` and the other commands reading
+//! artifacts accept either a volume or the original output name and read all volumes in order.
+//!
+//! `enc --delta-against old.bin` encodes the input as copies out of `old.bin` plus whatever is new, which makes
+//! updates of large files about as small as their changes (see [`crate::delta`]). the container records the size
+//! and blake3 hash of the reference, and `dec --delta-against old.bin` refuses any other file as the base. such
+//! containers need `--embed_to_file`. `--delta-format vcdiff` writes a standard VCDIFF patch instead, which
+//! `xdelta3 -d -s old.bin patch new.bin` and other third-party tools can apply. VCDIFF has no room for a pipeline,
+//! so the patch isn't compressed any further.
+//!
+//! `enc --append <path to file or folder> <existing archive>` adds the input to an existing directory archive under
+//! its own name. the frames already in the archive are kept as they are and only the new members are encoded, with
+//! the archive's own pipeline. the existing frames are still decoded once, to check them and to recompute the
+//! checksum, which covers all of them. a signed archive has to be signed again with `--sign` or loses its signature.
+//!
+//! `-` as the input or output of `enc` and `dec` reads from stdin or writes to stdout, as in
+//! `stackpack enc - - --using "bwt -> mtf -> arcode" < input > output.stpk`. logs go to stderr then. `enc` embeds
+//! the pipeline when writing to stdout unless `--raw` is given, since a sidecar would have nowhere to go, and
+//! `--split-size`, `--append` and directory archives need real paths.
+//!
+//! `enc --progress` and `dec --progress` report on stderr how far along the pipeline is, which stage and block it is
+//! on, the throughput so far and the time left (see [`crate::progress`]). stages report when they finish, so large
+//! inputs get finer reports when they are split with `--block-size`.
+//!
+//! `enc`, `dec`, `convert` and `extract` refuse to overwrite files that already exist. `--force` overwrites them,
+//! `--keep` leaves them alone and skips the work for them instead, so an interrupted batch over many files can be run
+//! again and only does what's missing. sidecars follow their output, and `--append` always rewrites its archive.
+//!
+//! `--limit-rate <RATE>` throttles file reads and writes (in `enc`, `dec`, `test` and `corpus`) so large jobs on
+//! shared disks don't saturate them. the rate accepts size suffixes such as `KiB`, `MiB` or `MB`.
 //!
 //! > `$exename dec <path to file or folder> <output path>
 //! >   [--using <pipeline name>]
 //! >   [--from_file <path to pipeline file>]
 //! >   [--preset <preset id>]
-//! >   [--try-brute <depth>]`
+//! >   [--try-brute <depth> [--brute-state <path>]]
+//! >   [--head <N>]
+//! >   [--checksum-only]
+//! >   [--max-output-size <SIZE>]`
+//!
+//! `dec --max-output-size <SIZE>` guards against decompression bombs, inputs crafted (or corrupted) so that a stage
+//! like rle or arcode expands them without bound. `rle1`, `arcode` and `lz4` check the limit as they decode and stop
+//! as soon as they cross it, so a bomb never gets to build its whole output. the output of every other stage is checked
+//! as soon as the stage finishes, as is the running total of block mode and archive outputs, and decoding aborts with
+//! an error naming the stage that went over. containers that record a larger original size are refused up front.
 //!
 //! another option is to have a compressor repository. this repository has a `stackpack-config.json` file
 //! that allows the decompressor to look up the pipeline used to compress the file based on the directory the file is in.
@@ -57,7 +259,11 @@
 //!
 //! # Decompression
 //!
-//! > `$exename dec <path to file> <output path> [--from_file <path to pipeline file>]`
+//! > `$exename dec <path to file> [output path] [--from_file <path to pipeline file>]`
+//!
+//! without an output path, `dec bigfile.bin.stpk` writes `bigfile.bin`, inputs not ending in `.stpk` need one. when
+//! the output path is an existing directory, the output is named after the file name the container recorded, or
+//! after the input without `.stpk`.
 //!
 //! the decompressor first needs to know which pipeline was used to compress the file. there are four cases:
 //!     1. the pipeline is stored in the file using the designated format (default case).
@@ -65,12 +271,57 @@
 //!     3. the pipeline is stored in a file, which will be used.
 //!     4. the pipeline is not known, and the decompressor will fail to decompress the file.
 //!
-//! for the first case, the file format is parsed and the pipeline is extracted.
+//! for the first case, the file format is parsed and the pipeline is extracted. the input is sniffed for the container
+//! magic before anything else, and the embedded pipeline is used even when one is given on the cli as well.
 //! for the second case, the pipeline is parsed from the cli argument as a string.
 //! for the third case, the pipeline is read from the file in json format.
-//! for the fourth case, if the `--try-brute N` flag is specified, the `format_validity_check` method of every available compressor is used
-//! to recursively attempt decompression of the file up to the specified depth. the depth must be specified because some compressors do not fail for any input,
-//! potentially causing infinite decompression. this should be a last resort option and avoided if possible.
+//! for the fourth case, if the `--try-brute N` flag is specified, every available compressor is reverted on the file
+//! recursively up to the specified depth, and the output that looks most like decoded data wins (see [`brute`]).
+//! the depth must be specified because some compressors do not fail for any input, potentially causing infinite
+//! decompression. this should be a last resort option and avoided if possible. `--brute-state <path>` saves the
+//! search as it goes, so that an interrupted deep search resumes instead of starting over.
+//!
+//! before giving up on an input without a pipeline, `dec` checks whether it is a gzip, bzip2, xz or zstd file
+//! (see [`crate::formats`]) and decodes it with the stage of that name if one is registered. there are no such
+//! built-in stages yet, so unless a plugin provides one the error names the format and the tool to use instead.
+//!
+//! if the file uses the designated format and a pipeline is passed on the cli as well, the two may disagree.
+//! decoding with the wrong pipeline silently produces garbage, so `dec` compares the fingerprint of the cli pipeline
+//! (see [`crate::algorithms::pipeline::CompressionPipeline::fingerprint`]) against the embedded one and refuses on
+//! mismatch. `--force-pipeline` decodes with the cli pipeline anyway, e.g. to recover a container whose header names
+//! the wrong stages.
+//!
+//! if only a preview of the original data is needed, `--head N` writes just the first `N` decompressed bytes
+//! to the output path. it is the same as `--range 0..N`, so block mode containers only decode their leading blocks
+//! and skip the checksum check, while other inputs are still decoded whole and cut.
+//!
+//! `--checksum-only` decodes the input into a hasher instead of the output path and prints the crc32 and size
+//! of the decompressed data. this allows validating archives without needing room for a second copy on disk,
+//! and the output path may be omitted. the blocks of a block mode container and every concatenated stream are hashed
+//! and dropped as they are decoded, so only one of them is held in memory at a time.
+//!
+//! containers can be concatenated like gzip members, so logs can be archived by appending to one file, e.g.
+//! `enc today.log -o - --embed_to_file >> logs.stpk`. `dec` decodes every stream of such a file in turn, each with
+//! its own pipeline and checks, into one output. directory archives and deltas can't be concatenated, and only
+//! containers written by a build that records their payload length can be followed by others (see
+//! [`crate::container`]).
+//!
+//! > `$exename extract <path to archive> <entry path> [-o <output path>]`
+//!
+//! pulls a single file, or a directory with everything below it, out of a directory archive. only the frames
+//! holding the requested members are decoded, which means the checksum of the whole archive can't be checked.
+//! without `-o` the entry is written under its own name in the current directory, and when the output is an
+//! existing directory a file entry is written inside it.
+//!
+//! `dec` and `extract` refuse archive members whose paths would land outside the output directory: absolute paths,
+//! drive prefixes such as `C:`, `..` parts, and paths leading through symlinks that point out of it. the paths in the
+//! table are all checked before anything is written, symlinks as every member is written. `--unsafe-paths` extracts
+//! such members where their paths point, for trusted archives only (see [`crate::archive`]).
+//!
+//! > `$exename cat <path to archive> <entry path>`
+//!
+//! decodes a single file of a directory archive, again only its frame, and writes it to stdout so it can be piped
+//! into other tools, e.g. `$exename cat logs.stpk app.log | grep ERROR`.
 //!
 //! # Testing
 //!
@@ -81,7 +332,100 @@
 //!
 //! the testing mode is used to verify that the pipelines produce the same output as the original file. the pipeline input is handled in the same manner as in the other modes.
 //! the program compresses the file using the pipeline, then immediately decompresses the output and compares the original file with the roundtripped file.
-//! if a discrepancy is found, the compressed and decompressed data are written to the output path.
+//! with `--report`, a failure writes a crash report, a zip of the pipeline, the versions and a stage by stage account
+//! of the round trip, to `<input name>.stackpack-report.zip`. `--report-data` also puts the data of every stage in
+//! it. `dec` and `corpus` take the same options (see [`report`]).
+//!
+//! > `$exename corpus --shuffle [--duration <duration>] [--seed <seed>]`
+//!
+//! soak tests the stage registry: round trips random files of `./test_data` through random pipelines of one to
+//! three registered stages, or the pipeline given, until `--duration` (e.g. `90s`, `10m`, `2h`) runs out or the
+//! process is interrupted. every failure is logged with the seed it was sampled with, and `--seed <seed>` samples
+//! that same file and pipeline first to replay it, on its own with `--duration 0`.
+//!
+//! > `$exename selftest [--cross-feature <path to another stackpack>]`
+//!
+//! round trips every registered stage and preset over a few generated samples and prints the size and crc32 of
+//! each encoded output. `--cross-feature` runs the same cases with another stackpack binary, typically one built
+//! with `--no-default-features`, and fails on any case whose encoded bytes differ, since the tracing and
+//! non-tracing builds dispatch through separate code paths that are supposed to produce identical output.
+//!
+//! > `$exename selftest --perf [--perf-tolerance <FACTOR>]`
+//!
+//! times every built-in stage on one thread over a generated sample and compares its encoding and decoding
+//! throughput with reference numbers recorded from a release build. a stage more than `--perf-tolerance` (10 by
+//! default) times slower than its reference fails the selftest, which is meant to catch a stage going quadratic in
+//! CI, not small slowdowns. debug builds print the numbers without checking them.
+//!
+//! > `$exename calibrate [--sample-size <SIZE>] [--no-save]`
+//!
+//! times `bwt`, `bsc` and `bwt -> mtf -> arcode` over a generated sample with different thread counts and block
+//! sizes, and saves the thread count and the block thresholds worth using on this machine to the user config, in
+//! place of the built-in defaults (see [`calibrate`]).
+//!
+//! > `$exename portable-decoder <path to file> <path to decoder.rs>`
+//!
+//! writes a single rust source file that decodes files made with the pipeline of the given one, containers, block
+//! mode and archives included, and needs nothing but `rustc decoder.rs` to build, for archives that have to stay
+//! readable long after this build is gone. raw payloads without a sidecar take the pipeline from `--using` and the
+//! like. only `bwt`, `mtf`, `arcode` and the `util` stages can be written out this way (see [`portable`]).
+//!
+//! > `$exename sanitize <path to file> <output path> [--using | --from_file | --preset] [--max-runs <N>] [--no-anonymize]`
+//!
+//! takes an input whose round trip through the pipeline fails and reduces it to a small one that fails the same way,
+//! cutting out what the failure doesn't need and zeroing the bytes it doesn't depend on, so bug reports can come
+//! with a reproduction that doesn't leak the original data (see [`sanitize`]).
+//!
+//! > `$exename heatmap <path to file> [--window <SIZE>] [--format sparkline|csv] [--using | --from_file | --preset]`
+//!
+//! compresses the file in windows of `--window` bytes (64KiB by default), each on its own, and reports the ratio of
+//! every window, either as a terminal sparkline where taller bars compress worse, or as csv for plotting. regions
+//! that don't shrink are worth routing around expensive stages.
+//!
+//! > `$exename analyze <path to file> [--top <N>]`
+//!
+//! describes the file before a pipeline is picked for it: what kind of file it looks like, its most common bytes,
+//! its order-0 and order-1 entropy and how much of it is runs, and suggests a pipeline that suits data like it, e.g.
+//! `bsc` or `bwt -> mtf -> arcode`, with the reason (see [`analyze`]).
+//!
+//! # Searching
+//!
+//! > `$exename grep <pattern> <path to file>... [--using | --from_file | --preset] [-i]`
+//!
+//! each input is decompressed in memory and its lines are matched against the regular expression. matches are
+//! printed as `{input}:{line number}:{line}` without writing any decompressed data to disk. the pipeline is picked
+//! as `dec` picks it: the one a container embeds, else the sidecar, else the one given on the cli. every file of a
+//! directory archive is searched on its own, a frame at a time, and its matches are printed as
+//! `{archive}:{member path}:{line number}:{line}`.
+//!
+//! # Inspecting Artifacts
+//!
+//! > `$exename info <path to file>`
+//!
+//! prints what a compressed artifact says about itself without decompressing the payload: the container version,
+//! the pipeline and its stages, the original and compressed sizes and the stored checksum. artifacts without a
+//! container are described from their sidecar, if one exists. for a directory archive it also says what the files hold,
+//! e.g. `composition: 60.2% text (12 files), 30.1% executable (2 files)`, from the content type `enc` detected for every
+//! file (see [`crate::content`]).
+//!
+//! > `$exename verify <path to file> [--members]`
+//!
+//! decodes the artifact in memory and checks every checksum along the way: the per-chunk checksums of stages
+//! that have them and the container's checksum of the original data. the damaged stage, and block if the stage
+//! reports one, is printed. the exit code is 0 when the artifact is intact, 1 when it can't be read, 2 when the
+//! container header is damaged and 3 when the payload is damaged. every stream of a file of concatenated containers
+//! is verified, and the first damaged one is named.
+//!
+//! `--members` checks every file of a directory archive against its own crc32 instead, a frame at a time, and lists
+//! every damaged member, so the intact ones can still be extracted with confidence. members appended by a build that
+//! didn't record their crc32 are only checked to decode.
+//!
+//! > `$exename diff <path to file> <path to file> [--context <BYTES>]`
+//!
+//! compares two files byte by byte, typically the original and the output of a round trip that went wrong, or two
+//! files of `--dump-stages`. it prints the first offset they differ at, a hex dump of both around it with the
+//! differing bytes marked, and how many bytes differ in how many runs, and exits with 1 when they differ like `cmp`
+//! (see [`diff`]).
 //!
 //! # Pipeline Management
 //!
@@ -97,22 +441,101 @@
 //! if the `--detailed` flag is passed, a description of what each algorithm is used for, its optimal usage scenarios,
 //! and a short description of its internals is printed.
 //!
+//! > `$exename pipeline save <name> <pipeline string>`
+//!
+//! this command stores a named pipeline in the user config (`config.json` in the stackpack config directory) so it
+//! can be used with `--preset <name>` in every command. built-in presets take precedence and can't be overwritten.
+//!
+//! > `$exename pipeline list [--saved]`
+//!
+//! this command lists the built-in presets, or the saved pipelines and their pipeline strings with `--saved`.
+//!
+//! > `$exename pipeline export-graph [--using | --from_file | --preset] [--format dot|mermaid] [--output <path>]`
+//!
+//! this command renders the resolved pipeline as a Graphviz DOT or Mermaid flowchart, so complex pipelines
+//! can be documented and reviewed visually. the graph is printed to stdout unless an output path is given.
+//!
+//! > `$exename pipeline lint [--using | --from_file | --preset]`
+//!
+//! this command warns about stage orderings that are known to compress badly, such as an entropy coder before the
+//! bwt, two entropy coders in a row, mtf without a bwt before it, or any transform after the entropy coder, and explains
+//! each warning. it exits with a non-zero status if there are warnings. `enc --strict` runs the same checks and
+//! refuses to encode with a pipeline that has warnings.
+//!
+//! > `$exename pipeline validate [--using | --from_file | --preset]`
+//!
+//! this command checks that every stage of a pipeline exists, plugin stages included when `--unsafe` loads them, and
+//! that its parameters are known and in range, without running it, e.g. before starting an encode that takes hours.
+//! unlike the other commands, which stop at the first broken stage, it reports every one of them with what is wrong,
+//! and exits with code 4 if there are any. a pipeline without problems is printed followed by `valid`.
+//!
+//! > `$exename pipeline explain [--using | --from_file | --preset]`
+//!
+//! this command describes every stage of a pipeline in the order it encodes: what it does, what it does to the
+//! entropy of the data, whether it grows or shrinks it, how decoding undoes it, and the parameters it runs with,
+//! defaults included. the text comes from the description and explanation every built-in stage is registered with
+//! (see [`crate::registered::StageExplanation`]). plugin stages report neither, so they are only named.
+//!
 //! > `$exename pipeline save-to-file <pipeline string> <output path>`
 //!
-//! this command converts a pipeline string into json format and saves it to the specified file.
+//! this command converts a pipeline string into json format and saves it to the specified file, for `--from_file`.
+//! the file holds a format `version` and the `stages`, each with its `name` and `params`, e.g.
+//! `{"version": 1, "stages": [{"name": "bwt", "params": {"block": "16MiB"}}, {"name": "arcode"}]}`. files of a
+//! newer version are refused, and files from before the format had a version, which list the stages as
+//! `"bwt(block=16MiB)"` strings, are still read (see [`crate::algorithms::pipeline::PIPELINE_FORMAT_VERSION`]).
 //! the pipeline string is of the form:
 //!     "pipeline_name1 -> pipeline_name2 -> ... -> pipeline_nameN"
 //! the order of pipelines is specified in encoding order, meaning that when encoding, "pipeline_name1" is applied first,
 //! followed by "pipeline_name2", and so on.
+pub mod analyze;
+pub mod brute;
+pub mod calibrate;
+pub mod cat;
+pub mod convert;
 pub mod corpus;
 pub mod decode;
+pub mod diff;
 pub mod encode;
+pub mod error;
+pub mod extract;
+pub mod grep;
+pub mod heatmap;
+pub mod info;
 pub mod pipeline;
+pub mod portable;
+pub mod report;
+pub mod sanitize;
+pub mod selftest;
+pub mod stats;
+pub mod summary;
 pub mod test;
+pub mod verify;
 
-use std::path::PathBuf;
+use std::{
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::{
+    algorithms::pipeline::TimeLimits,
+    blocks::ByteRange,
+    checksum::ChecksumAlgorithm,
+    cli::error::{CliError, CliResult, ErrorClass},
+    container::FILE_EXTENSION,
+    delta::DeltaFormat,
+    events::{self, EventFormat},
+    io::{Durability, IoPolicy, Overwrite, is_stdio},
+    settings,
+    units::{parse_byte_size, parse_duration},
+    verbosity::Verbosity,
+    volumes::volume_base,
+    walk::PathFilter,
+};
 
 #[derive(Debug, Parser)]
 #[command(
@@ -126,10 +549,54 @@ use clap::{Args, Parser, Subcommand};
 pub struct Cli {
     #[arg(long = "unsafe", global = true, help = "Enable things which can't be checked for safety (plugins)")]
     pub unsafe_mode: bool,
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        help = "Threads stages may use, defaults to what the machine can run in parallel."
+    )]
+    pub threads: Option<NonZeroUsize>,
+    #[arg(
+        long,
+        short = 'v',
+        global = true,
+        conflicts_with = "quiet",
+        help = "Report every encode, decode and stage with its sizes and timing on stderr."
+    )]
+    pub verbose: bool,
+    #[arg(long, short = 'q', global = true, help = "Only report warnings, errors and failures on stderr.")]
+    pub quiet: bool,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "FORMAT",
+        help = "Also report stages, blocks, extracted files, warnings and errors as machine readable events."
+    )]
+    pub events: Option<EventFormat>,
+    #[arg(
+        long = "events-fd",
+        global = true,
+        value_name = "FD",
+        default_value_t = 2,
+        requires = "events",
+        help = "File descriptor the events are written to, e.g. 3 with 3>events.ndjson. defaults to stderr."
+    )]
+    pub events_fd: i32,
     #[command(subcommand)]
     pub command: Command,
 }
 
+impl Cli {
+    pub fn verbosity(&self) -> Verbosity {
+        match (self.quiet, self.verbose) {
+            (true, _) => Verbosity::Quiet,
+            (_, true) => Verbosity::Verbose,
+            _ => Verbosity::Normal,
+        }
+    }
+}
+
 /// Supported stackpack subcommands.
 #[derive(Debug, Subcommand)]
 pub enum Command {
@@ -137,12 +604,66 @@ pub enum Command {
     Encode(EncodeArgs),
     #[command(name = "dec", aliases = ["d", "decode", "decompress", "u", "uncompress", "unpack"], about = "Decompress data produced by stackpack.")]
     Decode(DecodeArgs),
+    #[command(name = "convert", about = "Wrap a --raw output into a container without recompressing it.")]
+    Convert(ConvertArgs),
     #[command(name = "test", about = "Round-trip pipelines against input data.")]
     Test(TestArgs),
     #[command(name = "pipeline", about = "Inspect or manage available pipelines.", subcommand)]
     Pipeline(PipelineCommand),
     #[command(name = "corpus", about = "Run corpus compression benchmarks.")]
     Corpus(CorpusArgs),
+    #[command(name = "grep", about = "Search decompressed data for a regular expression.")]
+    Grep(GrepArgs),
+    #[command(name = "heatmap", about = "Show how well each region of a file compresses.")]
+    Heatmap(HeatmapArgs),
+    #[command(name = "analyze", about = "Describe the bytes of a file and suggest a pipeline for it.")]
+    Analyze(AnalyzeArgs),
+    #[command(name = "info", about = "Describe a compressed artifact without decompressing it.")]
+    Info(InfoArgs),
+    #[command(name = "verify", about = "Check the integrity of a compressed artifact without writing it out.")]
+    Verify(VerifyArgs),
+    #[command(name = "diff", about = "Show where and how much two files differ, e.g. the two sides of a failed round trip.")]
+    Diff(DiffArgs),
+    #[command(name = "cat", about = "Write a single archive entry to stdout.")]
+    Cat(CatArgs),
+    #[command(name = "extract", aliases = ["x"], about = "Extract a single entry from a directory archive.")]
+    Extract(ExtractArgs),
+    #[command(name = "selftest", about = "Round-trip built-in pipelines over generated samples.")]
+    Selftest(SelftestArgs),
+    #[command(name = "sanitize", about = "Shrink an input that breaks a pipeline into a shareable reproduction.")]
+    Sanitize(SanitizeArgs),
+    #[command(name = "calibrate", about = "Measure the thread count and block sizes worth using here.")]
+    Calibrate(CalibrateArgs),
+    #[command(
+        name = "portable-decoder",
+        about = "Write a dependency-free Rust source file that decodes files of one pipeline."
+    )]
+    PortableDecoder(PortableDecoderArgs),
+}
+
+impl Command {
+    /// Whether the command writes its data or a json document to stdout, where nothing else may be printed.
+    pub fn writes_to_stdout(&self) -> bool {
+        match self {
+            Command::Cat(_) => true,
+            Command::Encode(args) if args.format == OutputFormat::Json || args.stdout_stats => true,
+            // stdin is written back to stdout when no output is given
+            Command::Encode(args) => match args.given_paths() {
+                (_, Some(output)) => is_stdio(output),
+                (inputs, None) => inputs.iter().any(|input| is_stdio(input)),
+            },
+            Command::Decode(args) if args.format == OutputFormat::Json => true,
+            Command::Decode(args) => match (&args.to_tar, &args.output) {
+                (Some(tar), _) => is_stdio(tar),
+                (None, output) => output.as_deref().map_or(is_stdio(&args.input), is_stdio),
+            },
+            Command::Test(args) => args.format == OutputFormat::Json,
+            Command::Corpus(args) => args.format == OutputFormat::Json,
+            Command::Pipeline(PipelineCommand::ListCompressors { json, format, .. }) => *json || *format == OutputFormat::Json,
+            Command::PortableDecoder(args) => is_stdio(&args.output),
+            _ => false,
+        }
+    }
 }
 
 /// Common selectors for pipeline inputs.
@@ -151,28 +672,37 @@ pub struct PipelineSelector {
     #[arg(
 		long = "using",
 		value_name = "PIPELINE",
-		conflicts_with_all = ["from_file", "preset"],
-		help = "Inline pipeline description, e.g. \"bwt -> mtf -> arcode\"."
+		conflicts_with_all = ["from_file", "preset", "level"],
+		help = "Inline pipeline description, e.g. \"bwt -> mtf -> arcode\" or \"bsc(block=64m)\"."
 	)]
     pub inline: Option<String>,
     #[arg(
 		long = "from_file",
 		value_name = "PIPELINE_FILE",
-		conflicts_with_all = ["inline", "preset"],
+		conflicts_with_all = ["inline", "preset", "level"],
 		help = "Path to a JSON pipeline definition file."
 	)]
     pub from_file: Option<PathBuf>,
     #[arg(
 		long = "preset",
 		value_name = "PRESET",
-		conflicts_with_all = ["inline", "from_file"],
-		help = "Preset pipelines registered by stackpack."
+		conflicts_with_all = ["inline", "from_file", "level"],
+		help = "Preset pipelines registered by stackpack, or saved with `pipeline save`."
 	)]
     pub preset: Option<String>,
+    #[arg(
+		long = "level",
+		short = 'l',
+		value_name = "1..9",
+		value_parser = clap::value_parser!(u8).range(1..=9),
+		conflicts_with_all = ["inline", "from_file", "preset"],
+		help = "Pick a pipeline by speed and ratio like gzip -1..-9, 1 is the fastest, 9 compresses the most."
+	)]
+    pub level: Option<u8>,
 }
 
 impl PipelineSelector {
-    /// Resolve to a concrete pipeline selection, defaulting when no option is provided.
+    /// Resolve to a concrete pipeline selection, falling back on [`crate::settings`] when no option is provided.
     pub fn selection(&self) -> PipelineSelection {
         if let Some(inline) = &self.inline {
             PipelineSelection::Inline(inline.clone())
@@ -180,8 +710,10 @@ impl PipelineSelector {
             PipelineSelection::FromFile(path.clone())
         } else if let Some(preset) = &self.preset {
             PipelineSelection::Preset(preset.clone())
+        } else if let Some(level) = self.level {
+            PipelineSelection::Level(level)
         } else {
-            PipelineSelection::Default
+            settings::get().pipeline.clone().unwrap_or(PipelineSelection::Default)
         }
     }
 }
@@ -192,6 +724,7 @@ pub enum PipelineSelection {
     Inline(String),
     FromFile(PathBuf),
     Preset(String),
+    Level(u8),
     Default,
 }
 
@@ -210,6 +743,12 @@ pub struct PipelinePersistenceArgs {
         help = "Do not store pipeline metadata alongside the compressed output."
     )]
     pub raw: bool,
+    #[arg(
+        long = "sidecar-stats",
+        conflicts_with_all = ["embed_to_file", "raw"],
+        help = "Record the ratio, total time and the size and time of every stage in the sidecar as well."
+    )]
+    pub sidecar_stats: bool,
 }
 
 impl PipelinePersistenceArgs {
@@ -232,17 +771,276 @@ pub enum PipelinePersistence {
     Raw,
 }
 
+/// File I/O options shared by commands that read and write files.
+#[derive(Debug, Args, Clone, Copy, Default)]
+pub struct IoArgs {
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Durability::None,
+        help = "Whether outputs are flushed or fsynced before reporting success."
+    )]
+    pub durability: Durability,
+    #[arg(
+        long = "limit-rate",
+        value_name = "RATE",
+        value_parser = parse_rate,
+        help = "Limit file reads and writes to RATE bytes per second, e.g. 20MiB."
+    )]
+    pub limit_rate: Option<u64>,
+    #[arg(long, short = 'f', conflicts_with = "keep", help = "Overwrite outputs that already exist.")]
+    pub force: bool,
+    #[arg(
+        long,
+        help = "Leave outputs that already exist as they are and carry on, e.g. to finish an interrupted batch."
+    )]
+    pub keep: bool,
+}
+
+impl IoArgs {
+    pub fn policy(&self) -> IoPolicy {
+        IoPolicy {
+            durability: self.durability,
+            limit_rate: self.limit_rate,
+            overwrite: if self.force {
+                Overwrite::Force
+            } else if self.keep {
+                Overwrite::Keep
+            } else {
+                Overwrite::Refuse
+            },
+        }
+    }
+}
+
+/// Globs choosing what is used of directory inputs, see [`crate::walk`].
+#[derive(Debug, Args, Clone, Default)]
+pub struct FilterArgs {
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Only use the files of directory inputs matching GLOB, e.g. '*.rs'. can be given more than once."
+    )]
+    pub include: Vec<String>,
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Skip the files and directories of directory inputs matching GLOB, e.g. target or '*.bin'. can be given \
+                more than once."
+    )]
+    pub exclude: Vec<String>,
+}
+
+impl FilterArgs {
+    pub fn filter(&self) -> PathFilter {
+        match PathFilter::new(&self.include, &self.exclude) {
+            Ok(filter) => filter,
+            Err(e) => {
+                eprintln!("[error] stackpack: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Wall-clock budgets shared by `enc` and `dec`, see [`TimeLimits`].
+#[derive(Debug, Args, Clone, Copy, Default)]
+pub struct TimeLimitArgs {
+    #[arg(
+        long = "max-time",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "Give up once the whole run took longer than DURATION, e.g. 30m. a brute-force search stops too."
+    )]
+    pub max_time: Option<Duration>,
+    #[arg(
+        long = "max-stage-time",
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "Give up once a single stage ran longer than DURATION, e.g. 90s."
+    )]
+    pub max_stage_time: Option<Duration>,
+}
+
+impl TimeLimitArgs {
+    /// The limits of a run starting now.
+    pub fn start(&self) -> TimeLimits {
+        TimeLimits::starting_now(self.max_stage_time, self.max_time)
+    }
+}
+
+/// Crash report options shared by commands that round trip or decode data, see [`report`].
+#[derive(Debug, Args, Clone, Copy, Default)]
+pub struct ReportArgs {
+    #[arg(
+        long,
+        alias = "write_files_if_failed",
+        help = "Write a <input name>.stackpack-report.zip to the working directory when a round trip or decode fails."
+    )]
+    pub report: bool,
+    #[arg(
+        long = "report-data",
+        requires = "report",
+        help = "Include the data going into every stage in the report, cut to its first 64KiB. leave it out for \
+                private data."
+    )]
+    pub report_data: bool,
+}
+
 /// CLI arguments for the `enc` subcommand.
 #[derive(Debug, Args, Clone)]
 pub struct EncodeArgs {
-    #[arg(value_name = "path/to/input", help = "Path to the file or directory to compress.")]
+    #[arg(
+        value_name = "path/to/input",
+        num_args = 1..,
+        required = true,
+        help = "Paths to the files or directories to compress, or - for stdin. without -o, a second path is the \
+                destination of the first."
+    )]
+    pub paths: Vec<PathBuf>,
+    #[arg(
+        long = "output",
+        short = 'o',
+        value_name = "path/to/output",
+        help = "Destination path for the compressed output, or - for stdout. defaults to the input name with .stpk \
+                added, inside the output if it is a directory."
+    )]
+    pub output_arg: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "output_arg",
+        conflicts_with_all = ["raw", "block_size", "delta_against", "split_size", "append"],
+        help = "Put all inputs into one archive at the -o path instead of writing an artifact for each."
+    )]
+    pub archive: bool,
+    /// The input being encoded, one of `paths`.
+    #[arg(skip)]
     pub input: PathBuf,
-    #[arg(value_name = "path/to/output", help = "Destination path for the compressed output.")]
-    pub output: PathBuf,
+    /// Where `input` is written, once `enc` has filled in the default.
+    #[arg(skip)]
+    pub output: Option<PathBuf>,
     #[command(flatten)]
     pub pipeline: PipelineSelector,
     #[command(flatten)]
     pub persistence: PipelinePersistenceArgs,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ChecksumAlgorithm::Crc32,
+        help = "Hash stored in embedded containers to check the decoded data against."
+    )]
+    pub checksum: ChecksumAlgorithm,
+    #[arg(
+        long = "block-size",
+        value_name = "SIZE",
+        value_parser = parse_block_size,
+        requires = "embed_to_file",
+        help = "Encode the input in independent blocks of this size, indexed for random access."
+    )]
+    pub block_size: Option<u64>,
+    #[arg(
+        long,
+        value_name = "KEY_PEM",
+        requires = "embed_to_file",
+        help = "Sign the container with an ed25519 private key in PKCS#8 PEM format."
+    )]
+    pub sign: Option<PathBuf>,
+    #[arg(
+        long = "record-host",
+        help = "Record the stackpack version, features, OS, architecture and plugins used in the container, for `info`. \
+                single files need --embed_to_file."
+    )]
+    pub record_host: bool,
+    #[arg(
+        long,
+        help = "Record how often every byte value occurs in the input in the container, for `info` and `analyze`. \
+                single files need --embed_to_file."
+    )]
+    pub histogram: bool,
+    #[arg(
+        long = "small-file-threshold",
+        value_name = "SIZE",
+        value_parser = parse_byte_size,
+        default_value = "64KiB",
+        help = "When archiving a directory, files smaller than this share compression frames."
+    )]
+    pub small_file_threshold: u64,
+    #[arg(long, help = "Refuse to encode if `pipeline lint` has warnings about the pipeline.")]
+    pub strict: bool,
+    #[arg(
+        long = "strict-memory",
+        help = "Refuse to encode a file if the pipeline is estimated to need more memory than is available."
+    )]
+    pub strict_memory: bool,
+    #[arg(long, help = "Report progress, throughput and the time left on stderr.")]
+    pub progress: bool,
+    #[arg(
+        long = "split-size",
+        value_name = "SIZE",
+        value_parser = parse_block_size,
+        help = "Split the output into numbered volumes of at most SIZE bytes, e.g. 100MiB."
+    )]
+    pub split_size: Option<u64>,
+    #[arg(
+        long,
+        conflicts_with_all = ["raw", "block_size", "delta_against", "split_size"],
+        help = "Add the input to the directory archive at the output path instead of replacing it."
+    )]
+    pub append: bool,
+    #[arg(
+        long = "delta-against",
+        value_name = "REFERENCE",
+        conflicts_with = "block_size",
+        help = "Encode only the differences from a reference file, which dec then needs as well."
+    )]
+    pub delta_against: Option<PathBuf>,
+    #[arg(
+        long = "delta-format",
+        value_enum,
+        default_value_t = DeltaFormat::Stackpack,
+        requires = "delta_against",
+        help = "Write the delta as a stackpack container, or as a VCDIFF patch for xdelta3 and other tools."
+    )]
+    pub delta_format: DeltaFormat,
+    #[arg(
+        long = "dry-run",
+        conflicts_with_all = ["append", "format"],
+        help = "Check the pipeline and estimate the output from a sample of every input, without writing anything."
+    )]
+    pub dry_run: bool,
+    #[arg(
+        long = "skip-failing-stages",
+        help = "Pass the blocks a stage fails on through unchanged instead of failing, flagged in the output (on_error=skip)."
+    )]
+    pub skip_failing_stages: bool,
+    #[command(flatten)]
+    pub time_limits: TimeLimitArgs,
+    #[arg(
+        long = "dump-stages",
+        value_name = "DIR",
+        conflicts_with = "dry_run",
+        help = "Write the output of every stage to DIR as 00-bwt.bin, 01-mtf.bin and so on, to find a stage that corrupts data."
+    )]
+    pub dump_stages: Option<PathBuf>,
+    #[arg(long, help = "Print the input and output size, ratio, time and throughput of every stage once done.")]
+    pub stats: bool,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Print the results as text on stderr, or as a JSON document on stdout as well."
+    )]
+    pub format: OutputFormat,
+    #[arg(
+        long = "stdout-stats",
+        conflicts_with_all = ["format", "dry_run"],
+        help = "Print one key=value line with the sizes, ratio, pipeline fingerprint and time to stdout on success."
+    )]
+    pub stdout_stats: bool,
+    #[command(flatten)]
+    pub io: IoArgs,
 }
 
 impl EncodeArgs {
@@ -250,18 +1048,43 @@ impl EncodeArgs {
         self.pipeline.selection()
     }
 
+    /// The inputs and the output path as given. without `-o`, a second path is the output of the first.
+    pub fn given_paths(&self) -> (&[PathBuf], Option<&Path>) {
+        match (&self.output_arg, self.paths.as_slice()) {
+            (Some(output), paths) => (paths, Some(output)),
+            (None, [input, output]) => (std::slice::from_ref(input), Some(output)),
+            (None, paths) => (paths, None),
+        }
+    }
+
+    /// The output path, once `enc` has filled in the default one.
+    pub fn output(&self) -> &Path {
+        self.output.as_deref().expect("output path is resolved before encoding")
+    }
+
     pub fn persistence_mode(&self) -> PipelinePersistence {
-        self.persistence.mode()
+        match self.persistence.mode() {
+            // a sidecar has nowhere to go next to stdout, so the pipeline is embedded instead
+            PipelinePersistence::Sidecar if is_stdio(self.output()) => PipelinePersistence::Embedded,
+            mode => mode,
+        }
     }
 }
 
 /// CLI arguments for the `dec` subcommand.
 #[derive(Debug, Args, Clone)]
 pub struct DecodeArgs {
-    #[arg(value_name = "path/to/input", help = "Path to the file or directory to decompress.")]
+    #[arg(
+        value_name = "path/to/input",
+        help = "Path to the file or directory to decompress, or - for stdin."
+    )]
     pub input: PathBuf,
-    #[arg(value_name = "path/to/output", help = "Destination path for the decompressed data.")]
-    pub output: PathBuf,
+    #[arg(
+        value_name = "path/to/output",
+        help = "Destination path for the decompressed data, or - for stdout. defaults to the input name without its \
+                .stpk extension."
+    )]
+    pub output: Option<PathBuf>,
     #[command(flatten)]
     pub pipeline: PipelineSelector,
     #[arg(
@@ -271,6 +1094,92 @@ pub struct DecodeArgs {
 		help = "Attempt brute-force decompression up to the provided pipeline depth."
 	)]
     pub brute_force_depth: Option<usize>,
+    #[arg(
+        long = "brute-state",
+        value_name = "path",
+        requires = "brute_force_depth",
+        help = "Save the brute-force search to this file as it goes, and resume it from there if the file exists."
+    )]
+    pub brute_state: Option<PathBuf>,
+    #[arg(
+        long = "force-pipeline",
+        help = "Decode a container with the pipeline given on the cli even if it differs from the embedded one."
+    )]
+    pub force_pipeline: bool,
+    #[arg(long = "head", value_name = "N", help = "Only write the first N bytes of the decompressed data.")]
+    pub head: Option<usize>,
+    #[arg(
+        long = "checksum-only",
+        help = "Print the crc32 and size of the decompressed data instead of writing it."
+    )]
+    pub checksum_only: bool,
+    #[arg(
+        long,
+        value_name = "OFFSET..LEN",
+        value_parser = parse_byte_range,
+        conflicts_with = "head",
+        help = "Only write LEN bytes starting at OFFSET, e.g. 1m..4k. Block mode containers only decode the blocks covering it."
+    )]
+    pub range: Option<ByteRange>,
+    #[arg(
+        long = "restore-mtime",
+        help = "Set the output's modification time to the one recorded in the container."
+    )]
+    pub restore_mtime: bool,
+    #[arg(
+        long = "to-tar",
+        value_name = "path/to/output.tar",
+        conflicts_with_all = ["output", "head", "range", "checksum_only", "restore_mtime"],
+        help = "Write a directory archive as a tar stream to this path, or - for stdout to pipe it into tar tools."
+    )]
+    pub to_tar: Option<PathBuf>,
+    #[arg(
+        long = "unsafe-paths",
+        help = "Extract archive members whose paths are absolute or climb out of the output directory where they point."
+    )]
+    pub unsafe_paths: bool,
+    #[arg(
+        long = "verify-sig",
+        value_name = "PUBLIC_KEY_PEM",
+        help = "Refuse to decode unless the container is signed by this ed25519 public key."
+    )]
+    pub verify_sig: Option<PathBuf>,
+    #[arg(
+        long = "delta-against",
+        value_name = "REFERENCE",
+        help = "The reference file a container made with `enc --delta-against` was encoded against."
+    )]
+    pub delta_against: Option<PathBuf>,
+    #[arg(
+        long = "max-output-size",
+        value_name = "SIZE",
+        value_parser = parse_byte_size,
+        help = "Abort once decoding produces more than SIZE bytes, e.g. 1GiB, to guard against decompression bombs."
+    )]
+    pub max_output_size: Option<u64>,
+    #[command(flatten)]
+    pub time_limits: TimeLimitArgs,
+    #[arg(
+        long = "dump-stages",
+        value_name = "DIR",
+        help = "Write the output of every stage to DIR as 01-mtf.rev.bin, 00-bwt.rev.bin and so on, to find a stage that corrupts data."
+    )]
+    pub dump_stages: Option<PathBuf>,
+    #[arg(long, help = "Print the input and output size, ratio, time and throughput of every stage once done.")]
+    pub stats: bool,
+    #[arg(long, help = "Report progress, throughput and the time left on stderr.")]
+    pub progress: bool,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Print the results as text on stderr, or as a JSON document on stdout as well."
+    )]
+    pub format: OutputFormat,
+    #[command(flatten)]
+    pub io: IoArgs,
 }
 
 impl DecodeArgs {
@@ -279,6 +1188,44 @@ impl DecodeArgs {
     }
 }
 
+/// CLI arguments for the `convert` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct ConvertArgs {
+    #[arg(value_name = "path/to/input", help = "Output of `enc --raw` or a sidecar-mode `enc` to wrap.")]
+    pub input: PathBuf,
+    #[arg(value_name = "path/to/output", help = "Destination path for the container.")]
+    pub output: PathBuf,
+    #[command(flatten)]
+    pub pipeline: PipelineSelector,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ChecksumAlgorithm::Crc32,
+        help = "Hash stored in the container to check the decoded data against."
+    )]
+    pub checksum: ChecksumAlgorithm,
+    #[arg(
+        long,
+        value_name = "KEY_PEM",
+        help = "Sign the container with an ed25519 private key in PKCS#8 PEM format."
+    )]
+    pub sign: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Original file name to record, which `dec` uses when its output is a directory."
+    )]
+    pub name: Option<String>,
+    #[command(flatten)]
+    pub io: IoArgs,
+}
+
+impl ConvertArgs {
+    pub fn pipeline_selection(&self) -> PipelineSelection {
+        self.pipeline.selection()
+    }
+}
+
 /// CLI arguments for the `test` subcommand.
 #[derive(Debug, Args, Clone)]
 pub struct TestArgs {
@@ -286,11 +1233,21 @@ pub struct TestArgs {
     pub input: PathBuf,
     #[command(flatten)]
     pub pipeline: PipelineSelector,
+    #[arg(long, help = "Print the input and output size, ratio, time and throughput of every stage once done.")]
+    pub stats: bool,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    #[command(flatten)]
+    pub filter: FilterArgs,
     #[arg(
-        long = "write_files_if_failed",
-        help = "Write compressed and decompressed files to input directory if a test fails."
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Print the results as text on stderr, or as a JSON document on stdout as well."
     )]
-    pub write_files_if_failed: bool,
+    pub format: OutputFormat,
+    #[command(flatten)]
+    pub io: IoArgs,
 }
 
 impl TestArgs {
@@ -304,6 +1261,39 @@ impl TestArgs {
 pub struct CorpusArgs {
     #[command(flatten)]
     pub pipeline: PipelineSelector,
+    #[arg(
+        long,
+        help = "Keep sampling random files, and random pipelines of registered stages unless one is given, instead of running every file once."
+    )]
+    pub shuffle: bool,
+    #[arg(
+        long,
+        value_name = "DURATION",
+        requires = "shuffle",
+        value_parser = parse_duration,
+        help = "How long to keep sampling, e.g. 90s, 10m or 2h. Runs until interrupted without it."
+    )]
+    pub duration: Option<Duration>,
+    #[arg(
+        long,
+        value_name = "SEED",
+        requires = "shuffle",
+        help = "Seed of the first sample, as logged with a failure, to replay it."
+    )]
+    pub seed: Option<u64>,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+    #[command(flatten)]
+    pub report: ReportArgs,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Print the results as text on stderr, or as a JSON document on stdout as well."
+    )]
+    pub format: OutputFormat,
+    #[command(flatten)]
+    pub io: IoArgs,
 }
 
 impl CorpusArgs {
@@ -312,6 +1302,247 @@ impl CorpusArgs {
     }
 }
 
+/// CLI arguments for the `grep` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct GrepArgs {
+    #[arg(value_name = "PATTERN", help = "Regular expression to search for.")]
+    pub pattern: String,
+    #[arg(value_name = "path/to/input", required = true, help = "Compressed files to search.")]
+    pub inputs: Vec<PathBuf>,
+    #[command(flatten)]
+    pub pipeline: PipelineSelector,
+    #[arg(long = "ignore-case", short = 'i', help = "Match case-insensitively.")]
+    pub ignore_case: bool,
+}
+
+impl GrepArgs {
+    pub fn pipeline_selection(&self) -> PipelineSelection {
+        self.pipeline.selection()
+    }
+}
+
+/// CLI arguments for the `heatmap` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct HeatmapArgs {
+    #[arg(value_name = "path/to/input", help = "File to analyse.")]
+    pub input: PathBuf,
+    #[command(flatten)]
+    pub pipeline: PipelineSelector,
+    #[arg(
+        long = "window",
+        value_name = "SIZE",
+        default_value = "64KiB",
+        value_parser = parse_block_size,
+        help = "Size of the windows that are compressed on their own."
+    )]
+    pub window: u64,
+    #[arg(long, value_enum, default_value_t = HeatmapFormat::Sparkline, help = "How to print the per-window ratios.")]
+    pub format: HeatmapFormat,
+    #[command(flatten)]
+    pub io: IoArgs,
+}
+
+impl HeatmapArgs {
+    pub fn pipeline_selection(&self) -> PipelineSelection {
+        self.pipeline.selection()
+    }
+}
+
+/// CLI arguments for the `analyze` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct AnalyzeArgs {
+    #[arg(value_name = "path/to/input", help = "File to analyse.")]
+    pub input: PathBuf,
+    #[arg(long, value_name = "N", default_value_t = 8, help = "How many of the most common bytes to list.")]
+    pub top: usize,
+    #[command(flatten)]
+    pub io: IoArgs,
+}
+
+/// Output formats for the results of `enc`, `dec`, `test`, `corpus` and `pipeline list-compressors`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Output formats for `heatmap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HeatmapFormat {
+    Sparkline,
+    Csv,
+}
+
+/// CLI arguments for the `info` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct InfoArgs {
+    #[arg(value_name = "path/to/input", help = "Compressed file to describe.")]
+    pub input: PathBuf,
+}
+
+/// CLI arguments for the `diff` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct DiffArgs {
+    #[arg(value_name = "path/to/a", help = "First file, e.g. the original data.")]
+    pub a: PathBuf,
+    #[arg(value_name = "path/to/b", help = "Second file, e.g. what a round trip produced.")]
+    pub b: PathBuf,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = 32,
+        help = "How many bytes before and after the first difference the hex dump shows."
+    )]
+    pub context: u64,
+}
+
+/// CLI arguments for the `verify` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct VerifyArgs {
+    #[arg(value_name = "path/to/input", help = "Compressed file to check.")]
+    pub input: PathBuf,
+    #[arg(
+        long,
+        help = "Check every member of a directory archive against its own checksum and list the damaged ones."
+    )]
+    pub members: bool,
+}
+
+/// CLI arguments for the `cat` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct CatArgs {
+    #[arg(value_name = "path/to/archive", help = "Directory archive to read from.")]
+    pub input: PathBuf,
+    #[arg(value_name = "ENTRY", help = "Path of the file inside the archive, as `info` lists it.")]
+    pub entry: String,
+}
+
+/// CLI arguments for the `extract` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct ExtractArgs {
+    #[arg(value_name = "path/to/archive", help = "Directory archive to extract from.")]
+    pub input: PathBuf,
+    #[arg(value_name = "ENTRY", help = "Path of the entry inside the archive, as `info` lists it.")]
+    pub entry: String,
+    #[arg(
+        long = "output",
+        short = 'o',
+        value_name = "path/to/output",
+        help = "Where to write the entry, defaults to its name in the current directory."
+    )]
+    pub output: Option<PathBuf>,
+    #[arg(
+        long = "unsafe-paths",
+        help = "Extract archive members whose paths are absolute or climb out of the output directory where they point."
+    )]
+    pub unsafe_paths: bool,
+    #[command(flatten)]
+    pub io: IoArgs,
+}
+
+/// CLI arguments for the `sanitize` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct SanitizeArgs {
+    #[arg(value_name = "path/to/input", help = "File whose round trip through the pipeline fails.")]
+    pub input: PathBuf,
+    #[arg(value_name = "path/to/output", help = "Where the reduced reproduction is written.")]
+    pub output: PathBuf,
+    #[command(flatten)]
+    pub pipeline: PipelineSelector,
+    #[arg(
+        long = "max-runs",
+        value_name = "N",
+        default_value_t = 10_000,
+        help = "Give up shrinking after this many round trips and write what is left."
+    )]
+    pub max_runs: usize,
+    #[arg(
+        long = "no-anonymize",
+        help = "Only cut the input down, keep the bytes that are left instead of zeroing the ones the failure \
+                doesn't need."
+    )]
+    pub no_anonymize: bool,
+    #[command(flatten)]
+    pub io: IoArgs,
+}
+
+impl SanitizeArgs {
+    pub fn pipeline_selection(&self) -> PipelineSelection {
+        self.pipeline.selection()
+    }
+}
+
+/// CLI arguments for the `portable-decoder` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct PortableDecoderArgs {
+    #[arg(
+        value_name = "path/to/input",
+        help = "Compressed file to generate the decoder for, its pipeline is read from its container or sidecar."
+    )]
+    pub input: PathBuf,
+    #[arg(value_name = "path/to/decoder.rs", help = "Where the decoder source is written, or - for stdout.")]
+    pub output: PathBuf,
+    #[command(flatten)]
+    pub pipeline: PipelineSelector,
+    #[command(flatten)]
+    pub io: IoArgs,
+}
+
+impl PortableDecoderArgs {
+    pub fn pipeline_selection(&self) -> PipelineSelection {
+        self.pipeline.selection()
+    }
+}
+
+/// CLI arguments for the `selftest` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct SelftestArgs {
+    #[arg(
+        long = "cross-feature",
+        value_name = "path/to/stackpack",
+        help = "Compare the encoded outputs against another stackpack binary, e.g. one built with --no-default-features."
+    )]
+    pub cross_feature: Option<PathBuf>,
+    #[arg(
+        long,
+        hide = true,
+        conflicts_with = "cross_feature",
+        help = "Print machine readable fingerprints for --cross-feature."
+    )]
+    pub fingerprints: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["cross_feature", "fingerprints"],
+        help = "Time every built-in stage and fail on one far slower than its recorded reference throughput."
+    )]
+    pub perf: bool,
+    #[arg(
+        long = "perf-tolerance",
+        value_name = "FACTOR",
+        default_value_t = 10.0,
+        requires = "perf",
+        value_parser = parse_tolerance,
+        help = "How many times slower than its reference a stage may be before --perf fails it."
+    )]
+    pub perf_tolerance: f64,
+}
+
+/// CLI arguments for the `calibrate` subcommand.
+#[derive(Debug, Args, Clone)]
+pub struct CalibrateArgs {
+    #[arg(
+        long = "sample-size",
+        value_name = "SIZE",
+        default_value = "2MiB",
+        value_parser = parse_block_size,
+        help = "Size of the generated sample the stages are timed on, larger is steadier and slower."
+    )]
+    pub sample_size: u64,
+    #[arg(long = "no-save", help = "Print the recommended settings without saving them to the user config.")]
+    pub no_save: bool,
+}
+
 /// Pipeline inspection and management subcommands.
 #[derive(Debug, Subcommand)]
 pub enum PipelineCommand {
@@ -319,6 +1550,16 @@ pub enum PipelineCommand {
     ListCompressors {
         #[arg(long, help = "Print additional metadata for each compressor.")]
         detailed: bool,
+        #[arg(long, conflicts_with = "detailed", help = "Print the metadata of every compressor as JSON.")]
+        json: bool,
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = OutputFormat::Text,
+            conflicts_with = "detailed",
+            help = "Print the compressors as text, or as JSON like --json."
+        )]
+        format: OutputFormat,
     },
     #[command(name = "list-plugins", about = "List available plugins.")]
     ListPlugins,
@@ -329,6 +1570,54 @@ pub enum PipelineCommand {
         #[arg(value_name = "path/to/output", help = "Output path for the pipeline file.")]
         output: PathBuf,
     },
+    #[command(name = "save", about = "Save a named pipeline to the user config.")]
+    Save {
+        #[arg(value_name = "NAME", help = "Name to save the pipeline under, usable with --preset.")]
+        name: String,
+        #[arg(value_name = "PIPELINE", help = "Pipeline string in \"a -> b -> c\" form.")]
+        pipeline: String,
+    },
+    #[command(name = "list", about = "List preset pipelines.")]
+    List {
+        #[arg(long, help = "List pipelines saved with `pipeline save` instead of the built-in presets.")]
+        saved: bool,
+    },
+    #[command(name = "export-graph", about = "Render a pipeline as a Graphviz DOT or Mermaid graph.")]
+    ExportGraph {
+        #[command(flatten)]
+        pipeline: PipelineSelector,
+        #[arg(long, value_enum, default_value_t = GraphFormat::Dot, help = "Graph description language to emit.")]
+        format: GraphFormat,
+        #[arg(
+            long,
+            short,
+            value_name = "path/to/output",
+            help = "Write the graph to this file instead of stdout."
+        )]
+        output: Option<PathBuf>,
+    },
+    #[command(name = "lint", about = "Warn about stage orderings that are known to compress badly.")]
+    Lint {
+        #[command(flatten)]
+        pipeline: PipelineSelector,
+    },
+    #[command(name = "validate", about = "Check every stage name and parameter of a pipeline without running it.")]
+    Validate {
+        #[command(flatten)]
+        pipeline: PipelineSelector,
+    },
+    #[command(name = "explain", about = "Explain what every stage of a pipeline does to the data and how it is undone.")]
+    Explain {
+        #[command(flatten)]
+        pipeline: PipelineSelector,
+    },
+}
+
+/// Output languages for `pipeline export-graph`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
 }
 
 fn parse_positive_depth(raw: &str) -> Result<usize, String> {
@@ -340,6 +1629,98 @@ fn parse_positive_depth(raw: &str) -> Result<usize, String> {
     }
 }
 
+fn parse_byte_range(raw: &str) -> Result<ByteRange, String> {
+    let (offset, len) = raw
+        .split_once("..")
+        .ok_or_else(|| format!("range '{raw}' is not of the form <offset>..<len>"))?;
+    Ok(ByteRange {
+        offset: parse_byte_size(offset)?,
+        len: parse_byte_size(len)?,
+    })
+}
+
+fn parse_block_size(raw: &str) -> Result<u64, String> {
+    let size = parse_byte_size(raw)?;
+    if size == 0 {
+        Err("block size must be greater than zero".to_string())
+    } else {
+        Ok(size)
+    }
+}
+
+fn parse_rate(raw: &str) -> Result<u64, String> {
+    let rate = parse_byte_size(raw)?;
+    if rate == 0 {
+        Err("the rate must be greater than zero, leave --limit-rate out to not limit it".to_string())
+    } else {
+        Ok(rate)
+    }
+}
+
+fn parse_tolerance(raw: &str) -> Result<f64, String> {
+    let factor: f64 = raw.parse().map_err(|err| format!("failed to parse factor '{raw}': {err}"))?;
+    if factor >= 1.0 {
+        Ok(factor)
+    } else {
+        Err("the tolerance is how many times slower a stage may be, at least 1".to_string())
+    }
+}
+
 pub fn warn_unsafe_mode_enabled() {
-    eprintln!("[warn] stackpack: unsafe mode enabled, safety is not guaranteed.");
+    events::warn("unsafe mode enabled, safety is not guaranteed.");
+}
+
+/// Checks an output before any work goes into it. existing outputs are an error unless `--force` is given, with
+/// `--keep` they are left alone and this returns false, as there's nothing left to do.
+pub fn check_output(output: &Path, policy: IoPolicy) -> CliResult<bool> {
+    if is_stdio(output) || fs::symlink_metadata(output).is_err() {
+        return Ok(true);
+    }
+    match policy.overwrite {
+        Overwrite::Force => Ok(true),
+        Overwrite::Keep => {
+            events::warn(format_args!("{} already exists, keeping it (--keep).", output.display()));
+            Ok(false)
+        }
+        Overwrite::Refuse => Err(CliError::msg(
+            ErrorClass::Io,
+            format!("{} already exists, pass --force to overwrite it.", output.display()),
+        )),
+    }
+}
+
+/// Where `enc` writes the artifact of `input` when `output` is left out or is a directory: next to the input or inside
+/// the directory, named after the input with `.stpk` added.
+pub fn encode_output_path(input: &Path, output: Option<&Path>) -> CliResult<PathBuf> {
+    let directory = match output {
+        Some(output) if !output.is_dir() => return Ok(output.to_path_buf()),
+        None if is_stdio(input) => return Ok(PathBuf::from("-")),
+        directory => directory,
+    };
+    let Some(name) = input.file_name().filter(|_| !is_stdio(input)) else {
+        return Err(CliError::usage(format!(
+            "{} has no name to name the output after, pass the output path.",
+            input.display()
+        )));
+    };
+    let mut name = name.to_owned();
+    name.push(format!(".{}", FILE_EXTENSION));
+    Ok(match directory {
+        Some(directory) => directory.join(name),
+        None => input.with_file_name(name),
+    })
+}
+
+/// The name `dec` gives the output of `input` when it is left out: the input, or the output its volumes were split
+/// from, without the `.stpk` extension. `None` when the input doesn't have that extension.
+pub fn decode_output_path(input: &Path) -> Option<PathBuf> {
+    let base = volume_base(input).unwrap_or_else(|| input.to_path_buf());
+    (base.extension()? == FILE_EXTENSION).then(|| base.with_extension(""))
+}
+
+pub fn warn_raw_output(output: &Path) {
+    events::warn(format_args!(
+        "{} was written without pipeline metadata (--raw). it can only be decompressed by passing the same pipeline to `dec` with --using, --from_file or --preset, or wrapped into a container with `convert`.",
+        output.display()
+    ));
 }
@@ -0,0 +1,260 @@
+//! `analyze`, which describes what a file is made of before a pipeline is picked for it: what kind of file it
+//! looks like, how its bytes are spread (the most common ones and the order-0 entropy), how much the byte before
+//! each one tells about it (the order-1 entropy) and how much of it is runs of one byte. a pipeline that suits data
+//! like it is suggested from those, along with why:
+//!
+//! 1. containers, files of other compressors and data with an order-0 entropy of more than [`INCOMPRESSIBLE`] bits
+//!    per byte won't shrink by much, `lz4` at least gets through them quickly.
+//! 2. data whose order-1 entropy is less than [`CONTEXT_GAIN`] bits below its order-0 entropy gains little from the
+//!    context modelling of a bwt, `arcode` alone codes it about as small.
+//! 3. from [`LARGE_INPUT`] on, `bsc`, which does the bwt and the modelling in one stage and faster.
+//! 4. otherwise `bwt -> mtf -> arcode`, which is pure rust and can be written out by `portable-decoder`.
+//!
+//! a container made with `enc --histogram` also has its contents described from the histogram in its header, their
+//! size, order-0 entropy and most common bytes, without decoding them (see [`crate::histogram`]).
+use crate::{
+    cli::{
+        AnalyzeArgs,
+        error::{Classify, CliResult, ErrorClass},
+    },
+    container::{is_container, parse_container},
+    formats,
+    histogram::ByteHistogram,
+    io::read_file,
+    units::{ByteSize, Grouped, MEBIBYTES},
+};
+
+/// Order-0 entropy in bits per byte above which data counts as compressed or random already.
+const INCOMPRESSIBLE: f64 = 7.5;
+/// How many bits per byte the order-1 entropy has to be below the order-0 entropy for a bwt to be worth it.
+const CONTEXT_GAIN: f64 = 0.5;
+/// Size from which `bsc` is suggested over `bwt -> mtf -> arcode`.
+const LARGE_INPUT: usize = MEBIBYTES;
+/// Run lengths the run length distribution is split at, every bucket holds the runs up to its bound.
+const RUN_BUCKETS: [usize; 6] = [1, 2, 4, 8, 16, 64];
+
+/// Formats recognised by their magic bytes beyond those of [`formats::FOREIGN_FORMATS`], and whether they are
+/// compressed already.
+const KNOWN_FORMATS: &[(&str, &[u8], bool)] = &[
+    ("png image", b"\x89PNG\r\n\x1a\n", true),
+    ("jpeg image", &[0xff, 0xd8, 0xff], true),
+    ("gif image", b"GIF8", true),
+    ("zip archive", b"PK\x03\x04", true),
+    ("pdf document", b"%PDF-", false),
+    ("elf executable", b"\x7fELF", false),
+];
+
+pub fn analyze(args: AnalyzeArgs) -> CliResult {
+    let input = &args.input;
+    let data = read_file(input, args.io.policy()).classify(ErrorClass::Io, || format!("failed to read {}", input.display()))?;
+
+    let histogram = ByteHistogram::of(&data);
+    let file_type = file_type(&data);
+    let order0 = histogram.entropy();
+    let order1 = order1_entropy(&data);
+
+    println!("{}: {} ({} bytes)", input.display(), ByteSize(data.len() as u64), Grouped(data.len() as u64));
+    println!("type: {}", file_type.name);
+    if data.is_empty() {
+        println!("nothing to analyze in an empty file.");
+        return Ok(());
+    }
+    println!("entropy: {:.3} bits per byte (order 0), {:.3} given the byte before (order 1)", order0, order1);
+    print_histogram("distinct bytes", &histogram, args.top);
+    print_runs(&data);
+    if is_container(&data) {
+        print_contents(&data, args.top);
+    }
+
+    let (pipeline, reason) = suggest(&file_type, order0, order1, data.len());
+    println!("suggested pipeline: {}", pipeline);
+    println!("  {}", reason);
+    Ok(())
+}
+
+/// What kind of file the data looks like.
+struct FileType {
+    name: String,
+    compressed: bool,
+}
+
+fn file_type(data: &[u8]) -> FileType {
+    if is_container(data) {
+        return FileType {
+            name: "stackpack container".to_string(),
+            compressed: true,
+        };
+    }
+    if let Some(format) = formats::detect(data) {
+        return FileType {
+            name: format!("{} compressed", format.name),
+            compressed: true,
+        };
+    }
+    if let Some((name, _, compressed)) = KNOWN_FORMATS.iter().find(|(_, magic, _)| data.starts_with(magic)) {
+        return FileType {
+            name: name.to_string(),
+            compressed: *compressed,
+        };
+    }
+    if data.is_empty() {
+        return FileType {
+            name: "empty".to_string(),
+            compressed: false,
+        };
+    }
+    let text = data
+        .iter()
+        .filter(|&&byte| byte.is_ascii_graphic() || byte.is_ascii_whitespace() || byte >= 0x80)
+        .count();
+    let name = if text == data.len() && std::str::from_utf8(data).is_ok() {
+        "utf-8 text"
+    } else if text as f64 >= 0.95 * data.len() as f64 {
+        "mostly text"
+    } else {
+        "binary"
+    };
+    FileType {
+        name: name.to_string(),
+        compressed: false,
+    }
+}
+
+/// The entropy of every byte given the one before it, in bits per byte.
+fn order1_entropy(data: &[u8]) -> f64 {
+    if data.len() < 2 {
+        return 0.0;
+    }
+    let mut counts = vec![0u64; 256 * 256];
+    let mut contexts = [0u64; 256];
+    for pair in data.windows(2) {
+        counts[(pair[0] as usize) << 8 | pair[1] as usize] += 1;
+        contexts[pair[0] as usize] += 1;
+    }
+    let pairs = (data.len() - 1) as f64;
+    counts
+        .iter()
+        .enumerate()
+        .filter(|&(_, &count)| count > 0)
+        .map(|(index, &count)| {
+            let p = count as f64 / contexts[index >> 8] as f64;
+            -(count as f64 / pairs) * p.log2()
+        })
+        .sum()
+}
+
+/// Lists the `top` most common bytes after a line counting the distinct ones, which starts with `label`.
+fn print_histogram(label: &str, histogram: &ByteHistogram, top: usize) {
+    let (counts, len) = (&histogram.counts, histogram.total());
+    println!("{}: {} of 256", label, histogram.distinct());
+    let mut bytes = (0..=255u8).filter(|&byte| counts[byte as usize] > 0).collect::<Vec<_>>();
+    bytes.sort_by_key(|&byte| std::cmp::Reverse(counts[byte as usize]));
+    for byte in bytes.into_iter().take(top) {
+        let count = counts[byte as usize];
+        let shown = match byte {
+            b' ' => "' '".to_string(),
+            b'\0' => "'\\0'".to_string(),
+            b'\t' => "'\\t'".to_string(),
+            b'\n' => "'\\n'".to_string(),
+            b'\r' => "'\\r'".to_string(),
+            byte if byte.is_ascii_graphic() => format!("'{}'", byte as char),
+            _ => String::new(),
+        };
+        println!(
+            "  {:#04x} {:<8} {:>6.2}% {:>14}",
+            byte,
+            shown,
+            count as f64 * 100.0 / len as f64,
+            Grouped(count).to_string()
+        );
+    }
+}
+
+/// Describes the original data of the container in `data` from its stored histogram, if it has one.
+fn print_contents(data: &[u8], top: usize) {
+    let Some(histogram) = parse_container(data).ok().and_then(|container| container.histogram) else {
+        println!("contents: not recorded, `enc --histogram` stores a histogram of them in the container");
+        return;
+    };
+    let len = histogram.total();
+    println!("contents: {} ({} bytes), from the histogram stored in the container", ByteSize(len), Grouped(len));
+    if len == 0 {
+        return;
+    }
+    println!("contents entropy: {:.3} bits per byte (order 0)", histogram.entropy());
+    print_histogram("contents distinct bytes", &histogram, top);
+}
+
+fn print_runs(data: &[u8]) {
+    let mut runs = [0u64; RUN_BUCKETS.len() + 1];
+    let mut bytes_in = [0u64; RUN_BUCKETS.len() + 1];
+    for run in data.chunk_by(|a, b| a == b) {
+        let bucket = RUN_BUCKETS.iter().position(|&bound| run.len() <= bound).unwrap_or(RUN_BUCKETS.len());
+        runs[bucket] += 1;
+        bytes_in[bucket] += run.len() as u64;
+    }
+    let total = runs.iter().sum::<u64>();
+    println!(
+        "runs: {}, {:.2} bytes long on average",
+        Grouped(total),
+        data.len() as f64 / total as f64
+    );
+    for (bucket, &count) in runs.iter().enumerate().filter(|&(_, &count)| count > 0) {
+        let lower = bucket.checked_sub(1).map_or(1, |previous| RUN_BUCKETS[previous] + 1);
+        let lengths = match RUN_BUCKETS.get(bucket) {
+            Some(&bound) if bound == lower => bound.to_string(),
+            Some(&bound) => format!("{}-{}", lower, bound),
+            None => format!("{}+", lower),
+        };
+        println!(
+            "  length {:<6} {:>14} runs, {:>6.2}% of the data",
+            lengths,
+            Grouped(count).to_string(),
+            bytes_in[bucket] as f64 * 100.0 / data.len() as f64
+        );
+    }
+}
+
+/// The pipeline likely to do well on data like this, and why, by the rules in the module docs.
+fn suggest(file_type: &FileType, order0: f64, order1: f64, len: usize) -> (&'static str, String) {
+    if file_type.compressed || order0 > INCOMPRESSIBLE {
+        return (
+            "lz4",
+            format!(
+                "it looks compressed or random already ({}, {:.2} bits per byte), no stage will shrink it by much and \
+                 lz4 at least gets through it quickly.",
+                file_type.name, order0
+            ),
+        );
+    }
+    if order0 - order1 < CONTEXT_GAIN {
+        return (
+            "arcode",
+            format!(
+                "the byte before tells little about the next ({:.2} bits saved per byte), so a bwt buys little over \
+                 coding the bytes by how common they are.",
+                order0 - order1
+            ),
+        );
+    }
+    if len >= LARGE_INPUT {
+        return (
+            "bsc",
+            format!(
+                "the bytes depend a lot on their context ({:.2} bits saved per byte) and at {} bsc models that as well \
+                 as bwt -> mtf -> arcode and is faster.",
+                order0 - order1,
+                ByteSize(len as u64)
+            ),
+        );
+    }
+    (
+        "bwt -> mtf -> arcode",
+        format!(
+            "the bytes depend a lot on their context ({:.2} bits saved per byte), and at {} it does about as well as \
+             bsc while staying pure rust and decodable by portable-decoder.",
+            order0 - order1,
+            ByteSize(len as u64)
+        ),
+    )
+}
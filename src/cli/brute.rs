@@ -0,0 +1,369 @@
+//! brute-force decoding of inputs that come without a pipeline, asked for with `dec --try-brute <depth>`.
+//!
+//! every registered stage, with its default parameters, is reverted on the input, then every stage is reverted on
+//! each of the outputs, and so on level by level until `depth` stages were reverted. a revert that fails, panics,
+//! changes nothing, shrinks the data to less than half, grows it past [`MAX_GROWTH`] times or gives data already seen
+//! is a dead end.
+//! every other output is a candidate, scored by [`score`], and the search goes with the best one:
+//!
+//! > `$exename dec secret.bin secret.txt --try-brute 3 --brute-state secret.brute.json`
+//!
+//! the frontier, the pipelines still left to expand, is worked on in batches spread over `--threads` threads. with
+//! `--brute-state` it is written to the given file after every batch, and a search that finds the file resumes where
+//! the previous one stopped instead of starting over. the file is removed once the search is done. every new best
+//! candidate is printed as soon as it is found, so an interrupted search still tells what looked promising. Ctrl-C
+//! stops it between two reverts, and keeps the state saved after the last batch (see [`crate::interrupt`]).
+//!
+//! with `--max-stage-time` a revert that runs longer is a dead end too, and with `--max-time` the search fails once
+//! a batch ends after the run is out of time, keeping the state to resume from.
+use std::{
+    collections::HashSet,
+    fs,
+    io::ErrorKind,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::{
+    algorithms::{
+        heuristics,
+        pipeline::{CompressionPipeline, TimeLimits, run_within},
+    },
+    cli::error::{Classify, CliError, CliResult, ErrorClass},
+    events, interrupt,
+    io::{Durability, IoPolicy, Overwrite, write_file},
+    mutator::Mutator,
+    registered::{self, RegisteredCompressor},
+    units::{ByteSize, Elapsed},
+    verbosity,
+};
+
+/// Pipelines expanded per thread before the state is saved.
+const BATCH_PER_THREAD: usize = 8;
+/// A revert may grow its input this many times over, more than that is taken for garbage.
+const MAX_GROWTH: usize = 256;
+/// Inputs smaller than this may grow up to `MAX_GROWTH` times this size.
+const MIN_GROWTH_BASE: usize = 4096;
+
+/// Where a search stands, what `--brute-state` holds.
+#[derive(Debug, Serialize, Deserialize)]
+struct SearchState {
+    input_size: u64,
+    input_hash: u64,
+    depth: usize,
+    /// The stages searched, a resumed search has to search the same ones.
+    stages: Vec<String>,
+    /// Reverts run so far.
+    tried: u64,
+    /// Stages reverted to get to each pipeline still to expand, in the order they were reverted.
+    frontier: Vec<Vec<String>>,
+    /// Hashes of every output seen.
+    seen: Vec<u64>,
+    best: Option<Candidate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Candidate {
+    /// Stages reverted, in the order they were reverted.
+    reverted: Vec<String>,
+    size: u64,
+    score: f64,
+}
+
+impl Candidate {
+    /// The pipeline that encodes into the input, the reverted stages backwards.
+    fn pipeline(&self) -> CompressionPipeline {
+        self.reverted.iter().rev().fold(CompressionPipeline::new(), |pipeline, name| {
+            pipeline.with_algorithm(registered::lookup(name).expect("searched stages are registered"))
+        })
+    }
+}
+
+/// An output found while expanding a pipeline.
+struct Found {
+    reverted: Vec<String>,
+    hash: u64,
+    size: usize,
+    score: f64,
+}
+
+/// How unlikely `data` is to be decoded data, the lower the better: 16 times the share of bytes that aren't ascii
+/// text, plus 4 times the share that repeat the byte before them, less the order-0 entropy in bits per byte. the
+/// transforms in a pipeline lower the entropy of what they're given or gather it into runs, and the coders turn it
+/// into binary, so the decoded data is the text with the most entropy and the fewest runs. data that isn't text
+/// only ever gets the shortest pipeline right by chance.
+fn score(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum();
+    let binary = data
+        .iter()
+        .filter(|&&byte| !(byte.is_ascii_graphic() || byte.is_ascii_whitespace()))
+        .count();
+    let repeats = data.windows(2).filter(|pair| pair[0] == pair[1]).count();
+    (16.0 * binary as f64 + 4.0 * repeats as f64) / len - entropy
+}
+
+/// Reverts `stage` on its own, treating a panic or running longer than `budget` as an error.
+fn revert(stage: &RegisteredCompressor, data: &[u8], budget: Option<Duration>) -> Option<Vec<u8>> {
+    let run = || match budget {
+        Some(budget) => run_within(stage, Mutator::revert_mutation, data, budget),
+        None => {
+            let mut output = Vec::new();
+            Some(stage.clone().revert_mutation(data, &mut output).map(|()| output))
+        }
+    };
+    match panic::catch_unwind(AssertUnwindSafe(run)) {
+        Ok(Some(Ok(output))) => Some(output),
+        _ => None,
+    }
+}
+
+/// Reverts every searched stage on the output of `reverted`, returning how many reverts ran and what they found.
+fn expand(input: &[u8], stages: &[RegisteredCompressor], reverted: &[String], limit: usize, budget: Option<Duration>) -> (u64, Vec<Found>) {
+    let mut data = input.to_vec();
+    for name in reverted {
+        let stage = stages
+            .iter()
+            .find(|stage| stage.name == *name)
+            .expect("searched stages are registered");
+        // the pipeline was reverted this far before, so it always is again, however long it takes
+        data = revert(stage, &data, None).expect("stages revert deterministically");
+    }
+    let mut found = Vec::new();
+    for stage in stages {
+        interrupt::checkpoint();
+        let Some(output) = revert(stage, &data, budget) else {
+            continue;
+        };
+        // decoding hardly ever shrinks data by half, so that is taken for a stage misreading its input
+        if output == data || output.len() > limit || output.len() < data.len().div_ceil(2) {
+            continue;
+        }
+        let mut path = reverted.to_vec();
+        path.push(stage.name.to_string());
+        found.push(Found {
+            reverted: path,
+            hash: xxh3_64(&output),
+            size: output.len(),
+            score: score(&output),
+        });
+    }
+    (stages.len() as u64, found)
+}
+
+/// Loads the state of an interrupted search of `input` from `path`, if there is one that matches.
+fn load_state(path: &Path, input: &[u8], depth: usize, names: &[String]) -> CliResult<Option<SearchState>> {
+    let raw = match fs::read(path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).classify(ErrorClass::Io, || format!("failed to read the search state {}", path.display())),
+    };
+    let state: SearchState = serde_json::from_slice(&raw).classify(ErrorClass::Usage, || {
+        format!("{} is not a brute-force search state", path.display())
+    })?;
+    if state.input_size != input.len() as u64 || state.input_hash != xxh3_64(input) {
+        return Err(CliError::usage(format!("{} is the search state of another input.", path.display())));
+    }
+    if state.depth != depth || state.stages != names {
+        return Err(CliError::usage(format!(
+            "{} was saved by a search to depth {} over other stages, remove it to start over.",
+            path.display(),
+            state.depth
+        )));
+    }
+    Ok(Some(state))
+}
+
+fn report_best(best: &Candidate) {
+    events::warn(format_args!(
+        "best so far: {} ({}, score {:.3})",
+        best.pipeline(),
+        ByteSize(best.size),
+        best.score
+    ));
+}
+
+fn save_state(path: &Path, state: &SearchState) {
+    // written aside and renamed into place, an interrupt never leaves half a state behind
+    let policy = IoPolicy {
+        durability: Durability::Fsync,
+        limit_rate: None,
+        overwrite: Overwrite::Force,
+    };
+    let json = serde_json::to_vec(state).expect("search states are always serializable");
+    if let Err(e) = write_file(path, &json, policy) {
+        events::warn(format_args!("failed to save the search state to {}: {}", path.display(), e));
+    }
+}
+
+/// Searches for the pipeline `input` was most likely encoded with, reverting up to `depth` stages. fails when
+/// nothing decodes to anything more plausible than the input itself.
+pub fn search(
+    input_path: &Path,
+    input: &[u8],
+    depth: usize,
+    state_path: Option<&Path>,
+    max_output_size: Option<usize>,
+    time_limits: TimeLimits,
+) -> CliResult<CompressionPipeline> {
+    let stages = registered::stages();
+    let names: Vec<String> = stages.iter().map(|stage| stage.name.to_string()).collect();
+    let limit = max_output_size
+        .unwrap_or(usize::MAX)
+        .min(input.len().max(MIN_GROWTH_BASE).saturating_mul(MAX_GROWTH));
+    let resumed = match state_path {
+        Some(path) => load_state(path, input, depth, &names)?,
+        None => None,
+    };
+    let mut state = match resumed {
+        Some(state) => {
+            events::warn(format_args!(
+                "resuming the search of {}: {} reverts run, {} pipelines left to expand.",
+                input_path.display(),
+                state.tried,
+                state.frontier.len()
+            ));
+            if let Some(best) = &state.best {
+                report_best(best);
+            }
+            state
+        }
+        None => SearchState {
+            input_size: input.len() as u64,
+            input_hash: xxh3_64(input),
+            depth,
+            stages: names,
+            tried: 0,
+            frontier: vec![Vec::new()],
+            seen: vec![xxh3_64(input)],
+            best: None,
+        },
+    };
+    let mut seen: HashSet<u64> = state.seen.iter().copied().collect();
+    let baseline = score(input);
+    let threads = heuristics::available_threads();
+
+    // stages fed garbage are expected to panic, which is a dead end like any other here
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    while !state.frontier.is_empty() {
+        let batch_len = state.frontier.len().min(threads * BATCH_PER_THREAD);
+        let batch: Vec<Vec<String>> = state.frontier.drain(..batch_len).collect();
+        let chunk_len = batch.len().div_ceil(threads);
+        let expanded: Vec<(u64, Vec<Found>)> = thread::scope(|scope| {
+            let workers: Vec<_> = batch
+                .chunks(chunk_len)
+                .map(|chunk| {
+                    let stages = &stages;
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|reverted| expand(input, stages, reverted, limit, time_limits.stage))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("panics are caught in the workers"))
+                .collect()
+        });
+
+        // merged in frontier order, so the outcome doesn't depend on the number of threads
+        for (tried, found) in expanded {
+            state.tried += tried;
+            for found in found {
+                if !seen.insert(found.hash) {
+                    continue;
+                }
+                state.seen.push(found.hash);
+                let better = state.best.as_ref().map_or(baseline, |best| best.score);
+                if found.score < better {
+                    let best = Candidate {
+                        reverted: found.reverted.clone(),
+                        size: found.size as u64,
+                        score: found.score,
+                    };
+                    report_best(&best);
+                    state.best = Some(best);
+                }
+                if found.reverted.len() < depth {
+                    state.frontier.push(found.reverted);
+                }
+            }
+        }
+        if verbosity::is_verbose() {
+            eprintln!(
+                "  brute force: {} reverts run, {} pipelines left to expand",
+                state.tried,
+                state.frontier.len()
+            );
+        }
+        if let Some(path) = state_path {
+            save_state(path, &state);
+        }
+        if let Some((max_time, _)) = time_limits.run
+            && time_limits.run_is_over()
+            && !state.frontier.is_empty()
+        {
+            panic::set_hook(default_hook);
+            let resume = match state_path {
+                Some(path) => format!(", rerun with --brute-state {} to resume it", path.display()),
+                None => String::new(),
+            };
+            return Err(CliError::msg(
+                ErrorClass::Failure,
+                format!(
+                    "the brute-force search of {} ran past the --max-time of {} after {} reverts{}.",
+                    input_path.display(),
+                    Elapsed(max_time),
+                    state.tried,
+                    resume
+                ),
+            ));
+        }
+    }
+    panic::set_hook(default_hook);
+
+    if let Some(path) = state_path
+        && let Err(e) = fs::remove_file(path)
+        && e.kind() != ErrorKind::NotFound
+    {
+        events::warn(format_args!("failed to remove the search state {}: {}", path.display(), e));
+    }
+    let Some(best) = state.best else {
+        return Err(CliError::msg(
+            ErrorClass::Data,
+            format!(
+                "no pipeline of up to {} stages decodes {} into anything that looks more like data, after {} reverts.",
+                depth,
+                input_path.display(),
+                state.tried
+            ),
+        ));
+    };
+    let pipeline = best.pipeline();
+    events::warn(format_args!(
+        "{} has no pipeline, decoding it with {} found by brute force after {} reverts.",
+        input_path.display(),
+        pipeline,
+        state.tried
+    ));
+    Ok(pipeline)
+}
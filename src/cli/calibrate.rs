@@ -0,0 +1,275 @@
+//! `calibrate`, which measures what the block based stages should go by on the machine it runs on and saves it to the
+//! user config, where every later run picks it up (see [`crate::settings`]). three things are measured on a generated
+//! text sample of `--sample-size` bytes:
+//!
+//! 1. threads: `bwt` of the whole sample with 1, 2, 4 and so on threads, up to what the machine can run. the fewest
+//!    threads within [`TIME_TOLERANCE`] of the fastest are saved as `threads`, more would only heat the machine.
+//! 2. the least work worth a thread: `bwt` of ever larger prefixes of the sample with one and with two threads. half
+//!    the first prefix two threads encode [`TIME_TOLERANCE`] faster than one thread is saved as `min_bytes_per_thread`.
+//! 3. the smallest block: the sample encoded in ever larger blocks with `bsc` and with `bwt -> mtf -> arcode`. the
+//!    smallest blocks both encode within [`SIZE_TOLERANCE`] of the size and [`TIME_TOLERANCE`] of the time of the
+//!    largest ones are saved as `min_block_size`.
+//!
+//! `--no-save` prints the results without saving them. a larger sample gives steadier numbers and takes longer.
+use std::{
+    process, thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    algorithms::{
+        arcode::ArithmeticCoding,
+        bsc::Bsc,
+        bwt::Bwt,
+        heuristics::{self, Thresholds},
+        mtf::Mtf,
+        pipeline::CompressionPipeline,
+    },
+    cli::CalibrateArgs,
+    config::UserConfig,
+    mutator::Mutator,
+    units::{ByteSize, Elapsed},
+};
+
+/// How much slower than the best a measurement may be and still count as just as fast.
+const TIME_TOLERANCE: f64 = 0.10;
+/// How much larger than the best an output may be and still count as just as small.
+const SIZE_TOLERANCE: f64 = 0.02;
+/// Every measurement is the best of this many.
+const ROUNDS: usize = 3;
+/// The smallest prefix tried for the least work worth a thread.
+const MIN_PROBED_WORK: usize = 16 * 1024;
+/// The smallest block tried.
+const MIN_PROBED_BLOCK: usize = 64 * 1024;
+
+pub fn calibrate(args: CalibrateArgs) {
+    let sample = sample(args.sample_size as usize);
+    let machine_threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    println!(
+        "calibrating on a {} sample with up to {} threads, this takes a while.",
+        ByteSize(sample.len() as u64),
+        machine_threads
+    );
+
+    let threads = calibrate_threads(&sample, machine_threads);
+    let min_bytes_per_thread = match threads {
+        1 => {
+            println!("least work per thread: only one thread is worth using, keeping the default.");
+            Thresholds::default().min_bytes_per_thread
+        }
+        _ => calibrate_work_per_thread(&sample),
+    };
+    heuristics::set_threads(threads);
+    let min_block_size = calibrate_block_size(&sample);
+    let thresholds = Thresholds {
+        min_block_size,
+        min_bytes_per_thread,
+    };
+
+    let summary = format!(
+        "threads = {}, min_block_size = {}, min_bytes_per_thread = {}",
+        threads,
+        ByteSize(min_block_size as u64),
+        ByteSize(min_bytes_per_thread as u64)
+    );
+    if args.no_save {
+        println!("{} (not saved, --no-save)", summary);
+        return;
+    }
+    let mut config = UserConfig::load().unwrap_or_else(|e| {
+        eprintln!("[error] stackpack: failed to load the user config: {}", e);
+        process::exit(1);
+    });
+    config.threads = Some(threads.try_into().expect("at least one thread is measured"));
+    config.thresholds = Some(thresholds);
+    match config.save() {
+        Ok(path) => println!("saved {} to {}", summary, path.display()),
+        Err(e) => {
+            eprintln!("[error] stackpack: failed to save the user config: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Generated text of `len` bytes: words of a small vocabulary with some numbers and punctuation, compressible about
+/// as well as prose. xorshift, so every run sees the same sample.
+pub(super) fn sample(len: usize) -> Vec<u8> {
+    const WORDS: &str = "the of and to in stack pack block thread stage pipeline archive frame sort suffix transform encode \
+                         decode a is that with for on by from data model context symbol entropy run length window match \
+                         literal offset";
+    let words: Vec<&str> = WORDS.split_whitespace().collect();
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let mut sample = Vec::with_capacity(len + 16);
+    while sample.len() < len {
+        let roll = next();
+        match roll % 16 {
+            0 => sample.extend_from_slice((roll >> 8).rem_euclid(10_000).to_string().as_bytes()),
+            1 => sample.extend_from_slice(b".\n"),
+            2 => sample.extend_from_slice(b","),
+            _ => sample.extend_from_slice(words[(roll >> 8) as usize % words.len()].as_bytes()),
+        }
+        sample.push(b' ');
+    }
+    sample.truncate(len);
+    sample
+}
+
+/// The fastest of [`ROUNDS`] runs of `run`.
+fn best_of(mut run: impl FnMut()) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .expect("there is at least one round")
+}
+
+/// How long `bwt` takes over `data` on `threads` threads, repeated so that at least `work` bytes are encoded.
+fn time_bwt(data: &[u8], threads: usize, work: usize) -> Duration {
+    heuristics::set_threads(threads);
+    // every block is given all the threads, however small it is
+    heuristics::set_thresholds(Thresholds {
+        min_bytes_per_thread: 1,
+        ..Thresholds::default()
+    });
+    let repeats = work.div_ceil(data.len().max(1)).max(1);
+    let mut stage = Bwt;
+    let mut output = Vec::new();
+    best_of(|| {
+        for _ in 0..repeats {
+            stage.drive_mutation(data, &mut output).expect("bwt encodes any input");
+        }
+    })
+}
+
+fn calibrate_threads(sample: &[u8], machine_threads: usize) -> usize {
+    let mut counts: Vec<usize> = (0..).map(|power| 1 << power).take_while(|&count| count < machine_threads).collect();
+    counts.push(machine_threads);
+    let timings: Vec<(usize, Duration)> = counts
+        .into_iter()
+        .map(|count| (count, time_bwt(sample, count, sample.len())))
+        .collect();
+    let fastest = timings
+        .iter()
+        .map(|&(_, elapsed)| elapsed)
+        .min()
+        .expect("one thread is always measured");
+    let (threads, _) = *timings
+        .iter()
+        .find(|&&(_, elapsed)| elapsed.as_secs_f64() <= fastest.as_secs_f64() * (1.0 + TIME_TOLERANCE))
+        .expect("the fastest is within the tolerance of itself");
+    let report = timings
+        .iter()
+        .map(|&(count, elapsed)| format!("{}: {}", count, Elapsed(elapsed)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!("threads: {} -> {}", report, threads);
+    threads
+}
+
+fn calibrate_work_per_thread(sample: &[u8]) -> usize {
+    let mut reports = Vec::new();
+    let mut chosen = None;
+    let mut work = MIN_PROBED_WORK.min(sample.len());
+    loop {
+        let prefix = &sample[..work];
+        let one = time_bwt(prefix, 1, sample.len()).as_secs_f64();
+        let two = time_bwt(prefix, 2, sample.len()).as_secs_f64();
+        let speedup = one / two.max(f64::MIN_POSITIVE);
+        reports.push(format!("{}: {:.2}x", ByteSize(work as u64), speedup));
+        if speedup >= 1.0 + TIME_TOLERANCE {
+            chosen = Some(work / 2);
+            break;
+        }
+        if work == sample.len() {
+            break;
+        }
+        work = (work * 2).min(sample.len());
+    }
+    // two threads never paid off on the sample, so anything it holds is too little for a second thread
+    let min_bytes_per_thread = chosen.unwrap_or(sample.len()).max(1);
+    println!(
+        "least work per thread, speedup of two threads: {} -> {}",
+        reports.join(", "),
+        ByteSize(min_bytes_per_thread as u64)
+    );
+    min_bytes_per_thread
+}
+
+/// The size and encoding time of `sample` encoded in blocks of `block_size` bytes by `pipeline`.
+fn encode_in_blocks(pipeline: &mut CompressionPipeline, sample: &[u8], block_size: usize) -> (usize, Duration) {
+    let mut size = 0;
+    let mut output = Vec::new();
+    let elapsed = best_of(|| {
+        size = 0;
+        for block in sample.chunks(block_size) {
+            pipeline
+                .drive_mutation(block, &mut output)
+                .expect("the calibration pipelines encode any input");
+            size += output.len();
+        }
+    });
+    (size, elapsed)
+}
+
+fn calibrate_block_size(sample: &[u8]) -> usize {
+    // blocks of any size are taken as given
+    heuristics::set_thresholds(Thresholds {
+        min_block_size: 1,
+        ..heuristics::thresholds()
+    });
+    let mut block_sizes: Vec<usize> = (0..)
+        .map(|power| MIN_PROBED_BLOCK << power)
+        .take_while(|&block_size| block_size < sample.len())
+        .collect();
+    block_sizes.push(sample.len());
+    let pipelines = [
+        CompressionPipeline::new().with_algorithm(Bsc),
+        CompressionPipeline::new()
+            .with_algorithm(Bwt)
+            .with_algorithm(Mtf)
+            .with_algorithm(ArithmeticCoding),
+    ];
+
+    let mut smallest = MIN_PROBED_BLOCK.min(sample.len());
+    for mut pipeline in pipelines {
+        let results: Vec<(usize, usize, Duration)> = block_sizes
+            .iter()
+            .map(|&block_size| {
+                let (size, elapsed) = encode_in_blocks(&mut pipeline, sample, block_size);
+                (block_size, size, elapsed)
+            })
+            .collect();
+        let &(_, best_size, best_time) = results.last().expect("the whole sample is always a block size");
+        let (block_size, _, _) = *results
+            .iter()
+            .find(|&&(_, size, elapsed)| {
+                size as f64 <= best_size as f64 * (1.0 + SIZE_TOLERANCE)
+                    && elapsed.as_secs_f64() <= best_time.as_secs_f64() * (1.0 + TIME_TOLERANCE)
+            })
+            .unwrap_or(results.last().expect("the whole sample is always a block size"));
+        let report = results
+            .iter()
+            .map(|&(block_size, size, elapsed)| {
+                format!(
+                    "{}: {:+.1}% in {}",
+                    ByteSize(block_size as u64),
+                    (size as f64 / best_size.max(1) as f64 - 1.0) * 100.0,
+                    Elapsed(elapsed)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!("smallest block for {}: {} -> {}", pipeline, report, ByteSize(block_size as u64));
+        smallest = smallest.max(block_size);
+    }
+    smallest
+}
@@ -0,0 +1,58 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, ErrorKind, Write},
+    path::Path,
+    process,
+};
+
+use crate::{
+    archive::{read_entry, read_entry_from},
+    cli::{
+        CatArgs,
+        extract::{archive_table, parse_archive},
+    },
+    container::ContainerReader,
+    io::{IoPolicy, is_stdio},
+    volumes::{read_input, volume_base},
+};
+
+pub fn cat(args: CatArgs) {
+    let input_path = &args.input;
+    let data = if !is_stdio(input_path) && input_path.is_file() && volume_base(input_path).is_none() {
+        cat_file(input_path, &args.entry)
+    } else {
+        let input_data = read_input(input_path, IoPolicy::default()).expect("Failed to read input file");
+        let (container, table) = parse_archive(input_path, &input_data);
+        let mut pipeline = container.pipeline.clone();
+        read_entry(&mut pipeline, &table, container.payload, &args.entry)
+    };
+    let data = match data {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("[error] stackpack: {}: {}", input_path.display(), e);
+            process::exit(1);
+        }
+    };
+    let mut stdout = io::stdout().lock();
+    match stdout.write_all(&data).and_then(|()| stdout.flush()) {
+        // the reader went away, e.g. `| head`, which is not an error worth reporting
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => {}
+        result => result.expect("Failed to write to stdout"),
+    }
+}
+
+/// Reads `entry` from an archive on disk, reading only the header and the frame that holds the entry.
+fn cat_file(input_path: &Path, entry: &str) -> anyhow::Result<Vec<u8>> {
+    let file = File::open(input_path).expect("Failed to read input file");
+    let mut reader = match ContainerReader::new(BufReader::new(file)) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("[error] stackpack: {}: {}", input_path.display(), e);
+            process::exit(1);
+        }
+    };
+    let mut header = reader.header();
+    let table = archive_table(input_path, &mut header);
+    let mut pipeline = header.pipeline;
+    read_entry_from(&mut pipeline, &table, &mut reader, entry)
+}
@@ -0,0 +1,73 @@
+use std::process;
+
+use crate::{
+    cli::{self, ConvertArgs, PipelineSelection, pipeline},
+    container::{ContainerOptions, FileMetadata, is_container, write_container},
+    io::write_file,
+    mutator::Mutator,
+    sidecar::{read_sidecar, sidecar_path},
+    signing::load_signing_key,
+    volumes::{read_input, volume_base},
+};
+
+pub fn convert(args: ConvertArgs) {
+    let input_path = &args.input;
+    let policy = args.io.policy();
+    if !cli::check_output(&args.output, policy).unwrap_or_else(|e| e.exit()) {
+        return;
+    }
+    let payload = read_input(input_path, policy).expect("Failed to read input file");
+    if is_container(&payload) {
+        eprintln!("[error] stackpack: {} is already a stackpack container.", input_path.display());
+        process::exit(1);
+    }
+
+    let mut pipeline = match args.pipeline_selection() {
+        PipelineSelection::Default => {
+            match read_sidecar(&volume_base(input_path).unwrap_or(input_path.clone())).expect("Failed to read pipeline sidecar") {
+                Some(pipeline) => pipeline,
+                None => {
+                    eprintln!(
+                        "[error] stackpack: {} has no {} sidecar. pass the pipeline used to encode it with --using, --from_file or --preset.",
+                        input_path.display(),
+                        sidecar_path(input_path).display()
+                    );
+                    process::exit(1);
+                }
+            }
+        }
+        selection => pipeline::build_pipeline(selection).unwrap_or_else(|e| e.exit()),
+    };
+
+    // the container records the checksum and size of the original data, which only decoding can tell. this also
+    // catches a wrong pipeline before it is written into the container.
+    let mut original = Vec::new();
+    if let Err(e) = pipeline.revert_mutation(&payload, &mut original) {
+        eprintln!(
+            "[error] stackpack: {} doesn't decode with the pipeline \"{}\": {}",
+            input_path.display(),
+            pipeline,
+            e
+        );
+        process::exit(1);
+    }
+
+    let options = ContainerOptions {
+        checksum: args.checksum,
+        metadata: FileMetadata {
+            name: args.name.clone(),
+            modified: None,
+        },
+        signing_key: args
+            .sign
+            .as_deref()
+            .map(|key| load_signing_key(key).expect("Failed to load signing key")),
+        ..ContainerOptions::default()
+    };
+    let mut container = Vec::new();
+    write_container(&pipeline, &options, &original, &payload, &mut container).expect("Failed to build container");
+    write_file(&args.output, &container, policy).expect("Failed to write output file");
+    if_tracing! {{
+        tracing::info!(event = "convert_complete", input = %input_path.display(), output = %args.output.display(), pipeline = %pipeline, original_len = original.len(), "wrapped raw output into a container");
+    }}
+}
@@ -1,35 +1,89 @@
 use core::time::Duration;
-use std::fs;
-use std::path::Path;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::Result;
 use voxell_timer::time_fn;
 use walkdir::WalkDir;
 
 use crate::{
-    cli::{CorpusArgs, PipelineSelection, pipeline},
+    algorithms::pipeline::CompressionPipeline,
+    cli::{
+        CorpusArgs, OutputFormat, PipelineSelection, ReportArgs,
+        error::{Classify, CliResult, ErrorClass},
+        pipeline,
+        report::Report,
+        summary::{self, Run, Soak},
+    },
+    io::{IoPolicy, read_file},
     mutator::Mutator,
+    registered::{self, RegisteredCompressor},
+    units::{ByteSize, Elapsed, Grouped, Ratio, Throughput},
+    verbosity,
+    walk::PathFilter,
 };
 
-pub fn corpus(args: CorpusArgs) {
-    run_folder(Path::new("./test_data"), args.pipeline_selection(), true);
+/// Where the corpus files are read from.
+const CORPUS_DIR: &str = "./test_data";
+/// The longest pipeline `--shuffle` puts together.
+const MAX_SHUFFLED_STAGES: usize = 3;
+
+pub fn corpus(args: CorpusArgs) -> CliResult {
+    if args.format == OutputFormat::Json {
+        summary::start("corpus");
+    }
+    if args.shuffle {
+        return soak(&args);
+    }
+    run_folder(
+        Path::new(CORPUS_DIR),
+        args.pipeline_selection(),
+        &args.filter.filter(),
+        args.report,
+        args.io.policy(),
+    )
 }
 
-pub fn run_folder(input_dir: &Path, selection: PipelineSelection, write_results: bool) {
-    for entry in WalkDir::new(input_dir)
-        .into_iter()
+pub fn run_folder(input_dir: &Path, selection: PipelineSelection, filter: &PathFilter, report: ReportArgs, policy: IoPolicy) -> CliResult {
+    let resolved = pipeline::build_pipeline(selection)?;
+    pipeline::announce_pipeline(&resolved);
+
+    for entry in filter
+        .walk(WalkDir::new(input_dir))
         .filter_map(|e| e.ok())
         .filter(|e| e.file_type().is_file() || e.file_type().is_symlink())
     {
         let path = entry.path();
-        let mut pipeline = pipeline::build_pipeline(selection.clone());
+        let mut pipeline = resolved.clone();
 
-        let input = fs::read(path).unwrap();
+        let input = read_file(path, policy).classify(ErrorClass::Io, || format!("failed to read {}", path.display()))?;
         let mut compressed = Vec::new();
         let (res, comp_dur) = time_fn(|| pipeline.drive_mutation(&input, &mut compressed));
 
         let mut decompressed = Vec::new();
-        let (_, decomp_dur) = time_fn(|| pipeline.revert_mutation(&compressed, &mut decompressed));
+        let (decode_res, decomp_dur) = time_fn(|| pipeline.revert_mutation(&compressed, &mut decompressed));
+        let failure = match (&res, &decode_res) {
+            (Err(e), _) => Some(format!("encoding failed: {}", e)),
+            (_, Err(e)) => Some(format!("decoding failed: {}", e)),
+            _ if decompressed != input => Some("the decoded data differs from the input".to_string()),
+            _ => None,
+        };
+        let report_path = match &failure {
+            Some(error) if report.report => Report::round_trip(path, &resolved, &input, error).save(path, report, policy),
+            _ => None,
+        };
+        let mut run = Run::new(path, &resolved, input.len(), compressed.len())
+            .encoded_in(comp_dur)
+            .decoded_in(decomp_dur);
+        if let Some(error) = failure {
+            run = run.failed(error);
+        }
+        run.report = report_path.as_ref().map(|path| path.display().to_string());
+        summary::record(run);
         validate_and_print_results(
             res,
             path,
@@ -38,30 +92,152 @@ pub fn run_folder(input_dir: &Path, selection: PipelineSelection, write_results:
             &decompressed[..],
             comp_dur,
             decomp_dur,
-            write_results,
+            report_path,
         );
     }
+    Ok(())
 }
 
-fn save_failed_equality_results_to_file(expected: &[u8], intermediate: &[u8], got: &[u8], path: &Path) {
-    let filename = path.file_name().unwrap().to_str().unwrap();
-    let target_expected = format!("{}.expected.bin", filename);
-    let target_intermediate = format!("{}.intermediate.bin", filename);
-    let target_got = format!("{}.got.bin", filename);
+/// Splitmix64, so a seed samples the same file and pipeline on every platform and in every build.
+struct Sampler(u64);
 
-    if fs::exists(&target_expected).unwrap() {
-        fs::rename(&target_expected, format!("{}.old", &target_expected)).unwrap();
-    };
-    if fs::exists(&target_intermediate).unwrap() {
-        fs::rename(&target_intermediate, format!("{}.old", &target_intermediate)).unwrap();
+impl Sampler {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn pipeline(&mut self, stages: &[RegisteredCompressor]) -> CompressionPipeline {
+        let mut pipeline = CompressionPipeline::new();
+        for _ in 0..=self.below(MAX_SHUFFLED_STAGES) {
+            pipeline.push_algorithm(stages[self.below(stages.len())].clone());
+        }
+        pipeline
+    }
+}
+
+/// How a sampled round trip went.
+enum Outcome {
+    Passed,
+    /// The encoder turned the input down or panicked, which unfinished stages do, the way `selftest` sees it.
+    Refused,
+    Failed(String),
+}
+
+fn round_trip(mut pipeline: CompressionPipeline, data: &[u8]) -> Outcome {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let mut encoded = Vec::new();
+    let encode = panic::catch_unwind(AssertUnwindSafe(|| pipeline.drive_mutation(data, &mut encoded)));
+    let outcome = match encode {
+        Ok(Ok(())) => {
+            let mut decoded = Vec::new();
+            match panic::catch_unwind(AssertUnwindSafe(|| pipeline.revert_mutation(&encoded, &mut decoded))) {
+                Ok(Ok(())) if decoded == data => Outcome::Passed,
+                Ok(Ok(())) => Outcome::Failed("the decoded data differs from the input".to_string()),
+                Ok(Err(e)) => Outcome::Failed(format!("decoding failed: {}", e)),
+                Err(_) => Outcome::Failed("decoding panicked".to_string()),
+            }
+        }
+        _ => Outcome::Refused,
     };
-    if fs::exists(&target_got).unwrap() {
-        fs::rename(&target_got, format!("{}.old", &target_got)).unwrap();
+    panic::set_hook(default_hook);
+    outcome
+}
+
+/// Samples random files, and random pipelines unless one is given, until the `--duration` runs out.
+fn soak(args: &CorpusArgs) -> CliResult {
+    let policy = args.io.policy();
+    let files = args
+        .filter
+        .filter()
+        .walk(WalkDir::new(CORPUS_DIR))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() || e.file_type().is_symlink())
+        .map(|e| e.into_path())
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        eprintln!("[error] stackpack: there are no files to sample in {}.", CORPUS_DIR);
+        process::exit(1);
+    }
+    let fixed = match args.pipeline_selection() {
+        PipelineSelection::Default => None,
+        selection => Some(pipeline::build_pipeline(selection)?),
     };
+    if let Some(fixed) = &fixed {
+        pipeline::announce_pipeline(fixed);
+    }
+    let stages = registered::stages();
+
+    let mut seed = args.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64)
+    });
+    let start = Instant::now();
+    let (mut runs, mut refused, mut failed) = (0_u64, 0_u64, 0_u64);
+    // at least one sample is taken, so `--seed <seed> --duration 0` replays exactly one
+    loop {
+        let mut sampler = Sampler(seed);
+        let path = &files[sampler.below(files.len())];
+        let pipeline = fixed.clone().unwrap_or_else(|| sampler.pipeline(&stages));
+        let input = read_file(path, policy).classify(ErrorClass::Io, || format!("failed to read {}", path.display()))?;
+        match round_trip(pipeline.clone(), &input) {
+            Outcome::Passed => {}
+            Outcome::Refused => refused += 1,
+            Outcome::Failed(error) => {
+                failed += 1;
+                print_line(format!("FAILED {} with {}: {} (seed {})", path.display(), pipeline, error, seed));
+                let mut run = Run::new(path, &pipeline, input.len(), 0).failed(&error);
+                run.seed = Some(seed);
+                if args.report.report {
+                    let report_path = Report::round_trip(path, &pipeline, &input, &error).save(path, args.report, policy);
+                    run.report = report_path.map(|path| path.display().to_string());
+                }
+                summary::record(run);
+            }
+        }
+        // only failures are kept in the summary, the stages of the other round trips are dropped
+        verbosity::take_stages();
+        runs += 1;
+        seed = sampler.next();
+        if args.duration.is_some_and(|duration| start.elapsed() >= duration) {
+            break;
+        }
+    }
+    print_line(format!(
+        "{} round trips in {}, {} failed, {} turned down by the encoder",
+        Grouped(runs),
+        Elapsed(start.elapsed()),
+        Grouped(failed),
+        Grouped(refused)
+    ));
+    summary::record_soak(Soak {
+        round_trips: runs,
+        failed,
+        turned_down: refused,
+        duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+    });
+    if failed > 0 {
+        summary::finish();
+        process::exit(1);
+    }
+    Ok(())
+}
 
-    fs::write(&target_expected, expected).unwrap();
-    fs::write(&target_intermediate, intermediate).unwrap();
-    fs::write(&target_got, got).unwrap();
+/// Prints a line of the soak results, on stderr when stdout is taken by `--format json`.
+fn print_line(line: String) {
+    match summary::is_active() {
+        true => eprintln!("{}", line),
+        false => println!("{}", line),
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -73,59 +249,77 @@ fn validate_and_print_results(
     got: &[u8],
     compression_time: Duration,
     decompression_time: Duration,
-    write_results: bool,
+    report_path: Option<PathBuf>,
 ) {
     let equality = expected == got;
     let original_size = expected.len();
-    let compressed_size = intermediate.len();
-    let decompressed_size = got.len();
-
-    let ratio = if original_size == 0 {
-        1.0
-    } else {
-        compressed_size as f64 / original_size as f64
-    };
-
-    let bytes_saved = original_size as isize - compressed_size as isize;
-    let percent_saved = if original_size == 0 {
-        0.0
-    } else {
-        (bytes_saved as f64) / (original_size as f64) * 100.0
-    };
+    let ratio = Ratio::new(original_size, intermediate.len());
 
     let passed = equality && res.is_ok();
 
     let passed_string = if passed { "PASSED" } else { "FAILED" };
-    if !equality && write_results {
-        save_failed_equality_results_to_file(expected, intermediate, got, path);
-    }
 
     if_tracing! {{
         tracing::info!("==== {} {} ====", passed_string, path.display());
 
         tracing::debug!(
-            "encode: {:.0?}\ndecode: {:.0?}\noriginal: {} bytes\ncompressed: {} bytes\ndecompressed: {} bytes\nratio: {:.1}% (compressed/original)\nsaved: {:+} bytes ({:+.1}%)",
-            compression_time,
-            decompression_time,
-            original_size,
-            compressed_size,
-            decompressed_size,
-            ratio * 100.0,
-            bytes_saved,
-            percent_saved,
+            "encode: {} ({})\ndecode: {} ({})\noriginal: {} ({} bytes)\ncompressed: {} ({} bytes)\ndecompressed: {} ({} bytes)\nratio: {} (compressed/original)\nsaved: {:+.1}%",
+            Elapsed(compression_time),
+            Throughput { bytes: original_size as u64, elapsed: compression_time },
+            Elapsed(decompression_time),
+            Throughput { bytes: original_size as u64, elapsed: decompression_time },
+            ByteSize(original_size as u64),
+            Grouped(original_size as u64),
+            ByteSize(intermediate.len() as u64),
+            Grouped(intermediate.len() as u64),
+            ByteSize(got.len() as u64),
+            Grouped(got.len() as u64),
+            ratio,
+            ratio.percent_saved(),
         );
 
         if !passed {
-            let filename = path.file_name().and_then(|s| s.to_str()).unwrap_or("unknown");
-            let err_msg = res.as_ref().err().map(|e| e.to_string()).unwrap_or_else(|| "error".into());
-            tracing::error!(
-                "error: {}\nsee {}.expected.bin and {}.got.bin for details",
-                err_msg, filename, filename
-            );
+            let err_msg = res.as_ref().err().map(|e| e.to_string()).unwrap_or_else(|| "the decoded data differs from the input".into());
+            match &report_path {
+                Some(report_path) => tracing::error!("error: {}\nsee {} for details", err_msg, report_path.display()),
+                None => tracing::error!("error: {}\npass --report to get a crash report", err_msg),
+            }
         }
     }};
 
-    if_not_tracing! {
-        eprintln!("{} {}", passed_string, path.display());
+    if passed && verbosity::is_quiet() {
+        return;
+    }
+    eprintln!(
+        "{} {} {} -> {} ({})",
+        passed_string,
+        path.display(),
+        ByteSize(original_size as u64),
+        ByteSize(intermediate.len() as u64),
+        ratio
+    );
+    if verbosity::is_verbose() {
+        eprintln!(
+            "  encode {} ({}), decode {} ({})",
+            Elapsed(compression_time),
+            Throughput {
+                bytes: original_size as u64,
+                elapsed: compression_time
+            },
+            Elapsed(decompression_time),
+            Throughput {
+                bytes: original_size as u64,
+                elapsed: decompression_time
+            }
+        );
+    }
+    if !passed {
+        match &res {
+            Err(e) => eprintln!("  error: {}", e),
+            Ok(()) => eprintln!("  error: the decoded data differs from the input"),
+        }
+    }
+    if let Some(report_path) = &report_path {
+        eprintln!("see {} for details", report_path.display());
     }
 }
@@ -1,33 +1,668 @@
-use std::fs;
-
-if_tracing! {
-    use voxell_timer::time_fn;
-}
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 use crate::{
-    cli::{DecodeArgs, pipeline},
+    algorithms::pipeline::{CompressionPipeline, TimeLimits},
+    archive::{ArchiveTable, PathPolicy, decode_frames, extract_all, write_tar},
+    blocks::{ByteRange, decode_blocks, decode_blocks_with, decode_range},
+    checksum::{Checksum, ChecksumWriter, DigestHasher},
+    cli::{
+        self, DecodeArgs, OutputFormat, PipelineSelection, brute,
+        error::{Classify, CliError, CliResult, ErrorClass},
+        pipeline,
+        report::Report,
+        stats,
+        summary::{self, Run},
+    },
+    container::{Container, FILE_EXTENSION, is_container, parse_container},
+    delta::apply_delta,
+    events,
+    formats::detect,
+    io::{is_stdio, read_file, write_file},
     mutator::Mutator,
+    progress,
+    sidecar::{read_sidecar, sidecar_path},
+    signing::load_verifying_key,
+    units::{ByteSize, Elapsed, Throughput},
+    verbosity,
+    volumes::{read_input, volume_base},
 };
+use voxell_timer::time_fn;
 
-pub fn decode(args: DecodeArgs) {
+pub fn decode(mut args: DecodeArgs) -> CliResult {
+    let time_limits = args.time_limits.start();
+    if args.output.is_none() && !args.checksum_only && args.to_tar.is_none() {
+        args.output = Some(match cli::decode_output_path(&args.input) {
+            _ if is_stdio(&args.input) => PathBuf::from("-"),
+            Some(output) => output,
+            None => {
+                return Err(CliError::usage(format!(
+                    "{} doesn't end in .{}, pass the output path.",
+                    args.input.display(),
+                    FILE_EXTENSION
+                )));
+            }
+        });
+    }
     let input_path = &args.input;
-    let output_path = &args.output;
-    let mut pipeline = pipeline::build_pipeline(args.pipeline_selection());
+    let policy = args.io.policy();
+    if args.format == OutputFormat::Json {
+        if args.output.as_deref().or(args.to_tar.as_deref()).is_some_and(is_stdio) {
+            return Err(CliError::usage(
+                "--format json prints the results to stdout, write the output to a file.",
+            ));
+        }
+        summary::start("dec");
+    }
+    if args.stats {
+        stats::start();
+    }
+    // outputs named after the original file are only known once the container is read, see below
+    if let Some(output_path) = args.output.as_deref().filter(|output| !args.checksum_only && !output.is_dir())
+        && !cli::check_output(output_path, policy)?
+    {
+        return Ok(());
+    }
+    if let Some(tar_path) = &args.to_tar
+        && !cli::check_output(tar_path, policy)?
+    {
+        return Ok(());
+    }
+    let input_data = read_input(input_path, policy).classify(ErrorClass::Io, || format!("failed to read {}", input_path.display()))?;
 
-    let compressed_data = fs::read(input_path).expect("Failed to read input file");
+    let selection = args.pipeline_selection();
+    // the embedded pipeline is authoritative, the cli selection is only used for inputs that don't carry one or
+    // to override the embedded pipeline with --force-pipeline.
+    let (mut pipeline, compressed_data, container) = if is_container(&input_data) {
+        let container = parse_container(&input_data).classify(ErrorClass::Data, || input_path.display().to_string())?;
+        verify_signature(&args, &container, 1)?;
+        if !container.trailing.is_empty() {
+            check_concatenable(&args, &container, 1)?;
+        }
+        if_tracing! {{
+            tracing::info!(event = "container_detected", input = %input_path.display(), version = container.version, pipeline = %container.pipeline, "using embedded pipeline");
+        }}
+        let mut pipeline = container.pipeline.clone();
+        if selection != PipelineSelection::Default {
+            let requested = pipeline::build_pipeline(selection)?;
+            if requested.fingerprint() != container.pipeline.fingerprint() {
+                if !args.force_pipeline {
+                    return Err(CliError::msg(
+                        ErrorClass::Pipeline,
+                        format!(
+                            "{} embeds the pipeline \"{}\", not the requested pipeline \"{}\". pass --force-pipeline to decode with the requested one anyway.",
+                            input_path.display(),
+                            container.pipeline,
+                            requested
+                        ),
+                    ));
+                }
+                events::warn(format_args!(
+                    "{} embeds the pipeline \"{}\", decoding with the requested pipeline \"{}\" instead.",
+                    input_path.display(),
+                    container.pipeline,
+                    requested
+                ));
+                pipeline = requested;
+            }
+        }
+        (pipeline, container.payload, Some(container))
+    } else {
+        if args.verify_sig.is_some() {
+            return Err(CliError::msg(
+                ErrorClass::Data,
+                format!(
+                    "{} failed signature verification: only containers can be signed.",
+                    input_path.display()
+                ),
+            ));
+        }
+        let pipeline = match selection {
+            // the sidecar is written next to the output `enc` was given, not next to its volumes
+            PipelineSelection::Default => {
+                let sidecar = read_sidecar(&volume_base(input_path).unwrap_or(input_path.clone()))
+                    .classify(ErrorClass::Pipeline, || "failed to read the pipeline sidecar")?;
+                match sidecar {
+                    Some(pipeline) => {
+                        if_tracing! {{
+                            tracing::info!(event = "sidecar_found", input = %input_path.display(), "using pipeline from sidecar");
+                        }}
+                        pipeline
+                    }
+                    None => match args.brute_force_depth {
+                        // a known format is decoded with its stage, there is nothing to search
+                        Some(depth) if detect(&input_data).is_none() => brute::search(
+                            input_path,
+                            &input_data,
+                            depth,
+                            args.brute_state.as_deref(),
+                            args.max_output_size.map(|limit| usize::try_from(limit).unwrap_or(usize::MAX)),
+                            time_limits,
+                        )?,
+                        _ => foreign_pipeline(input_path, &input_data)?,
+                    },
+                }
+            }
+            selection => pipeline::build_pipeline(selection)?,
+        };
+        (pipeline, &input_data[..], None)
+    };
+    // --head is the range at the start of the output, so a block mode container only decodes its leading blocks
+    let range = args.range.or(args.head.map(|len| ByteRange { offset: 0, len: len as u64 }));
+    if let Some(limit) = args.max_output_size {
+        let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+        // the recorded size is only a claim, the pipeline enforces the limit while decoding as well. a range may
+        // only need a few blocks of a larger file, so it is left to the pipeline.
+        if let Some(original_size) = container.as_ref().and_then(|container| container.original_size)
+            && range.is_none()
+            && original_size > limit as u64
+        {
+            return Err(CliError::msg(
+                ErrorClass::Data,
+                format!(
+                    "{} decodes to {} bytes, over the --max-output-size limit of {} bytes.",
+                    input_path.display(),
+                    original_size,
+                    limit
+                ),
+            ));
+        }
+        pipeline.set_max_output_size(Some(limit));
+    }
+    pipeline.set_time_limits(time_limits);
+    pipeline::dump_stages(&mut pipeline, args.dump_stages.as_deref())?;
+    if let Some(container) = &container
+        && let Some(table) = &container.archive
+    {
+        return decode_archive(&args, container, table, &mut pipeline);
+    }
+    if args.to_tar.is_some() {
+        return Err(CliError::usage(format!(
+            "{} is not a directory archive, --to-tar only works on archives.",
+            input_path.display()
+        )));
+    }
+    let reference = match (
+        container.as_ref().and_then(|container| container.delta_base.as_ref()),
+        &args.delta_against,
+    ) {
+        (Some(base), Some(reference_path)) => {
+            let reference = read_file(reference_path, policy).classify(ErrorClass::Io, || {
+                format!("failed to read the delta reference {}", reference_path.display())
+            })?;
+            base.check(&reference).classify(ErrorClass::Usage, || {
+                format!(
+                    "{} is not the reference {} was encoded against",
+                    reference_path.display(),
+                    input_path.display()
+                )
+            })?;
+            Some(reference)
+        }
+        (Some(base), None) => {
+            return Err(CliError::usage(format!(
+                "{} is a delta against a {} byte reference with {}, pass that file with --delta-against.",
+                input_path.display(),
+                base.len,
+                base.checksum
+            )));
+        }
+        (None, Some(reference_path)) => {
+            events::warn(format_args!(
+                "{} is not a delta, ignoring --delta-against {}.",
+                input_path.display(),
+                reference_path.display()
+            ));
+            None
+        }
+        (None, None) => None,
+    };
+    let block_size = container.as_ref().and_then(|container| container.block_size);
+    let trailing = container.as_ref().map_or(&[][..], |container| container.trailing);
+    // a range of a block mode container decodes only some blocks, so there is nothing to check the checksum against.
+    // with more streams after it, the range is of all of them and cut out of the output instead.
+    let partial = block_size.is_some() && range.is_some() && trailing.is_empty();
     let mut decompressed_data = Vec::new();
+    if args.progress {
+        // the recorded size is that of the whole original, not of a delta or a range of it
+        let len = container
+            .as_ref()
+            .and_then(|container| container.original_size)
+            .filter(|_| reference.is_none() && range.is_none());
+        progress::start(format!("decoding {}", input_path.display()), len, pipeline.stages().len());
+    }
+    if args.checksum_only
+        && reference.is_none()
+        && range.is_none()
+        && let Some(container) = &container
+    {
+        return checksum_streams(&args, &mut pipeline, container, time_limits);
+    }
+    // only a payload the pipeline encoded as a whole can be rerun stage by stage in a crash report
+    let whole_payload = block_size.is_none().then_some(compressed_data);
+    let decode_payload = |pipeline: &mut CompressionPipeline, out: &mut Vec<u8>| match (block_size, range) {
+        (Some(block_size), Some(range)) if partial => decode_range(pipeline, compressed_data, block_size, range, out),
+        (Some(_), _) => decode_blocks(pipeline, compressed_data, out),
+        (None, _) => pipeline.revert_mutation(compressed_data, out),
+    };
+    let (result, decomp_dur) = time_fn(|| decode_payload(&mut pipeline, &mut decompressed_data));
+    if let Err(e) = result {
+        progress::finish();
+        report_failure(&args, &pipeline, whole_payload, &e.to_string());
+        return Err(CliError::new(
+            ErrorClass::Stage,
+            e.context(format!("failed to decode {}", input_path.display())),
+        ));
+    }
     if_tracing! {{
-        let ((), decomp_dur) = time_fn(|| {
-            pipeline
-                .revert_mutation(&compressed_data, &mut decompressed_data)
-                .expect("Decompression failed")
-        });
-        tracing::info!(event = "decode_complete", input = %input_path.display(), output = %output_path.display(), elapsed_ms = ?decomp_dur, decompressed_len = decompressed_data.len(), "decode finished");
+        tracing::info!(event = "decode_complete", input = %input_path.display(), elapsed_ms = ?decomp_dur, decompressed_len = decompressed_data.len(), "decode finished");
     }};
-    if_not_tracing! {{
+    progress::finish();
+    if let Some(reference) = &reference {
+        decompressed_data = apply_delta(reference, &decompressed_data).classify(ErrorClass::Data, || "failed to apply the delta")?;
         pipeline
-            .revert_mutation(&compressed_data, &mut decompressed_data)
-            .expect("Decompression failed");
-    }};
-    fs::write(output_path, decompressed_data).expect("Failed to write output file");
+            .check_output_size(decompressed_data.len())
+            .classify(ErrorClass::Data, || "failed to apply the delta")?;
+    }
+    if let Some(container) = &container
+        && !partial
+        && let Err(e) = container.verify(&decompressed_data)
+    {
+        report_failure(&args, &pipeline, whole_payload, &format!("integrity check failed: {}", e));
+        return Err(CliError::new(
+            ErrorClass::Data,
+            e.context(format!("{} failed its integrity check", input_path.display())),
+        ));
+    }
+    let (result, trailing_dur) = time_fn(|| {
+        let written = decompressed_data.len();
+        decode_trailing(&args, trailing, time_limits, written, &mut |data| decompressed_data.extend_from_slice(data))
+    });
+    result?;
+    let decomp_dur = decomp_dur + trailing_dur;
+    if verbosity::is_verbose() {
+        eprintln!(
+            "decoded {} with {}: {} -> {} in {} ({})",
+            input_path.display(),
+            pipeline,
+            ByteSize(compressed_data.len() as u64),
+            ByteSize(decompressed_data.len() as u64),
+            Elapsed(decomp_dur),
+            Throughput {
+                bytes: decompressed_data.len() as u64,
+                elapsed: decomp_dur
+            }
+        );
+    }
+    let run = Run::new(input_path, &pipeline, decompressed_data.len(), compressed_data.len()).decoded_in(decomp_dur);
+    if let Some(range) = range
+        && !partial
+    {
+        let offset = usize::try_from(range.offset).unwrap_or(usize::MAX).min(decompressed_data.len());
+        decompressed_data.drain(..offset);
+        decompressed_data.truncate(usize::try_from(range.len).unwrap_or(usize::MAX));
+    }
+
+    if args.checksum_only {
+        let mut sink = ChecksumWriter::new();
+        sink.write_all(&decompressed_data).expect("hashing into memory can't fail");
+        let (crc, len) = sink.finish();
+        if !summary::is_active() {
+            println!("crc32 {:08x}  {} bytes  {}", crc, len, input_path.display());
+        }
+        summary::record(Run {
+            crc32: Some(format!("{:08x}", crc)),
+            ..run
+        });
+        return Ok(());
+    }
+
+    let mut output_path = args
+        .output
+        .clone()
+        .expect("output path is required unless --checksum-only is given");
+    let metadata = container.map(|container| container.metadata).unwrap_or_default();
+    if output_path.is_dir() {
+        // only the last component of the stored name is used, so a crafted name can't escape the directory
+        let stored = metadata
+            .name
+            .as_deref()
+            .and_then(|name| Path::new(name).file_name())
+            .map(PathBuf::from);
+        match stored.or_else(|| cli::decode_output_path(input_path)?.file_name().map(PathBuf::from)) {
+            Some(name) => {
+                output_path.push(name);
+                if !cli::check_output(&output_path, policy)? {
+                    return Ok(());
+                }
+            }
+            None => {
+                return Err(CliError::usage(format!(
+                    "{} is a directory and {} neither records its original file name nor ends in .{}.",
+                    output_path.display(),
+                    input_path.display(),
+                    FILE_EXTENSION
+                )));
+            }
+        }
+    }
+    write_file(&output_path, &decompressed_data, policy)
+        .classify(ErrorClass::Io, || format!("failed to write {}", output_path.display()))?;
+    summary::record(run.output(&output_path));
+
+    if args.restore_mtime && is_stdio(&output_path) {
+        events::warn("--restore-mtime has no effect when writing to stdout.");
+    } else if args.restore_mtime {
+        match metadata.modified {
+            Some(modified) => File::options()
+                .write(true)
+                .open(&output_path)
+                .and_then(|file| file.set_modified(modified))
+                .classify(ErrorClass::Io, || {
+                    format!("failed to restore the modification time of {}", output_path.display())
+                })?,
+            None => events::warn(format_args!(
+                "{} doesn't record a modification time, leaving it as is.",
+                input_path.display()
+            )),
+        }
+    }
+    Ok(())
+}
+
+fn verify_signature(args: &DecodeArgs, container: &Container, stream: usize) -> CliResult {
+    let Some(key_path) = &args.verify_sig else {
+        return Ok(());
+    };
+    let key = load_verifying_key(key_path).classify(ErrorClass::Io, || format!("failed to load the public key {}", key_path.display()))?;
+    container
+        .verify_signature(&key)
+        .classify(ErrorClass::Data, || format!("{} failed signature verification", stream_name(&args.input, stream)))
+}
+
+/// `input`, or its `stream`th stream when there are several, counting from 1.
+fn stream_name(input: &Path, stream: usize) -> String {
+    match stream {
+        1 => input.display().to_string(),
+        stream => format!("stream {} of {}", stream, input.display()),
+    }
+}
+
+/// Refuses a stream of a file with several concatenated ones that can't be decoded into a shared output.
+fn check_concatenable(args: &DecodeArgs, container: &Container, stream: usize) -> CliResult {
+    let kind = match (&container.archive, &container.delta_base) {
+        (Some(_), _) => "a directory archive",
+        (_, Some(_)) => "a delta",
+        (None, None) => return Ok(()),
+    };
+    Err(CliError::msg(
+        ErrorClass::Data,
+        format!(
+            "{} is {} followed by or following other streams, only single files can be concatenated.",
+            stream_name(&args.input, stream),
+            kind
+        ),
+    ))
+}
+
+/// Decodes the streams concatenated after the first one, e.g. by `enc log -o - >> logs.stpk`, into `sink`. `written`
+/// is how much the streams before them decoded to, which counts against `--max-output-size`.
+fn decode_trailing(
+    args: &DecodeArgs,
+    mut trailing: &[u8],
+    time_limits: TimeLimits,
+    mut written: usize,
+    sink: &mut dyn FnMut(&[u8]),
+) -> CliResult {
+    let mut stream = 1;
+    while !trailing.is_empty() {
+        stream += 1;
+        if !is_container(trailing) {
+            return Err(CliError::msg(
+                ErrorClass::Data,
+                format!(
+                    "{} has {} bytes after its last stream that aren't a stackpack container.",
+                    args.input.display(),
+                    trailing.len()
+                ),
+            ));
+        }
+        let container = parse_container(trailing).classify(ErrorClass::Data, || stream_name(&args.input, stream))?;
+        verify_signature(args, &container, stream)?;
+        check_concatenable(args, &container, stream)?;
+        let mut pipeline = container.pipeline.clone();
+        if let Some(limit) = args.max_output_size {
+            let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+            pipeline.set_max_output_size(Some(limit.saturating_sub(written)));
+        }
+        pipeline.set_time_limits(time_limits);
+        let (len, digest) = decode_hashed(&mut pipeline, &container, sink)
+            .classify(ErrorClass::Stage, || format!("failed to decode {}", stream_name(&args.input, stream)))?;
+        container
+            .verify_streamed(len, digest)
+            .classify(ErrorClass::Data, || format!("{} failed its integrity check", stream_name(&args.input, stream)))?;
+        written = written.saturating_add(usize::try_from(len).unwrap_or(usize::MAX));
+        trailing = container.trailing;
+    }
+    Ok(())
+}
+
+/// Decodes the payload of `container` into `sink` a block at a time, hashing it on the way with the algorithm of the
+/// stored checksum. returns the decoded size and digest to check with [`Container::verify_streamed`].
+fn decode_hashed(
+    pipeline: &mut CompressionPipeline,
+    container: &Container,
+    sink: &mut dyn FnMut(&[u8]),
+) -> anyhow::Result<(u64, Option<Checksum>)> {
+    let mut hasher = container.checksum.as_ref().map(|checksum| checksum.algorithm.hasher());
+    let mut len = 0;
+    let mut feed = |data: &[u8]| {
+        if let Some(hasher) = &mut hasher {
+            hasher.update(data);
+        }
+        len += data.len() as u64;
+        sink(data);
+    };
+    match container.block_size {
+        Some(_) => decode_blocks_with(pipeline, container.payload, &mut feed)?,
+        None => {
+            let mut decoded = Vec::new();
+            pipeline.revert_mutation(container.payload, &mut decoded)?;
+            feed(&decoded);
+        }
+    }
+    Ok((len, hasher.map(DigestHasher::finish)))
+}
+
+/// `dec --checksum-only` of a container: every block and stream goes into the hasher as soon as it is decoded and is
+/// dropped after, so the decoded data is never held in memory as a whole.
+fn checksum_streams(
+    args: &DecodeArgs,
+    pipeline: &mut CompressionPipeline,
+    container: &Container,
+    time_limits: TimeLimits,
+) -> CliResult {
+    let input_path = &args.input;
+    let mut sink = ChecksumWriter::new();
+    let mut hash = |data: &[u8]| sink.write_all(data).expect("hashing into memory can't fail");
+    let (result, decomp_dur) = time_fn(|| decode_hashed(pipeline, container, &mut hash));
+    progress::finish();
+    let whole_payload = container.block_size.is_none().then_some(container.payload);
+    let (len, digest) = match result {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            report_failure(args, pipeline, whole_payload, &e.to_string());
+            return Err(CliError::new(
+                ErrorClass::Stage,
+                e.context(format!("failed to decode {}", input_path.display())),
+            ));
+        }
+    };
+    if let Err(e) = container.verify_streamed(len, digest) {
+        report_failure(args, pipeline, whole_payload, &format!("integrity check failed: {}", e));
+        return Err(CliError::new(
+            ErrorClass::Data,
+            e.context(format!("{} failed its integrity check", input_path.display())),
+        ));
+    }
+    let written = usize::try_from(len).unwrap_or(usize::MAX);
+    let (result, trailing_dur) = time_fn(|| decode_trailing(args, container.trailing, time_limits, written, &mut hash));
+    result?;
+    let (crc, len) = sink.finish();
+    let decomp_dur = decomp_dur + trailing_dur;
+    if verbosity::is_verbose() {
+        eprintln!(
+            "decoded {} with {}: {} -> {} in {} ({})",
+            input_path.display(),
+            pipeline,
+            ByteSize(container.payload.len() as u64),
+            ByteSize(len),
+            Elapsed(decomp_dur),
+            Throughput {
+                bytes: len,
+                elapsed: decomp_dur
+            }
+        );
+    }
+    if !summary::is_active() {
+        println!("crc32 {:08x}  {} bytes  {}", crc, len, input_path.display());
+    }
+    let run = Run::new(input_path, pipeline, usize::try_from(len).unwrap_or(usize::MAX), container.payload.len());
+    summary::record(Run {
+        crc32: Some(format!("{:08x}", crc)),
+        ..run.decoded_in(decomp_dur)
+    });
+    Ok(())
+}
+
+/// Picks the stage for an input made by another compressor, as a last resort for inputs without any pipeline.
+pub(super) fn foreign_pipeline(input_path: &Path, input_data: &[u8]) -> CliResult<CompressionPipeline> {
+    let Some(format) = detect(input_data) else {
+        return Err(CliError::msg(
+            ErrorClass::Data,
+            format!(
+                "{} has no embedded pipeline and no {} sidecar, it was probably encoded with --raw. pass the pipeline used to encode it with --using, --from_file or --preset.",
+                input_path.display(),
+                sidecar_path(input_path).display()
+            ),
+        ));
+    };
+    let Some(stage) = format.stage() else {
+        return Err(CliError::msg(
+            ErrorClass::Pipeline,
+            format!(
+                "{} is a {} file, which this build has no {} stage for. decompress it with `{}`, or enable a plugin that provides the stage.",
+                input_path.display(),
+                format.name,
+                format.stage,
+                format.tool
+            ),
+        ));
+    };
+    events::warn(format_args!(
+        "{} is a {} file, decoding it with the {} stage.",
+        input_path.display(),
+        format.name,
+        format.stage
+    ));
+    Ok(CompressionPipeline::new().with_algorithm(stage))
+}
+
+/// Writes a crash report of a failed decode if `--report` asked for one. `payload` is what the pipeline encoded, when
+/// it encoded it as a whole.
+fn report_failure(args: &DecodeArgs, pipeline: &CompressionPipeline, payload: Option<&[u8]>, error: &str) {
+    let mut run = Run::new(&args.input, pipeline, 0, payload.map_or(0, <[u8]>::len)).failed(error);
+    if args.report.report {
+        let path = Report::decode(&args.input, pipeline, payload, error).save(&args.input, args.report, args.io.policy());
+        run.report = path.map(|path| path.display().to_string());
+    }
+    // the command fails right after, main prints the document before exiting
+    summary::record(run);
+}
+
+/// Recreates an archived directory tree inside the output directory.
+fn decode_archive(args: &DecodeArgs, container: &Container, table: &ArchiveTable, pipeline: &mut CompressionPipeline) -> CliResult {
+    let input_path = &args.input;
+    if args.head.is_some() || args.range.is_some() || args.checksum_only {
+        return Err(CliError::usage(format!(
+            "{} is a directory archive, --head, --range and --checksum-only only work on single files.",
+            input_path.display()
+        )));
+    }
+    let output_path = match &args.to_tar {
+        Some(tar_path) => tar_path,
+        None => args
+            .output
+            .as_ref()
+            .expect("output path is required unless --checksum-only is given"),
+    };
+    if args.to_tar.is_none() && is_stdio(output_path) {
+        return Err(CliError::usage(format!(
+            "{} is a directory archive, it has to be extracted into a directory and can't be written to stdout. pass --to-tar - for a tar stream.",
+            input_path.display()
+        )));
+    }
+    if args.to_tar.is_none() && output_path.exists() && !output_path.is_dir() {
+        return Err(CliError::usage(format!(
+            "{} is a directory archive, it has to be extracted into a directory but {} is a file.",
+            input_path.display(),
+            output_path.display()
+        )));
+    }
+
+    if args.progress {
+        progress::start(
+            format!("decoding {}", input_path.display()),
+            container.original_size,
+            pipeline.stages().len(),
+        );
+    }
+    let (frames, decomp_dur) = time_fn(|| decode_frames(pipeline, table, container.payload));
+    let frames = match frames {
+        Ok(frames) => frames,
+        Err(e) => {
+            progress::finish();
+            report_failure(args, pipeline, None, &e.to_string());
+            return Err(CliError::new(
+                ErrorClass::Stage,
+                e.context(format!("failed to decode {}", input_path.display())),
+            ));
+        }
+    };
+    progress::finish();
+    let original = frames.concat();
+    if let Err(e) = container.verify(&original) {
+        report_failure(args, pipeline, None, &format!("integrity check failed: {}", e));
+        return Err(CliError::new(
+            ErrorClass::Data,
+            e.context(format!("{} failed its integrity check", input_path.display())),
+        ));
+    }
+    match args.to_tar {
+        Some(_) => {
+            let mtime = container
+                .metadata
+                .modified
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_secs());
+            let mut tar = Vec::new();
+            write_tar(table, &frames, mtime, &mut tar).classify(ErrorClass::Failure, || "failed to build the tar stream")?;
+            write_file(output_path, &tar, args.io.policy())
+                .classify(ErrorClass::Io, || format!("failed to write {}", output_path.display()))?;
+        }
+        None => extract_all(table, &frames, output_path, args.io.policy(), PathPolicy::new(args.unsafe_paths)).classify(ErrorClass::Io, || {
+            format!("failed to extract {} into {}", input_path.display(), output_path.display())
+        })?,
+    }
+    summary::record(
+        Run::new(input_path, pipeline, original.len(), container.payload.len())
+            .output(output_path)
+            .decoded_in(decomp_dur),
+    );
+    if_tracing! {{
+        tracing::info!(event = "archive_extracted", input = %input_path.display(), output = %output_path.display(), members = table.members.len(), "extracted archive");
+    }}
+    Ok(())
 }
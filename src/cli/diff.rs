@@ -0,0 +1,115 @@
+//! `diff`, which compares two files byte by byte, e.g. the input of a failed round trip with what came back out or
+//! two stage outputs of `--dump-stages`, and says where and how much they differ: the first differing offset, a hex
+//! dump of both files around it with the differing bytes marked, and how many bytes differ in how many runs.
+//!
+//! like `cmp`, it exits with [`EXIT_SAME`] when the files are identical and [`EXIT_DIFFERENT`] when they aren't.
+use std::{cmp::Ordering, path::Path, process};
+
+use crate::{
+    cli::{
+        DiffArgs,
+        error::{Classify, CliResult, ErrorClass},
+    },
+    io::{IoPolicy, read_file},
+    units::Grouped,
+};
+
+/// The files are identical.
+pub const EXIT_SAME: i32 = 0;
+/// The files differ.
+pub const EXIT_DIFFERENT: i32 = 1;
+/// Bytes per hex dump row.
+const ROW: usize = 16;
+
+pub fn diff(args: DiffArgs) -> CliResult {
+    let a = read(&args.a)?;
+    let b = read(&args.b)?;
+    let (a_name, b_name) = (args.a.display().to_string(), args.b.display().to_string());
+    let width = a_name.len().max(b_name.len());
+    println!("{:<width$}  {} bytes", a_name, Grouped(a.len() as u64));
+    println!("{:<width$}  {} bytes", b_name, Grouped(b.len() as u64));
+
+    let common = a.len().min(b.len());
+    let Some(first) = (0..common).find(|&offset| a[offset] != b[offset]).or((a.len() != b.len()).then_some(common))
+    else {
+        println!("the files are identical.");
+        process::exit(EXIT_SAME);
+    };
+    println!("first difference at byte {} ({:#x})", Grouped(first as u64), first);
+    println!();
+    let context = args.context as usize;
+    let start = first.saturating_sub(context) / ROW * ROW;
+    let end = (first + context + 1).min(a.len().max(b.len()));
+    for row in (start..end).step_by(ROW) {
+        let a_row = a.get(row..(row + ROW).min(a.len())).unwrap_or_default();
+        let b_row = b.get(row..(row + ROW).min(b.len())).unwrap_or_default();
+        println!("{:<width$}  {}", a_name, hex_row(row, a_row));
+        println!("{:<width$}  {}", b_name, hex_row(row, b_row));
+        if a_row != b_row {
+            println!("{:<width$}  {}", "", markers(a_row, b_row));
+        }
+    }
+    println!();
+
+    let mut differing = 0;
+    let mut runs = 0;
+    let mut last = None;
+    for offset in (first..common).filter(|&offset| a[offset] != b[offset]) {
+        differing += 1;
+        if last != Some(offset - 1) {
+            runs += 1;
+        }
+        last = Some(offset);
+    }
+    match differing {
+        0 if common == 0 => {}
+        0 => println!("the {} bytes both files have are the same", Grouped(common as u64)),
+        differing => println!(
+            "{} of the {} bytes both files have differ, in {} runs",
+            Grouped(differing),
+            Grouped(common as u64),
+            Grouped(runs)
+        ),
+    }
+    if let Some(last) = last {
+        println!("last difference at byte {} ({:#x})", Grouped(last as u64), last);
+    }
+    match a.len().cmp(&b.len()) {
+        Ordering::Less => println!("{} is {} bytes longer", b_name, Grouped((b.len() - a.len()) as u64)),
+        Ordering::Greater => println!("{} is {} bytes longer", a_name, Grouped((a.len() - b.len()) as u64)),
+        Ordering::Equal => {}
+    }
+    process::exit(EXIT_DIFFERENT);
+}
+
+fn read(path: &Path) -> CliResult<Vec<u8>> {
+    read_file(path, IoPolicy::default()).classify(ErrorClass::Io, || format!("failed to read {}", path.display()))
+}
+
+/// `offset  hex bytes  |ascii|`, padded to a full row so the ascii columns line up.
+fn hex_row(offset: usize, bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return format!("{:08x}  (end of file)", offset);
+    }
+    let mut line = format!("{:08x} ", offset);
+    for index in 0..ROW {
+        match bytes.get(index) {
+            Some(byte) => line += &format!(" {:02x}", byte),
+            None => line += "   ",
+        }
+    }
+    let ascii = bytes
+        .iter()
+        .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+        .collect::<String>();
+    line + "  |" + &ascii + "|"
+}
+
+/// `^^` under every byte that differs between the rows or that only one of them has.
+fn markers(a: &[u8], b: &[u8]) -> String {
+    let mut line = " ".repeat(9);
+    for index in 0..a.len().max(b.len()) {
+        line += if a.get(index) == b.get(index) { "   " } else { " ^^" };
+    }
+    line.trim_end().to_string()
+}
@@ -1,25 +1,668 @@
-use crate::cli::{EncodeArgs, pipeline};
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ed25519_dalek::SigningKey;
+
+use crate::algorithms::{heuristics, pipeline::CompressionPipeline};
+use crate::archive::{EncodedArchive, append_to_archive, decode_frames, encode_directory};
+use crate::blocks::encode_blocks;
+use crate::cli::error::{Classify, CliError, CliResult, ErrorClass};
+use crate::cli::summary::{self, Run};
+use crate::cli::{self, EncodeArgs, OutputFormat, PipelinePersistence, PipelineSelection, pipeline, stats};
+use crate::container::{ContainerOptions, FileMetadata, is_container, parse_container, write_container};
+use crate::delta::{DeltaBase, DeltaFormat, encode_delta, vcdiff::encode_vcdiff};
+use crate::events;
+use crate::host::HostInfo;
+use crate::io::{is_stdio, read_file, write_file};
 use crate::mutator::Mutator;
-use std::fs;
+use crate::progress;
+use crate::sidecar::{SidecarStats, check_sidecar, write_sidecar};
+use crate::signing::load_signing_key;
+use crate::units::{ByteSize, Elapsed, Ratio, Throughput};
+use crate::verbosity;
+use crate::volumes::{volume_path, write_volumes};
+use crate::walk::PathFilter;
 use voxell_timer::time_fn;
+use walkdir::WalkDir;
 
-pub fn encode(args: EncodeArgs) {
-    let input_path = &args.input;
-    let output_path = &args.output;
-    let mut pipeline = pipeline::build_pipeline(args.pipeline_selection());
+/// Bytes of every input `--dry-run` encodes to estimate the rest.
+const DRY_RUN_SAMPLE: usize = 1024 * 1024;
+
+pub fn encode(args: EncodeArgs) -> CliResult {
+    let time_limits = args.time_limits.start();
+    let (inputs, output) = args.given_paths();
+    if (args.append || args.archive) && output.is_none() {
+        return Err(CliError::usage("--append and --archive need the archive as the output path."));
+    }
+    if inputs.len() > 1 && output.is_none() {
+        return Err(CliError::usage(
+            "pass -o <directory> to encode several inputs, or -o <archive> --archive to put them in one archive.",
+        ));
+    }
+    if inputs.len() > 1 && inputs.iter().any(|input| is_stdio(input)) {
+        return Err(CliError::usage("- can only be encoded on its own."));
+    }
+    if args.format == OutputFormat::Json {
+        if output.map_or(inputs.iter().any(|input| is_stdio(input)), is_stdio) {
+            return Err(CliError::usage(
+                "--format json prints the results to stdout, write the output to a file.",
+            ));
+        }
+        summary::start("enc");
+    }
+    if args.stdout_stats && output.map_or(inputs.iter().any(|input| is_stdio(input)), is_stdio) {
+        return Err(CliError::usage("--stdout-stats prints a line to stdout, write the output to a file."));
+    }
+    if args.stats {
+        stats::start();
+    }
+    // the pipeline is built and checked once and shared by all inputs
+    let mut pipeline = pipeline::build_pipeline(args.pipeline_selection())?;
+    if args.skip_failing_stages {
+        pipeline.skip_failing_stages();
+    }
+    pipeline.set_time_limits(time_limits);
+    pipeline::dump_stages(&mut pipeline, args.dump_stages.as_deref())?;
+    pipeline::announce_pipeline(&pipeline);
+    if args.stdout_stats {
+        summary::start_line("enc", &pipeline);
+    }
+    if args.strict && pipeline::report_lints(&pipeline) {
+        return Err(CliError::msg(
+            ErrorClass::Pipeline,
+            "refusing to encode with a pipeline that has lint warnings (--strict).",
+        ));
+    }
+    if args.dry_run {
+        return dry_run(&args, inputs, output, &mut pipeline);
+    }
 
-    let input_data = fs::read(input_path).expect("Failed to read input file");
+    if args.append || args.archive {
+        let args = EncodeArgs {
+            output: output.map(Path::to_path_buf),
+            ..args.clone()
+        };
+        return match args.append {
+            true => append_archive(&args, inputs, &pipeline),
+            false if cli::check_output(args.output(), args.io.policy())? => encode_archive(&args, inputs, &mut pipeline),
+            false => Ok(()),
+        };
+    }
+    if inputs.len() > 1
+        && let Some(directory) = output
+    {
+        if directory.exists() && !directory.is_dir() {
+            return Err(CliError::usage(format!(
+                "{} is not a directory, several inputs are written into one unless --archive is given.",
+                directory.display()
+            )));
+        }
+        fs::create_dir_all(directory).classify(ErrorClass::Io, || format!("failed to create {}", directory.display()))?;
+    }
+    for input in inputs {
+        let args = EncodeArgs {
+            input: input.clone(),
+            output: Some(cli::encode_output_path(input, output)?),
+            ..args.clone()
+        };
+        encode_file(&args, &mut pipeline)?;
+    }
+    Ok(())
+}
+
+/// Prints what encoding `inputs` would do, estimating the output from the first [`DRY_RUN_SAMPLE`] bytes of each.
+fn dry_run(args: &EncodeArgs, inputs: &[PathBuf], output: Option<&Path>, pipeline: &mut CompressionPipeline) -> CliResult {
+    if inputs.iter().any(|input| is_stdio(input)) {
+        return Err(CliError::usage(
+            "--dry-run can't tell how much stdin holds without reading all of it, pass a file.",
+        ));
+    }
+    let filter = args.filter.filter();
+    for input in inputs {
+        // archives are always containers
+        let stored = match args.persistence.mode() {
+            _ if args.archive || input.is_dir() => "with the pipeline embedded",
+            PipelinePersistence::Sidecar => "with the pipeline in a sidecar",
+            PipelinePersistence::Embedded => "with the pipeline embedded",
+            PipelinePersistence::Raw => "without the pipeline (--raw)",
+        };
+        let (size, sample) = sample_input(input, &filter).classify(ErrorClass::Io, || format!("failed to read {}", input.display()))?;
+        let mut encoded = Vec::new();
+        let (result, elapsed) = time_fn(|| pipeline.drive_mutation(&sample, &mut encoded));
+        result.classify(ErrorClass::Stage, || format!("encoding a sample of {} failed", input.display()))?;
+        let scale = match sample.len() {
+            0 => 1.0,
+            len => size as f64 / len as f64,
+        };
+        let estimate = Ratio {
+            original: size,
+            compressed: (encoded.len() as f64 * scale) as u64,
+        };
+        let output_path = match output {
+            Some(archive) if args.archive => archive.to_path_buf(),
+            // several inputs go into the directory, which doesn't have to exist yet
+            Some(directory) if inputs.len() > 1 => directory.join(
+                cli::encode_output_path(input, None)?
+                    .file_name()
+                    .expect("outputs are named after inputs"),
+            ),
+            output => cli::encode_output_path(input, output)?,
+        };
+        println!(
+            "{}: {} -> ~{} ({}) in ~{}, estimated from the first {}",
+            input.display(),
+            ByteSize(size),
+            ByteSize(estimate.compressed),
+            estimate,
+            Elapsed(elapsed.mul_f64(scale)),
+            ByteSize(sample.len() as u64)
+        );
+        let exists = match fs::symlink_metadata(&output_path) {
+            Ok(_) => ", which exists",
+            Err(_) => "",
+        };
+        println!("  would write {}{} {}", output_path.display(), exists, stored);
+        // archives go through the pipeline a frame at a time, which isn't known before they are walked
+        if !input.is_dir() {
+            let peak = ByteSize(estimate_memory(args, pipeline, size as usize));
+            match heuristics::available_memory() {
+                Some(available) => println!("  needs about {} of memory at its peak, {} is available", peak, ByteSize(available)),
+                None => println!("  needs about {} of memory at its peak", peak),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The size of `input`, a file or directory, and up to [`DRY_RUN_SAMPLE`] bytes from the start of it. a directory is
+/// sampled from its files in the order they are archived.
+fn sample_input(input: &Path, filter: &PathFilter) -> io::Result<(u64, Vec<u8>)> {
+    let files = match input.is_dir() {
+        true => filter
+            .walk(WalkDir::new(input).min_depth(1))
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path())
+            .collect(),
+        false => vec![input.to_path_buf()],
+    };
+    let mut size = 0;
+    let mut sample = Vec::new();
+    for file in files {
+        size += fs::metadata(&file)?.len();
+        let wanted = DRY_RUN_SAMPLE.saturating_sub(sample.len()) as u64;
+        File::open(&file)?.take(wanted).read_to_end(&mut sample)?;
+    }
+    Ok((size, sample))
+}
+
+/// Encodes the single input of `args`, a file or a directory.
+fn encode_file(args: &EncodeArgs, pipeline: &mut CompressionPipeline) -> CliResult {
+    let input_path = &args.input;
+    let output_path = args.output();
+    let policy = args.io.policy();
+    if args.split_size.is_some() && (is_stdio(input_path) || is_stdio(output_path)) {
+        return Err(CliError::usage(
+            "--split-size works on files, it can't read from stdin or write to stdout.",
+        ));
+    }
+    // archives are always containers, a single file only is one with --embed_to_file or when written to stdout
+    if !input_path.is_dir() && args.persistence_mode() != PipelinePersistence::Embedded {
+        if args.histogram {
+            return Err(CliError::usage(
+                "--histogram is recorded in the container, pass --embed_to_file as well.",
+            ));
+        }
+        if args.record_host {
+            return Err(CliError::usage(
+                "--record-host is recorded in the container, pass --embed_to_file as well.",
+            ));
+        }
+    }
+    let first_output = match args.split_size {
+        Some(_) => volume_path(output_path, 1),
+        None => output_path.to_path_buf(),
+    };
+    if !cli::check_output(&first_output, policy)? {
+        return Ok(());
+    }
+    // a sidecar in the way is found before anything is encoded
+    if args.persistence_mode() == PipelinePersistence::Sidecar && !is_stdio(output_path) {
+        check_sidecar(output_path, pipeline, policy).classify(ErrorClass::Io, || "can't write the pipeline sidecar")?;
+    }
+    if input_path.is_dir() {
+        return encode_archive(args, std::slice::from_ref(input_path), pipeline);
+    }
+    let input_data = read_file(input_path, policy).classify(ErrorClass::Io, || format!("failed to read {}", input_path.display()))?;
+    // with a reference, the pipeline encodes the delta against it, the container still describes the input itself
+    let reference = match args.delta_against.as_deref() {
+        Some(reference) => Some(read_file(reference, policy).classify(ErrorClass::Io, || {
+            format!("failed to read the delta reference {}", reference.display())
+        })?),
+        None => None,
+    };
+    if let Some(reference) = &reference {
+        match args.delta_format {
+            DeltaFormat::Vcdiff => return write_output(args, &encode_vcdiff(reference, &input_data)),
+            DeltaFormat::Stackpack if args.persistence_mode() != PipelinePersistence::Embedded => {
+                return Err(CliError::usage(
+                    "--delta-against records the reference in the container, pass --embed_to_file as well or use --delta-format vcdiff.",
+                ));
+            }
+            DeltaFormat::Stackpack => {}
+        }
+    }
+    let delta = reference.as_deref().map(|reference| encode_delta(reference, &input_data));
+    let pipeline_input = delta.as_deref().unwrap_or(&input_data);
+    check_memory(args, pipeline, pipeline_input.len())?;
     let mut compressed_data = Vec::new();
-    let (res, comp_dur) = time_fn(|| pipeline.drive_mutation(&input_data, &mut compressed_data));
+    if args.progress {
+        progress::start(
+            format!("encoding {}", input_path.display()),
+            Some(pipeline_input.len() as u64),
+            pipeline.stages().len(),
+        );
+    }
+    if args.persistence.sidecar_stats {
+        // only the stages of this encode go into the sidecar, not those of sampling or of earlier inputs
+        verbosity::record_stages();
+    }
+    let (res, comp_dur) = time_fn(|| match args.block_size {
+        Some(block_size) => encode_blocks(pipeline, pipeline_input, block_size as usize, &mut compressed_data),
+        None => pipeline.drive_mutation(pipeline_input, &mut compressed_data),
+    });
+    progress::finish();
     if_tracing! {{
         tracing::info!(event = "encode_complete", input = %input_path.display(), output = %output_path.display(), elapsed = ?comp_dur, compressed_len = compressed_data.len(), "encode finished");
     }}
 
-    if res.is_err() {
+    if let Err(e) = res {
+        summary::record(
+            Run::new(input_path, pipeline, pipeline_input.len(), 0)
+                .output(output_path)
+                .encoded_in(comp_dur)
+                .failed(e.to_string()),
+        );
         if_tracing! {{
             tracing::info!(event = "encode_failed", input = %input_path.display(), output = %output_path.display(), "encode failed");
         }}
+        return Err(CliError::new(
+            ErrorClass::Stage,
+            e.context(format!("failed to encode {}", input_path.display())),
+        ));
     } else {
-        fs::write(output_path, compressed_data).expect("Failed to write output file");
+        let output_data = match args.persistence_mode() {
+            PipelinePersistence::Embedded => {
+                let mut container = Vec::new();
+                let options = ContainerOptions {
+                    checksum: args.checksum,
+                    metadata: FileMetadata::of(input_path),
+                    block_size: args.block_size,
+                    signing_key: signing_key(args)?,
+                    archive: None,
+                    delta_base: reference.as_deref().map(DeltaBase::of),
+                    host: args.record_host.then(|| HostInfo::current(pipeline)),
+                    histogram: args.histogram,
+                };
+                write_container(pipeline, &options, &input_data, &compressed_data, &mut container)
+                    .classify(ErrorClass::Failure, || "failed to build the container")?;
+                container
+            }
+            PipelinePersistence::Sidecar | PipelinePersistence::Raw => compressed_data,
+        };
+        write_output(args, &output_data)?;
+        let sidecar_stats = args.persistence.sidecar_stats.then(|| SidecarStats {
+            original_size: input_data.len() as u64,
+            compressed_size: output_data.len() as u64,
+            ratio: Ratio::new(input_data.len(), output_data.len()).value(),
+            duration_ms: comp_dur.as_secs_f64() * 1000.0,
+            stages: verbosity::peek_stages(),
+        });
+        report_encode(input_path, output_path, pipeline, input_data.len(), output_data.len(), comp_dur);
+        if args.persistence_mode() == PipelinePersistence::Raw {
+            cli::warn_raw_output(output_path);
+        }
+        if args.persistence_mode() == PipelinePersistence::Sidecar {
+            let _sidecar =
+                write_sidecar(output_path, pipeline, sidecar_stats.as_ref(), policy).classify(ErrorClass::Io, || "failed to write the pipeline sidecar")?;
+            if_tracing! {{
+                tracing::info!(event = "sidecar_written", path = %_sidecar.display(), "wrote pipeline sidecar");
+            }}
+        }
+    }
+    Ok(())
+}
+
+/// Estimated peak memory of encoding `input_len` bytes with `pipeline` and the block size of `args`.
+fn estimate_memory(args: &EncodeArgs, pipeline: &CompressionPipeline, input_len: usize) -> u64 {
+    match args.block_size {
+        // the whole input and output stay alive while the blocks go through the pipeline one at a time
+        Some(block_size) => (2 * input_len as u64).saturating_add(pipeline.estimate_memory(input_len.min(block_size as usize))),
+        None => pipeline.estimate_memory(input_len),
+    }
+}
+
+/// Warns, or fails with `--strict-memory`, when encoding `input_len` bytes likely needs more memory than is available.
+fn check_memory(args: &EncodeArgs, pipeline: &CompressionPipeline, input_len: usize) -> CliResult {
+    let Some(available) = heuristics::available_memory() else {
+        return Ok(());
+    };
+    let peak = estimate_memory(args, pipeline, input_len);
+    // the input is already read, it counts towards the estimate but no longer towards the available memory
+    let available = available.saturating_add(input_len as u64);
+    if peak <= available {
+        return Ok(());
+    }
+    let message = format!(
+        "encoding {} with {} needs about {} of memory, only {} is available",
+        args.input.display(),
+        pipeline,
+        ByteSize(peak),
+        ByteSize(available)
+    );
+    if args.strict_memory {
+        return Err(CliError::msg(
+            ErrorClass::Failure,
+            format!(
+                "refusing to start, {} (--strict-memory). pass --block-size to encode it in smaller blocks.",
+                message
+            ),
+        ));
+    }
+    events::warn(format_args!(
+        "{}, it may fail or swap. pass --block-size to encode it in smaller blocks.",
+        message
+    ));
+    Ok(())
+}
+
+/// Writes the output file, or its volumes with `--split-size`.
+fn write_output(args: &EncodeArgs, data: &[u8]) -> CliResult {
+    let policy = args.io.policy();
+    let failed = || format!("failed to write {}", args.output().display());
+    match args.split_size {
+        Some(split_size) => {
+            let _volumes = write_volumes(args.output(), data, split_size as usize, policy.forced()).classify(ErrorClass::Io, failed)?;
+            if_tracing! {{
+                tracing::info!(event = "volumes_written", output = %args.output().display(), volumes = _volumes.len(), "wrote output volumes");
+            }}
+            Ok(())
+        }
+        None => write_file(args.output(), data, policy).classify(ErrorClass::Io, failed),
+    }
+}
+
+fn signing_key(args: &EncodeArgs) -> CliResult<Option<SigningKey>> {
+    args.sign
+        .as_deref()
+        .map(|key| load_signing_key(key).classify(ErrorClass::Io, || format!("failed to load the signing key {}", key.display())))
+        .transpose()
+}
+
+/// Archives a directory, or with `--archive` all `inputs` under their own names. Archives keep their member table in
+/// the container header, so they are always written as containers, whatever persistence mode was asked for.
+fn encode_archive(args: &EncodeArgs, inputs: &[PathBuf], pipeline: &mut CompressionPipeline) -> CliResult {
+    let output_path = args.output();
+    let policy = args.io.policy();
+    let filter = args.filter.filter();
+    let label = label(inputs);
+    if args.persistence_mode() == PipelinePersistence::Raw || args.block_size.is_some() || args.delta_against.is_some() {
+        return Err(CliError::usage(format!(
+            "{} is a directory, directories are archived into containers and can't be combined with --raw, --block-size or --delta-against.",
+            label
+        )));
+    }
+
+    if args.progress {
+        // the size of the tree isn't known up front, so there's no percentage or estimate for archives
+        progress::start(format!("archiving {}", label), None, pipeline.stages().len());
+    }
+    let (archive, archive_dur) = time_fn(|| match args.archive {
+        true => append_to_archive(
+            pipeline,
+            EncodedArchive::default(),
+            inputs,
+            &filter,
+            args.small_file_threshold,
+            policy,
+        ),
+        false => encode_directory(pipeline, &inputs[0], &filter, args.small_file_threshold, policy),
+    });
+    progress::finish();
+    let archive = archive.classify(ErrorClass::Failure, || format!("failed to archive {}", label))?;
+    if_tracing! {{
+        tracing::info!(event = "archive_complete", input = %label, output = %output_path.display(), elapsed = ?archive_dur, members = archive.table.members.len(), frames = archive.table.frames.len(), "archive finished");
+    }}
+
+    let options = ContainerOptions {
+        checksum: args.checksum,
+        // an archive of several inputs isn't named after any of them
+        metadata: match inputs {
+            [input] if !args.archive => FileMetadata::of(input),
+            _ => FileMetadata::default(),
+        },
+        block_size: None,
+        signing_key: signing_key(args)?,
+        archive: Some(archive.table),
+        delta_base: None,
+        host: args.record_host.then(|| HostInfo::current(pipeline)),
+        histogram: args.histogram,
+    };
+    let mut container = Vec::new();
+    write_container(pipeline, &options, &archive.original, &archive.payload, &mut container)
+        .classify(ErrorClass::Failure, || "failed to build the container")?;
+    write_output(args, &container)?;
+    report_encode(
+        Path::new(&label),
+        output_path,
+        pipeline,
+        archive.original.len(),
+        container.len(),
+        archive_dur,
+    );
+    Ok(())
+}
+
+/// Names `inputs` in reports.
+fn label(inputs: &[PathBuf]) -> String {
+    match inputs {
+        [input] => input.display().to_string(),
+        inputs => format!("{} inputs", inputs.len()),
+    }
+}
+
+/// Reports an encode with its ratio and timing, in verbose mode and in the `--format json` summary.
+fn report_encode(
+    input_path: &Path,
+    output_path: &Path,
+    pipeline: &CompressionPipeline,
+    input_len: usize,
+    output_len: usize,
+    elapsed: Duration,
+) {
+    summary::record(
+        Run::new(input_path, pipeline, input_len, output_len)
+            .output(output_path)
+            .encoded_in(elapsed),
+    );
+    if verbosity::is_verbose() {
+        eprintln!(
+            "encoded {} with {}: {} -> {} ({}) in {} ({})",
+            input_path.display(),
+            pipeline,
+            ByteSize(input_len as u64),
+            ByteSize(output_len as u64),
+            Ratio::new(input_len, output_len),
+            Elapsed(elapsed),
+            Throughput {
+                bytes: input_len as u64,
+                elapsed
+            }
+        );
+    }
+}
+
+/// Adds the inputs to the archive at the output path, encoding only the new members.
+fn append_archive(args: &EncodeArgs, inputs: &[PathBuf], requested: &CompressionPipeline) -> CliResult {
+    let output_path = args.output();
+    let policy = args.io.policy();
+    if is_stdio(output_path) || inputs.iter().any(|input| is_stdio(input)) {
+        return Err(CliError::usage(
+            "--append works on files, it can't read from stdin or write to stdout.",
+        ));
+    }
+    let existing = read_file(output_path, policy).classify(ErrorClass::Io, || format!("failed to read {}", output_path.display()))?;
+    let container = match is_container(&existing).then(|| parse_container(&existing)) {
+        Some(Ok(container)) if container.archive.is_some() => container,
+        Some(Err(e)) => return Err(CliError::new(ErrorClass::Data, e.context(output_path.display().to_string()))),
+        _ => {
+            return Err(CliError::usage(format!(
+                "{} is not a directory archive, only archives can be appended to.",
+                output_path.display()
+            )));
+        }
+    };
+    let mut pipeline = container.pipeline.clone();
+    if args.pipeline_selection() != PipelineSelection::Default && requested.to_string() != pipeline.to_string() {
+        events::warn(format_args!(
+            "{} was encoded with \"{}\", appending with it instead of the requested \"{}\".",
+            output_path.display(),
+            pipeline,
+            requested
+        ));
+    }
+    if container.signature.is_some() && args.sign.is_none() {
+        events::warn(format_args!(
+            "{} is signed, the signature doesn't cover the new members and is dropped. pass --sign to sign it again.",
+            output_path.display()
+        ));
+    }
+
+    // the members already in the archive are decoded and encoded again, their dumps come first
+    pipeline.set_dump_dir(args.dump_stages.clone());
+    let table = container.archive.clone().expect("checked above");
+    let frames = decode_frames(&mut pipeline, &table, container.payload)
+        .classify(ErrorClass::Stage, || format!("failed to decode {}", output_path.display()))?;
+    let original = frames.concat();
+    container
+        .verify(&original)
+        .classify(ErrorClass::Data, || format!("{} failed its integrity check", output_path.display()))?;
+    let archive = EncodedArchive {
+        table,
+        payload: container.payload.to_vec(),
+        original,
+    };
+    let (appended, append_dur) = time_fn(|| {
+        append_to_archive(
+            &mut pipeline,
+            archive,
+            inputs,
+            &args.filter.filter(),
+            args.small_file_threshold,
+            policy,
+        )
+    });
+    let archive = appended.classify(ErrorClass::Failure, || format!("failed to append to {}", output_path.display()))?;
+    if_tracing! {{
+        tracing::info!(event = "append_complete", inputs = inputs.len(), output = %output_path.display(), members = archive.table.members.len(), frames = archive.table.frames.len(), "append finished");
+    }}
+
+    let options = ContainerOptions {
+        checksum: container.checksum.as_ref().map_or(args.checksum, |checksum| checksum.algorithm),
+        metadata: container.metadata.clone(),
+        block_size: None,
+        signing_key: signing_key(args)?,
+        archive: Some(archive.table),
+        delta_base: None,
+        // the archive keeps the record it was made with unless asked for a new one
+        host: match args.record_host {
+            true => Some(HostInfo::current(&pipeline)),
+            false => container.host.clone(),
+        },
+        // the histogram covers the whole archive, so it is counted again with the appended files
+        histogram: args.histogram || container.histogram.is_some(),
+    };
+    let mut out = Vec::new();
+    write_container(&pipeline, &options, &archive.original, &archive.payload, &mut out)
+        .classify(ErrorClass::Failure, || "failed to build the container")?;
+    // the archive is rewritten in place
+    write_file(output_path, &out, policy.forced()).classify(ErrorClass::Io, || format!("failed to write {}", output_path.display()))?;
+    report_encode(
+        Path::new(&label(inputs)),
+        output_path,
+        &pipeline,
+        archive.original.len(),
+        out.len(),
+        append_dur,
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::cli::{Cli, Command};
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("stackpack-encode-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn encode_with(dir: &TempDir, flags: &[&str]) -> CliResult<PathBuf> {
+        let input = dir.0.join("in.txt");
+        let output = dir.0.join("in.txt.stpk");
+        fs::write(&input, "some text to count the bytes of").unwrap();
+        let _ = fs::remove_file(&output);
+        let mut argv = vec!["stackpack".into(), "enc".into(), input.into_os_string(), "-o".into(), output.clone().into()];
+        argv.extend(flags.iter().map(Into::into));
+        let Command::Encode(args) = Cli::try_parse_from(argv).unwrap().command else {
+            unreachable!("enc parses as the encode command")
+        };
+        encode(args).map(|()| output)
+    }
+
+    #[test]
+    fn histogram_needs_a_container() {
+        let dir = TempDir::new("histogram");
+        for flags in [&["--histogram"][..], &["--histogram", "--raw"]] {
+            let error = encode_with(&dir, flags).unwrap_err();
+            assert_eq!(error.class, ErrorClass::Usage, "{:?}: {}", flags, error);
+            assert!(!dir.0.join("in.txt.stpk").exists(), "{:?} wrote an output", flags);
+        }
+        let output = encode_with(&dir, &["--histogram", "--embed_to_file"]).unwrap();
+        let container = fs::read(output).unwrap();
+        assert!(parse_container(&container).unwrap().histogram.is_some());
+    }
+
+    #[test]
+    fn host_record_needs_a_container() {
+        let dir = TempDir::new("host");
+        for flags in [&["--record-host"][..], &["--record-host", "--raw"]] {
+            let error = encode_with(&dir, flags).unwrap_err();
+            assert_eq!(error.class, ErrorClass::Usage, "{:?}: {}", flags, error);
+        }
+        let output = encode_with(&dir, &["--record-host", "--embed_to_file"]).unwrap();
+        let container = fs::read(output).unwrap();
+        assert!(parse_container(&container).unwrap().host.is_some());
     }
 }
@@ -0,0 +1,114 @@
+//! the errors commands stop on, and the exit codes they map to. `main` prints an error as
+//! `[error] stackpack: <what failed>: <why>` and exits with the code of its class:
+//!
+//! | code | class       | e.g.                                                                                |
+//! |------|-------------|-------------------------------------------------------------------------------------|
+//! | 1    | failure     | anything not covered below                                                          |
+//! | 2    | usage       | flags that don't go together or don't fit the input, as for the flags clap rejects  |
+//! | 3    | io          | an input that can't be read, an output that can't be written or already exists      |
+//! | 4    | pipeline    | an unknown stage, invalid stage parameters, a corrupt pipeline file or sidecar      |
+//! | 5    | data        | a corrupt or foreign input, a failed checksum or signature, a missing pipeline      |
+//! | 6    | stage       | a stage failing to encode or decode the data it is given                            |
+//! | 130  | interrupted | Ctrl-C, see [`crate::interrupt`]                                                    |
+//!
+//! commands return a [`CliResult`], and attach a class and what they were doing to the errors of the layers below
+//! with [`Classify`], the way anyhow's `Context` does.
+use core::fmt::{self, Display};
+use std::process;
+
+use anyhow::anyhow;
+
+use crate::{
+    events::{self, Event},
+    progress,
+};
+
+/// What kind of thing went wrong, which decides the exit code. see the module docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    Failure,
+    Usage,
+    Io,
+    Pipeline,
+    Data,
+    Stage,
+}
+
+impl ErrorClass {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorClass::Failure => 1,
+            ErrorClass::Usage => 2,
+            ErrorClass::Io => 3,
+            ErrorClass::Pipeline => 4,
+            ErrorClass::Data => 5,
+            ErrorClass::Stage => 6,
+        }
+    }
+}
+
+/// An error a command stops on.
+#[derive(Debug)]
+pub struct CliError {
+    pub class: ErrorClass,
+    error: anyhow::Error,
+}
+
+pub type CliResult<T = ()> = Result<T, CliError>;
+
+impl CliError {
+    pub fn new(class: ErrorClass, error: impl Into<anyhow::Error>) -> Self {
+        CliError {
+            class,
+            error: error.into(),
+        }
+    }
+
+    /// An error of `class` with just a message.
+    pub fn msg(class: ErrorClass, message: impl Display + Send + Sync + 'static) -> Self {
+        CliError::new(class, anyhow!("{}", message))
+    }
+
+    pub fn usage(message: impl Display + Send + Sync + 'static) -> Self {
+        CliError::msg(ErrorClass::Usage, message)
+    }
+
+    /// Prints the error and exits with the code of its class.
+    pub fn exit(self) -> ! {
+        progress::abandon();
+        let message = self.to_string();
+        eprintln!("[error] stackpack: {}", message);
+        events::emit(Event::Error {
+            message: &message,
+            code: self.class.exit_code(),
+        });
+        process::exit(self.class.exit_code());
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the alternate form lists the causes after the context, `failed to read a.txt: No such file or directory`
+        write!(f, "{:#}", self.error)
+    }
+}
+
+/// Classifies the errors of the layers below, see the module docs.
+pub trait Classify<T> {
+    /// Gives the error `class`, with `context` saying what was being done.
+    fn classify<C>(self, class: ErrorClass, context: impl FnOnce() -> C) -> CliResult<T>
+    where
+        C: Display + Send + Sync + 'static;
+}
+
+impl<T, E> Classify<T> for Result<T, E>
+where
+    E: Into<anyhow::Error>,
+{
+    fn classify<C>(self, class: ErrorClass, context: impl FnOnce() -> C) -> CliResult<T>
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        self.map_err(|error| CliError::new(class, error.into().context(context())))
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::{
+    archive::{ArchiveTable, MemberKind, PathPolicy, extract_entry},
+    cli::{self, ExtractArgs},
+    container::{Container, is_container, parse_container},
+    volumes::read_input,
+};
+
+pub fn extract(args: ExtractArgs) {
+    let input_path = &args.input;
+    let input_data = read_input(input_path, args.io.policy()).expect("Failed to read input file");
+    let (container, table) = parse_archive(input_path, &input_data);
+
+    let entry = args.entry.trim_matches('/');
+    let Some(member) = table.members.iter().find(|member| member.path == entry) else {
+        eprintln!(
+            "[error] stackpack: {} has no entry {:?}, `stackpack info` lists what it holds.",
+            input_path.display(),
+            entry
+        );
+        process::exit(1);
+    };
+    let name = entry.rsplit('/').next().unwrap_or(entry);
+    let output_path = match args.output.clone() {
+        Some(mut output) => {
+            if member.kind == MemberKind::File && output.is_dir() {
+                output.push(name);
+            }
+            output
+        }
+        None => PathBuf::from(name),
+    };
+    // directory entries are checked file by file as they are written
+    if member.kind == MemberKind::File && !cli::check_output(&output_path, args.io.policy()).unwrap_or_else(|e| e.exit()) {
+        return;
+    }
+
+    let mut pipeline = container.pipeline.clone();
+    let _written = extract_entry(
+        &mut pipeline,
+        &table,
+        container.payload,
+        entry,
+        &output_path,
+        args.io.policy(),
+        PathPolicy::new(args.unsafe_paths),
+    )
+    .expect("Failed to extract entry");
+    if_tracing! {{
+        tracing::info!(event = "entry_extracted", input = %input_path.display(), entry = entry, output = %output_path.display(), members = _written, "extracted entry");
+    }}
+}
+
+/// Parses a directory archive, exiting with an error for anything else. The member table is taken out of the
+/// container.
+pub(super) fn parse_archive<'a>(input_path: &Path, input_data: &'a [u8]) -> (Container<'a>, ArchiveTable) {
+    let mut container = match is_container(input_data).then(|| parse_container(input_data)) {
+        Some(Ok(container)) => container,
+        Some(Err(e)) => {
+            eprintln!("[error] stackpack: {}: {}", input_path.display(), e);
+            process::exit(1);
+        }
+        None => {
+            eprintln!("[error] stackpack: {} is not a stackpack container.", input_path.display());
+            process::exit(1);
+        }
+    };
+    let table = archive_table(input_path, &mut container);
+    (container, table)
+}
+
+/// Takes the archive table out of `container`, exiting if it holds a single file.
+pub(super) fn archive_table(input_path: &Path, container: &mut Container) -> ArchiveTable {
+    let Some(table) = container.archive.take() else {
+        eprintln!(
+            "[error] stackpack: {} holds a single file, not a directory archive, use dec to decode it.",
+            input_path.display()
+        );
+        process::exit(1);
+    };
+    table
+}
@@ -0,0 +1,126 @@
+use std::io::{self, ErrorKind, Write};
+use std::path::Path;
+use std::process;
+
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::{
+    algorithms::pipeline::CompressionPipeline,
+    archive::{MemberKind, decode_frame},
+    blocks::decode_blocks,
+    cli::{
+        GrepArgs, PipelineSelection,
+        decode::foreign_pipeline,
+        error::{Classify, CliError, CliResult, ErrorClass},
+        pipeline,
+    },
+    container::{is_container, parse_container},
+    io::IoPolicy,
+    mutator::Mutator,
+    sidecar::read_sidecar,
+    volumes::{read_input, volume_base},
+};
+
+pub fn grep(args: GrepArgs) -> CliResult {
+    let regex = RegexBuilder::new(&args.pattern)
+        .case_insensitive(args.ignore_case)
+        .build()
+        .map_err(|e| CliError::usage(format!("invalid regular expression: {}", e)))?;
+
+    let mut out = io::stdout().lock();
+    for input_path in &args.inputs {
+        grep_input(&args, &regex, input_path, &mut out)?;
+    }
+    Ok(())
+}
+
+/// Searches one input, picking its pipeline the way `dec` does: the embedded one of a container, then the sidecar,
+/// then the pipeline given on the cli or the stage of a known foreign format.
+fn grep_input(args: &GrepArgs, regex: &Regex, input_path: &Path, out: &mut impl Write) -> CliResult {
+    let input_data =
+        read_input(input_path, IoPolicy::default()).classify(ErrorClass::Io, || format!("failed to read {}", input_path.display()))?;
+    let selection = args.pipeline_selection();
+    let decode_failed = || format!("failed to decode {}", input_path.display());
+
+    if !is_container(&input_data) {
+        let mut pipeline = match selection {
+            // the sidecar is written next to the output `enc` was given, not next to its volumes
+            PipelineSelection::Default => {
+                let sidecar = read_sidecar(&volume_base(input_path).unwrap_or(input_path.to_path_buf()))
+                    .classify(ErrorClass::Pipeline, || "failed to read the pipeline sidecar")?;
+                match sidecar {
+                    Some(pipeline) => pipeline,
+                    None => foreign_pipeline(input_path, &input_data)?,
+                }
+            }
+            selection => pipeline::build_pipeline(selection)?,
+        };
+        let mut data = Vec::new();
+        pipeline.revert_mutation(&input_data, &mut data).classify(ErrorClass::Stage, decode_failed)?;
+        return grep_lines(regex, &input_path.display().to_string(), &data, out);
+    }
+
+    let container = parse_container(&input_data).classify(ErrorClass::Data, || input_path.display().to_string())?;
+    let mut pipeline = container.pipeline.clone();
+    if selection != PipelineSelection::Default {
+        let requested = pipeline::build_pipeline(selection)?;
+        if requested.fingerprint() != container.pipeline.fingerprint() {
+            return Err(CliError::msg(
+                ErrorClass::Pipeline,
+                format!(
+                    "{} embeds the pipeline \"{}\", not the requested pipeline \"{}\".",
+                    input_path.display(),
+                    container.pipeline,
+                    requested
+                ),
+            ));
+        }
+    }
+    if container.delta_base.is_some() {
+        return Err(CliError::usage(format!(
+            "{} is a delta, decode it with `dec --delta-against` and search the output.",
+            input_path.display()
+        )));
+    }
+    if let Some(table) = &container.archive {
+        // a frame at a time, so only the members of one frame are held in memory at once
+        for frame in 0..table.frames.len() {
+            let data = decode_frame(&mut pipeline, table, container.payload, frame).classify(ErrorClass::Stage, decode_failed)?;
+            for member in table.members.iter().filter(|member| member.kind == MemberKind::File && member.frame == frame) {
+                let start = member.offset as usize;
+                let name = format!("{}:{}", input_path.display(), member.path);
+                grep_lines(regex, &name, &data[start..start + member.size as usize], out)?;
+            }
+        }
+        return Ok(());
+    }
+    let data = decode_container(&mut pipeline, container.payload, container.block_size.is_some())
+        .classify(ErrorClass::Stage, decode_failed)?;
+    container
+        .verify(&data)
+        .classify(ErrorClass::Data, || format!("{} failed its integrity check", input_path.display()))?;
+    grep_lines(regex, &input_path.display().to_string(), &data, out)
+}
+
+fn decode_container(pipeline: &mut CompressionPipeline, payload: &[u8], block_mode: bool) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    match block_mode {
+        true => decode_blocks(pipeline, payload, &mut data)?,
+        false => pipeline.revert_mutation(payload, &mut data)?,
+    }
+    Ok(data)
+}
+
+/// Prints every line of `data` that matches as `{name}:{line number}:{line}`.
+fn grep_lines(regex: &Regex, name: &str, data: &[u8], out: &mut impl Write) -> CliResult {
+    for (index, line) in data.split(|&b| b == b'\n').enumerate() {
+        if regex.is_match(line) {
+            match writeln!(out, "{}:{}:{}", name, index + 1, String::from_utf8_lossy(line)) {
+                // the reader went away, e.g. `| head`, which is not an error worth reporting
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => process::exit(0),
+                result => result.classify(ErrorClass::Io, || "failed to write to stdout")?,
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use crate::{
+    cli::{HeatmapArgs, HeatmapFormat, pipeline},
+    io::read_file,
+    mutator::Mutator,
+    units::Ratio,
+};
+
+/// Bars of the sparkline, from compressing best to not compressing at all.
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Windows per sparkline row.
+const ROW_WIDTH: usize = 64;
+
+pub fn heatmap(args: HeatmapArgs) {
+    let input_path = &args.input;
+    let mut pipeline = pipeline::build_pipeline(args.pipeline_selection()).unwrap_or_else(|e| e.exit());
+    let data = read_file(input_path, args.io.policy()).expect("Failed to read input file");
+    let window = args.window as usize;
+
+    let mut compressed = Vec::new();
+    let sizes = data
+        .chunks(window)
+        .map(|chunk| {
+            pipeline.drive_mutation(chunk, &mut compressed).expect("Compression failed");
+            (chunk.len(), compressed.len())
+        })
+        .collect::<Vec<_>>();
+    let ratio = |(original, compressed): (usize, usize)| Ratio::new(original, compressed).value();
+
+    match args.format {
+        HeatmapFormat::Csv => {
+            println!("offset,length,compressed,ratio");
+            for (index, &(original, compressed)) in sizes.iter().enumerate() {
+                println!(
+                    "{},{},{},{:.4}",
+                    index * window,
+                    original,
+                    compressed,
+                    ratio((original, compressed))
+                );
+            }
+        }
+        HeatmapFormat::Sparkline => {
+            println!("{} with {}, {} byte windows", input_path.display(), pipeline, window);
+            for (row, windows) in sizes.chunks(ROW_WIDTH).enumerate() {
+                let bars = windows
+                    .iter()
+                    .map(|&sizes| BARS[((ratio(sizes) * BARS.len() as f64) as usize).min(BARS.len() - 1)])
+                    .collect::<String>();
+                println!("{:>12} {}", row * ROW_WIDTH * window, bars);
+            }
+            let incompressible = sizes.iter().filter(|&&(original, compressed)| compressed >= original).count();
+            let total = sizes.iter().map(|&(_, compressed)| compressed).sum::<usize>();
+            println!(
+                "{} windows, {} didn't shrink, {} -> {} bytes overall. each bar is one window, taller bars compress worse, a full bar saved less than an eighth.",
+                sizes.len(),
+                incompressible,
+                data.len(),
+                total
+            );
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::{process, time::UNIX_EPOCH};
+
+use crate::{
+    archive::MemberKind,
+    blocks::BlockIndex,
+    cli::InfoArgs,
+    container::{is_container, parse_container, read_host},
+    content,
+    host::HostInfo,
+    io::IoPolicy,
+    sidecar::{read_sidecar, sidecar_path},
+    units::{ByteSize, Grouped, Ratio},
+    volumes::read_input,
+};
+
+pub fn info(args: InfoArgs) {
+    let input_path = &args.input;
+    let bytes = read_input(input_path, IoPolicy::default()).expect("Failed to read input file");
+
+    println!("file: {}", input_path.display());
+    if !is_container(&bytes) {
+        let Some(pipeline) = read_sidecar(input_path).expect("Failed to read pipeline sidecar") else {
+            eprintln!(
+                "[error] stackpack: {} is not a stackpack container and has no {} sidecar.",
+                input_path.display(),
+                sidecar_path(input_path).display()
+            );
+            process::exit(1);
+        };
+        println!("format: raw payload with sidecar {}", sidecar_path(input_path).display());
+        println!("pipeline: {}", pipeline);
+        println!("compressed size: {}", size_line(bytes.len() as u64));
+        return;
+    }
+
+    let container = match parse_container(&bytes) {
+        Ok(container) => container,
+        Err(e) => {
+            // a pipeline of plugin stages that aren't loaded doesn't parse, but the record says what they are
+            if let Some(host) = read_host(&bytes) {
+                print_host(&host);
+            }
+            eprintln!("[error] stackpack: {}: {}", input_path.display(), e);
+            process::exit(1);
+        }
+    };
+    println!("format: stackpack container, version {}", container.version);
+    // a file of concatenated streams is described by its first one
+    let stream_len = (bytes.len() - container.trailing.len()) as u64;
+    if !container.trailing.is_empty() {
+        match count_streams(container.trailing) {
+            Some(streams) => println!("streams: {} concatenated, describing the first", streams + 1),
+            None => println!("streams: several, some unreadable, describing the first"),
+        }
+    }
+    if let Some(name) = &container.metadata.name {
+        println!("original name: {}", name);
+    }
+    if let Some(since_epoch) = container.metadata.modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()) {
+        println!("modified: {} seconds since the unix epoch", since_epoch.as_secs());
+    }
+    println!("pipeline: {}", container.pipeline);
+    for (index, stage) in container.pipeline.stages().iter().enumerate() {
+        println!("  stage {}: {}", index, stage);
+    }
+    if let Some(table) = &container.archive {
+        let files = table.members.iter().filter(|member| member.kind == MemberKind::File).count();
+        println!(
+            "archive: {} files and {} directories in {} frames",
+            files,
+            table.members.len() - files,
+            table.frames.len()
+        );
+        let composition = content::composition(table);
+        let total: u64 = composition.values().map(|share| share.bytes).sum();
+        let typed: u64 = composition.values().map(|share| share.files).sum();
+        if !composition.is_empty() {
+            let mut shares = composition.into_iter().collect::<Vec<_>>();
+            shares.sort_by_key(|(_, share)| std::cmp::Reverse(share.bytes));
+            let shares = shares
+                .iter()
+                .map(|(content, share)| {
+                    let percent = match total {
+                        0 => 100.0 * share.files as f64 / typed as f64,
+                        total => 100.0 * share.bytes as f64 / total as f64,
+                    };
+                    format!("{:.1}% {} ({} files)", percent, content.name(), share.files)
+                })
+                .collect::<Vec<_>>();
+            println!("composition: {}", shares.join(", "));
+        }
+    }
+    if let Some(block_size) = container.block_size {
+        match BlockIndex::parse(container.payload) {
+            Ok(index) => println!("blocks: {} of {}", index.len(), ByteSize(block_size)),
+            Err(e) => println!("blocks: {} each, index unreadable: {}", ByteSize(block_size), e),
+        }
+    }
+    if let Some(base) = &container.delta_base {
+        println!("delta against: a {} reference with {}", ByteSize(base.len), base.checksum);
+    }
+    match container.original_size {
+        Some(size) => println!("original size: {}", size_line(size)),
+        None => println!("original size: unknown"),
+    }
+    println!(
+        "compressed size: {}, {} bytes payload",
+        size_line(stream_len),
+        Grouped(container.payload.len() as u64)
+    );
+    if let Some(size) = container.original_size
+        && size != 0
+    {
+        let ratio = Ratio {
+            original: size,
+            compressed: stream_len,
+        };
+        println!("ratio: {} of the original, {:.1}% saved", ratio, ratio.percent_saved());
+    }
+    match container.checksum {
+        Some(checksum) => println!("checksum: {}", checksum),
+        None => println!("checksum: none"),
+    }
+    if container.signature.is_some() {
+        println!("signature: ed25519");
+    }
+    if let Some(host) = &container.host {
+        print_host(host);
+    }
+    if let Some(histogram) = &container.histogram {
+        println!(
+            "histogram: {} distinct bytes, {:.3} bits per byte (order 0)",
+            histogram.distinct(),
+            histogram.entropy()
+        );
+    }
+    if !container.skipped_fields.is_empty() {
+        let tags = container.skipped_fields.iter().map(|tag| format!("{:#x}", tag)).collect::<Vec<_>>();
+        println!("unknown header fields (skipped): {}", tags.join(", "));
+    }
+}
+
+/// Prints what the file was encoded with, see [`crate::host`].
+/// How many containers are concatenated in `trailing`, `None` if one of them is unreadable.
+fn count_streams(mut trailing: &[u8]) -> Option<usize> {
+    let mut streams = 0;
+    while !trailing.is_empty() {
+        trailing = parse_container(trailing).ok()?.trailing;
+        streams += 1;
+    }
+    Some(streams)
+}
+
+fn print_host(host: &HostInfo) {
+    println!("encoded with: {}", host);
+    match host.plugins.len() {
+        0 => println!("plugins needed: none"),
+        count => println!("plugins needed: {}", count),
+    }
+    for plugin in &host.plugins {
+        println!("  {}", plugin);
+    }
+}
+
+/// An exact byte count followed by its human readable size, e.g. `1,048,576 bytes (1.0 MiB)`.
+fn size_line(bytes: u64) -> String {
+    format!("{} bytes ({})", Grouped(bytes), ByteSize(bytes))
+}
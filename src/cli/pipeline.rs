@@ -1,65 +1,183 @@
+use std::fmt::Write;
 use std::fs;
+use std::path::Path;
+use std::process;
+use std::str;
 
 use crate::{
-    algorithms::pipeline::{CompressionPipeline, default_pipeline, get_preset, get_specific_compressor_from_name},
-    cli::{PipelineCommand, PipelineSelection},
+    algorithms::{
+        lint::lint,
+        pipeline::{CompressionPipeline, LEVELS, PRESET_NAMES, default_pipeline, get_level, get_preset, parse_stage},
+    },
+    cli::{
+        GraphFormat, OutputFormat, PipelineCommand, PipelineSelection,
+        error::{Classify, CliError, CliResult, ErrorClass},
+    },
+    config::UserConfig,
+    events,
     plugins::LOADED_PLUGINS,
-    registered::ALL_COMPRESSORS,
+    registered::{self, RegisteredCompressor, StageOrigin, stage_descriptors},
+    verbosity,
 };
 
-pub fn build_pipeline(selection: PipelineSelection) -> CompressionPipeline {
+/// Resolves the pipeline the cli flags select. unknown stages and presets and pipeline files that don't parse are
+/// [`ErrorClass::Pipeline`] errors.
+pub fn build_pipeline(selection: PipelineSelection) -> CliResult<CompressionPipeline> {
     match selection {
-        PipelineSelection::Inline(string) => {
-            let parts = string.split("->").map(|s| s.trim()).collect::<Vec<_>>();
-
-            let mut pipeline = CompressionPipeline::new();
-
-            for part in parts {
-                if let Some(comp) = get_specific_compressor_from_name(part) {
-                    pipeline.push_algorithm(comp.clone());
-                } else {
-                    if_tracing! {{
-                        tracing::error!(event = "unknown_algorithm", algorithm = %part, "unknown algorithm specified in inline pipeline");
-                    }}
-                    panic!(
-                        "unknown algorithm {:?}. you may have forgotten to enable plugins (unsafe), or not have the required plugins installed.",
-                        part
-                    );
+        PipelineSelection::Inline(string) => parse_inline(&string),
+        PipelineSelection::FromFile(path) => {
+            let data = fs::read(&path).classify(ErrorClass::Io, || format!("failed to read the pipeline file {}", path.display()))?;
+            let corrupt = || format!("the pipeline file {} is invalid", path.display());
+            if data.trim_ascii_start().starts_with(b"{") {
+                let json = str::from_utf8(&data).classify(ErrorClass::Pipeline, corrupt)?;
+                CompressionPipeline::try_from_json(json).classify(ErrorClass::Pipeline, corrupt)
+            } else {
+                CompressionPipeline::try_from_bytes(&data).ok_or_else(|| CliError::msg(ErrorClass::Pipeline, corrupt()))
+            }
+        }
+        PipelineSelection::Preset(preset_name) => match get_preset(&preset_name) {
+            Some(t) => Ok(t()),
+            None => {
+                let config = UserConfig::load().classify(ErrorClass::Io, || "failed to load the user config")?;
+                match config.pipelines.get(&preset_name) {
+                    Some(saved) => parse_inline(saved),
+                    None => Err(CliError::msg(
+                        ErrorClass::Pipeline,
+                        format!(
+                            "there is no preset or saved pipeline called \"{}\", `pipeline list --saved` lists the saved ones",
+                            preset_name
+                        ),
+                    )),
                 }
             }
+        },
+        PipelineSelection::Level(level) => Ok(get_level(level).expect("clap keeps levels within 1..=9")),
+        PipelineSelection::Default => Ok(default_pipeline()),
+    }
+}
 
-            pipeline
-        }
+/// The stages of the pipeline `selection` picks, each parsed on its own along with the form it was given in, so
+/// that every broken stage can be reported rather than the first. presets and levels are built in and parse as a
+/// whole, and so do pipeline files in the old comma separated format.
+fn parse_stages(selection: PipelineSelection) -> CliResult<Vec<(String, anyhow::Result<RegisteredCompressor>)>> {
+    match selection {
+        PipelineSelection::Inline(string) => Ok(string
+            .split("->")
+            .map(str::trim)
+            .map(|part| (part.to_string(), parse_stage(part)))
+            .collect()),
         PipelineSelection::FromFile(path) => {
-            let data = fs::read(&path).expect("couldn't read pipeline file");
-            CompressionPipeline::try_from_bytes(&data).expect("pipeline file corrupt")
+            let data = fs::read(&path).classify(ErrorClass::Io, || format!("failed to read the pipeline file {}", path.display()))?;
+            if !data.trim_ascii_start().starts_with(b"{") {
+                return whole_stages(PipelineSelection::FromFile(path));
+            }
+            let invalid = || format!("the pipeline file {} is invalid", path.display());
+            let json = str::from_utf8(&data).classify(ErrorClass::Pipeline, invalid)?;
+            CompressionPipeline::try_stages_from_json(json).classify(ErrorClass::Pipeline, invalid)
         }
-        PipelineSelection::Preset(preset_name) => match get_preset(&preset_name) {
-            Some(t) => t(),
-            None => default_pipeline(),
-        },
-        PipelineSelection::Default => default_pipeline(),
+        selection => whole_stages(selection),
+    }
+}
+
+fn whole_stages(selection: PipelineSelection) -> CliResult<Vec<(String, anyhow::Result<RegisteredCompressor>)>> {
+    let pipeline = build_pipeline(selection)?;
+    Ok(pipeline.stages().iter().map(|stage| (stage.to_string(), Ok(stage.clone()))).collect())
+}
+
+/// Records the resolved pipeline at the start of a run so logs show exactly what was executed.
+pub fn announce_pipeline(pipeline: &CompressionPipeline) {
+    if_tracing! {{
+        tracing::info!(event = "pipeline_resolved", pipeline = %pipeline, "running pipeline");
+    }}
+    if !verbosity::is_quiet() {
+        eprintln!("pipeline: {}", pipeline);
+    }
+}
+
+/// Makes `pipeline` write the output of every stage to `dir`, created if needed, for `--dump-stages`.
+pub fn dump_stages(pipeline: &mut CompressionPipeline, dir: Option<&Path>) -> CliResult {
+    if let Some(dir) = dir {
+        fs::create_dir_all(dir).classify(ErrorClass::Io, || format!("failed to create {}", dir.display()))?;
     }
+    pipeline.set_dump_dir(dir.map(Path::to_path_buf));
+    Ok(())
 }
 
-pub fn pipeline(args: PipelineCommand) {
+/// Parses a pipeline string of the form `"a -> b -> c"`.
+fn parse_inline(string: &str) -> CliResult<CompressionPipeline> {
+    let parts = string.split("->").map(|s| s.trim()).collect::<Vec<_>>();
+
+    let mut pipeline = CompressionPipeline::new();
+
+    for part in parts {
+        match parse_stage(part) {
+            Ok(comp) => pipeline.push_algorithm(comp),
+            Err(e) => {
+                if_tracing! {{
+                    tracing::error!(event = "invalid_stage", stage = %part, error = %e, "invalid stage in inline pipeline");
+                }}
+                return Err(CliError::new(ErrorClass::Pipeline, e.context(format!("invalid stage {:?}", part))));
+            }
+        }
+    }
+
+    Ok(pipeline)
+}
+
+pub fn pipeline(args: PipelineCommand) -> CliResult {
     match args {
-        PipelineCommand::ListCompressors { detailed } => {
-            for algo in ALL_COMPRESSORS.lock().iter() {
-                if detailed && let Some(desc) = algo.short_description {
-                    println!("Name: {}\nDescription: {}\n", algo.name, desc);
-                } else {
-                    println!("{}", algo.name);
+        PipelineCommand::ListCompressors { detailed, json, format } => {
+            let descriptors = stage_descriptors();
+            if json || format == OutputFormat::Json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&descriptors).expect("stage descriptors are always serializable")
+                );
+                return Ok(());
+            }
+            for stage in descriptors {
+                if !detailed {
+                    println!("{}", stage.name);
+                    continue;
+                }
+                println!("Name: {}", stage.name);
+                if !stage.aliases.is_empty() {
+                    println!("Aliases: {}", stage.aliases.join(", "));
+                }
+                println!("Category: {}", stage.category);
+                if let Some(desc) = stage.description {
+                    println!("Description: {}", desc);
+                }
+                for param in stage.params {
+                    match param.default {
+                        Some(default) => println!(
+                            "Parameter: {}={} (default {}) {}",
+                            param.name, param.kind, default, param.description
+                        ),
+                        None => println!("Parameter: {}={} {}", param.name, param.kind, param.description),
+                    }
                 }
+                match (&stage.origin, stage.version) {
+                    (StageOrigin::Plugin { path }, _) => println!("Origin: plugin {}", path.display()),
+                    (StageOrigin::Builtin, Some(version)) => println!("Origin: built in, stackpack {}", version),
+                    (StageOrigin::Builtin, None) => println!("Origin: built in"),
+                }
+                println!();
             }
         }
         PipelineCommand::ListPlugins => {
             let lock = LOADED_PLUGINS.lock();
             for item in lock.iter() {
+                // the short name is only listed where it picks this plugin's stage, see `registered::Registry`
+                let short_name = registered::lookup(item.api.short_name).filter(|stage| stage.name == item.stage_name);
                 println!(
-                    "Plugin loaded from: {:?}\nName: {}{}\n",
+                    "Plugin loaded from: {:?}\nName: {}{}{}\n",
                     item.loaded_from,
-                    item.api.short_name,
+                    item.stage_name,
+                    match short_name {
+                        Some(_) => format!("\nShort name: {}", item.api.short_name),
+                        None => String::new(),
+                    },
                     if let Some(desc) = item.api.description.as_option() {
                         format!("\nDescription: {}", desc)
                     } else {
@@ -68,6 +186,173 @@ pub fn pipeline(args: PipelineCommand) {
                 );
             }
         }
-        _ => todo!(),
+        PipelineCommand::Save { name, pipeline } => {
+            if PRESET_NAMES.contains(&name.as_str()) {
+                return Err(CliError::usage(format!(
+                    "{:?} is a built-in preset and can't be overwritten, save the pipeline under another name.",
+                    name
+                )));
+            }
+            // parse the pipeline so typos are caught now instead of when the preset is used.
+            parse_inline(&pipeline)?;
+            let mut config = UserConfig::load().classify(ErrorClass::Io, || "failed to load the user config")?;
+            config.pipelines.insert(name, pipeline);
+            let path = config.save().classify(ErrorClass::Io, || "failed to save the user config")?;
+            println!("saved pipeline to {}", path.display());
+        }
+        PipelineCommand::SaveToFile { pipeline, output } => {
+            let pipeline = parse_inline(&pipeline)?;
+            fs::write(&output, pipeline.to_json()).classify(ErrorClass::Io, || format!("failed to write {}", output.display()))?;
+            println!("saved pipeline to {}", output.display());
+        }
+        PipelineCommand::List { saved } => {
+            if saved {
+                let config = UserConfig::load().classify(ErrorClass::Io, || "failed to load the user config")?;
+                for (name, pipeline) in &config.pipelines {
+                    println!("{}: {}", name, pipeline);
+                }
+            } else {
+                for name in PRESET_NAMES {
+                    println!("{}", name);
+                }
+                for (level, pipeline) in LEVELS.iter().enumerate() {
+                    println!("--level {}: {}", level + 1, pipeline);
+                }
+            }
+        }
+        PipelineCommand::ExportGraph { pipeline, format, output } => {
+            let pipeline = build_pipeline(pipeline.selection())?;
+            let graph = match format {
+                GraphFormat::Dot => render_dot(&pipeline),
+                GraphFormat::Mermaid => render_mermaid(&pipeline),
+            };
+            match output {
+                Some(path) => fs::write(&path, graph).classify(ErrorClass::Io, || format!("failed to write {}", path.display()))?,
+                None => print!("{}", graph),
+            }
+        }
+        PipelineCommand::Validate { pipeline } => {
+            let stages = parse_stages(pipeline.selection())?;
+            let total = stages.len();
+            let mut valid = CompressionPipeline::new();
+            let mut invalid = 0;
+            for (index, (given, stage)) in stages.into_iter().enumerate() {
+                match stage {
+                    Ok(stage) => valid.push_algorithm(stage),
+                    Err(e) => {
+                        invalid += 1;
+                        println!("stage {} {:?}: {:#}", index, given, e);
+                    }
+                }
+            }
+            if invalid > 0 {
+                return Err(CliError::msg(
+                    ErrorClass::Pipeline,
+                    format!("{} of the {} stages {} invalid", invalid, total, if invalid == 1 { "is" } else { "are" }),
+                ));
+            }
+            println!("{}: valid", valid);
+        }
+        PipelineCommand::Explain { pipeline } => {
+            let pipeline = build_pipeline(pipeline.selection())?;
+            print!("{}", explain(&pipeline));
+        }
+        PipelineCommand::Lint { pipeline } => {
+            let pipeline = build_pipeline(pipeline.selection())?;
+            if !report_lints(&pipeline) {
+                println!("{}: no issues found", pipeline);
+                return Ok(());
+            }
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the lint warnings for `pipeline` to stderr, returning whether there were any.
+pub fn report_lints(pipeline: &CompressionPipeline) -> bool {
+    let warnings = lint(pipeline);
+    for warning in &warnings {
+        events::warn(warning);
+    }
+    !warnings.is_empty()
+}
+
+/// Describes every stage of `pipeline` for `pipeline explain`.
+fn explain(pipeline: &CompressionPipeline) -> String {
+    let mut out = format!("{}\n", pipeline);
+    for (index, stage) in pipeline.stages().iter().enumerate() {
+        let category = stage.info.category;
+        writeln!(out, "\nstage {}: {} ({})", index, stage.name, category).unwrap();
+        if let Some(description) = stage.short_description {
+            writeln!(out, "  {}", description).unwrap();
+        }
+        let explanation = stage.info.explanation;
+        writeln!(out, "  entropy: {}", explanation.entropy).unwrap();
+        writeln!(out, "  size: {}", explanation.size).unwrap();
+        writeln!(out, "  decoding: {}", explanation.decoding).unwrap();
+        let mut params = stage.params.entries().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>();
+        for param in stage.info.params.iter().filter(|param| stage.params.get(param.name).is_none()) {
+            match param.default {
+                Some(default) => params.push(format!("{}={} (default)", param.name, default)),
+                None => params.push(format!("{} picked when it runs", param.name)),
+            }
+        }
+        if !params.is_empty() {
+            writeln!(out, "  parameters: {}", params.join(", ")).unwrap();
+        }
+    }
+    if pipeline.stages().len() > 1 {
+        let reversed = pipeline.stages().iter().rev().map(|stage| stage.name).collect::<Vec<_>>();
+        writeln!(out, "\ndecoding runs the stages backwards: {}", reversed.join(", ")).unwrap();
+    }
+    out
+}
+
+/// Renders the encoding direction of a pipeline as a Graphviz digraph.
+fn render_dot(pipeline: &CompressionPipeline) -> String {
+    let mut out = String::from("digraph pipeline {\n    rankdir=LR;\n    node [shape=box];\n");
+    out.push_str("    input [shape=ellipse, label=\"input\"];\n");
+    for (index, stage) in pipeline.stages().iter().enumerate() {
+        writeln!(out, "    stage{} [label=\"{}\"];", index, dot_escape(&stage.to_string())).unwrap();
+    }
+    out.push_str("    output [shape=ellipse, label=\"output\"];\n");
+
+    let mut previous = String::from("input");
+    for index in 0..pipeline.stages().len() {
+        let current = format!("stage{}", index);
+        writeln!(out, "    {} -> {};", previous, current).unwrap();
+        previous = current;
+    }
+    writeln!(out, "    {} -> output;", previous).unwrap();
+    out.push_str("}\n");
+    out
+}
+
+/// Escapes `label` for a quoted DOT string, stage parameters such as file paths can hold quotes and backslashes.
+fn dot_escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes `label` for a quoted Mermaid node label, which takes entity codes instead of backslash escapes.
+fn mermaid_escape(label: &str) -> String {
+    label.replace('#', "#35;").replace('"', "#quot;")
+}
+
+/// Renders the encoding direction of a pipeline as a Mermaid flowchart.
+fn render_mermaid(pipeline: &CompressionPipeline) -> String {
+    let mut out = String::from("flowchart LR\n    input([input])\n");
+    for (index, stage) in pipeline.stages().iter().enumerate() {
+        writeln!(out, "    stage{}[\"{}\"]", index, mermaid_escape(&stage.to_string())).unwrap();
+    }
+    out.push_str("    output([output])\n");
+
+    let mut previous = String::from("input");
+    for index in 0..pipeline.stages().len() {
+        let current = format!("stage{}", index);
+        writeln!(out, "    {} --> {}", previous, current).unwrap();
+        previous = current;
     }
+    writeln!(out, "    {} --> output", previous).unwrap();
+    out
 }
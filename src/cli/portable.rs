@@ -0,0 +1,144 @@
+//! `portable-decoder` writes a single rust source file that decodes the files of one pipeline with nothing but a
+//! rust compiler, so data archived today can still be decoded long after this build and its dependencies are gone.
+//!
+//! the file holds the container, block mode and archive reading of [`crate::container`], [`crate::blocks`] and
+//! [`crate::archive`], and a decoder for every stage of the pipeline, written out from the templates next to this
+//! module. only stages simple enough to be reimplemented without their libraries have one: `bwt`, `mtf`, `arcode`,
+//! `identity`, `reverse` and `xor`, along with the `skip_head`, `skip_tail` and `on_error` parameters of every stage
+//! (see [`crate::algorithms::passthrough`]). pipelines with other stages, and delta containers, are refused.
+use crate::{
+    algorithms::{
+        passthrough::{Passthrough, skips_failures},
+        pipeline::CompressionPipeline,
+    },
+    cli::{
+        PortableDecoderArgs, check_output,
+        error::{Classify, CliError, CliResult, ErrorClass},
+        pipeline,
+    },
+    container::{is_container, parse_container},
+    io::{read_file, write_file},
+    registered::RegisteredCompressor,
+    sidecar::read_sidecar,
+};
+
+const MAIN: &str = include_str!("portable/main.rs.in");
+const UTIL: &str = include_str!("portable/util.rs.in");
+const MTF: &str = include_str!("portable/mtf.rs.in");
+const BWT: &str = include_str!("portable/bwt.rs.in");
+const ARCODE: &str = include_str!("portable/arcode.rs.in");
+const ON_ERROR: &str = include_str!("portable/on_error.rs.in");
+const REGIONS: &str = include_str!("portable/regions.rs.in");
+
+/// Stages the portable decoder can decode, and the template their decoder comes from.
+const STAGES: &[(&str, &str)] = &[
+    ("identity", UTIL),
+    ("reverse", UTIL),
+    ("xor", UTIL),
+    ("mtf", MTF),
+    ("bwt", BWT),
+    ("arcode", ARCODE),
+];
+
+pub fn portable_decoder(args: PortableDecoderArgs) -> CliResult {
+    let policy = args.io.policy();
+    if !check_output(&args.output, policy)? {
+        return Ok(());
+    }
+    let pipeline = resolve_pipeline(&args)?;
+    let source = generate(&pipeline)?;
+    write_file(&args.output, source.as_bytes(), policy).classify(ErrorClass::Io, || format!("failed to write {}", args.output.display()))
+}
+
+/// The pipeline of the input: the one in its container or sidecar, or the one the flags select for raw payloads.
+fn resolve_pipeline(args: &PortableDecoderArgs) -> CliResult<CompressionPipeline> {
+    let input = &args.input;
+    let bytes = read_file(input, args.io.policy()).classify(ErrorClass::Io, || format!("failed to read {}", input.display()))?;
+    if is_container(&bytes) {
+        let container = parse_container(&bytes).classify(ErrorClass::Data, || format!("failed to read {}", input.display()))?;
+        if container.delta_base.is_some() {
+            return Err(CliError::msg(
+                ErrorClass::Pipeline,
+                format!("{} is a delta, which the portable decoder can't apply", input.display()),
+            ));
+        }
+        return Ok(container.pipeline);
+    }
+    match read_sidecar(input).classify(ErrorClass::Pipeline, || format!("failed to read the sidecar of {}", input.display()))? {
+        Some(pipeline) => Ok(pipeline),
+        None => pipeline::build_pipeline(args.pipeline_selection()),
+    }
+}
+
+/// The source of the decoder for `pipeline`.
+pub fn generate(pipeline: &CompressionPipeline) -> CliResult<String> {
+    let unsupported = pipeline
+        .stages()
+        .iter()
+        .filter(|stage| !STAGES.iter().any(|(name, _)| *name == stage.name))
+        .map(|stage| stage.name)
+        .collect::<Vec<_>>();
+    if !unsupported.is_empty() {
+        let supported = STAGES.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        return Err(CliError::msg(
+            ErrorClass::Pipeline,
+            format!(
+                "the portable decoder can't decode {}, only {}",
+                unsupported.join(", "),
+                supported.join(", ")
+            ),
+        ));
+    }
+
+    let mut stages = String::new();
+    let mut stage_fns = String::new();
+    for (index, stage) in pipeline.stages().iter().enumerate().rev() {
+        stages += &format!("    data = stage_{}(&data)?;\n", index);
+        stage_fns += &format!(
+            "\n/// Stage {}, `{}`.\nfn stage_{}(data: &[u8]) -> Result<Vec<u8>> {{\n    {}\n}}\n",
+            index,
+            stage,
+            index,
+            revert_call(stage)
+        );
+    }
+    let mut source = MAIN
+        .replace("{{PIPELINE}}", &pipeline.to_string())
+        .replace("{{VERSION}}", env!("CARGO_PKG_VERSION"))
+        .replace("{{STAGES}}", &stages)
+        .replace("{{STAGE_FNS}}", &stage_fns);
+    // every template needed once, the stages in the order of STAGES
+    let mut templates: Vec<&str> = Vec::new();
+    for (name, template) in STAGES {
+        if pipeline.stages().iter().any(|stage| stage.name == *name) && !templates.contains(template) {
+            templates.push(template);
+        }
+    }
+    if pipeline.stages().iter().any(|stage| skips_failures(&stage.params)) {
+        templates.push(ON_ERROR);
+    }
+    if pipeline.stages().iter().any(|stage| Passthrough::from_params(&stage.params).is_some()) {
+        templates.push(REGIONS);
+    }
+    for template in templates {
+        source += template;
+    }
+    Ok(source)
+}
+
+/// The expression that runs `stage` backwards on `data` in the generated source.
+fn revert_call(stage: &RegisteredCompressor) -> String {
+    let mut call = match stage.name {
+        "xor" => format!("xor(data, {})", stage.params.get("key").unwrap_or("255")),
+        "arcode" => format!("arcode_decode(data, {})", stage.params.get("precision").unwrap_or("48")),
+        "identity" | "reverse" => format!("{}(data)", stage.name),
+        name => format!("{}_decode(data)", name),
+    };
+    if Passthrough::from_params(&stage.params).is_some() {
+        call = format!("revert_regions(data, &|data: &[u8]| {})", call);
+    }
+    if skips_failures(&stage.params) {
+        call = format!("revert_or_skip(data, &|data: &[u8]| {})", call);
+    }
+    call
+}
@@ -0,0 +1,242 @@
+//! crash reports, asked for with `--report`. when a round trip in `test` or `corpus` fails, or `dec` can't decode
+//! its input, everything needed to look into it goes into a single `<input name>.stackpack-report.zip` in the
+//! working directory:
+//!
+//! | entry           | contents                                                                   |
+//! |-----------------|----------------------------------------------------------------------------|
+//! | `report.txt`    | what failed and why, the stackpack version and build, platform and command |
+//! | `pipeline.json` | the pipeline, readable with `--from_file`                                  |
+//! | `stages.txt`    | the stages run one at a time, with the size of what went in and came out   |
+//! | `data/`         | the input of every stage, cut to [`DATA_LIMIT`] bytes, with `--report-data` |
+//!
+//! the data itself stays out of the report unless `--report-data` is given, so reports of private files can be
+//! shared. `sanitize` shrinks such files into something that can be shared along with the report.
+use std::{
+    env,
+    fmt::Write as _,
+    io::{Cursor, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    algorithms::pipeline::CompressionPipeline,
+    cli::{ReportArgs, selftest::build_name},
+    events,
+    io::{IoPolicy, write_file},
+    mutator::Mutator,
+    registered::RegisteredCompressor,
+};
+
+/// Stage data in a report is cut to this many bytes.
+pub const DATA_LIMIT: usize = 64 * 1024;
+/// Added to the input name to name the report.
+const REPORT_SUFFIX: &str = ".stackpack-report.zip";
+
+/// A failure along with the stage by stage account of it.
+pub struct Report {
+    summary: String,
+    pipeline: CompressionPipeline,
+    stages: String,
+    data: Vec<(String, Vec<u8>)>,
+}
+
+impl Report {
+    /// Reruns the round trip of `data` one stage at a time, checking every stage's decoded output against what went
+    /// into it.
+    pub fn round_trip(input_path: &Path, pipeline: &CompressionPipeline, data: &[u8], error: &str) -> Self {
+        let mut report = Report::new(format!("round trip of {} failed: {}", input_path.display(), error), pipeline);
+        let stages = pipeline.stages();
+        let mut inputs = vec![data.to_vec()];
+        for (index, stage) in stages.iter().enumerate() {
+            let input = &inputs[index];
+            report.data.push((format!("data/encode-{}-{}.bin", index, stage.name), head(input)));
+            match run_stage(stage, input, true) {
+                Ok(output) => {
+                    let _ = writeln!(
+                        report.stages,
+                        "encode {} ({}): {} -> {} bytes",
+                        index,
+                        stage,
+                        input.len(),
+                        output.len()
+                    );
+                    inputs.push(output);
+                }
+                Err(e) => {
+                    let _ = writeln!(report.stages, "encode {} ({}): {} bytes, failed: {}", index, stage, input.len(), e);
+                    return report;
+                }
+            }
+        }
+        let mut current = inputs.pop().expect("the input is always there");
+        for (index, stage) in stages.iter().enumerate().rev() {
+            report
+                .data
+                .push((format!("data/decode-{}-{}.bin", index, stage.name), head(&current)));
+            match run_stage(stage, &current, false) {
+                Ok(output) => {
+                    let expected = &inputs[index];
+                    let verdict = match output.iter().zip(expected).position(|(got, expected)| got != expected) {
+                        Some(offset) => format!(", differs from the encoder input at byte {}", offset),
+                        None if output.len() != expected.len() => {
+                            format!(", encoder input was {} bytes", expected.len())
+                        }
+                        None => String::new(),
+                    };
+                    let _ = writeln!(
+                        report.stages,
+                        "decode {} ({}): {} -> {} bytes{}",
+                        index,
+                        stage,
+                        current.len(),
+                        output.len(),
+                        verdict
+                    );
+                    current = output;
+                }
+                Err(e) => {
+                    let _ = writeln!(
+                        report.stages,
+                        "decode {} ({}): {} bytes, failed: {}",
+                        index,
+                        stage,
+                        current.len(),
+                        e
+                    );
+                    return report;
+                }
+            }
+        }
+        report
+    }
+
+    /// Reverts `payload` one stage at a time. `payload` is `None` when it isn't the output of the pipeline as a
+    /// whole, e.g. in block mode, and only the error is reported.
+    pub fn decode(input_path: &Path, pipeline: &CompressionPipeline, payload: Option<&[u8]>, error: &str) -> Self {
+        let mut report = Report::new(format!("decoding {} failed: {}", input_path.display(), error), pipeline);
+        let Some(payload) = payload else {
+            report
+                .stages
+                .push_str("the payload is split into blocks or frames, the stages weren't rerun\n");
+            return report;
+        };
+        let mut current = payload.to_vec();
+        for (index, stage) in pipeline.stages().iter().enumerate().rev() {
+            report
+                .data
+                .push((format!("data/decode-{}-{}.bin", index, stage.name), head(&current)));
+            match run_stage(stage, &current, false) {
+                Ok(output) => {
+                    let _ = writeln!(
+                        report.stages,
+                        "decode {} ({}): {} -> {} bytes",
+                        index,
+                        stage,
+                        current.len(),
+                        output.len()
+                    );
+                    current = output;
+                }
+                Err(e) => {
+                    let _ = writeln!(
+                        report.stages,
+                        "decode {} ({}): {} bytes, failed: {}",
+                        index,
+                        stage,
+                        current.len(),
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+        report
+    }
+
+    fn new(summary: String, pipeline: &CompressionPipeline) -> Self {
+        Report {
+            summary,
+            pipeline: pipeline.clone(),
+            stages: String::new(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Writes the report next to where the command runs, named after `input_path`, and returns its path.
+    pub fn write(&self, input_path: &Path, include_data: bool, policy: IoPolicy) -> Result<PathBuf> {
+        let name = input_path.file_name().map_or("stdin".into(), |name| name.to_string_lossy());
+        let path = PathBuf::from(format!("{}{}", name, REPORT_SUFFIX));
+
+        let mut summary = format!("{}\n\n", self.summary);
+        let _ = writeln!(summary, "stackpack {} ({} build)", env!("CARGO_PKG_VERSION"), build_name());
+        let _ = writeln!(summary, "platform: {} {}", env::consts::OS, env::consts::ARCH);
+        let _ = writeln!(summary, "command: {}", env::args().collect::<Vec<_>>().join(" "));
+        let _ = writeln!(summary, "pipeline: {}", self.pipeline);
+        match include_data {
+            true => {
+                let _ = writeln!(summary, "stage data: included, each cut to {} bytes", DATA_LIMIT);
+            }
+            false => summary.push_str("stage data: left out, pass --report-data to include it\n"),
+        }
+
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("report.txt", options)?;
+        zip.write_all(summary.as_bytes())?;
+        zip.start_file("pipeline.json", options)?;
+        zip.write_all(self.pipeline.to_json().as_bytes())?;
+        zip.start_file("stages.txt", options)?;
+        zip.write_all(self.stages.as_bytes())?;
+        if include_data {
+            for (name, data) in &self.data {
+                zip.start_file(name, options)?;
+                zip.write_all(data)?;
+            }
+        }
+        let bytes = zip.finish()?.into_inner();
+        // reports are scratch files, the one of the last failure replaces earlier ones
+        write_file(&path, &bytes, policy.forced())?;
+        Ok(path)
+    }
+
+    /// Writes the report as `args` asked for, telling the user where it went.
+    pub fn save(&self, input_path: &Path, args: ReportArgs, policy: IoPolicy) -> Option<PathBuf> {
+        match self.write(input_path, args.report_data, policy) {
+            Ok(path) => {
+                events::warn(format_args!("wrote a crash report to {}.", path.display()));
+                Some(path)
+            }
+            Err(e) => {
+                events::warn(format_args!("failed to write a crash report for {}: {}", input_path.display(), e));
+                None
+            }
+        }
+    }
+}
+
+/// The part of stage data a report keeps.
+fn head(data: &[u8]) -> Vec<u8> {
+    data[..data.len().min(DATA_LIMIT)].to_vec()
+}
+
+/// Runs one stage on its own, treating a panic as an error.
+fn run_stage(stage: &RegisteredCompressor, data: &[u8], encode: bool) -> Result<Vec<u8>, String> {
+    let mut stage = stage.clone();
+    let mut output = Vec::new();
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match encode {
+        true => stage.drive_mutation(data, &mut output),
+        false => stage.revert_mutation(data, &mut output),
+    }));
+    panic::set_hook(default_hook);
+    match result {
+        Ok(Ok(())) => Ok(output),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("the stage panicked".to_string()),
+    }
+}
@@ -0,0 +1,191 @@
+//! shrinks an input that breaks the round trip of a pipeline into a small one that still breaks it the same way, so
+//! a reproduction can be shared without the private data it came from.
+//!
+//! the input is first minimized by delta debugging: pieces of it are cut out, halving the piece size down to single
+//! bytes, and every cut after which the round trip still fails the same way is kept. then, unless `--no-anonymize` is
+//! given, the bytes that are left are overwritten with zeros in the same fashion wherever the failure survives it, so
+//! the only original bytes in the result are the ones the failure depends on.
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    process,
+};
+
+use crate::{
+    algorithms::pipeline::CompressionPipeline,
+    cli::{SanitizeArgs, check_output, pipeline},
+    events,
+    io::{read_file, write_file},
+    mutator::Mutator,
+};
+
+/// What replaces the bytes the failure doesn't depend on.
+const FILLER: u8 = 0;
+
+/// How a round trip went wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    EncodeFailed,
+    EncodePanicked,
+    DecodeFailed,
+    DecodePanicked,
+    Mismatch,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Failure::EncodeFailed => "encoding fails",
+            Failure::EncodePanicked => "encoding panics",
+            Failure::DecodeFailed => "decoding fails",
+            Failure::DecodePanicked => "decoding panics",
+            Failure::Mismatch => "the decoded data differs",
+        })
+    }
+}
+
+fn round_trip(mut pipeline: CompressionPipeline, data: &[u8]) -> Option<Failure> {
+    let mut encoded = Vec::new();
+    match panic::catch_unwind(AssertUnwindSafe(|| pipeline.drive_mutation(data, &mut encoded))) {
+        Ok(Ok(())) => {}
+        Ok(Err(_)) => return Some(Failure::EncodeFailed),
+        Err(_) => return Some(Failure::EncodePanicked),
+    }
+    let mut decoded = Vec::new();
+    match panic::catch_unwind(AssertUnwindSafe(|| pipeline.revert_mutation(&encoded, &mut decoded))) {
+        Ok(Ok(())) if decoded == data => None,
+        Ok(Ok(())) => Some(Failure::Mismatch),
+        Ok(Err(_)) => Some(Failure::DecodeFailed),
+        Err(_) => Some(Failure::DecodePanicked),
+    }
+}
+
+/// Tries candidates against the pipeline, up to a budget of round trips.
+struct Reducer {
+    pipeline: CompressionPipeline,
+    failure: Failure,
+    runs: usize,
+    max_runs: usize,
+}
+
+impl Reducer {
+    /// Whether `candidate` still fails the way the input did. once the budget is spent nothing does.
+    fn still_fails(&mut self, candidate: &[u8]) -> bool {
+        if self.runs >= self.max_runs {
+            return false;
+        }
+        self.runs += 1;
+        round_trip(self.pipeline.clone(), candidate) == Some(self.failure)
+    }
+
+    fn exhausted(&self) -> bool {
+        self.runs >= self.max_runs
+    }
+
+    /// Cuts out every piece of `data` the failure doesn't need.
+    fn minimize(&mut self, mut data: Vec<u8>) -> Vec<u8> {
+        let mut piece = (data.len() / 2).max(1);
+        while !data.is_empty() && !self.exhausted() {
+            let mut cut = false;
+            let mut start = 0;
+            while start < data.len() && !self.exhausted() {
+                let end = (start + piece).min(data.len());
+                let candidate = [&data[..start], &data[end..]].concat();
+                if self.still_fails(&candidate) {
+                    data = candidate;
+                    cut = true;
+                } else {
+                    start = end;
+                }
+            }
+            if !cut {
+                if piece == 1 {
+                    break;
+                }
+                piece /= 2;
+            }
+        }
+        data
+    }
+
+    /// Overwrites every piece of `data` the failure doesn't depend on with [`FILLER`].
+    fn anonymize(&mut self, mut data: Vec<u8>) -> Vec<u8> {
+        let mut piece = (data.len() / 2).max(1);
+        loop {
+            for start in (0..data.len()).step_by(piece) {
+                let end = (start + piece).min(data.len());
+                if self.exhausted() {
+                    return data;
+                }
+                if data[start..end].iter().all(|&byte| byte == FILLER) {
+                    continue;
+                }
+                let mut candidate = data.clone();
+                candidate[start..end].fill(FILLER);
+                if self.still_fails(&candidate) {
+                    data = candidate;
+                }
+            }
+            if piece == 1 {
+                return data;
+            }
+            piece /= 2;
+        }
+    }
+}
+
+pub fn sanitize(args: SanitizeArgs) {
+    let input_path = &args.input;
+    let output_path = &args.output;
+    let policy = args.io.policy();
+    if !check_output(output_path, policy).unwrap_or_else(|e| e.exit()) {
+        return;
+    }
+    let pipeline = pipeline::build_pipeline(args.pipeline_selection()).unwrap_or_else(|e| e.exit());
+    pipeline::announce_pipeline(&pipeline);
+    let data = read_file(input_path, policy).expect("Failed to read input file");
+
+    // the stage that breaks may well panic, which is an outcome like any other here
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let Some(failure) = round_trip(pipeline.clone(), &data) else {
+        panic::set_hook(default_hook);
+        eprintln!(
+            "[error] stackpack: {} round trips through {} just fine, there is no failure to reduce.",
+            input_path.display(),
+            pipeline
+        );
+        process::exit(1);
+    };
+    let mut reducer = Reducer {
+        pipeline,
+        failure,
+        runs: 1,
+        max_runs: args.max_runs,
+    };
+    let mut reduced = reducer.minimize(data.clone());
+    if !args.no_anonymize {
+        reduced = reducer.anonymize(reduced);
+    }
+    panic::set_hook(default_hook);
+
+    write_file(output_path, &reduced, policy).expect("Failed to write output file");
+    let kept = reduced.iter().filter(|&&byte| byte != FILLER).count();
+    println!(
+        "{}: {} with {}, reduced from {} to {} bytes, {} of them not filler, in {} round trips",
+        input_path.display(),
+        failure,
+        reducer.pipeline,
+        data.len(),
+        reduced.len(),
+        kept,
+        reducer.runs
+    );
+    if reducer.exhausted() {
+        events::warn(format_args!(
+            "stopped after --max-runs {} round trips, {} may shrink further.",
+            args.max_runs,
+            output_path.display()
+        ));
+    }
+}
@@ -0,0 +1,248 @@
+//! round trips a fixed set of pipelines over generated samples and fingerprints the encoded output.
+//!
+//! the samples are generated rather than read from disk, so two stackpack binaries always see the same input.
+//! `selftest --cross-feature <other stackpack>` runs the other binary's selftest, usually the one built with the
+//! other setting of the `tracing` feature, and compares the encoded outputs case by case. `registered.rs` and several
+//! stages dispatch through different code depending on that feature, and this is what catches them drifting apart.
+//!
+//! `selftest --perf` times the stages instead, see [`perf`].
+use std::{
+    collections::BTreeMap,
+    panic::{self, AssertUnwindSafe},
+    process::{self, Command},
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    algorithms::pipeline::{CompressionPipeline, PRESET_NAMES, get_preset, parse_stage},
+    cli::SelftestArgs,
+    events,
+    mutator::Mutator,
+    registered,
+};
+
+mod perf;
+
+/// Prefix of the machine readable lines `--fingerprints` prints, tracing output may be interleaved with them.
+const FINGERPRINT_PREFIX: &str = "fingerprint\t";
+
+/// Stages with parameters, which go through the parameterised dispatch path.
+const PARAMETERISED_PIPELINES: &[&str] = &["bsc(block=4k, width=16)", "xor(key=90)", "bpe(merges=16)", "lz4(streams=split)"];
+
+/// The outcome of one pipeline on one sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Outcome {
+    status: String,
+    encoded_len: usize,
+    crc: u32,
+}
+
+impl Outcome {
+    /// Stages refusing to encode a sample, or not being finished yet, is fine. Getting something other than the sample
+    /// back is not.
+    fn passed(&self) -> bool {
+        matches!(self.status.as_str(), "ok" | "encode-failed")
+    }
+}
+
+type Cases = BTreeMap<(String, String), Outcome>;
+
+pub fn selftest(args: SelftestArgs) {
+    if args.perf {
+        if !perf::run(args.perf_tolerance) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    let cases = run_cases();
+    let build = build_name();
+
+    if args.fingerprints {
+        println!("{}build\t{}", FINGERPRINT_PREFIX, build);
+        for ((pipeline, sample), outcome) in &cases {
+            println!(
+                "{}{}\t{}\t{}\t{}\t{:08x}",
+                FINGERPRINT_PREFIX, pipeline, sample, outcome.status, outcome.encoded_len, outcome.crc
+            );
+        }
+        return;
+    }
+
+    let mut failed = false;
+    for ((pipeline, sample), outcome) in &cases {
+        println!(
+            "{:<28} {:<8} {:<16} {:>8} bytes  crc32 {:08x}",
+            pipeline, sample, outcome.status, outcome.encoded_len, outcome.crc
+        );
+        failed |= !outcome.passed();
+    }
+
+    if let Some(other) = &args.cross_feature {
+        let (other_build, other_cases) = match fingerprints_of(other) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("[error] stackpack: failed to run the selftest of {}: {}", other.display(), e);
+                process::exit(1);
+            }
+        };
+        if other_build == build {
+            events::warn(format_args!(
+                "{} is also a {} build, the comparison won't cover the other feature set.",
+                other.display(),
+                build
+            ));
+        }
+        let mut divergences = 0;
+        for key in cases.keys().chain(other_cases.keys().filter(|key| !cases.contains_key(*key))) {
+            let (pipeline, sample) = key;
+            match (cases.get(key), other_cases.get(key)) {
+                (Some(ours), Some(theirs)) if ours == theirs => continue,
+                (Some(ours), Some(theirs)) => println!(
+                    "diverged: {} on {}: {} build gave {} ({} bytes, crc32 {:08x}), {} build gave {} ({} bytes, crc32 {:08x})",
+                    pipeline,
+                    sample,
+                    build,
+                    ours.status,
+                    ours.encoded_len,
+                    ours.crc,
+                    other_build,
+                    theirs.status,
+                    theirs.encoded_len,
+                    theirs.crc
+                ),
+                (Some(_), None) => println!("diverged: {} on {} only ran in the {} build", pipeline, sample, build),
+                (None, _) => println!("diverged: {} on {} only ran in the {} build", pipeline, sample, other_build),
+            }
+            divergences += 1;
+        }
+        if divergences == 0 {
+            println!(
+                "{} and {} builds produced identical output for {} cases",
+                build,
+                other_build,
+                cases.len()
+            );
+        }
+        failed |= divergences > 0;
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+pub(super) fn build_name() -> &'static str {
+    if cfg!(feature = "tracing") { "tracing" } else { "non-tracing" }
+}
+
+/// Runs `stackpack selftest --fingerprints` with the given binary and parses its output.
+fn fingerprints_of(binary: &std::path::Path) -> Result<(String, Cases)> {
+    let output = Command::new(binary)
+        .args(["selftest", "--fingerprints"])
+        .env("RUST_LOG", "error")
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!("it exited with {}", output.status));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut build = None;
+    let mut cases = Cases::new();
+    for line in stdout.lines().filter_map(|line| line.strip_prefix(FINGERPRINT_PREFIX)) {
+        let fields = line.split('\t').collect::<Vec<_>>();
+        match fields.as_slice() {
+            ["build", name] => build = Some(name.to_string()),
+            [pipeline, sample, status, encoded_len, crc] => {
+                let outcome = Outcome {
+                    status: status.to_string(),
+                    encoded_len: encoded_len.parse()?,
+                    crc: u32::from_str_radix(crc, 16)?,
+                };
+                cases.insert((pipeline.to_string(), sample.to_string()), outcome);
+            }
+            _ => return Err(anyhow!("unexpected fingerprint line {:?}", line)),
+        }
+    }
+    let build = build.ok_or_else(|| anyhow!("it printed no fingerprints, is it a stackpack with selftest?"))?;
+    Ok((build, cases))
+}
+
+fn pipelines() -> Vec<CompressionPipeline> {
+    let mut pipelines = registered::stages()
+        .into_iter()
+        .map(|compressor| CompressionPipeline::new().with_algorithm(compressor.clone()))
+        .collect::<Vec<_>>();
+    pipelines.extend(PRESET_NAMES.iter().filter_map(|name| get_preset(name)).map(|preset| preset()));
+    for stage in PARAMETERISED_PIPELINES {
+        pipelines.push(CompressionPipeline::new().with_algorithm(parse_stage(stage).expect("built-in selftest stage is valid")));
+    }
+    pipelines
+}
+
+fn samples() -> Vec<(&'static str, Vec<u8>)> {
+    let text = b"the quick brown fox jumps over the lazy dog, then naps in the sun. "
+        .iter()
+        .copied()
+        .cycle()
+        .take(16 * 1024)
+        .collect();
+    let runs = (0..16 * 1024).map(|i: u32| (i / 97 % 7) as u8 * 31).collect();
+    // xorshift, so the noise is the same on every platform and in every build
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+    let noise = (0..16 * 1024)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    vec![
+        ("empty", Vec::new()),
+        ("byte", vec![0x2a]),
+        ("text", text),
+        ("runs", runs),
+        ("noise", noise),
+    ]
+}
+
+fn run_cases() -> Cases {
+    let samples = samples();
+    let mut cases = Cases::new();
+    // unfinished stages panic instead of returning an error, which is an outcome like any other here
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    for pipeline in pipelines() {
+        for (name, sample) in &samples {
+            cases.insert((pipeline.to_string(), name.to_string()), round_trip(pipeline.clone(), sample));
+        }
+    }
+    panic::set_hook(default_hook);
+    cases
+}
+
+fn round_trip(mut pipeline: CompressionPipeline, sample: &[u8]) -> Outcome {
+    let mut encoded = Vec::new();
+    let encode = panic::catch_unwind(AssertUnwindSafe(|| pipeline.drive_mutation(sample, &mut encoded)));
+    if !matches!(encode, Ok(Ok(()))) {
+        return Outcome {
+            status: "encode-failed".to_string(),
+            encoded_len: 0,
+            crc: 0,
+        };
+    }
+    let mut decoded = Vec::new();
+    let decode = panic::catch_unwind(AssertUnwindSafe(|| pipeline.revert_mutation(&encoded, &mut decoded)));
+    let status = match decode {
+        Ok(Ok(())) if decoded == sample => "ok",
+        Ok(Ok(())) => "mismatch",
+        Ok(Err(_)) => "decode-failed",
+        Err(_) => "decode-panicked",
+    };
+    Outcome {
+        status: status.to_string(),
+        encoded_len: encoded.len(),
+        crc: crc32fast::hash(&encoded),
+    }
+}
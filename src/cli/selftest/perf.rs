@@ -0,0 +1,152 @@
+//! `selftest --perf`, which times every built-in stage over a generated sample and compares its throughput with the
+//! numbers recorded in [`REFERENCE`]. a stage more than `--perf-tolerance` times slower than its reference in either
+//! direction fails the selftest. the tolerance is loose on purpose: machines differ by a few times, and what this is
+//! after is a stage suddenly going quadratic, not a few percent lost.
+//!
+//! the stages run on one thread, so the machine's core count doesn't move the numbers. the references are of a
+//! release build, a debug build prints its numbers without checking them.
+use std::{
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    algorithms::heuristics,
+    cli::calibrate,
+    mutator::Mutator,
+    registered::{self, RegisteredCompressor, StageOrigin},
+    units::{ByteSize, MEBIBYTES, Throughput},
+};
+
+/// Size of the generated sample every stage is timed on.
+const SAMPLE_SIZE: usize = 256 * 1024;
+/// Every measurement is the best of this many.
+const ROUNDS: usize = 3;
+
+/// Encoding and decoding throughput in MiB/s of each built-in stage on the sample, in a release build on one thread.
+const REFERENCE: &[(&str, f64, f64)] = &[
+    ("arcode", 6.6, 3.3),
+    ("bwt", 26.5, 88.3),
+    ("mtf", 22.2, 61.1),
+    ("bsc", 4.2, 4.9),
+    ("lz4", 239.7, 968.2),
+    ("bpe", 3.4, 307.2),
+    ("alphabet", 915.9, 441.1),
+    ("rle1", 99.6, 246.0),
+    ("identity", 29800.0, 28400.0),
+    ("reverse", 7900.0, 7900.0),
+    ("xor", 29200.0, 29100.0),
+];
+
+/// How one stage did.
+enum Measurement {
+    Timed {
+        encode: Duration,
+        decode: Duration,
+    },
+    /// The stage refused the sample, panicked on it, or didn't give it back.
+    Broken(&'static str),
+}
+
+/// Runs the performance selftest, returns whether every stage kept up with its reference.
+pub fn run(tolerance: f64) -> bool {
+    let sample = calibrate::sample(SAMPLE_SIZE);
+    let checked = !cfg!(debug_assertions);
+    heuristics::set_threads(1);
+    println!(
+        "timing the built-in stages on a {} sample, failing below 1/{} of the reference throughput{}.",
+        ByteSize(SAMPLE_SIZE as u64),
+        tolerance,
+        if checked { "" } else { " (debug build, not checked)" }
+    );
+
+    // unfinished stages panic instead of returning an error, they are reported like any other broken stage
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let mut passed = true;
+    for stage in registered::stages()
+        .into_iter()
+        .filter(|stage| matches!(stage.descriptor().origin, StageOrigin::Builtin))
+    {
+        let name = stage.name;
+        let (encode, decode) = match measure(stage, &sample) {
+            Measurement::Timed { encode, decode } => (encode, decode),
+            Measurement::Broken(why) => {
+                println!("{:<12} {}", name, why);
+                continue;
+            }
+        };
+        let Some(&(_, encode_reference, decode_reference)) = REFERENCE.iter().find(|(reference, ..)| *reference == name) else {
+            println!(
+                "{:<12} encode {:>12}  decode {:>12}  no reference",
+                name,
+                throughput(encode),
+                throughput(decode)
+            );
+            continue;
+        };
+        let encode_ok = mebibytes_per_second(encode) * tolerance >= encode_reference;
+        let decode_ok = mebibytes_per_second(decode) * tolerance >= decode_reference;
+        let verdict = match (encode_ok, decode_ok) {
+            (true, true) => "ok",
+            (false, true) => "encoding regressed",
+            (true, false) => "decoding regressed",
+            (false, false) => "regressed",
+        };
+        println!(
+            "{:<12} encode {:>12} (reference {:>12})  decode {:>12} (reference {:>12})  {}",
+            name,
+            throughput(encode),
+            reference(encode_reference),
+            throughput(decode),
+            reference(decode_reference),
+            verdict
+        );
+        passed &= !checked || (encode_ok && decode_ok);
+    }
+    panic::set_hook(default_hook);
+    passed
+}
+
+fn throughput(elapsed: Duration) -> String {
+    // formatted first, `Throughput` ignores the width
+    Throughput {
+        bytes: SAMPLE_SIZE as u64,
+        elapsed,
+    }
+    .to_string()
+}
+
+fn reference(mebibytes_per_second: f64) -> String {
+    format!("{}/s", ByteSize((mebibytes_per_second * MEBIBYTES as f64) as u64))
+}
+
+fn mebibytes_per_second(elapsed: Duration) -> f64 {
+    SAMPLE_SIZE as f64 / MEBIBYTES as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+}
+
+/// The fastest of [`ROUNDS`] encodings and decodings of `sample` by `stage`.
+fn measure(mut stage: RegisteredCompressor, sample: &[u8]) -> Measurement {
+    let mut encoded = Vec::new();
+    let mut decoded = Vec::new();
+    let mut encode = Duration::MAX;
+    let mut decode = Duration::MAX;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        match panic::catch_unwind(AssertUnwindSafe(|| stage.drive_mutation(sample, &mut encoded))) {
+            Ok(Ok(())) => encode = encode.min(start.elapsed()),
+            Ok(Err(_)) => return Measurement::Broken("encode-failed"),
+            Err(_) => return Measurement::Broken("encode-panicked"),
+        }
+        let start = Instant::now();
+        match panic::catch_unwind(AssertUnwindSafe(|| stage.revert_mutation(&encoded, &mut decoded))) {
+            Ok(Ok(())) => decode = decode.min(start.elapsed()),
+            Ok(Err(_)) => return Measurement::Broken("decode-failed"),
+            Err(_) => return Measurement::Broken("decode-panicked"),
+        }
+        if decoded != sample {
+            return Measurement::Broken("mismatch");
+        }
+    }
+    Measurement::Timed { encode, decode }
+}
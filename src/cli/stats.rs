@@ -0,0 +1,69 @@
+//! the per-stage table `enc`, `dec` and `test` print on stderr with `--stats`, once the command is done:
+//!
+//! ```text
+//! direction   #  stage             input     output   ratio        time    throughput
+//! encode      0  bwt             4.0 KiB    4.0 KiB  100.1%      1.2 ms     3.3 MiB/s
+//! encode      1  mtf             4.0 KiB    4.0 KiB  100.0%       45 µs    88.8 MiB/s
+//! encode      2  arcode          4.0 KiB    1.2 KiB   29.5%      0.9 ms     4.4 MiB/s
+//! ```
+//!
+//! every stage is summed over all inputs, blocks and frames of the command, and the throughput is of its input. with
+//! `--format json` the same stages go into the document on stdout as `stats` instead (see [`super::summary`]).
+use core::time::Duration;
+
+use crate::{
+    cli::summary,
+    units::{ByteSize, Elapsed, Ratio, Throughput},
+    verbosity::{self, StageStats},
+};
+
+/// Starts adding up the stages the command runs, for [`finish`].
+pub fn start() {
+    verbosity::record_totals();
+}
+
+/// Prints the stages run since [`start`], or hands them to the json document if one was started. does nothing unless
+/// [`start`] was called.
+pub fn finish() {
+    let Some(stages) = verbosity::take_totals() else {
+        return;
+    };
+    if summary::is_active() {
+        summary::record_stats(stages);
+    } else {
+        print_table(&stages);
+    }
+}
+
+fn print_table(stages: &[StageStats]) {
+    if stages.is_empty() {
+        eprintln!("no stages ran.");
+        return;
+    }
+    eprintln!(
+        "{:<10} {:>2}  {:<12} {:>10} {:>10} {:>7} {:>11} {:>13}",
+        "direction", "#", "stage", "input", "output", "ratio", "time", "throughput"
+    );
+    for stats in stages {
+        let elapsed = Duration::from_secs_f64(stats.duration_ms / 1000.0);
+        eprintln!(
+            "{:<10} {:>2}  {:<12} {:>10} {:>10} {:>7} {:>11} {:>13}",
+            stats.direction,
+            stats.index,
+            stats.stage,
+            ByteSize(stats.input_size).to_string(),
+            ByteSize(stats.output_size).to_string(),
+            Ratio {
+                original: stats.input_size,
+                compressed: stats.output_size
+            }
+            .to_string(),
+            Elapsed(elapsed).to_string(),
+            Throughput {
+                bytes: stats.input_size,
+                elapsed
+            }
+            .to_string(),
+        );
+    }
+}
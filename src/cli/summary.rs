@@ -0,0 +1,238 @@
+//! the json documents `enc`, `dec`, `test` and `corpus` print on stdout with `--format json`, so scripts and CI can
+//! read their results. the human readable reports keep going to stderr as `--verbose` and `--quiet` ask for.
+//!
+//! a command that was asked for a document starts one with [`start`], records a [`Run`] for every input it
+//! processes, and the document is printed once the command is done, or right before it exits on an error:
+//!
+//! ```json
+//! {
+//!   "command": "test",
+//!   "passed": true,
+//!   "runs": [
+//!     {
+//!       "input": "a.txt",
+//!       "pipeline": "bwt -> mtf -> arcode",
+//!       "original_size": 4096,
+//!       "compressed_size": 1210,
+//!       "ratio": 0.2954,
+//!       "encode_ms": 1.2,
+//!       "decode_ms": 0.9,
+//!       "passed": true,
+//!       "stages": [{ "direction": "encode", "index": 0, "stage": "bwt", "input_size": 4096, ... }]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! sizes are in bytes, `ratio` is the compressed size over the original size, and `stages` are summed over all
+//! blocks and frames of a run (see [`StageStats`]). `corpus --shuffle` only lists its failures as runs and adds a
+//! `soak` object with the totals, and `--stats` adds the stages of all runs together as `stats`.
+//!
+//! `enc --stdout-stats` asks for the runs as a single line instead, started with [`start_line`] and printed only if
+//! every run passed and the command succeeded, with the sizes summed over all inputs:
+//!
+//! ```text
+//! input_bytes=4096 output_bytes=1210 ratio=0.2954 pipeline_fingerprint=5d1c3a7e elapsed_ms=3.4
+//! ```
+//!
+//! `elapsed_ms` is the time from the start of the command to the line, and the fingerprint is the one of
+//! [`CompressionPipeline::fingerprint`].
+use core::time::Duration;
+use std::{path::Path, time::Instant};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::{
+    algorithms::pipeline::CompressionPipeline,
+    units::Ratio,
+    verbosity::{self, StageStats},
+};
+
+static DOCUMENT: Mutex<Option<Document>> = Mutex::new(None);
+
+#[derive(Debug, Serialize)]
+struct Document {
+    command: &'static str,
+    passed: bool,
+    runs: Vec<Run>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    soak: Option<Soak>,
+    /// The stages of all runs together, with `--stats`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<Vec<StageStats>>,
+    /// When the command started, if it asked for a single line instead of the document.
+    #[serde(skip)]
+    line: Option<(Instant, u32)>,
+}
+
+/// One input going through a pipeline. `original_size` is the size of the data before encoding, also when it was
+/// decoded.
+#[derive(Debug, Clone, Serialize)]
+pub struct Run {
+    pub input: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    pub pipeline: String,
+    pub original_size: u64,
+    pub compressed_size: u64,
+    pub ratio: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encode_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decode_ms: Option<f64>,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The crash report written for a failed run, see [`crate::cli::report`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<String>,
+    /// The seed `corpus --shuffle` sampled the run with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crc32: Option<String>,
+    pub stages: Vec<StageStats>,
+}
+
+impl Run {
+    /// A run of `pipeline` on `input`, taking the stages recorded since the last run.
+    pub fn new(input: &Path, pipeline: &CompressionPipeline, original_size: usize, compressed_size: usize) -> Self {
+        Run {
+            input: input.display().to_string(),
+            output: None,
+            pipeline: pipeline.to_string(),
+            original_size: original_size as u64,
+            compressed_size: compressed_size as u64,
+            ratio: Ratio::new(original_size, compressed_size).value(),
+            encode_ms: None,
+            decode_ms: None,
+            passed: true,
+            error: None,
+            report: None,
+            seed: None,
+            crc32: None,
+            stages: verbosity::take_stages(),
+        }
+    }
+
+    pub fn output(mut self, output: &Path) -> Self {
+        self.output = Some(output.display().to_string());
+        self
+    }
+
+    pub fn encoded_in(mut self, elapsed: Duration) -> Self {
+        self.encode_ms = Some(millis(elapsed));
+        self
+    }
+
+    pub fn decoded_in(mut self, elapsed: Duration) -> Self {
+        self.decode_ms = Some(millis(elapsed));
+        self
+    }
+
+    pub fn failed(mut self, error: impl Into<String>) -> Self {
+        self.passed = false;
+        self.error = Some(error.into());
+        self
+    }
+}
+
+/// The totals of a `corpus --shuffle` run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Soak {
+    pub round_trips: u64,
+    pub failed: u64,
+    pub turned_down: u64,
+    pub duration_ms: f64,
+}
+
+fn millis(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1000.0
+}
+
+/// Starts the document of `command`, and the recording of the stages that run.
+pub fn start(command: &'static str) {
+    verbosity::record_stages();
+    *DOCUMENT.lock() = Some(Document {
+        command,
+        passed: true,
+        runs: Vec::new(),
+        soak: None,
+        stats: None,
+        line: None,
+    });
+}
+
+/// Like [`start`], but for the single line of `--stdout-stats` about runs of `pipeline`.
+pub fn start_line(command: &'static str, pipeline: &CompressionPipeline) {
+    start(command);
+    if let Some(document) = DOCUMENT.lock().as_mut() {
+        document.line = Some((Instant::now(), pipeline.fingerprint()));
+    }
+}
+
+/// Whether a json document was started, rather than a line or nothing.
+pub fn is_active() -> bool {
+    DOCUMENT.lock().as_ref().is_some_and(|document| document.line.is_none())
+}
+
+/// Adds `run` to the document, if one was started.
+pub fn record(run: Run) {
+    if let Some(document) = DOCUMENT.lock().as_mut() {
+        document.passed &= run.passed;
+        document.runs.push(run);
+    }
+}
+
+/// Adds the totals of a `corpus --shuffle` run to the document, if one was started.
+pub fn record_soak(soak: Soak) {
+    if let Some(document) = DOCUMENT.lock().as_mut() {
+        document.passed &= soak.failed == 0;
+        document.soak = Some(soak);
+    }
+}
+
+/// Adds the stages of all runs together to the document, if one was started, see [`super::stats`].
+pub fn record_stats(stages: Vec<StageStats>) {
+    if let Some(document) = DOCUMENT.lock().as_mut() {
+        document.stats = Some(stages);
+    }
+}
+
+/// Marks the document as failed, for a command that stops on an error no run recorded.
+pub fn fail() {
+    if let Some(document) = DOCUMENT.lock().as_mut() {
+        document.passed = false;
+    }
+}
+
+/// Prints the document, if one was started, and ends it.
+pub fn finish() {
+    let Some(document) = DOCUMENT.lock().take() else {
+        return;
+    };
+    match document.line {
+        Some((started, fingerprint)) if document.passed => {
+            let original = document.runs.iter().map(|run| run.original_size).sum::<u64>();
+            let compressed = document.runs.iter().map(|run| run.compressed_size).sum::<u64>();
+            println!(
+                "input_bytes={} output_bytes={} ratio={:.4} pipeline_fingerprint={:08x} elapsed_ms={:.1}",
+                original,
+                compressed,
+                Ratio {
+                    original,
+                    compressed
+                }
+                .value(),
+                fingerprint,
+                millis(started.elapsed())
+            );
+        }
+        Some(_) => {}
+        None => println!(
+            "{}",
+            serde_json::to_string_pretty(&document).expect("summaries are always serializable")
+        ),
+    }
+}
@@ -1,5 +1,17 @@
-use crate::cli::{TestArgs, corpus::run_folder};
+use crate::cli::{OutputFormat, TestArgs, corpus::run_folder, error::CliResult, stats, summary};
 
-pub fn test(args: TestArgs) {
-    run_folder(&args.input, args.pipeline_selection(), args.write_files_if_failed);
+pub fn test(args: TestArgs) -> CliResult {
+    if args.format == OutputFormat::Json {
+        summary::start("test");
+    }
+    if args.stats {
+        stats::start();
+    }
+    run_folder(
+        &args.input,
+        args.pipeline_selection(),
+        &args.filter.filter(),
+        args.report,
+        args.io.policy(),
+    )
 }
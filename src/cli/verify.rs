@@ -0,0 +1,224 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    process,
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    algorithms::pipeline::CompressionPipeline,
+    archive::{self, ArchiveTable, MemberCheck},
+    blocks::BlockIndex,
+    cli::VerifyArgs,
+    container::{Container, is_container, parse_container},
+    io::IoPolicy,
+    mutator::Mutator,
+    sidecar::read_sidecar,
+    volumes::read_input,
+};
+
+/// The artifact decoded and matched its stored checksum.
+pub const EXIT_OK: i32 = 0;
+/// The artifact couldn't be read or isn't something stackpack produced.
+pub const EXIT_UNREADABLE: i32 = 1;
+/// The container header is damaged.
+pub const EXIT_HEADER_DAMAGED: i32 = 2;
+/// The header is intact, but a stage failed to decode the payload or the checksum doesn't match.
+pub const EXIT_PAYLOAD_DAMAGED: i32 = 3;
+
+pub fn verify(args: VerifyArgs) {
+    let input_path = &args.input;
+    let bytes = match read_input(input_path, IoPolicy::default()) {
+        Ok(bytes) => bytes,
+        Err(e) => report(input_path.display(), EXIT_UNREADABLE, &format!("failed to read: {}", e)),
+    };
+
+    if args.members {
+        verify_members(input_path, &bytes);
+    }
+
+    if !is_container(&bytes) {
+        let pipeline = match read_sidecar(input_path) {
+            Ok(Some(pipeline)) => pipeline,
+            Ok(None) => report(
+                input_path.display(),
+                EXIT_UNREADABLE,
+                "not a stackpack container and no sidecar found",
+            ),
+            Err(e) => report(input_path.display(), EXIT_UNREADABLE, &format!("unreadable sidecar: {}", e)),
+        };
+        if let Err(e) = revert_stages(&pipeline, &bytes) {
+            report(input_path.display(), EXIT_PAYLOAD_DAMAGED, &e.to_string());
+        }
+        report(input_path.display(), EXIT_OK, "ok, decoded without errors (no checksum stored)");
+    }
+
+    // concatenated streams are verified one after another, like `dec` decodes them
+    let mut rest: &[u8] = &bytes;
+    let mut stream = 0;
+    while !rest.is_empty() {
+        stream += 1;
+        let name = stream_name(input_path, stream);
+        if !is_container(rest) {
+            report(
+                input_path.display(),
+                EXIT_HEADER_DAMAGED,
+                &format!("{} bytes after the last stream aren't a stackpack container", rest.len()),
+            );
+        }
+        let container = match parse_container(rest) {
+            Ok(container) => container,
+            Err(e) => report(name, EXIT_HEADER_DAMAGED, &format!("header damaged: {}", e)),
+        };
+        if stream == 1 && container.trailing.is_empty() {
+            match verify_stream(&container) {
+                Ok(message) => report(name, EXIT_OK, &message),
+                Err((code, message)) => report(name, code, &message),
+            }
+        }
+        let name = format!("stream {} of {}", stream, input_path.display());
+        let message = match verify_stream(&container) {
+            Ok(message) => message,
+            Err((code, message)) => report(name, code, &message),
+        };
+        println!("{}: {}", name, message);
+        rest = container.trailing;
+    }
+    report(input_path.display(), EXIT_OK, &format!("ok, {} concatenated streams", stream));
+}
+
+/// Decodes the payload of one stream and checks it, returning what was checked or the exit code and what is damaged.
+fn verify_stream(container: &Container) -> Result<String, (i32, String)> {
+    let decoded = match (&container.archive, container.block_size) {
+        (Some(table), _) => revert_frames(&container.pipeline, table, container.payload),
+        (None, Some(_)) => revert_blocks(&container.pipeline, container.payload),
+        (None, None) => revert_stages(&container.pipeline, container.payload),
+    };
+    let decoded = decoded.map_err(|e| (EXIT_PAYLOAD_DAMAGED, e.to_string()))?;
+    if container.delta_base.is_some() {
+        return Ok(
+            "ok, decoded without errors (a delta, its checksum can only be checked by decoding against the reference)"
+                .to_string(),
+        );
+    }
+    container.verify(&decoded).map_err(|e| (EXIT_PAYLOAD_DAMAGED, e.to_string()))?;
+    match &container.checksum {
+        Some(checksum) => Ok(format!("ok, {}", checksum)),
+        None => Ok("ok, decoded without errors (no checksum stored)".to_string()),
+    }
+}
+
+/// `verify --members`: checks every member of a directory archive on its own and lists the damaged ones.
+fn verify_members(input_path: &Path, bytes: &[u8]) -> ! {
+    let name = input_path.display();
+    if !is_container(bytes) {
+        report(name, EXIT_UNREADABLE, "not a stackpack container, --members only checks directory archives");
+    }
+    let container = match parse_container(bytes) {
+        Ok(container) => container,
+        Err(e) => report(name, EXIT_HEADER_DAMAGED, &format!("header damaged: {}", e)),
+    };
+    let Some(table) = &container.archive else {
+        report(name, EXIT_UNREADABLE, "not a directory archive, --members only checks directory archives");
+    };
+    let mut pipeline = container.pipeline.clone();
+    // stages that panic on damaged frames are reported as damage, not with a backtrace
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let checks = archive::verify_members(&mut pipeline, table, container.payload);
+    panic::set_hook(default_hook);
+
+    let mut damaged = 0;
+    let mut unchecked = 0;
+    for (index, check) in &checks {
+        let path = &table.members[*index].path;
+        match check {
+            MemberCheck::Ok => {}
+            MemberCheck::Unchecked => unchecked += 1,
+            MemberCheck::Damaged { expected, actual } => {
+                damaged += 1;
+                println!("{}: {}: damaged, crc32 {:08x} expected, decoded data has {:08x}", name, path, expected, actual);
+            }
+            MemberCheck::Undecodable(e) => {
+                damaged += 1;
+                println!("{}: {}: damaged, {}", name, path, e);
+            }
+        }
+    }
+    if damaged > 0 {
+        report(name, EXIT_PAYLOAD_DAMAGED, &format!("{} of {} members damaged", damaged, checks.len()));
+    }
+    let message = match unchecked {
+        0 => format!("ok, all {} members match their crc32", checks.len()),
+        unchecked => format!(
+            "ok, {} members match their crc32, {} have none recorded and decoded without errors",
+            checks.len() - unchecked,
+            unchecked
+        ),
+    };
+    report(name, EXIT_OK, &message)
+}
+
+fn report(input: impl std::fmt::Display, code: i32, message: &str) -> ! {
+    println!("{}: {}", input, message);
+    process::exit(code);
+}
+
+/// `input`, or its `stream`th stream when there are several, counting from 1.
+fn stream_name(input: &Path, stream: usize) -> String {
+    match stream {
+        1 => input.display().to_string(),
+        stream => format!("stream {} of {}", stream, input.display()),
+    }
+}
+
+/// Reverts every block of a block mode payload, naming the first damaged block.
+fn revert_blocks(pipeline: &CompressionPipeline, payload: &[u8]) -> Result<Vec<u8>> {
+    let index = BlockIndex::parse(payload).map_err(|e| anyhow!("block index is damaged: {}", e))?;
+    let mut decoded = Vec::new();
+    for block in 0..index.len() {
+        let block_data = revert_stages(pipeline, index.block(block)).map_err(|e| anyhow!("block {}: {}", block, e))?;
+        decoded.extend_from_slice(&block_data);
+    }
+    Ok(decoded)
+}
+
+/// Reverts every frame of a directory archive, naming the first damaged frame.
+fn revert_frames(pipeline: &CompressionPipeline, table: &ArchiveTable, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    for index in 0..table.frames.len() {
+        let frame = table.frame(payload, index)?;
+        let frame_data = revert_stages(pipeline, frame).map_err(|e| anyhow!("frame {}: {}", index, e))?;
+        decoded.extend_from_slice(&frame_data);
+    }
+    Ok(decoded)
+}
+
+/// Reverts the stages one at a time so a failure can be pinned to the stage, and through the stage's own error,
+/// to the block that is damaged. Stages may panic on inputs they don't validate, which is reported as damage too.
+fn revert_stages(pipeline: &CompressionPipeline, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut current = payload.to_vec();
+    let mut next = Vec::new();
+    let stages = pipeline.stages();
+    for (index, stage) in stages.iter().enumerate().rev() {
+        let mut stage = stage.clone();
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| stage.revert_mutation(&current, &mut next)));
+        panic::set_hook(default_hook);
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(anyhow!("stage {} ({}) is damaged: {}", index, stage, e)),
+            Err(_) => {
+                return Err(anyhow!(
+                    "stage {} ({}) is damaged: decoder crashed on malformed input",
+                    index,
+                    stage
+                ));
+            }
+        }
+        core::mem::swap(&mut current, &mut next);
+    }
+    Ok(current)
+}
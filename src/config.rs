@@ -0,0 +1,77 @@
+//! per-user stackpack configuration, stored as json in the platform config directory. besides the pipelines saved
+//! with `pipeline save` it holds the fallbacks of some cli flags, see [`crate::settings`].
+use std::{collections::BTreeMap, env, fs, num::NonZeroUsize, path::PathBuf};
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::algorithms::heuristics::Thresholds;
+
+pub const CONFIG_FILE_NAME: &str = "config.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UserConfig {
+    /// Named pipelines saved with `pipeline save`, mapping names to pipeline strings.
+    #[serde(default)]
+    pub pipelines: BTreeMap<String, String>,
+    /// Pipeline string used when neither a flag nor `STACKPACK_PIPELINE` selects one, see [`crate::settings`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<String>,
+    /// Preset used when neither a flag nor `STACKPACK_PRESET` selects a pipeline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Threads stages may use when neither `--threads` nor `STACKPACK_THREADS` is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<NonZeroUsize>,
+    /// Directories plugins are loaded from when `STACKPACK_PLUGIN_DIRS` isn't set.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugin_dirs: Vec<PathBuf>,
+    /// Block thresholds measured by `calibrate`, the built-in defaults are used without them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thresholds: Option<Thresholds>,
+}
+
+/// The directory holding the user configuration.
+///
+/// `STACKPACK_CONFIG_DIR` takes precedence, followed by the platform default
+/// (`$XDG_CONFIG_HOME/stackpack`, `~/.config/stackpack` or `%APPDATA%\stackpack`).
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("STACKPACK_CONFIG_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join("stackpack"))
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(CONFIG_FILE_NAME))
+}
+
+impl UserConfig {
+    /// Loads the user configuration, returning the default configuration if none was saved yet.
+    pub fn load() -> Result<Self> {
+        let Some(path) = config_path() else {
+            return Ok(Self::default());
+        };
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let json = fs::read_to_string(&path)?;
+        serde_json::from_str(&json).map_err(|err| anyhow!("user config at {} is corrupt: {}", path.display(), err))
+    }
+
+    pub fn save(&self) -> Result<PathBuf> {
+        let path = config_path().ok_or_else(|| anyhow!("could not determine the user config directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
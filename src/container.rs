@@ -0,0 +1,602 @@
+//! the `.stpk` container format used by `enc --embed_to_file`.
+//!
+//! the layout of the current version is:
+//!
+//! | field            | size            | notes                                                |
+//! |------------------|-----------------|------------------------------------------------------|
+//! | magic            | 4               | `STPK`                                               |
+//! | format version   | 1               | currently [`FORMAT_VERSION`]                         |
+//! | header length    | varint          | length of the header fields that follow              |
+//! | header fields    | header length   | `[tag: varint][length: varint][value]` per field     |
+//! | payload          | payload length  | output of the pipeline's `drive_mutation`            |
+//!
+//! the header fields are:
+//!
+//! | tag                   | value                                                                |
+//! |-----------------------|----------------------------------------------------------------------|
+//! | [`TAG_PIPELINE`]      | pipeline serialized with [`CompressionPipeline::to_bytes`]           |
+//! | [`TAG_ORIGINAL_SIZE`] | length of the original data as a varint                              |
+//! | [`TAG_CHECKSUM`]      | [`ChecksumAlgorithm::id`] as one byte, then the digest of the original data |
+//! | [`TAG_FILE_NAME`]     | utf-8 file name of the encoded file, without directories             |
+//! | [`TAG_MODIFIED`]      | modification time of the encoded file as varint seconds and nanoseconds since the unix epoch |
+//! | [`TAG_BLOCK_SIZE`]    | block size as a varint, marks a block mode payload described in [`crate::blocks`] |
+//! | [`TAG_ARCHIVE`]       | [`ArchiveTable`] of a directory archive, the payload holds its frames |
+//! | [`TAG_MEMBER_CHECKSUMS`] | crc32 of every file member of the archive, see [`crate::archive`]  |
+//! | [`TAG_CONTENT_TYPES`] | content type of every file member and the archive composition, see [`crate::content`] |
+//! | [`TAG_DELTA_BASE`]    | [`DeltaBase`] of the reference a delta payload (see [`crate::delta`]) was made against |
+//! | [`TAG_HOST`]          | [`HostInfo`] of the build and machine that encoded the file, see [`crate::host`] |
+//! | [`TAG_PAYLOAD_LEN`]   | length of the payload as a varint, without it the payload is the rest of the file |
+//! | [`TAG_HISTOGRAM`]     | [`ByteHistogram`] of the original data, see [`crate::histogram`]       |
+//! | [`TAG_SIGNATURE`]     | ed25519 signature, always the last field, see [`crate::signing`]    |
+//! | [`TAG_CRC32`]         | little endian crc32 of the original data, only read, superseded by [`TAG_CHECKSUM`] |
+//!
+//! # compatibility rules
+//!
+//! new metadata is added as new header fields without bumping the format version. a reader skips fields with tags
+//! it doesn't know, unless the lowest bit of the tag is set, which marks the field as required for decoding. a file
+//! with an unknown required field, or with a format version newer than [`FORMAT_VERSION`], is rejected with an error
+//! saying that a newer stackpack is needed. the format version is only bumped for changes that older readers can't
+//! skip over.
+//!
+//! version 1 and 2 containers used fixed headers and are still readable: version 1 stored a `u32` pipeline length
+//! and the pipeline, version 2 additionally stored a `u64` original size and a crc32 before them.
+//!
+//! # concatenation
+//!
+//! containers can be appended to each other in one file, like gzip members, e.g. with `enc log -o - >> logs.stpk`.
+//! the payload length ends the payload of each one, [`Container::trailing`] holds the containers after it, and `dec`
+//! decodes them all one after another into one output. containers written before the payload length field take the
+//! rest of the file as their payload, so only the last container of a file may lack it.
+//!
+//! besides whole containers in memory, [`write_container_to`] writes into any [`Write`], and [`ContainerReader`]
+//! reads the header through any [`Read`] + [`Seek`] and the payload only as far as asked for, so single frames of an
+//! archive can be read out of large files, embedded resources or cached network streams.
+use std::{
+    fs,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, anyhow, bail};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use crate::{
+    algorithms::pipeline::CompressionPipeline,
+    archive::ArchiveTable,
+    checksum::{Checksum, ChecksumAlgorithm},
+    content,
+    delta::DeltaBase,
+    histogram::ByteHistogram,
+    host::HostInfo,
+    io::is_stdio,
+    signing, varint,
+};
+
+pub const MAGIC: [u8; 4] = *b"STPK";
+pub const FORMAT_VERSION: u8 = 3;
+pub const FILE_EXTENSION: &str = "stpk";
+
+/// Set in the tags of header fields that readers must understand to decode the payload.
+pub const REQUIRED_TAG_BIT: u64 = 1;
+pub const TAG_PIPELINE: u64 = 0x01;
+pub const TAG_ORIGINAL_SIZE: u64 = 0x02;
+pub const TAG_CRC32: u64 = 0x04;
+pub const TAG_CHECKSUM: u64 = 0x06;
+pub const TAG_FILE_NAME: u64 = 0x08;
+pub const TAG_MODIFIED: u64 = 0x0a;
+pub const TAG_BLOCK_SIZE: u64 = 0x0b;
+pub const TAG_SIGNATURE: u64 = 0x0c;
+pub const TAG_ARCHIVE: u64 = 0x0d;
+pub const TAG_DELTA_BASE: u64 = 0x0f;
+pub const TAG_HOST: u64 = 0x10;
+pub const TAG_PAYLOAD_LEN: u64 = 0x12;
+pub const TAG_HISTOGRAM: u64 = 0x14;
+pub const TAG_MEMBER_CHECKSUMS: u64 = 0x16;
+pub const TAG_CONTENT_TYPES: u64 = 0x18;
+
+/// Everything written into a container header besides the pipeline.
+#[derive(Debug, Default)]
+pub struct ContainerOptions {
+    pub checksum: ChecksumAlgorithm,
+    pub metadata: FileMetadata,
+    /// Marks the payload as block mode with blocks of this many bytes.
+    pub block_size: Option<u64>,
+    /// Signs the header and payload when given.
+    pub signing_key: Option<SigningKey>,
+    /// Marks the payload as the frames of a directory archive.
+    pub archive: Option<ArchiveTable>,
+    /// Marks the payload as a delta against this reference.
+    pub delta_base: Option<DeltaBase>,
+    /// Records what the file was encoded with, see [`crate::host`].
+    pub host: Option<HostInfo>,
+    /// Records how often every byte value occurs in the original data, see [`crate::histogram`].
+    pub histogram: bool,
+}
+
+/// Attributes of the encoded file that `dec` can restore.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub name: Option<String>,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileMetadata {
+    /// Collects what the file system knows about `path`, leaving out anything it can't provide.
+    pub fn of(path: &Path) -> Self {
+        if is_stdio(path) {
+            return FileMetadata::default();
+        }
+        FileMetadata {
+            name: path.file_name().map(|name| name.to_string_lossy().into_owned()),
+            modified: fs::metadata(path).and_then(|meta| meta.modified()).ok(),
+        }
+    }
+}
+
+/// A parsed container borrowing its payload from the input bytes.
+#[derive(Debug)]
+pub struct Container<'a> {
+    pub version: u8,
+    pub pipeline: CompressionPipeline,
+    /// Length of the original data, absent in version 1 containers.
+    pub original_size: Option<u64>,
+    /// Checksum of the original data, absent in version 1 containers.
+    pub checksum: Option<Checksum>,
+    pub metadata: FileMetadata,
+    /// Set when the payload is split into independently encoded blocks of this many bytes.
+    pub block_size: Option<u64>,
+    /// Set for directory archives, whose payload holds the frames described by the table.
+    pub archive: Option<ArchiveTable>,
+    /// Set when the payload decodes to a delta against this reference rather than to the original data.
+    pub delta_base: Option<DeltaBase>,
+    /// What the file was encoded with, if `enc --record-host` recorded it.
+    pub host: Option<HostInfo>,
+    /// How often every byte value occurs in the original data, if `enc --histogram` recorded it.
+    pub histogram: Option<ByteHistogram>,
+    /// The ed25519 signature and the header bytes it covers, if the container is signed.
+    pub signature: Option<(&'a [u8], &'a [u8])>,
+    /// Tags of optional header fields this build doesn't know and skipped.
+    pub skipped_fields: Vec<u64>,
+    pub payload: &'a [u8],
+    /// The bytes after the payload, the containers concatenated after this one.
+    pub trailing: &'a [u8],
+}
+
+impl Container<'_> {
+    /// Checks the signature against `key`, failing if the container isn't signed.
+    pub fn verify_signature(&self, key: &VerifyingKey) -> Result<()> {
+        let (signed_header, signature) = self.signature.ok_or_else(|| anyhow!("the container is not signed"))?;
+        signing::verify(key, signed_header, self.payload, signature)
+    }
+
+    /// Checks decoded data against the stored size and checksum, if the container has them.
+    pub fn verify(&self, decoded: &[u8]) -> Result<()> {
+        let digest = self.checksum.as_ref().map(|checksum| checksum.algorithm.digest(decoded));
+        self.verify_streamed(decoded.len() as u64, digest)
+    }
+
+    /// Like [`Container::verify`] for data that was hashed while it was decoded instead of kept: `len` bytes with
+    /// `digest`, made with the algorithm of the stored checksum.
+    pub fn verify_streamed(&self, len: u64, digest: Option<Checksum>) -> Result<()> {
+        if let Some(size) = self.original_size
+            && size != len
+        {
+            bail!("size mismatch: container expects {} bytes, decoding produced {} bytes", size, len);
+        }
+        if let Some(checksum) = &self.checksum
+            && let Some(actual) = digest
+            && actual != *checksum
+        {
+            bail!("checksum mismatch: container expects {}, decoded data has {}", checksum, actual);
+        }
+        Ok(())
+    }
+}
+
+/// Whether `bytes` start with the container magic.
+pub fn is_container(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+fn write_field(out: &mut Vec<u8>, tag: u64, value: &[u8]) {
+    varint::write_u64(out, tag);
+    varint::write_u64(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+/// Wraps an already encoded payload together with the pipeline that produced it and a checksum of `original`.
+pub fn write_container(
+    pipeline: &CompressionPipeline,
+    options: &ContainerOptions,
+    original: &[u8],
+    payload: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    out.clear();
+    write_container_to(pipeline, options, original, payload, out)
+}
+
+/// Writes the container [`write_container`] builds into `out`.
+pub fn write_container_to(
+    pipeline: &CompressionPipeline,
+    options: &ContainerOptions,
+    original: &[u8],
+    payload: &[u8],
+    mut out: impl Write,
+) -> Result<()> {
+    let mut header = Vec::new();
+    write_field(&mut header, TAG_PIPELINE, &pipeline.to_bytes());
+    let mut size = Vec::new();
+    varint::write_u64(&mut size, original.len() as u64);
+    write_field(&mut header, TAG_ORIGINAL_SIZE, &size);
+    let checksum = options.checksum.digest(original);
+    let mut checksum_field = vec![checksum.algorithm.id()];
+    checksum_field.extend_from_slice(&checksum.digest);
+    write_field(&mut header, TAG_CHECKSUM, &checksum_field);
+    if let Some(block_size) = options.block_size {
+        let mut value = Vec::new();
+        varint::write_u64(&mut value, block_size);
+        write_field(&mut header, TAG_BLOCK_SIZE, &value);
+    }
+    if let Some(archive) = &options.archive {
+        write_field(&mut header, TAG_ARCHIVE, &archive.to_bytes());
+        if let Some(checksums) = archive.member_checksums() {
+            write_field(&mut header, TAG_MEMBER_CHECKSUMS, &checksums);
+        }
+        if let Some(content_types) = content::to_bytes(archive) {
+            write_field(&mut header, TAG_CONTENT_TYPES, &content_types);
+        }
+    }
+    if let Some(delta_base) = &options.delta_base {
+        write_field(&mut header, TAG_DELTA_BASE, &delta_base.to_bytes());
+    }
+    if let Some(host) = &options.host {
+        write_field(&mut header, TAG_HOST, &host.to_bytes());
+    }
+    if options.histogram {
+        write_field(&mut header, TAG_HISTOGRAM, &ByteHistogram::of(original).to_bytes());
+    }
+    let metadata = &options.metadata;
+    if let Some(name) = &metadata.name {
+        write_field(&mut header, TAG_FILE_NAME, name.as_bytes());
+    }
+    // times before the epoch are rare enough to not be worth a signed encoding, they are left out
+    if let Some(since_epoch) = metadata.modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()) {
+        let mut modified = Vec::new();
+        varint::write_u64(&mut modified, since_epoch.as_secs());
+        varint::write_u64(&mut modified, u64::from(since_epoch.subsec_nanos()));
+        write_field(&mut header, TAG_MODIFIED, &modified);
+    }
+    let mut payload_len = Vec::new();
+    varint::write_u64(&mut payload_len, payload.len() as u64);
+    write_field(&mut header, TAG_PAYLOAD_LEN, &payload_len);
+    if let Some(key) = &options.signing_key {
+        let signature = signing::sign(key, &header, payload);
+        write_field(&mut header, TAG_SIGNATURE, &signature);
+    }
+
+    let mut prefix = Vec::with_capacity(MAGIC.len() + 1 + 10);
+    prefix.extend_from_slice(&MAGIC);
+    prefix.push(FORMAT_VERSION);
+    varint::write_u64(&mut prefix, header.len() as u64);
+    out.write_all(&prefix)?;
+    out.write_all(&header)?;
+    out.write_all(payload)?;
+    Ok(())
+}
+
+/// A container read through [`Read`] + [`Seek`], with only its header held in memory. The payload stays in the
+/// source and is read on demand.
+pub struct ContainerReader<R> {
+    source: R,
+    /// Everything before the payload.
+    prefix: Vec<u8>,
+    payload_len: u64,
+}
+
+impl<R: Read + Seek> ContainerReader<R> {
+    /// Reads and checks the header of the container at the start of `source`.
+    pub fn new(mut source: R) -> Result<Self> {
+        let len = source.seek(SeekFrom::End(0))?;
+        source.seek(SeekFrom::Start(0))?;
+        let mut prefix = Vec::new();
+        read_more(&mut source, &mut prefix, MAGIC.len() + 1, "format version")?;
+        if !is_container(&prefix) {
+            bail!("not a stackpack container: magic bytes missing");
+        }
+        match prefix[MAGIC.len()] {
+            1 => read_fixed_prefix(&mut source, &mut prefix)?,
+            2 => {
+                read_more(&mut source, &mut prefix, 12, "original size")?;
+                read_fixed_prefix(&mut source, &mut prefix)?;
+            }
+            3 => {
+                // the header length is a varint, read a byte at a time up to its last byte
+                loop {
+                    read_more(&mut source, &mut prefix, 1, "header length")?;
+                    if prefix[prefix.len() - 1] & 0x80 == 0 {
+                        break;
+                    }
+                }
+                let header_len = varint::read_u64(&mut &prefix[MAGIC.len() + 1..])?;
+                let header_len = usize::try_from(header_len).map_err(|_| anyhow!("container header length overflows"))?;
+                read_more(&mut source, &mut prefix, header_len, "header")?;
+            }
+            // parsing reports the version as too new
+            _ => {}
+        }
+        let header = parse_header(&prefix)?;
+        let rest = len.saturating_sub(prefix.len() as u64);
+        let payload_len = match header.payload_len {
+            Some(payload_len) if payload_len > rest => bail!("container truncated: payload is cut off"),
+            Some(payload_len) => payload_len,
+            None => rest,
+        };
+        Ok(ContainerReader {
+            source,
+            payload_len,
+            prefix,
+        })
+    }
+
+    /// The parsed header. its `payload` is empty, the payload is read with [`ContainerReader::read_payload`] and
+    /// [`ContainerReader::read_payload_range`].
+    pub fn header(&self) -> Container<'_> {
+        parse_header(&self.prefix)
+            .expect("the header was checked when the reader was created")
+            .container
+    }
+
+    pub fn payload_len(&self) -> u64 {
+        self.payload_len
+    }
+
+    /// Reads `len` bytes of the payload, starting `offset` bytes into it.
+    pub fn read_payload_range(&mut self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        if offset.checked_add(len).is_none_or(|end| end > self.payload_len) {
+            bail!("{} bytes at {} lie outside the {} byte payload", len, offset, self.payload_len);
+        }
+        self.source.seek(SeekFrom::Start(self.prefix.len() as u64 + offset))?;
+        let mut data = vec![0; usize::try_from(len).map_err(|_| anyhow!("payload range too large"))?];
+        self.source.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    pub fn read_payload(&mut self) -> Result<Vec<u8>> {
+        self.read_payload_range(0, self.payload_len)
+    }
+}
+
+/// Appends the next `len` bytes of `source` to `prefix`. a corrupt length only reads as far as the source goes.
+fn read_more(source: &mut impl Read, prefix: &mut Vec<u8>, len: usize, what: &str) -> Result<()> {
+    let read = source.take(len as u64).read_to_end(prefix)?;
+    if read != len {
+        bail!("container truncated: {} is cut off", what);
+    }
+    Ok(())
+}
+
+/// Reads the `u32` pipeline length and the pipeline of a version 1 or 2 header.
+fn read_fixed_prefix(source: &mut impl Read, prefix: &mut Vec<u8>) -> Result<()> {
+    read_more(source, prefix, 4, "pipeline length")?;
+    let pipeline_len = u32::from_le_bytes(prefix[prefix.len() - 4..].try_into().unwrap()) as usize;
+    read_more(source, prefix, pipeline_len, "pipeline description")
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize, what: &str) -> Result<&'a [u8]> {
+    let (head, rest) = data
+        .split_at_checked(len)
+        .ok_or_else(|| anyhow!("container truncated: {} is cut off", what))?;
+    *data = rest;
+    Ok(head)
+}
+
+fn crc32_checksum(crc: u32) -> Checksum {
+    Checksum {
+        algorithm: ChecksumAlgorithm::Crc32,
+        digest: crc.to_be_bytes().to_vec(),
+    }
+}
+
+fn parse_pipeline(bytes: &[u8]) -> Result<CompressionPipeline> {
+    CompressionPipeline::try_from_bytes(bytes).ok_or_else(|| anyhow!("container holds an invalid or unknown pipeline description"))
+}
+
+pub fn parse_container(bytes: &[u8]) -> Result<Container<'_>> {
+    let Header {
+        mut container,
+        payload_len,
+    } = parse_header(bytes)?;
+    if let Some(payload_len) = payload_len {
+        let payload_len = usize::try_from(payload_len).map_err(|_| anyhow!("container payload length overflows"))?;
+        let mut rest = container.payload;
+        container.payload = take(&mut rest, payload_len, "payload")?;
+        container.trailing = rest;
+    }
+    Ok(container)
+}
+
+/// A parsed header, with everything after it as the payload of the container.
+struct Header<'a> {
+    container: Container<'a>,
+    payload_len: Option<u64>,
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header<'_>> {
+    let mut rest = bytes
+        .strip_prefix(&MAGIC)
+        .ok_or_else(|| anyhow!("not a stackpack container: magic bytes missing"))?;
+    let version = take(&mut rest, 1, "format version")?[0];
+    match version {
+        1 | 2 => Ok(Header {
+            container: parse_fixed_header(version, rest)?,
+            payload_len: None,
+        }),
+        3 => parse_fields(version, rest),
+        _ => bail!(
+            "this file requires a newer stackpack: it uses container version {}, this build reads up to version {}",
+            version,
+            FORMAT_VERSION
+        ),
+    }
+}
+
+fn parse_fields(version: u8, mut rest: &[u8]) -> Result<Header<'_>> {
+    let header_len = usize::try_from(varint::read_u64(&mut rest)?).map_err(|_| anyhow!("container header length overflows"))?;
+    let mut header = take(&mut rest, header_len, "header")?;
+
+    let mut pipeline = None;
+    let mut original_size = None;
+    let mut checksum = None;
+    let mut metadata = FileMetadata::default();
+    let mut block_size = None;
+    let mut archive = None;
+    let mut member_checksums = None;
+    let mut content_types = None;
+    let mut delta_base = None;
+    let mut host = None;
+    let mut histogram = None;
+    let mut payload_len = None;
+    let mut signature = None;
+    let mut skipped_fields = Vec::new();
+    let full_header = header;
+    while !header.is_empty() {
+        if signature.is_some() {
+            bail!("container header has fields after its signature");
+        }
+        let field_start = full_header.len() - header.len();
+        let tag = varint::read_u64(&mut header)?;
+        let len = usize::try_from(varint::read_u64(&mut header)?).map_err(|_| anyhow!("header field length overflows"))?;
+        let mut value = take(&mut header, len, "header field")?;
+        match tag {
+            TAG_PIPELINE => pipeline = Some(parse_pipeline(value)?),
+            TAG_ORIGINAL_SIZE => original_size = Some(varint::read_u64(&mut value)?),
+            TAG_CRC32 => {
+                let bytes: [u8; 4] = value.try_into().map_err(|_| anyhow!("crc32 header field has the wrong length"))?;
+                checksum = Some(crc32_checksum(u32::from_le_bytes(bytes)));
+            }
+            TAG_CHECKSUM => {
+                let (&id, digest) = value.split_first().ok_or_else(|| anyhow!("checksum header field is empty"))?;
+                // a checksum this build can't compute is as good as none, so it is skipped like an unknown field
+                match ChecksumAlgorithm::from_id(id) {
+                    Some(algorithm) => {
+                        checksum = Some(Checksum {
+                            algorithm,
+                            digest: digest.to_vec(),
+                        })
+                    }
+                    None => skipped_fields.push(tag),
+                }
+            }
+            TAG_SIGNATURE => signature = Some((&full_header[..field_start], value)),
+            TAG_BLOCK_SIZE => block_size = Some(varint::read_u64(&mut value)?),
+            TAG_ARCHIVE => archive = Some(ArchiveTable::parse(value)?),
+            TAG_MEMBER_CHECKSUMS => member_checksums = Some(value),
+            TAG_CONTENT_TYPES => content_types = Some(value),
+            TAG_DELTA_BASE => delta_base = Some(DeltaBase::parse(value)?),
+            TAG_HOST => host = Some(HostInfo::parse(value)?),
+            TAG_HISTOGRAM => histogram = Some(ByteHistogram::parse(value)?),
+            TAG_PAYLOAD_LEN => payload_len = Some(varint::read_u64(&mut value)?),
+            TAG_FILE_NAME => metadata.name = Some(String::from_utf8(value.to_vec()).map_err(|_| anyhow!("file name is not utf-8"))?),
+            TAG_MODIFIED => {
+                let secs = varint::read_u64(&mut value)?;
+                let nanos = u32::try_from(varint::read_u64(&mut value)?)
+                    .ok()
+                    .filter(|&nanos| nanos < 1_000_000_000)
+                    .ok_or_else(|| anyhow!("modification time is malformed"))?;
+                metadata.modified = UNIX_EPOCH.checked_add(Duration::new(secs, nanos));
+            }
+            tag if tag & REQUIRED_TAG_BIT != 0 => bail!(
+                "this file requires a newer stackpack: it uses the required header field {:#x}, which this build doesn't know",
+                tag
+            ),
+            tag => skipped_fields.push(tag),
+        }
+    }
+
+    if let Some(checksums) = member_checksums {
+        archive
+            .as_mut()
+            .ok_or_else(|| anyhow!("container header has member checksums but no archive table"))?
+            .parse_member_checksums(checksums)?;
+    }
+    if let Some(content_types) = content_types {
+        let table = archive
+            .as_mut()
+            .ok_or_else(|| anyhow!("container header has content types but no archive table"))?;
+        content::parse(table, content_types)?;
+    }
+    if let (Some(histogram), Some(size)) = (&histogram, original_size)
+        && histogram.total() != size
+    {
+        bail!("histogram counts {} bytes, the original data has {}", histogram.total(), size);
+    }
+
+    Ok(Header {
+        container: Container {
+            version,
+            pipeline: pipeline.ok_or_else(|| anyhow!("container header has no pipeline"))?,
+            original_size,
+            checksum,
+            metadata,
+            block_size,
+            archive,
+            delta_base,
+            host,
+            histogram,
+            signature,
+            skipped_fields,
+            payload: rest,
+            trailing: &[],
+        },
+        payload_len,
+    })
+}
+
+/// The [`HostInfo`] recorded in the container in `bytes`, read without parsing anything else, so it is there even when
+/// the pipeline uses stages this build doesn't know. `None` when there is none or the header is unreadable.
+pub fn read_host(bytes: &[u8]) -> Option<HostInfo> {
+    let mut rest = bytes.strip_prefix(&MAGIC)?.strip_prefix(&[FORMAT_VERSION])?;
+    let header_len = usize::try_from(varint::read_u64(&mut rest).ok()?).ok()?;
+    let mut header = take(&mut rest, header_len, "header").ok()?;
+    while !header.is_empty() {
+        let tag = varint::read_u64(&mut header).ok()?;
+        let len = usize::try_from(varint::read_u64(&mut header).ok()?).ok()?;
+        let value = take(&mut header, len, "header field").ok()?;
+        if tag == TAG_HOST {
+            return HostInfo::parse(value).ok();
+        }
+    }
+    None
+}
+
+fn parse_fixed_header(version: u8, mut rest: &[u8]) -> Result<Container<'_>> {
+    let (original_size, checksum) = if version == 2 {
+        let size = u64::from_le_bytes(take(&mut rest, 8, "original size")?.try_into().unwrap());
+        let checksum = u32::from_le_bytes(take(&mut rest, 4, "checksum")?.try_into().unwrap());
+        (Some(size), Some(crc32_checksum(checksum)))
+    } else {
+        (None, None)
+    };
+
+    let pipeline_len = u32::from_le_bytes(take(&mut rest, 4, "pipeline length")?.try_into().unwrap()) as usize;
+    let pipeline = parse_pipeline(take(&mut rest, pipeline_len, "pipeline description")?)?;
+
+    Ok(Container {
+        version,
+        pipeline,
+        original_size,
+        checksum,
+        metadata: FileMetadata::default(),
+        block_size: None,
+        archive: None,
+        delta_base: None,
+        host: None,
+        histogram: None,
+        signature: None,
+        skipped_fields: Vec::new(),
+        payload: rest,
+        trailing: &[],
+    })
+}
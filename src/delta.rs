@@ -0,0 +1,178 @@
+//! delta payloads, which describe the input as copies out of a reference file plus the bytes the reference doesn't
+//! have. the delta runs through the pipeline like any other input, so a new version of a large file that shares most
+//! of its contents with the old one shrinks to roughly the size of its changes.
+//!
+//! a delta is a sequence of instructions, each of them
+//!
+//! | field          | notes                                                    |
+//! |----------------|----------------------------------------------------------|
+//! | literal length | varint                                                   |
+//! | literals       | bytes copied to the output as they are                   |
+//! | copy offset    | varint, where the copy starts in the reference           |
+//! | copy length    | varint, how many bytes of the reference follow the literals |
+//!
+//! matches are found on [`MATCH_LEN`] byte windows of the reference that start at multiples of [`MATCH_LEN`], and
+//! then grown in both directions. the container records the length and blake3 hash of the reference in a
+//! [`DeltaBase`], so decoding against the wrong reference fails instead of producing garbage.
+//!
+//! the same matches can be written as a standard VCDIFF patch instead, see [`vcdiff`].
+pub mod vcdiff;
+
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow, bail};
+use clap::ValueEnum;
+
+use crate::{
+    checksum::{Checksum, ChecksumAlgorithm},
+    varint,
+};
+
+/// How `enc --delta-against` writes the delta.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum DeltaFormat {
+    /// A stackpack container, with the delta run through the pipeline.
+    #[default]
+    Stackpack,
+    /// A plain VCDIFF patch that xdelta3 and other tools can apply.
+    Vcdiff,
+}
+
+/// Length of the reference windows that are indexed for matching.
+pub const MATCH_LEN: usize = 32;
+
+/// Identifies the reference a delta was made against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeltaBase {
+    pub len: u64,
+    pub checksum: Checksum,
+}
+
+impl DeltaBase {
+    pub fn of(reference: &[u8]) -> Self {
+        DeltaBase {
+            len: reference.len() as u64,
+            checksum: ChecksumAlgorithm::Blake3.digest(reference),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint::write_u64(&mut out, self.len);
+        out.push(self.checksum.algorithm.id());
+        out.extend_from_slice(&self.checksum.digest);
+        out
+    }
+
+    pub fn parse(mut bytes: &[u8]) -> Result<Self> {
+        let len = varint::read_u64(&mut bytes)?;
+        let (&id, digest) = bytes.split_first().ok_or_else(|| anyhow!("delta base is missing its checksum"))?;
+        let algorithm = ChecksumAlgorithm::from_id(id).ok_or_else(|| anyhow!("delta base uses unknown checksum {}", id))?;
+        Ok(DeltaBase {
+            len,
+            checksum: Checksum {
+                algorithm,
+                digest: digest.to_vec(),
+            },
+        })
+    }
+
+    /// Fails unless `reference` is the file the delta was made against.
+    pub fn check(&self, reference: &[u8]) -> Result<()> {
+        if reference.len() as u64 != self.len {
+            bail!(
+                "the reference is {} bytes, the delta was made against {} bytes",
+                reference.len(),
+                self.len
+            );
+        }
+        let actual = self.checksum.algorithm.digest(reference);
+        if actual != self.checksum {
+            bail!("the reference has {}, the delta was made against {}", actual, self.checksum);
+        }
+        Ok(())
+    }
+}
+
+/// One step of a delta: append `literals`, then `copy_len` bytes of the reference starting at `copy_offset`.
+struct Instruction<'a> {
+    literals: &'a [u8],
+    copy_offset: usize,
+    copy_len: usize,
+}
+
+/// Finds the parts of `target` that can be copied out of `reference`.
+fn diff<'a>(reference: &[u8], target: &'a [u8]) -> Vec<Instruction<'a>> {
+    let mut windows = HashMap::new();
+    for (index, window) in reference.chunks_exact(MATCH_LEN).enumerate() {
+        windows.entry(window).or_insert(index * MATCH_LEN);
+    }
+
+    let mut instructions = Vec::new();
+    let mut literal_start = 0;
+    let mut position = 0;
+    while position + MATCH_LEN <= target.len() {
+        let Some(&offset) = windows.get(&target[position..position + MATCH_LEN]) else {
+            position += 1;
+            continue;
+        };
+        let forward = reference[offset..]
+            .iter()
+            .zip(&target[position..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        let backward = reference[..offset]
+            .iter()
+            .rev()
+            .zip(target[literal_start..position].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        instructions.push(Instruction {
+            literals: &target[literal_start..position - backward],
+            copy_offset: offset - backward,
+            copy_len: backward + forward,
+        });
+        position += forward;
+        literal_start = position;
+    }
+    if literal_start < target.len() {
+        instructions.push(Instruction {
+            literals: &target[literal_start..],
+            copy_offset: 0,
+            copy_len: 0,
+        });
+    }
+    instructions
+}
+
+/// Describes `target` as a delta against `reference`.
+pub fn encode_delta(reference: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for instruction in diff(reference, target) {
+        varint::write_u64(&mut out, instruction.literals.len() as u64);
+        out.extend_from_slice(instruction.literals);
+        varint::write_u64(&mut out, instruction.copy_offset as u64);
+        varint::write_u64(&mut out, instruction.copy_len as u64);
+    }
+    out
+}
+
+/// Rebuilds the target of `delta` from its `reference`.
+pub fn apply_delta(reference: &[u8], mut delta: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    while !delta.is_empty() {
+        let literal_len = usize::try_from(varint::read_u64(&mut delta)?).map_err(|_| anyhow!("delta literal length overflows"))?;
+        let (literals, rest) = delta.split_at_checked(literal_len).ok_or_else(|| anyhow!("delta truncated"))?;
+        out.extend_from_slice(literals);
+        delta = rest;
+        let offset = varint::read_u64(&mut delta)?;
+        let len = varint::read_u64(&mut delta)?;
+        let copy = usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(len).ok())
+            .and_then(|(offset, len)| reference.get(offset..offset.checked_add(len)?))
+            .ok_or_else(|| anyhow!("delta copies {} bytes at {}, past the end of the reference", len, offset))?;
+        out.extend_from_slice(copy);
+    }
+    Ok(out)
+}
@@ -0,0 +1,118 @@
+//! [VCDIFF](https://www.rfc-editor.org/rfc/rfc3284) output for deltas, so `xdelta3 -d -s old.bin patch.vcdiff new.bin`
+//! and other third-party tools can apply them.
+//!
+//! only the subset of the format that is needed is written: no secondary compression, the default code table, every
+//! instruction with its size in the instruction section and copy addresses in `VCD_SELF` mode. copies always come
+//! from the source segment, which is the whole reference. targets are cut into windows of at most [`WINDOW_SIZE`]
+//! bytes, since decoders limit how large a window they accept.
+use crate::{
+    delta::{Instruction, diff},
+    units::MEBIBYTES,
+};
+
+const MAGIC: [u8; 4] = [0xd6, 0xc3, 0xc4, 0x00];
+/// Window indicator bit for windows that copy from the source file.
+const VCD_SOURCE: u8 = 0x01;
+/// Default code table entry for an ADD whose size follows in the instruction section.
+const ADD: u8 = 1;
+/// Default code table entry for a COPY in `VCD_SELF` mode whose size follows in the instruction section.
+const COPY_SELF: u8 = 19;
+pub const WINDOW_SIZE: usize = 8 * MEBIBYTES;
+
+/// VCDIFF integers are base 128 like varints, but with the most significant digit first.
+fn write_integer(out: &mut Vec<u8>, value: u64) {
+    let mut digits = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest != 0 {
+        digits.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    out.extend(digits.iter().rev());
+}
+
+#[derive(Default)]
+struct Window {
+    target_len: usize,
+    data: Vec<u8>,
+    instructions: Vec<u8>,
+    addresses: Vec<u8>,
+}
+
+impl Window {
+    fn add(&mut self, literals: &[u8]) {
+        self.instructions.push(ADD);
+        write_integer(&mut self.instructions, literals.len() as u64);
+        self.data.extend_from_slice(literals);
+        self.target_len += literals.len();
+    }
+
+    fn copy(&mut self, offset: usize, len: usize) {
+        self.instructions.push(COPY_SELF);
+        write_integer(&mut self.instructions, len as u64);
+        write_integer(&mut self.addresses, offset as u64);
+        self.target_len += len;
+    }
+
+    fn room(&self) -> usize {
+        WINDOW_SIZE - self.target_len
+    }
+
+    fn write(&self, source_len: usize, out: &mut Vec<u8>) {
+        let mut encoding = Vec::new();
+        write_integer(&mut encoding, self.target_len as u64);
+        // no secondary compression of any section
+        encoding.push(0);
+        write_integer(&mut encoding, self.data.len() as u64);
+        write_integer(&mut encoding, self.instructions.len() as u64);
+        write_integer(&mut encoding, self.addresses.len() as u64);
+        encoding.extend_from_slice(&self.data);
+        encoding.extend_from_slice(&self.instructions);
+        encoding.extend_from_slice(&self.addresses);
+
+        if source_len == 0 {
+            out.push(0);
+        } else {
+            out.push(VCD_SOURCE);
+            write_integer(out, source_len as u64);
+            write_integer(out, 0);
+        }
+        write_integer(out, encoding.len() as u64);
+        out.extend_from_slice(&encoding);
+    }
+}
+
+/// Describes `target` as a VCDIFF patch against `reference`.
+pub fn encode_vcdiff(reference: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    // no secondary compressor, code table or application data
+    out.push(0);
+
+    let mut window = Window::default();
+    for Instruction {
+        mut literals,
+        mut copy_offset,
+        mut copy_len,
+    } in diff(reference, target)
+    {
+        while !literals.is_empty() || copy_len != 0 {
+            if window.room() == 0 {
+                window.write(reference.len(), &mut out);
+                window = Window::default();
+            }
+            if !literals.is_empty() {
+                let (now, later) = literals.split_at(literals.len().min(window.room()));
+                window.add(now);
+                literals = later;
+            } else {
+                let now = copy_len.min(window.room());
+                window.copy(copy_offset, now);
+                copy_offset += now;
+                copy_len -= now;
+            }
+        }
+    }
+    if window.target_len != 0 {
+        window.write(reference.len(), &mut out);
+    }
+    out
+}
@@ -0,0 +1,136 @@
+//! the machine readable event stream asked for with the global `--events ndjson`, for GUIs and wrappers that draw
+//! their own progress instead of parsing the reports on stderr, which are meant for people and change between
+//! versions. every event is a json object on a line of its own, written to the file descriptor given with
+//! `--events-fd`, stderr unless told otherwise. `--events-fd 3 3>events.ndjson` keeps the events apart from the
+//! reports. every event names itself in `event` and carries `elapsed_ms`, the time since the run started:
+//!
+//! - `stage_started` with `index`, `count` and `stage`: a stage starts on the input, a block or an archive frame.
+//! - `stage_finished` with `direction`, `index`, `count`, `stage`, `input_size`, `output_size` and `duration_ms`:
+//!   the stage is done with it.
+//! - `unit_finished` with `unit` (`block` or `frame`), `index`, `count` and `output_size`: a block or archive frame
+//!   went through the whole pipeline.
+//! - `member_written` with `path` and `size`: `dec` extracted a file of an archive.
+//! - `warning` with `message`: a `[warn]` line was printed.
+//! - `error` with `message` and `code`: `enc` or `dec` failed, or Ctrl-C stopped the run, and it exits with `code`.
+//!
+//! stage indices are in encoding order, so decoding counts them down.
+use core::fmt::Display;
+use std::{
+    io::{self, Write},
+    path::Path,
+    sync::OnceLock,
+    time::Instant,
+};
+
+use clap::ValueEnum;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+static SINK: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// How events are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EventFormat {
+    /// One json object per line.
+    Ndjson,
+}
+
+/// Something that happened during a run, see the module docs.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    StageStarted {
+        index: usize,
+        count: usize,
+        stage: &'a str,
+    },
+    StageFinished {
+        direction: &'a str,
+        index: usize,
+        count: usize,
+        stage: &'a str,
+        input_size: u64,
+        output_size: u64,
+        duration_ms: f64,
+    },
+    UnitFinished {
+        unit: &'a str,
+        index: usize,
+        /// `null` while an archive is being encoded, the number of frames isn't known until it is done.
+        count: Option<usize>,
+        output_size: u64,
+    },
+    MemberWritten {
+        path: &'a Path,
+        size: u64,
+    },
+    Warning {
+        message: &'a str,
+    },
+    Error {
+        message: &'a str,
+        code: i32,
+    },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    #[serde(flatten)]
+    event: Event<'a>,
+    elapsed_ms: f64,
+}
+
+/// Sends the events of the rest of the run to file descriptor `fd`. only stdout and stderr can be given outside unix.
+pub fn open(format: EventFormat, fd: i32) -> io::Result<()> {
+    let EventFormat::Ndjson = format;
+    let sink: Box<dyn Write + Send> = match fd {
+        1 => Box::new(io::stdout()),
+        2 => Box::new(io::stderr()),
+        #[cfg(unix)]
+        fd => {
+            use std::{fs::File, os::fd::FromRawFd};
+            // SAFETY: `fcntl` only looks the descriptor up.
+            if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: the descriptor is open, and the file owning it is kept until the process exits.
+            Box::new(unsafe { File::from_raw_fd(fd) })
+        }
+        #[cfg(not(unix))]
+        fd => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("only 1 (stdout) and 2 (stderr) are supported here, not {}", fd),
+            ));
+        }
+    };
+    STARTED.get_or_init(Instant::now);
+    *SINK.lock() = Some(sink);
+    Ok(())
+}
+
+/// Sends `event`, if events were asked for.
+pub fn emit(event: Event<'_>) {
+    let mut sink = SINK.lock();
+    let Some(writer) = sink.as_mut() else {
+        return;
+    };
+    let line = Line {
+        event,
+        elapsed_ms: STARTED.get().map_or(0.0, |started| started.elapsed().as_secs_f64() * 1000.0),
+    };
+    let mut json = serde_json::to_vec(&line).expect("events are always serializable");
+    json.push(b'\n');
+    // whoever reads the events went away, the run goes on without them
+    if writer.write_all(&json).and_then(|()| writer.flush()).is_err() {
+        *sink = None;
+    }
+}
+
+/// Prints a `[warn]` line on stderr and sends it as a `warning` event.
+pub fn warn(message: impl Display) {
+    let message = message.to_string();
+    eprintln!("[warn] stackpack: {}", message);
+    emit(Event::Warning { message: &message });
+}
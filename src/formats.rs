@@ -0,0 +1,61 @@
+//! recognition of files made by other compressors. `dec` sniffs inputs that are neither containers nor have a
+//! sidecar, and decodes the formats below with the stage of the same name when one is registered, e.g. by a plugin.
+//! only lz4 has a built-in stage. for the others, unless a plugin provides the stage, it can at least name the format
+//! and the tool that reads it, instead of failing over a missing pipeline.
+use crate::{algorithms::pipeline::get_specific_compressor_from_name, registered::RegisteredCompressor};
+
+/// A compressed file format stackpack can recognise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForeignFormat {
+    pub name: &'static str,
+    pub magic: &'static [u8],
+    /// Name of the stage that decodes it.
+    pub stage: &'static str,
+    /// Command that decompresses it outside of stackpack.
+    pub tool: &'static str,
+}
+
+pub const FOREIGN_FORMATS: &[ForeignFormat] = &[
+    ForeignFormat {
+        name: "gzip",
+        magic: &[0x1f, 0x8b],
+        stage: "gzip",
+        tool: "gzip -d",
+    },
+    ForeignFormat {
+        name: "bzip2",
+        magic: b"BZh",
+        stage: "bzip2",
+        tool: "bzip2 -d",
+    },
+    ForeignFormat {
+        name: "xz",
+        magic: &[0xfd, b'7', b'z', b'X', b'Z', 0x00],
+        stage: "xz",
+        tool: "xz -d",
+    },
+    ForeignFormat {
+        name: "zstd",
+        magic: &[0x28, 0xb5, 0x2f, 0xfd],
+        stage: "zstd",
+        tool: "zstd -d",
+    },
+    ForeignFormat {
+        name: "lz4",
+        magic: &[0x04, 0x22, 0x4d, 0x18],
+        stage: "lz4",
+        tool: "lz4 -d",
+    },
+];
+
+/// The format `bytes` are in, judging by their magic bytes.
+pub fn detect(bytes: &[u8]) -> Option<&'static ForeignFormat> {
+    FOREIGN_FORMATS.iter().find(|format| bytes.starts_with(format.magic))
+}
+
+impl ForeignFormat {
+    /// The registered stage that decodes this format, if there is one.
+    pub fn stage(&self) -> Option<RegisteredCompressor> {
+        get_specific_compressor_from_name(self.stage)
+    }
+}
@@ -0,0 +1,87 @@
+//! how often every byte value occurs in the original data, recorded by `enc --histogram` so `info` and `analyze` can
+//! tell what an archive holds, e.g. text or binary and how much entropy is left in it, without decoding it.
+//!
+//! the histogram is encoded as:
+//!
+//! | field        | notes                                                         |
+//! |--------------|---------------------------------------------------------------|
+//! | symbol count | varint, how many distinct byte values occur                   |
+//! | symbols      | `[byte: u8][count: varint]` per value that occurs, ascending  |
+//!
+//! values that don't occur are left out, so text takes little more than a hundred bytes and even random data a
+//! little over a kilobyte.
+use anyhow::{Result, anyhow, bail};
+
+use crate::varint;
+
+/// How often every byte value occurs in some data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteHistogram {
+    pub counts: [u64; 256],
+}
+
+impl ByteHistogram {
+    pub fn of(data: &[u8]) -> Self {
+        let mut counts = [0u64; 256];
+        for &byte in data {
+            counts[byte as usize] += 1;
+        }
+        ByteHistogram { counts }
+    }
+
+    /// How many bytes the histogram counts.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// How many distinct byte values occur.
+    pub fn distinct(&self) -> usize {
+        self.counts.iter().filter(|&&count| count > 0).count()
+    }
+
+    /// The order-0 entropy of the counted bytes in bits per byte, 0 when there are none.
+    pub fn entropy(&self) -> f64 {
+        let total = self.total() as f64;
+        self.counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint::write_u64(&mut out, self.distinct() as u64);
+        for (byte, &count) in self.counts.iter().enumerate().filter(|&(_, &count)| count > 0) {
+            out.push(byte as u8);
+            varint::write_u64(&mut out, count);
+        }
+        out
+    }
+
+    pub fn parse(mut bytes: &[u8]) -> Result<Self> {
+        let symbols = varint::read_u64(&mut bytes)?;
+        if symbols > 256 {
+            bail!("histogram has {} symbols, a byte has only 256 values", symbols);
+        }
+        let mut counts = [0u64; 256];
+        let mut last = None;
+        for _ in 0..symbols {
+            let (&byte, rest) = bytes.split_first().ok_or_else(|| anyhow!("histogram truncated: a symbol is cut off"))?;
+            bytes = rest;
+            if last.is_some_and(|last| byte <= last) {
+                bail!("histogram symbols are not in ascending order");
+            }
+            last = Some(byte);
+            counts[byte as usize] = varint::read_u64(&mut bytes)?;
+        }
+        // the counts come from the file, and `total` relies on their sum fitting
+        if counts.iter().try_fold(0u64, |total, &count| total.checked_add(count)).is_none() {
+            bail!("histogram counts add up to more bytes than a file can hold");
+        }
+        Ok(ByteHistogram { counts })
+    }
+}
@@ -0,0 +1,177 @@
+//! the build and machine a container was encoded with, recorded by `enc --record-host` so `info` can say what it
+//! takes to decode a file made elsewhere, e.g. which plugins have to be installed.
+//!
+//! the record is encoded as:
+//!
+//! | field         | notes                                                                       |
+//! |---------------|-----------------------------------------------------------------------------|
+//! | version       | `[length: varint][utf-8]`, the stackpack version that encoded the file      |
+//! | os            | `[length: varint][utf-8]`, e.g. `linux`                                     |
+//! | arch          | `[length: varint][utf-8]`, e.g. `x86_64`                                    |
+//! | feature count | varint                                                                      |
+//! | features      | `[length: varint][utf-8]` per cargo feature the build was made with         |
+//! | plugin count  | varint                                                                      |
+//! | plugins       | `[name length: varint][stage name][checksum id: u8][digest length: varint][digest]` per plugin stage |
+//!
+//! plugins are listed by the `<plugin>.<stage>` name of every plugin stage the pipeline uses, along with a blake3
+//! hash of the library it was loaded from. a library that can't be read for the hash is recorded with an empty digest.
+use core::fmt::{self, Display};
+use std::{fs, str};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    algorithms::pipeline::CompressionPipeline,
+    checksum::{Checksum, ChecksumAlgorithm},
+    registered::EnumMutator,
+    varint,
+};
+
+/// Cargo features that change what a build can decode or report.
+const FEATURES: &[(&str, bool)] = &[("tracing", cfg!(feature = "tracing")), ("image", cfg!(feature = "image"))];
+
+/// What a container was encoded with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostInfo {
+    pub version: String,
+    pub os: String,
+    pub arch: String,
+    pub features: Vec<String>,
+    pub plugins: Vec<PluginStage>,
+}
+
+/// A plugin stage a pipeline uses, and the library that provided it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginStage {
+    /// `<plugin>.<stage>`, see [`crate::registered`].
+    pub name: String,
+    pub checksum: Checksum,
+}
+
+impl HostInfo {
+    /// This build and machine, with the plugin stages of `pipeline`.
+    pub fn current(pipeline: &CompressionPipeline) -> Self {
+        let mut plugins: Vec<PluginStage> = Vec::new();
+        for stage in pipeline.stages() {
+            let EnumMutator::Ffi(mutator) = &stage.mutator else {
+                continue;
+            };
+            if plugins.iter().any(|plugin| plugin.name == stage.name) {
+                continue;
+            }
+            let checksum = match fs::read(mutator.loaded_from()) {
+                Ok(library) => ChecksumAlgorithm::Blake3.digest(&library),
+                Err(_) => Checksum {
+                    algorithm: ChecksumAlgorithm::Blake3,
+                    digest: Vec::new(),
+                },
+            };
+            plugins.push(PluginStage {
+                name: stage.name.to_string(),
+                checksum,
+            });
+        }
+        HostInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            features: FEATURES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name.to_string()).collect(),
+            plugins,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_str(&mut out, &self.version);
+        write_str(&mut out, &self.os);
+        write_str(&mut out, &self.arch);
+        varint::write_u64(&mut out, self.features.len() as u64);
+        for feature in &self.features {
+            write_str(&mut out, feature);
+        }
+        varint::write_u64(&mut out, self.plugins.len() as u64);
+        for plugin in &self.plugins {
+            write_str(&mut out, &plugin.name);
+            out.push(plugin.checksum.algorithm.id());
+            varint::write_u64(&mut out, plugin.checksum.digest.len() as u64);
+            out.extend_from_slice(&plugin.checksum.digest);
+        }
+        out
+    }
+
+    pub fn parse(mut bytes: &[u8]) -> Result<Self> {
+        let version = read_str(&mut bytes)?;
+        let os = read_str(&mut bytes)?;
+        let arch = read_str(&mut bytes)?;
+        let feature_count = varint::read_u64(&mut bytes)?;
+        let mut features = Vec::new();
+        for _ in 0..feature_count {
+            features.push(read_str(&mut bytes)?);
+        }
+        let plugin_count = varint::read_u64(&mut bytes)?;
+        let mut plugins = Vec::new();
+        for _ in 0..plugin_count {
+            let name = read_str(&mut bytes)?;
+            let (&id, rest) = bytes.split_first().ok_or_else(|| anyhow!("host record truncated: plugin checksum is cut off"))?;
+            bytes = rest;
+            let algorithm = ChecksumAlgorithm::from_id(id).ok_or_else(|| anyhow!("host record uses unknown checksum {}", id))?;
+            let digest = take(&mut bytes, "plugin checksum")?;
+            plugins.push(PluginStage {
+                name,
+                checksum: Checksum {
+                    algorithm,
+                    digest: digest.to_vec(),
+                },
+            });
+        }
+        Ok(HostInfo {
+            version,
+            os,
+            arch,
+            features,
+            plugins,
+        })
+    }
+}
+
+/// Formats the build as `stackpack 1.1.1 on linux/x86_64, features: tracing`.
+impl Display for HostInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stackpack {} on {}/{}", self.version, self.os, self.arch)?;
+        match self.features.as_slice() {
+            [] => f.write_str(", no features"),
+            features => write!(f, ", features: {}", features.join(", ")),
+        }
+    }
+}
+
+/// Formats the plugin stage as `name (blake3 hexdigest)`, or says its library couldn't be hashed.
+impl Display for PluginStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.checksum.digest.is_empty() {
+            write!(f, "{} (library not hashed)", self.name)
+        } else {
+            write!(f, "{} ({})", self.name, self.checksum)
+        }
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, value: &str) {
+    varint::write_u64(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// Reads a varint length and that many bytes from the front of `bytes`.
+fn take<'a>(bytes: &mut &'a [u8], what: &str) -> Result<&'a [u8]> {
+    let len = usize::try_from(varint::read_u64(bytes)?).map_err(|_| anyhow!("host record {} length overflows", what))?;
+    let (head, rest) = bytes
+        .split_at_checked(len)
+        .ok_or_else(|| anyhow!("host record truncated: {} is cut off", what))?;
+    *bytes = rest;
+    Ok(head)
+}
+
+fn read_str(bytes: &mut &[u8]) -> Result<String> {
+    let value = take(bytes, "string")?;
+    Ok(str::from_utf8(value).map_err(|_| anyhow!("host record holds a string that is not utf-8"))?.to_string())
+}
@@ -0,0 +1,100 @@
+//! what Ctrl-C does. the first one asks the run to stop, which it does at the next [`checkpoint`]: between the stages
+//! of a pipeline, and so between the blocks and archive frames it runs on, and between the chunks of an output being
+//! written. the outputs being written at that point are removed, outputs finished before are kept, and stackpack exits
+//! with [`EXIT_INTERRUPTED`], the code shells give a process killed by Ctrl-C.
+//!
+//! a stage is never cut short, so a second Ctrl-C exits right away instead, e.g. in the middle of a huge `bwt` or
+//! while waiting on stdin. that can leave a partial output behind, a `.partial` sibling with `--durability fsync`.
+use std::{
+    fs,
+    io::ErrorKind,
+    mem,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    events::{self, Event},
+    progress,
+};
+
+/// The exit code of an interrupted run, 128 plus the number of SIGINT.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static PARTIAL_OUTPUTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Handles Ctrl-C as the module docs describe, for the rest of the run.
+pub fn install() {
+    #[cfg(unix)]
+    // SAFETY: the handler only touches an atomic and calls `_exit`, both of which are async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as *const () as libc::sighandler_t);
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        // SAFETY: `_exit` is async-signal-safe, unlike the cleanup `process::exit` runs.
+        unsafe { libc::_exit(EXIT_INTERRUPTED) };
+    }
+}
+
+/// Whether Ctrl-C was pressed.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Stops the run if Ctrl-C was pressed, removing the outputs being written.
+pub fn checkpoint() {
+    if requested() {
+        stop();
+    }
+}
+
+fn stop() -> ! {
+    let partial = mem::take(&mut *PARTIAL_OUTPUTS.lock());
+    progress::abandon();
+    for path in &partial {
+        if let Err(e) = fs::remove_file(path)
+            && e.kind() != ErrorKind::NotFound
+        {
+            events::warn(format_args!("failed to remove the partial output {}: {}", path.display(), e));
+        }
+    }
+    let message = match partial.len() {
+        0 => "interrupted.".to_string(),
+        count => format!("interrupted, removed {} partial output(s).", count),
+    };
+    eprintln!("[error] stackpack: {}", message);
+    events::emit(Event::Error {
+        message: &message,
+        code: EXIT_INTERRUPTED,
+    });
+    process::exit(EXIT_INTERRUPTED);
+}
+
+/// An output being written, removed if the run is interrupted before the guard is dropped.
+#[must_use]
+pub struct PartialOutput {
+    path: PathBuf,
+}
+
+/// Marks `path` as being written until the returned guard is dropped.
+pub fn partial_output(path: &Path) -> PartialOutput {
+    PARTIAL_OUTPUTS.lock().push(path.to_path_buf());
+    PartialOutput { path: path.to_path_buf() }
+}
+
+impl Drop for PartialOutput {
+    fn drop(&mut self) {
+        let mut partial = PARTIAL_OUTPUTS.lock();
+        if let Some(index) = partial.iter().rposition(|path| *path == self.path) {
+            partial.remove(index);
+        }
+    }
+}
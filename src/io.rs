@@ -0,0 +1,217 @@
+//! file I/O shared by the cli commands. `-` in place of a path reads from stdin or writes to stdout.
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+
+use crate::{events, interrupt};
+
+/// How hard stackpack tries to make written outputs survive a crash before reporting success.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Durability {
+    /// Hand the data to the operating system and return.
+    #[default]
+    None,
+    /// Flush userspace buffers before returning.
+    Flush,
+    /// Write to a temporary file, fsync it, rename it over the target and fsync the parent directory.
+    Fsync,
+}
+
+/// What happens to outputs that already exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overwrite {
+    /// Fail instead of replacing them.
+    #[default]
+    Refuse,
+    /// Replace them.
+    Force,
+    /// Leave them as they are and carry on, so that batch jobs can be run again to finish what's missing.
+    Keep,
+}
+
+/// I/O behaviour requested on the command line.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoPolicy {
+    pub durability: Durability,
+    /// Maximum throughput in bytes per second for reads and writes, if any.
+    pub limit_rate: Option<u64>,
+    pub overwrite: Overwrite,
+}
+
+impl IoPolicy {
+    /// The same policy, but replacing existing outputs. for outputs that are updated in place or belong to another.
+    pub fn forced(self) -> Self {
+        IoPolicy {
+            overwrite: Overwrite::Force,
+            ..self
+        }
+    }
+}
+
+/// Chunk size used when throughput is limited, small enough to keep the rate smooth.
+const THROTTLE_CHUNK: usize = 64 * 1024;
+/// Chunk size outputs are written in otherwise, small enough for Ctrl-C to stop a write promptly.
+const WRITE_CHUNK: usize = 8 * 1024 * 1024;
+
+/// Sleeps as needed to keep the transferred byte count under a fixed rate.
+struct Throttle {
+    bytes_per_second: u64,
+    started: Instant,
+    transferred: u64,
+}
+
+impl Throttle {
+    fn new(bytes_per_second: u64) -> Self {
+        Throttle {
+            bytes_per_second: bytes_per_second.max(1),
+            started: Instant::now(),
+            transferred: 0,
+        }
+    }
+
+    fn account(&mut self, bytes: usize) {
+        self.transferred += bytes as u64;
+        let expected = Duration::from_secs_f64(self.transferred as f64 / self.bytes_per_second as f64);
+        let elapsed = self.started.elapsed();
+        if expected > elapsed {
+            thread::sleep(expected - elapsed);
+        }
+    }
+}
+
+/// Whether `path` is `-`, which stands for stdin or stdout.
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+pub fn read_file(path: &Path, policy: IoPolicy) -> io::Result<Vec<u8>> {
+    if is_stdio(path) {
+        return read_all_throttled(&mut io::stdin().lock(), Vec::new(), policy.limit_rate);
+    }
+    let Some(rate) = policy.limit_rate else {
+        return fs::read(path);
+    };
+
+    let mut file = File::open(path)?;
+    let data = Vec::with_capacity(file.metadata().map(|m| m.len() as usize).unwrap_or(0));
+    read_all_throttled(&mut file, data, Some(rate))
+}
+
+fn read_all_throttled(reader: &mut impl Read, mut data: Vec<u8>, limit_rate: Option<u64>) -> io::Result<Vec<u8>> {
+    let Some(rate) = limit_rate else {
+        reader.read_to_end(&mut data)?;
+        return Ok(data);
+    };
+
+    let mut chunk = vec![0u8; THROTTLE_CHUNK];
+    let mut throttle = Throttle::new(rate);
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        data.extend_from_slice(&chunk[..read]);
+        throttle.account(read);
+    }
+    Ok(data)
+}
+
+/// Writes `data` in chunks, stopping between them if the run is interrupted (see [`interrupt`]).
+fn write_all_throttled(writer: &mut impl Write, data: &[u8], limit_rate: Option<u64>) -> io::Result<()> {
+    let mut throttle = limit_rate.map(Throttle::new);
+    let chunk_len = match throttle {
+        Some(_) => THROTTLE_CHUNK,
+        None => WRITE_CHUNK,
+    };
+    for chunk in data.chunks(chunk_len) {
+        interrupt::checkpoint();
+        writer.write_all(chunk)?;
+        if let Some(throttle) = &mut throttle {
+            throttle.account(chunk.len());
+        }
+    }
+    Ok(())
+}
+
+pub fn write_file(path: &Path, data: &[u8], policy: IoPolicy) -> io::Result<()> {
+    if is_stdio(path) {
+        // there is no file to sync, flushing is all that can be done for stdout
+        let mut stdout = io::stdout().lock();
+        write_all_throttled(&mut stdout, data, policy.limit_rate)?;
+        return stdout.flush();
+    }
+    if fs::symlink_metadata(path).is_ok() {
+        match policy.overwrite {
+            Overwrite::Refuse => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists, pass --force to overwrite it", path.display()),
+                ));
+            }
+            Overwrite::Keep => {
+                events::warn(format_args!("{} already exists, keeping it (--keep).", path.display()));
+                return Ok(());
+            }
+            Overwrite::Force => {}
+        }
+    }
+    match policy.durability {
+        Durability::None => {
+            let _partial = interrupt::partial_output(path);
+            write_all_throttled(&mut File::create(path)?, data, policy.limit_rate)
+        }
+        Durability::Flush => {
+            let _partial = interrupt::partial_output(path);
+            let mut writer = BufWriter::new(File::create(path)?);
+            write_all_throttled(&mut writer, data, policy.limit_rate)?;
+            writer.flush()
+        }
+        Durability::Fsync => {
+            let temp_path = temporary_sibling(path);
+            let _partial = interrupt::partial_output(&temp_path);
+            let written = write_synced(&temp_path, data, policy.limit_rate).and_then(|()| fs::rename(&temp_path, path));
+            if written.is_err() {
+                // the half written file is of no use to anyone, the error is what matters
+                let _ = fs::remove_file(&temp_path);
+            }
+            written?;
+            sync_parent_dir(path)
+        }
+    }
+}
+
+/// Writes `data` to a new file at `path` and fsyncs it.
+fn write_synced(path: &Path, data: &[u8], limit_rate: Option<u64>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_all_throttled(&mut writer, data, limit_rate)?;
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+fn temporary_sibling(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    // directories can't be opened for syncing on this platform, the rename itself is the best we can do.
+    Ok(())
+}
@@ -5,21 +5,33 @@
 extern crate anyhow;
 extern crate arcode;
 extern crate clap;
+#[cfg(feature = "libsais")]
 extern crate libsais;
 // extern crate derive_fromstr;
 // extern crate lzw;
 // extern crate log;
 // extern crate no_panic;
-// extern crate serde;
-// extern crate serde_json;
 // extern crate thiserror;
 // extern crate voxell_rng;
+extern crate blake3;
 extern crate bsc_m03_sys;
 extern crate cfg_if;
+extern crate crc32c;
+extern crate crc32fast;
+extern crate ed25519_dalek;
+extern crate globset;
+#[cfg(unix)]
+extern crate libc;
 extern crate libloading;
+extern crate lz4_flex;
 extern crate parking_lot;
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate tar;
 extern crate voxell_timer;
 extern crate walkdir;
+extern crate xxhash_rust;
 if_tracing! {
     extern crate tracing;
     extern crate tracing_log;
@@ -51,17 +63,43 @@ macro_rules! if_not_tracing {
 }
 
 pub mod algorithms;
+pub mod archive;
+pub mod blocks;
+pub mod checksum;
 pub mod cli;
+pub mod config;
+pub mod container;
+pub mod content;
+pub mod delta;
+pub mod events;
+pub mod formats;
+pub mod histogram;
+pub mod host;
+pub mod interrupt;
+pub mod io;
 pub mod mutator;
 pub mod plugins;
+pub mod progress;
 pub mod registered;
+pub mod settings;
+pub mod sidecar;
+pub mod signing;
+pub mod varint;
+pub mod verbosity;
+pub mod volumes;
+pub mod walk;
 
-use crate::cli::{Cli, Command};
+use crate::cli::{
+    Cli, Command,
+    error::{Classify, ErrorClass},
+};
 use clap::Parser;
 
 mod units;
 
 fn main() {
+    let cli = Cli::parse();
+
     if_tracing! {
         let max_level = {
             fn parse_level(s: &str) -> Option<tracing::Level> {
@@ -88,15 +126,47 @@ fn main() {
                 .unwrap_or(tracing::Level::TRACE)
         };
 
+        // spans are only reported through the events inside them unless asked for, `STACKPACK_SPAN_EVENTS=close`
+        // also reports when each span closes along with its busy and idle time, `full` when it opens too.
+        let span_events = match std::env::var("STACKPACK_SPAN_EVENTS").ok().as_deref().map(str::trim) {
+            Some("close") => tracing_subscriber::fmt::format::FmtSpan::CLOSE,
+            Some("full") => tracing_subscriber::fmt::format::FmtSpan::NEW | tracing_subscriber::fmt::format::FmtSpan::CLOSE,
+            _ => tracing_subscriber::fmt::format::FmtSpan::NONE,
+        };
+
+        // commands writing their data to stdout need their logs to go elsewhere
+        let writer = if cli.command.writes_to_stdout() {
+            tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::io::stderr)
+        } else {
+            tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::io::stdout)
+        };
+
         let subscriber = tracing_subscriber::fmt()
             .with_max_level(max_level)
+            .with_span_events(span_events)
+            .with_writer(writer)
             .with_ansi(true)
             .with_target(false)
             .finish();
         tracing::subscriber::set_global_default(subscriber).ok();
     }
 
-    let cli = Cli::parse();
+    verbosity::set(cli.verbosity());
+    if let Some(format) = cli.events {
+        events::open(format, cli.events_fd)
+            .classify(ErrorClass::Usage, || {
+                format!("can't write events to file descriptor {}", cli.events_fd)
+            })
+            .unwrap_or_else(|e| e.exit());
+    }
+    interrupt::install();
+
+    if let Some(threads) = cli.threads.or(settings::get().threads) {
+        algorithms::heuristics::set_threads(threads.get());
+    }
+    if let Some(thresholds) = settings::get().thresholds {
+        algorithms::heuristics::set_thresholds(thresholds);
+    }
 
     if cli.unsafe_mode {
         cli::warn_unsafe_mode_enabled();
@@ -106,13 +176,19 @@ fn main() {
         unsafe { plugins::load_plugins() };
     }
 
-    match cli.command {
+    let result = match cli.command {
         Command::Encode(args) => cli::encode::encode(args),
         Command::Decode(args) => cli::decode::decode(args),
-        Command::Test(args) => cli::test::test(args),
-        Command::Corpus(args) => cli::corpus::corpus(args),
-        Command::Pipeline(command) => cli::pipeline::pipeline(command),
+        command => {
+            run_command(command);
+            Ok(())
+        }
     };
+    cli::stats::finish();
+    if result.is_err() {
+        cli::summary::fail();
+    }
+    cli::summary::finish();
 
     if cli.unsafe_mode {
         // SAFETY: user has explicitly opted in to unsafe mode,
@@ -120,4 +196,30 @@ fn main() {
         // for safety.
         unsafe { plugins::unload_plugins() };
     }
+    if let Err(e) = result {
+        e.exit();
+    }
+}
+
+/// Runs the commands that report their own errors.
+fn run_command(command: Command) {
+    match command {
+        Command::Encode(_) | Command::Decode(_) => unreachable!("encode and decode are run by main"),
+        Command::Convert(args) => cli::convert::convert(args),
+        Command::Test(args) => cli::test::test(args).unwrap_or_else(|e| e.exit()),
+        Command::Cat(args) => cli::cat::cat(args),
+        Command::Corpus(args) => cli::corpus::corpus(args).unwrap_or_else(|e| e.exit()),
+        Command::Grep(args) => cli::grep::grep(args).unwrap_or_else(|e| e.exit()),
+        Command::Heatmap(args) => cli::heatmap::heatmap(args),
+        Command::Analyze(args) => cli::analyze::analyze(args).unwrap_or_else(|e| e.exit()),
+        Command::Info(args) => cli::info::info(args),
+        Command::Verify(args) => cli::verify::verify(args),
+        Command::Diff(args) => cli::diff::diff(args).unwrap_or_else(|e| e.exit()),
+        Command::Extract(args) => cli::extract::extract(args),
+        Command::Selftest(args) => cli::selftest::selftest(args),
+        Command::Sanitize(args) => cli::sanitize::sanitize(args),
+        Command::Calibrate(args) => cli::calibrate::calibrate(args),
+        Command::PortableDecoder(args) => cli::portable::portable_decoder(args).unwrap_or_else(|e| e.exit()),
+        Command::Pipeline(command) => cli::pipeline::pipeline(command).unwrap_or_else(|e| e.exit()),
+    }
 }
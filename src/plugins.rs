@@ -1,14 +1,16 @@
 use core::mem;
 use parking_lot::Mutex;
-use std::{env, ffi::OsStr, mem::MaybeUninit, path::PathBuf, sync::LazyLock};
+use std::{ffi::OsStr, mem::MaybeUninit, path::PathBuf, sync::LazyLock};
 
 use anyhow::Result;
 use libloading::Library;
 use walkdir::WalkDir;
 
 use crate::{
+    events,
     mutator::Mutator,
-    registered::{ALL_COMPRESSORS, RegisteredCompressor},
+    registered::{self, RegisteredCompressor, ShortName},
+    settings,
 };
 
 #[repr(C)]
@@ -59,6 +61,9 @@ pub struct StackpackPluginAPI {
 }
 
 impl StackpackPluginAPI {
+    /// # Safety
+    ///
+    /// `lib` must export the plugin symbols with the exact types declared by the Stackpack Plugin API.
     pub unsafe fn from_library(lib: &Library) -> Result<Self, APIError> {
         unsafe {
             let short_name = lib
@@ -87,92 +92,123 @@ impl StackpackPluginAPI {
 
 pub struct Plugin {
     pub(crate) loaded_from: PathBuf,
+    /// `<plugin>.<stage>`, the name its stage is registered under, see [`registered::register_plugin`].
+    pub(crate) stage_name: &'static str,
     pub(crate) api: StackpackPluginAPI,
     pub(crate) _lib: Library,
 }
 
 impl Plugin {
     pub fn new(loaded_from: PathBuf, api: StackpackPluginAPI, lib: Library) -> Self {
-        Plugin { loaded_from, api, _lib: lib }
+        let file_stem = loaded_from.file_stem().unwrap_or_default().to_string_lossy();
+        let namespace = file_stem.strip_prefix("lib").filter(|name| !name.is_empty()).unwrap_or(&file_stem);
+        // registered names are static, and a plugin stays loaded for about as long as the process runs
+        let stage_name = Box::leak(format!("{}.{}", namespace, api.short_name).into_boxed_str());
+        Plugin {
+            loaded_from,
+            stage_name,
+            api,
+            _lib: lib,
+        }
     }
 }
 
 pub static LOADED_PLUGINS: LazyLock<Mutex<Vec<Plugin>>> = LazyLock::new(|| Mutex::new(vec![]));
 
+/// # Safety
+///
+/// Runs initialization code of arbitrary dynamic libraries found in the plugin directories, see [`crate::settings`].
 pub unsafe fn load_plugins() {
     if_tracing! {{
         tracing::trace!(event = "loading_plugins");
     }}
 
-    let path = match env::var_os("STACKPACK_PLUGINS_ROOT") {
-        Some(t) => t,
-        None => {
-            if_tracing! {{
-                tracing::info!(event = "no_plugins_path", "`STACKPACK_PLUGINS_ROOT` environment variable not set, skipping plugin loading");
-            }};
-            return;
-        }
-    };
+    let dirs = &settings::get().plugin_dirs;
+    if dirs.is_empty() {
+        if_tracing! {{
+            tracing::info!(event = "no_plugins_path", "neither `STACKPACK_PLUGIN_DIRS` nor `STACKPACK_PLUGINS_ROOT` is set, skipping plugin loading");
+        }};
+        return;
+    }
 
-    let mut pathbuf = PathBuf::from(path);
-    pathbuf.push("plugins");
+    for dir in dirs {
+        if_tracing! {{
+            tracing::debug!(event = "plugins", path = ?dir.display(), "looking for plugins here");
+        }};
 
-    if_tracing! {{
-        tracing::debug!(event = "plugins", path = ?pathbuf.display(), "looking for plugins here");
-    }};
-
-    for entry in WalkDir::new(&pathbuf)
-        .max_depth(1)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-    {
-        let path = entry.path();
-        let ext = path.extension().unwrap_or(OsStr::new(""));
-
-        if ext == OsStr::new("dll") || ext == OsStr::new("so") || ext == OsStr::new("dylib") {
-            match unsafe { libloading::Library::new(path) } {
-                Ok(lib) => {
-                    let api = match unsafe { StackpackPluginAPI::from_library(&lib) } {
-                        Ok(t) => t,
-                        Err(e) => {
-                            if_tracing! {{
-                                tracing::error!(event = "plugins", path = ?path.display(), error = ?e, "plugin does not conform to Stackpack Plugin API");
-                            }};
-                            eprintln!("[WARN] plugin at {} does not conform to Stackpack Plugin API: {:?}", path.display(), e);
-                            continue;
-                        }
-                    };
-                    let plug = Plugin::new(path.to_path_buf(), api, lib);
-                    let mut lock = LOADED_PLUGINS.lock();
-                    lock.push(plug);
-                    drop(lock);
-                    if_tracing! {{
-                        tracing::info!(event = "plugins", path = ?path.display(), "successfully loaded plugin");
-                    }}
-                }
-                Err(e) => {
-                    if_tracing! {{
-                        tracing::error!(event = "plugins", path = ?path.display(), error = %e, "failed to load plugin");
-                    }};
-                    eprintln!("[WARN] failed to load plugin from {}: {}", path.display(), e);
+        for entry in WalkDir::new(dir)
+            .max_depth(1)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let path = entry.path();
+            let ext = path.extension().unwrap_or(OsStr::new(""));
+
+            if ext == OsStr::new("dll") || ext == OsStr::new("so") || ext == OsStr::new("dylib") {
+                match unsafe { libloading::Library::new(path) } {
+                    Ok(lib) => {
+                        let api = match unsafe { StackpackPluginAPI::from_library(&lib) } {
+                            Ok(t) => t,
+                            Err(e) => {
+                                if_tracing! {{
+                                    tracing::error!(event = "plugins", path = ?path.display(), error = ?e, "plugin does not conform to Stackpack Plugin API");
+                                }};
+                                eprintln!(
+                                    "[WARN] plugin at {} does not conform to Stackpack Plugin API: {:?}",
+                                    path.display(),
+                                    e
+                                );
+                                continue;
+                            }
+                        };
+                        let plug = Plugin::new(path.to_path_buf(), api, lib);
+                        let mut lock = LOADED_PLUGINS.lock();
+                        lock.push(plug);
+                        drop(lock);
+                        if_tracing! {{
+                            tracing::info!(event = "plugins", path = ?path.display(), "successfully loaded plugin");
+                        }}
+                    }
+                    Err(e) => {
+                        if_tracing! {{
+                            tracing::error!(event = "plugins", path = ?path.display(), error = %e, "failed to load plugin");
+                        }};
+                        eprintln!("[WARN] failed to load plugin from {}: {}", path.display(), e);
+                    }
                 }
             }
         }
     }
 
-    {
-        let mut registry_lock = ALL_COMPRESSORS.lock();
-        for (index, plug) in LOADED_PLUGINS.lock().iter().enumerate() {
-            if_tracing! {{
-                tracing::debug!(event = "registry", index = index, name = plug.api.short_name, path = ?plug.loaded_from.display(), "registered compressor");
-            }};
-
-            registry_lock.push(RegisteredCompressor::new_ffi(
-                FfiMutator { plugin_index: index },
-                plug.api.short_name,
-                plug.api.description.as_option().copied(),
-            ));
+    for (index, plug) in LOADED_PLUGINS.lock().iter().enumerate() {
+        let stage = RegisteredCompressor::new_ffi(
+            FfiMutator { plugin_index: index },
+            plug.stage_name,
+            plug.api.description.as_option().copied(),
+        );
+        match registered::register_plugin(stage, plug.api.short_name) {
+            Ok(short_name) => {
+                if_tracing! {{
+                    tracing::debug!(event = "registry", index = index, name = plug.stage_name, short_name = ?short_name, path = ?plug.loaded_from.display(), "registered compressor");
+                }};
+                match short_name {
+                    ShortName::Claimed => {}
+                    ShortName::Builtin => events::warn(format_args!(
+                        "{} names its stage {:?} like a built-in stage, it is only available as {:?}.",
+                        plug.loaded_from.display(),
+                        plug.api.short_name,
+                        plug.stage_name
+                    )),
+                    ShortName::Shared(others) => events::warn(format_args!(
+                        "{:?} is provided by {} and {}, refer to them by these names instead.",
+                        plug.api.short_name,
+                        others.join(", "),
+                        plug.stage_name
+                    )),
+                }
+            }
+            Err(e) => events::warn(format_args!("not registering the stage of {}: {}", plug.loaded_from.display(), e)),
         }
     }
 }
@@ -182,6 +218,13 @@ pub struct FfiMutator {
     plugin_index: usize,
 }
 
+impl FfiMutator {
+    /// The library this stage was loaded from.
+    pub fn loaded_from(&self) -> PathBuf {
+        LOADED_PLUGINS.lock()[self.plugin_index].loaded_from.clone()
+    }
+}
+
 impl Mutator for FfiMutator {
     fn drive_mutation(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
         let api = &LOADED_PLUGINS.lock()[self.plugin_index].api;
@@ -222,7 +265,11 @@ impl Mutator for FfiMutator {
     }
 }
 
+/// # Safety
+///
+/// No compressor registered by a plugin may be used after its library is unloaded.
 pub unsafe fn unload_plugins() {
+    registered::unregister_plugins();
     let mut lock = LOADED_PLUGINS.lock();
     lock.clear();
 }
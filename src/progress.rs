@@ -0,0 +1,151 @@
+//! progress reports on stderr for long encodes and decodes, asked for with `--progress`. the work of a run is the
+//! bytes that pass through each stage, summed over all stages, blocks and archive frames. [`CompressionPipeline`]
+//! reports every stage as it finishes, block mode and archives report which block or frame they are on, and the
+//! report shows how much of the work is done, the throughput so far and an estimate of the time left.
+//!
+//! stages run as a whole, so a single block encoded with a single stage only reports once it is done. splitting a
+//! large input into blocks with `--block-size` makes the reports finer.
+//!
+//! [`CompressionPipeline`]: crate::algorithms::pipeline::CompressionPipeline
+use std::{
+    io::{IsTerminal, Write, stderr},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    events::{self, Event},
+    units::{ByteSize, Elapsed, Throughput},
+};
+
+/// How often the report is redrawn on a terminal.
+const TERMINAL_INTERVAL: Duration = Duration::from_millis(100);
+/// How often a report line is written when stderr is redirected, where lines can't be redrawn.
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+static PROGRESS: Mutex<Option<Progress>> = Mutex::new(None);
+
+struct Progress {
+    label: String,
+    /// Bytes all stages are expected to process together, `None` when the size of the data isn't known.
+    total: Option<u64>,
+    done: u64,
+    /// Stages every byte goes through, to turn the work done into bytes of input.
+    stages: u64,
+    stage: Option<(usize, usize, String)>,
+    unit: Option<(&'static str, usize, usize)>,
+    started: Instant,
+    last_report: Option<Instant>,
+    terminal: bool,
+}
+
+impl Progress {
+    fn report(&mut self, force: bool) {
+        let now = Instant::now();
+        let interval = if self.terminal { TERMINAL_INTERVAL } else { LOG_INTERVAL };
+        if !force && self.last_report.is_some_and(|last| now - last < interval) {
+            return;
+        }
+        self.last_report = Some(now);
+
+        let elapsed = now - self.started;
+        let mut line = format!("[progress] stackpack: {}", self.label);
+        match self.total {
+            Some(total) if total != 0 => {
+                let fraction = (self.done as f64 / total as f64).min(1.0);
+                line.push_str(&format!(" {:5.1}%", fraction * 100.0));
+                if self.done != 0 && fraction < 1.0 {
+                    let left = elapsed.mul_f64((1.0 - fraction) / fraction);
+                    line.push_str(&format!(", eta {}", Elapsed(left)));
+                }
+            }
+            _ => line.push_str(&format!(" {} processed", ByteSize(self.done / self.stages))),
+        }
+        if let Some((index, count, name)) = &self.stage {
+            line.push_str(&format!(", stage {}/{} ({})", index + 1, count, name));
+        }
+        if let Some((unit, index, count)) = self.unit {
+            line.push_str(&format!(", {} {}/{}", unit, index + 1, count));
+        }
+        line.push_str(&format!(
+            ", {}",
+            Throughput {
+                bytes: self.done / self.stages,
+                elapsed
+            }
+        ));
+
+        let mut stderr = stderr().lock();
+        // the report can get shorter, so the rest of the previous one is cleared
+        let _ = if self.terminal {
+            write!(stderr, "\r{}\x1b[K", line)
+        } else {
+            writeln!(stderr, "{}", line)
+        };
+        let _ = stderr.flush();
+    }
+}
+
+/// Starts reporting progress of `label`. the work is `len` bytes going through each of `stages` stages.
+pub fn start(label: impl Into<String>, len: Option<u64>, stages: usize) {
+    let terminal = stderr().is_terminal();
+    *PROGRESS.lock() = Some(Progress {
+        label: label.into(),
+        total: len.map(|len| len.saturating_mul(stages.max(1) as u64)),
+        done: 0,
+        stages: stages.max(1) as u64,
+        stage: None,
+        unit: None,
+        started: Instant::now(),
+        last_report: None,
+        terminal,
+    });
+}
+
+/// Marks stage `index` of `count` as the one running, and sends it as a `stage_started` event.
+pub fn stage(index: usize, count: usize, name: &str) {
+    events::emit(Event::StageStarted { index, count, stage: name });
+    if let Some(progress) = PROGRESS.lock().as_mut() {
+        progress.stage = Some((index, count, name.to_string()));
+        progress.report(false);
+    }
+}
+
+/// Marks `unit` `index` of `count`, e.g. a block or an archive frame, as the one being processed.
+pub fn unit(unit: &'static str, index: usize, count: usize) {
+    if let Some(progress) = PROGRESS.lock().as_mut() {
+        progress.unit = Some((unit, index, count));
+        progress.report(false);
+    }
+}
+
+/// Records that a stage got through `bytes` more bytes.
+pub fn advance(bytes: usize) {
+    if let Some(progress) = PROGRESS.lock().as_mut() {
+        progress.done += bytes as u64;
+        progress.report(false);
+    }
+}
+
+/// Stops reporting without a final report, for runs that are cut short.
+pub fn abandon() {
+    if let Some(progress) = PROGRESS.lock().take()
+        && progress.terminal
+    {
+        eprintln!();
+    }
+}
+
+/// Prints the final report and stops reporting.
+pub fn finish() {
+    if let Some(mut progress) = PROGRESS.lock().take() {
+        progress.total = progress.total.map(|_| progress.done);
+        progress.stage = None;
+        progress.unit = None;
+        progress.report(true);
+        if progress.terminal {
+            eprintln!();
+        }
+    }
+}
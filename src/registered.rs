@@ -1,10 +1,19 @@
-use std::sync::LazyLock;
+use core::fmt::{self, Display};
+use std::{collections::HashMap, iter, path::PathBuf, sync::LazyLock};
 
-use anyhow::Result;
-use parking_lot::Mutex;
+use anyhow::{Result, bail};
+use parking_lot::RwLock;
+use serde::Serialize;
 
 use crate::{
-    algorithms::{DynMutator, arcode, bsc, bwt, imgdecode, mtf, re_pair},
+    algorithms::{
+        DynMutator, ParamMutator, RevertContext, alphabet, arcode, bpe, bsc, bwt, dict,
+        heuristics::MemoryCost,
+        imgdecode, lz4, mtf,
+        params::{ParamDescriptor, StageParams},
+        passthrough::{PASSTHROUGH_PARAMS, Passthrough, drive_or_skip, revert_or_skip, skips_failures},
+        re_pair, rle1, util,
+    },
     mutator::Mutator,
     plugins::FfiMutator,
 };
@@ -13,6 +22,7 @@ use crate::{
 pub enum EnumMutator {
     Dyn(DynMutator),
     Ffi(FfiMutator),
+    Param(ParamMutator),
 }
 
 #[derive(Debug, Clone)]
@@ -20,6 +30,106 @@ pub struct RegisteredCompressor {
     pub(crate) mutator: EnumMutator,
     pub(crate) name: &'static str,
     pub(crate) short_description: Option<&'static str>,
+    /// Parameters given to this stage, always empty for algorithms that don't take any.
+    pub(crate) params: StageParams,
+    pub(crate) info: StageInfo,
+    /// What the stage decodes under, set by the pipeline before every run so it travels with the stage, also onto the
+    /// thread a timed stage runs on.
+    pub(crate) revert_context: RevertContext,
+}
+
+/// What a stage does to its input, for tools that group or filter stages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StageCategory {
+    /// Reorders or recodes the input without shrinking it, so that the stages after it do better.
+    Transform,
+    /// Codes symbols in fewer bits the more probable they are.
+    EntropyCoder,
+    /// Replaces repeated content with references to it.
+    Dictionary,
+    /// A complete compressor on its own.
+    Compressor,
+    /// Works on a media format such as images.
+    Media,
+    /// Doesn't compress, for testing and benchmarking pipelines.
+    Util,
+    /// Plugins don't say what their stages do.
+    Unknown,
+}
+
+/// Formats the category the way it is serialized, e.g. `entropy-coder`.
+impl Display for StageCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StageCategory::Transform => "transform",
+            StageCategory::EntropyCoder => "entropy-coder",
+            StageCategory::Dictionary => "dictionary",
+            StageCategory::Compressor => "compressor",
+            StageCategory::Media => "media",
+            StageCategory::Util => "util",
+            StageCategory::Unknown => "unknown",
+        })
+    }
+}
+
+/// Static metadata of a stage, attached with [`RegisteredCompressor::with_info`].
+#[derive(Debug, Clone, Copy)]
+pub struct StageInfo {
+    pub category: StageCategory,
+    /// Other names the stage can be referred to by in pipelines. it is always stored under its own name.
+    pub aliases: &'static [&'static str],
+    pub params: &'static [ParamDescriptor],
+    /// How much memory the stage needs for its input, see [`CompressionPipeline::estimate_memory`].
+    pub memory: MemoryCost,
+    pub explanation: StageExplanation,
+}
+
+impl StageInfo {
+    pub const UNKNOWN: StageInfo = StageInfo {
+        category: StageCategory::Unknown,
+        aliases: &[],
+        params: &[],
+        // plugins don't say, so they are assumed to hold just their input and output
+        memory: MemoryCost::PerByte(2),
+        explanation: StageExplanation {
+            entropy: "unknown, plugins don't say what their stages do",
+            size: "unknown",
+            decoding: "calls the revert function of the plugin",
+        },
+    };
+}
+
+/// What a stage does to the data, for `pipeline explain`.
+#[derive(Debug, Clone, Copy)]
+pub struct StageExplanation {
+    /// What the stage does to the entropy of the data.
+    pub entropy: &'static str,
+    /// Whether the stage grows or shrinks the data.
+    pub size: &'static str,
+    /// How the stage is undone.
+    pub decoding: &'static str,
+}
+
+/// Where a registered stage comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum StageOrigin {
+    Builtin,
+    Plugin { path: PathBuf },
+}
+
+/// Everything known about a registered stage, see [`stage_descriptors`].
+#[derive(Debug, Clone, Serialize)]
+pub struct StageDescriptor {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub category: StageCategory,
+    pub description: Option<&'static str>,
+    pub params: &'static [ParamDescriptor],
+    /// The stackpack version a built-in stage ships with. plugins don't report one.
+    pub version: Option<&'static str>,
+    pub origin: StageOrigin,
 }
 
 impl RegisteredCompressor {
@@ -28,6 +138,9 @@ impl RegisteredCompressor {
             mutator: EnumMutator::Dyn(mutator),
             name,
             short_description,
+            params: StageParams::new(),
+            info: StageInfo::UNKNOWN,
+            revert_context: RevertContext::new(),
         }
     }
 
@@ -36,50 +149,277 @@ impl RegisteredCompressor {
             mutator: EnumMutator::Ffi(mutator),
             name,
             short_description,
+            params: StageParams::new(),
+            info: StageInfo::UNKNOWN,
+            revert_context: RevertContext::new(),
+        }
+    }
+
+    pub const fn new_param(mutator: ParamMutator, name: &'static str, short_description: Option<&'static str>) -> Self {
+        RegisteredCompressor {
+            mutator: EnumMutator::Param(mutator),
+            name,
+            short_description,
+            params: StageParams::new(),
+            info: StageInfo::UNKNOWN,
+            revert_context: RevertContext::new(),
+        }
+    }
+
+    /// Attaches the category, aliases and parameters reported by [`RegisteredCompressor::descriptor`].
+    pub const fn with_info(mut self, info: StageInfo) -> Self {
+        self.info = info;
+        self
+    }
+
+    /// The name of the stage followed by its aliases.
+    fn names(&self) -> impl Iterator<Item = &'static str> + Clone + use<> {
+        iter::once(self.name).chain(self.info.aliases.iter().copied())
+    }
+
+    pub fn descriptor(&self) -> StageDescriptor {
+        let (version, origin) = match &self.mutator {
+            EnumMutator::Ffi(m) => (None, StageOrigin::Plugin { path: m.loaded_from() }),
+            EnumMutator::Dyn(_) | EnumMutator::Param(_) => (Some(env!("CARGO_PKG_VERSION")), StageOrigin::Builtin),
+        };
+        StageDescriptor {
+            name: self.name,
+            aliases: self.info.aliases,
+            category: self.info.category,
+            description: self.short_description,
+            params: self.info.params,
+            version,
+            origin,
+        }
+    }
+
+    /// Attaches stage parameters, checking them against what the algorithm accepts. every stage also accepts the
+    /// [`PASSTHROUGH_PARAMS`], which are handled here rather than by the algorithm.
+    pub fn with_params(mut self, params: StageParams) -> Result<Self> {
+        // validated even when empty, for stages with required parameters
+        match self.mutator {
+            EnumMutator::Param(m) => {
+                params.check_schema(self.name, &[self.info.params, PASSTHROUGH_PARAMS].concat())?;
+                (m.validate)(&params)?;
+            }
+            // these can't be handed parameters at all, so only the passthrough ones are known
+            EnumMutator::Dyn(_) | EnumMutator::Ffi(_) => params.check_schema(self.name, PASSTHROUGH_PARAMS)?,
+        }
+        self.params = params;
+        Ok(self)
+    }
+
+    /// Runs the stage on everything but the regions it passes through, see [`Passthrough`].
+    fn drive_regions(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+        match Passthrough::from_params(&self.params) {
+            Some(passthrough) => passthrough.drive(data, buf, |body, out| self.drive_stage(body, out)),
+            None => self.drive_stage(data, buf),
+        }
+    }
+
+    fn revert_regions(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+        match Passthrough::from_params(&self.params) {
+            Some(_) => Passthrough::revert(data, buf, |body, out| self.revert_stage(body, out)),
+            None => self.revert_stage(data, buf),
+        }
+    }
+
+    fn drive_stage(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+        match self.mutator {
+            EnumMutator::Dyn(m) => (m.drive_mutation)(data, buf),
+            EnumMutator::Ffi(ref mut m) => m.drive_mutation(data, buf),
+            EnumMutator::Param(m) => (m.drive_mutation)(data, buf, &self.params),
+        }
+    }
+
+    fn revert_stage(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+        match self.mutator {
+            EnumMutator::Dyn(m) => (m.revert_mutation)(data, buf, &self.revert_context),
+            EnumMutator::Ffi(ref mut m) => m.revert_mutation(data, buf),
+            EnumMutator::Param(m) => (m.revert_mutation)(data, buf, &self.params, &self.revert_context),
         }
     }
 }
 
-/// Algorithms that are available to stackpack, and ones that are loaded at runtime.
-pub static ALL_COMPRESSORS: LazyLock<Mutex<Vec<RegisteredCompressor>>> =
-    LazyLock::new(|| Mutex::new(vec![arcode::ArithmeticCoding, bwt::Bwt, mtf::Mtf, bsc::Bsc, re_pair::RePair, imgdecode::ImgDecoder]));
+/// Formats the stage as `name` or `name(key=value, ...)`, the form accepted in pipeline descriptions.
+impl Display for RegisteredCompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.params.is_empty() {
+            f.write_str(self.name)
+        } else {
+            write!(f, "{}({})", self.name, self.params)
+        }
+    }
+}
+
+/// Every stage stackpack knows by name: the built-in ones, and those plugins register at runtime.
+///
+/// plugin stages are registered under `<plugin>.<stage>`, the name of the library they come from without its `lib`
+/// prefix and extension followed by the name the plugin gives its stage, so `libfast.so` providing `rle` is
+/// `fast.rle`. they can also be referred to by the stage's name alone as long as that is unambiguous: built-in stages
+/// keep their names and aliases, a plugin naming its stage `bwt` only gets `<plugin>.bwt`, and a short name provided
+/// by more than one plugin refers to none of them. [`register_plugin`] reports which of these happened.
+struct Registry {
+    /// Built-in stages first, then plugin stages in the order they were registered.
+    stages: Vec<RegisteredCompressor>,
+    /// Index into `stages` of every name and alias.
+    by_name: HashMap<&'static str, usize>,
+    /// Index into `stages` of every plugin stage going by a short name. only a name with one of them resolves.
+    by_short_name: HashMap<&'static str, Vec<usize>>,
+}
+
+impl Registry {
+    fn insert(&mut self, stage: RegisteredCompressor) -> Result<()> {
+        let names = stage.names();
+        if let Some(taken) = names.clone().find(|name| self.by_name.contains_key(name)) {
+            bail!("a stage named {:?} is already registered", taken);
+        }
+        let index = self.stages.len();
+        self.by_name.extend(names.map(|name| (name, index)));
+        self.stages.push(stage);
+        Ok(())
+    }
+
+    fn reindex(&mut self) {
+        self.by_name.clear();
+        for (index, stage) in self.stages.iter().enumerate() {
+            self.by_name.extend(stage.names().map(|name| (name, index)));
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Result<&RegisteredCompressor> {
+        if let Some(&index) = self.by_name.get(name) {
+            return Ok(&self.stages[index]);
+        }
+        match self.by_short_name.get(name).map(Vec::as_slice) {
+            Some(&[index]) => Ok(&self.stages[index]),
+            Some(indices) => bail!(
+                "algorithm {:?} is ambiguous, it is provided by the plugin stages {}. use one of these names instead.",
+                name,
+                indices.iter().map(|&index| self.stages[index].name).collect::<Vec<_>>().join(", ")
+            ),
+            None => bail!(
+                "unknown algorithm {:?}. you may have forgotten to enable plugins (unsafe), or not have the required plugins installed.",
+                name
+            ),
+        }
+    }
+}
+
+/// Readers take a snapshot of what they need and let go, so pipelines can be built from several threads while a
+/// plugin registers its stages.
+static REGISTRY: LazyLock<RwLock<Registry>> = LazyLock::new(|| {
+    let mut registry = Registry {
+        stages: Vec::new(),
+        by_name: HashMap::new(),
+        by_short_name: HashMap::new(),
+    };
+    for stage in [
+        arcode::ArithmeticCoding,
+        bwt::Bwt,
+        mtf::Mtf,
+        bsc::Bsc,
+        lz4::Lz4,
+        re_pair::RePair,
+        bpe::Bpe,
+        dict::PrependDict,
+        imgdecode::ImgDecoder,
+        alphabet::Alphabet,
+        rle1::Rle1,
+        util::Identity,
+        util::Reverse,
+        util::Xor,
+    ] {
+        registry.insert(stage).expect("built-in stage names are unique");
+    }
+    RwLock::new(registry)
+});
+
+/// The stage registered under `name` or one of its aliases, or the plugin stage that alone goes by the short name
+/// `name`.
+pub fn lookup(name: &str) -> Option<RegisteredCompressor> {
+    resolve(name).ok()
+}
+
+/// Like [`lookup`], but says why there is no stage by that name: it is unknown, or the short name of several plugin
+/// stages.
+pub fn resolve(name: &str) -> Result<RegisteredCompressor> {
+    REGISTRY.read().resolve(name).cloned()
+}
+
+/// A snapshot of every registered stage, built-in ones first and then those loaded from plugins.
+pub fn stages() -> Vec<RegisteredCompressor> {
+    REGISTRY.read().stages.clone()
+}
+
+/// Registers a stage at runtime. fails when its name or one of its aliases is taken.
+pub fn register(stage: RegisteredCompressor) -> Result<()> {
+    REGISTRY.write().insert(stage)
+}
+
+/// What became of the short name of a plugin stage, see [`register_plugin`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShortName {
+    /// The stage can be referred to by it.
+    Claimed,
+    /// A built-in stage goes by it, the plugin stage is only known by its qualified name.
+    Builtin,
+    /// These other plugin stages go by it too, so it refers to none of them.
+    Shared(Vec<&'static str>),
+}
+
+/// Registers the stage of a plugin under its qualified name, and under `short_name` as far as that is unambiguous
+/// (see [`Registry`]). fails when the qualified name is taken, by a plugin of the same name loaded from elsewhere.
+pub fn register_plugin(stage: RegisteredCompressor, short_name: &'static str) -> Result<ShortName> {
+    let mut registry = REGISTRY.write();
+    registry.insert(stage)?;
+    let index = registry.stages.len() - 1;
+    if registry.by_name.contains_key(short_name) {
+        return Ok(ShortName::Builtin);
+    }
+    let sharing = registry.by_short_name.entry(short_name).or_default();
+    sharing.push(index);
+    let others = sharing[..sharing.len() - 1].to_vec();
+    Ok(match others.as_slice() {
+        [] => ShortName::Claimed,
+        _ => ShortName::Shared(others.iter().map(|&other| registry.stages[other].name).collect()),
+    })
+}
+
+/// Removes every stage registered by a plugin, before the plugins are unloaded.
+pub fn unregister_plugins() {
+    let mut registry = REGISTRY.write();
+    registry.stages.retain(|stage| !matches!(stage.mutator, EnumMutator::Ffi(_)));
+    registry.by_short_name.clear();
+    registry.reindex();
+}
+
+/// Describes every registered stage, built-in ones first and then those loaded from plugins.
+pub fn stage_descriptors() -> Vec<StageDescriptor> {
+    stages().iter().map(RegisteredCompressor::descriptor).collect()
+}
 
 impl Mutator for RegisteredCompressor {
     fn drive_mutation(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
         if_tracing! {
             let span = tracing::span!(tracing::Level::DEBUG, "registered compressor", name = self.name);
             let _span = span.enter();
-            let res = match self.mutator {
-                EnumMutator::Dyn(m) => (m.drive_mutation)(data, buf),
-                EnumMutator::Ffi(ref mut m) => m.drive_mutation(data, buf),
-            };
-            drop(_span);
-            res
-        }
-        if_not_tracing! {
-            match self.mutator {
-                EnumMutator::Dyn(m) => (m.drive_mutation)(data, buf),
-                EnumMutator::Ffi(ref mut m) => m.drive_mutation(data, buf),
-            }
         }
+        if skips_failures(&self.params) {
+            let name = self.name;
+            return drive_or_skip(name, data, buf, |data, out| self.drive_regions(data, out));
+        }
+        self.drive_regions(data, buf)
     }
 
     fn revert_mutation(&mut self, data: &[u8], buf: &mut Vec<u8>) -> Result<()> {
         if_tracing! {
             let span = tracing::span!(tracing::Level::DEBUG, "registered decompressor", name = self.name);
             let _span = span.enter();
-            let res = match self.mutator {
-                EnumMutator::Dyn(m) => (m.revert_mutation)(data, buf),
-                EnumMutator::Ffi(ref mut m) => m.revert_mutation(data, buf),
-            };
-            drop(_span);
-            res
-        }
-        if_not_tracing! {
-            match self.mutator {
-                EnumMutator::Dyn(m) => (m.revert_mutation)(data, buf),
-                EnumMutator::Ffi(ref mut m) => m.revert_mutation(data, buf),
-            }
         }
+        if skips_failures(&self.params) {
+            return revert_or_skip(data, buf, |data, out| self.revert_regions(data, out));
+        }
+        self.revert_regions(data, buf)
     }
 }
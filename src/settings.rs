@@ -0,0 +1,101 @@
+//! what the cli falls back on when a flag isn't given, resolved here once so every subcommand behaves the same. each
+//! setting is taken from the first of these that has it:
+//!
+//! 1. the cli flag,
+//! 2. the environment variable,
+//! 3. the key of the user configuration (see [`crate::config`]),
+//! 4. the built-in default.
+//!
+//! | setting  | flag        | environment variable    | config key    | default                           |
+//! |----------|-------------|-------------------------|---------------|-----------------------------------|
+//! | pipeline | `--using`   | `STACKPACK_PIPELINE`    | `pipeline`    | the default pipeline              |
+//! | preset   | `--preset`  | `STACKPACK_PRESET`      | `preset`      | the default pipeline              |
+//! | threads  | `--threads` | `STACKPACK_THREADS`     | `threads`     | what the machine can run          |
+//! | plugins  |             | `STACKPACK_PLUGIN_DIRS` | `plugin_dirs` | `$STACKPACK_PLUGINS_ROOT/plugins` |
+//!
+//! a pipeline and a preset can't both be set at the same level, and any pipeline selecting flag (`--using`,
+//! `--from_file`, `--preset`, `--level`) wins over both. `STACKPACK_PLUGIN_DIRS` is a list of directories joined like
+//! `PATH`, the plugins of all of them are loaded with `--unsafe`.
+//!
+//! the block thresholds of [`crate::algorithms::heuristics`] only come from the `thresholds` key, which `calibrate`
+//! writes along with `threads`.
+use std::{env, ffi::OsString, num::NonZeroUsize, path::PathBuf, process, sync::LazyLock};
+
+use crate::{algorithms::heuristics::Thresholds, cli::PipelineSelection, config::UserConfig, events};
+
+static SETTINGS: LazyLock<Settings> = LazyLock::new(Settings::resolve);
+
+/// The fallbacks of the cli flags, see the module docs.
+#[derive(Debug, Default)]
+pub struct Settings {
+    /// The pipeline to use when no flag selects one.
+    pub pipeline: Option<PipelineSelection>,
+    pub threads: Option<NonZeroUsize>,
+    /// Directories plugins are loaded from.
+    pub plugin_dirs: Vec<PathBuf>,
+    pub thresholds: Option<Thresholds>,
+}
+
+/// The settings, resolved the first time they are needed.
+pub fn get() -> &'static Settings {
+    &SETTINGS
+}
+
+impl Settings {
+    fn resolve() -> Self {
+        let config = UserConfig::load().unwrap_or_else(|e| {
+            events::warn(format_args!("ignoring the user config: {}", e));
+            UserConfig::default()
+        });
+
+        let pipeline = match (env_string("STACKPACK_PIPELINE"), env_string("STACKPACK_PRESET")) {
+            (Some(_), Some(_)) => fail("STACKPACK_PIPELINE and STACKPACK_PRESET are both set, unset one of them"),
+            (Some(pipeline), None) => Some(PipelineSelection::Inline(pipeline)),
+            (None, Some(preset)) => Some(PipelineSelection::Preset(preset)),
+            (None, None) => match (&config.pipeline, &config.preset) {
+                (Some(_), Some(_)) => fail("the user config sets both `pipeline` and `preset`, remove one of them"),
+                (Some(pipeline), None) => Some(PipelineSelection::Inline(pipeline.clone())),
+                (None, Some(preset)) => Some(PipelineSelection::Preset(preset.clone())),
+                (None, None) => None,
+            },
+        };
+
+        let threads = match env_string("STACKPACK_THREADS") {
+            Some(raw) => match raw.trim().parse::<NonZeroUsize>() {
+                Ok(threads) => Some(threads),
+                Err(_) => fail(&format!("STACKPACK_THREADS must be a positive number of threads, not \"{}\"", raw)),
+            },
+            None => config.threads,
+        };
+
+        let plugin_dirs = match env::var_os("STACKPACK_PLUGIN_DIRS").filter(|dirs| !dirs.is_empty()) {
+            Some(dirs) => env::split_paths(&dirs).collect(),
+            None if !config.plugin_dirs.is_empty() => config.plugin_dirs,
+            None => env::var_os("STACKPACK_PLUGINS_ROOT")
+                .map(|root| PathBuf::from(root).join("plugins"))
+                .into_iter()
+                .collect(),
+        };
+
+        Settings {
+            pipeline,
+            threads,
+            plugin_dirs,
+            thresholds: config.thresholds,
+        }
+    }
+}
+
+/// The value of the environment variable `name`, unless it is unset or empty.
+fn env_string(name: &str) -> Option<String> {
+    env::var_os(name).filter(|value| !value.is_empty()).map(|value| {
+        value
+            .into_string()
+            .unwrap_or_else(|value: OsString| fail(&format!("{} is not valid utf-8: {}", name, value.to_string_lossy())))
+    })
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("[error] stackpack: {}.", message);
+    process::exit(1);
+}
@@ -0,0 +1,107 @@
+//! `{file stem}.pipeline.json` files written next to compressed outputs in the default persistence mode.
+//!
+//! a sidecar holds the pipeline as a pipeline file does (see [`crate::algorithms::pipeline`]). with `enc
+//! --sidecar-stats` it also records what the encode that wrote it achieved, which `dec` ignores:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "stages": [{ "name": "bwt" }, { "name": "mtf" }, { "name": "arcode" }],
+//!   "stats": {
+//!     "original_size": 68953,
+//!     "compressed_size": 19155,
+//!     "ratio": 0.2778,
+//!     "duration_ms": 12.4,
+//!     "stages": [
+//!       { "direction": "encode", "index": 0, "stage": "bwt", "input_size": 68953, "output_size": 68957, "duration_ms": 8.1 },
+//!       ...
+//!     ]
+//!   }
+//! }
+//! ```
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+
+use crate::{
+    algorithms::pipeline::CompressionPipeline,
+    io::{IoPolicy, Overwrite, write_file},
+    verbosity::StageStats,
+};
+
+pub const SIDECAR_SUFFIX: &str = ".pipeline.json";
+
+/// What the encode that wrote a sidecar achieved, recorded with `enc --sidecar-stats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SidecarStats {
+    pub original_size: u64,
+    pub compressed_size: u64,
+    /// The compressed size over the original size.
+    pub ratio: f64,
+    pub duration_ms: f64,
+    /// Every stage the encode ran, summed over the blocks of a block mode output.
+    pub stages: Vec<StageStats>,
+}
+
+/// The sidecar path belonging to a compressed artifact, e.g. `out/data.bin` -> `out/data.pipeline.json`.
+pub fn sidecar_path(artifact: &Path) -> PathBuf {
+    let mut name = artifact.file_stem().unwrap_or_default().to_os_string();
+    name.push(SIDECAR_SUFFIX);
+    artifact.with_file_name(name)
+}
+
+/// Fails if the sidecar `artifact` gets already describes another pipeline, unless `policy` forces overwriting it.
+/// artifacts that only differ in their extension, e.g. `a.bin` and `a.txt`, share a sidecar, so one would otherwise
+/// silently take it from the other. a sidecar with the same pipeline is rewritten either way.
+pub fn check_sidecar(artifact: &Path, pipeline: &CompressionPipeline, policy: IoPolicy) -> Result<()> {
+    let path = sidecar_path(artifact);
+    if policy.overwrite == Overwrite::Force || fs::symlink_metadata(&path).is_err() {
+        return Ok(());
+    }
+    // one that doesn't parse, e.g. naming plugin stages that aren't loaded, can't be told to be the same
+    let existing = read_sidecar(artifact).ok().flatten();
+    if existing.is_none_or(|existing| existing.to_string() != pipeline.to_string()) {
+        bail!(
+            "{} already exists with another pipeline, it may belong to another file named like {}, pass --force to \
+             overwrite it",
+            path.display(),
+            artifact.display()
+        );
+    }
+    Ok(())
+}
+
+/// Writes the sidecar of `artifact`, with `stats` if given. see [`check_sidecar`] for when an existing one is replaced.
+pub fn write_sidecar(
+    artifact: &Path,
+    pipeline: &CompressionPipeline,
+    stats: Option<&SidecarStats>,
+    policy: IoPolicy,
+) -> Result<PathBuf> {
+    #[derive(Serialize)]
+    struct Stats<'a> {
+        stats: &'a SidecarStats,
+    }
+    check_sidecar(artifact, pipeline, policy)?;
+    let path = sidecar_path(artifact);
+    let json = match stats {
+        Some(stats) => pipeline.to_json_with(&Stats { stats }),
+        None => pipeline.to_json(),
+    };
+    write_file(&path, json.as_bytes(), policy.forced())?;
+    Ok(path)
+}
+
+/// Reads the sidecar of `artifact`, returning `Ok(None)` if there is none.
+pub fn read_sidecar(artifact: &Path) -> Result<Option<CompressionPipeline>> {
+    let path = sidecar_path(artifact);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let json = fs::read_to_string(&path)?;
+    Ok(Some(CompressionPipeline::try_from_json(&json)?))
+}
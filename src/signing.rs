@@ -0,0 +1,45 @@
+//! ed25519 signatures over container headers and payloads.
+//!
+//! the signed message is [`SIGNATURE_CONTEXT`], followed by the blake3 hash of the container header fields that
+//! precede the signature field, followed by the blake3 hash of the payload. the header holds the pipeline and the
+//! checksum of the original data, so a valid signature vouches for both the encoded and the decoded content.
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, anyhow};
+use ed25519_dalek::{
+    Signature, Signer, SigningKey, Verifier, VerifyingKey,
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+};
+
+/// Separates stackpack signatures from signatures the same key makes for anything else.
+pub const SIGNATURE_CONTEXT: &[u8] = b"stackpack container signature v1\0";
+pub const SIGNATURE_LEN: usize = Signature::BYTE_SIZE;
+
+/// Reads a PKCS#8 PEM private key, as written by `openssl genpkey -algorithm ed25519`.
+pub fn load_signing_key(path: &Path) -> Result<SigningKey> {
+    let pem = fs::read_to_string(path).with_context(|| format!("failed to read signing key {}", path.display()))?;
+    SigningKey::from_pkcs8_pem(&pem).map_err(|e| anyhow!("{} is not an ed25519 private key in PKCS#8 PEM format: {}", path.display(), e))
+}
+
+/// Reads a PEM public key, as written by `openssl pkey -pubout`.
+pub fn load_verifying_key(path: &Path) -> Result<VerifyingKey> {
+    let pem = fs::read_to_string(path).with_context(|| format!("failed to read public key {}", path.display()))?;
+    VerifyingKey::from_public_key_pem(&pem).map_err(|e| anyhow!("{} is not an ed25519 public key in PEM format: {}", path.display(), e))
+}
+
+fn signed_message(header: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut message = SIGNATURE_CONTEXT.to_vec();
+    message.extend_from_slice(blake3::hash(header).as_bytes());
+    message.extend_from_slice(blake3::hash(payload).as_bytes());
+    message
+}
+
+pub fn sign(key: &SigningKey, header: &[u8], payload: &[u8]) -> [u8; SIGNATURE_LEN] {
+    key.sign(&signed_message(header, payload)).to_bytes()
+}
+
+pub fn verify(key: &VerifyingKey, header: &[u8], payload: &[u8], signature: &[u8]) -> Result<()> {
+    let signature = Signature::from_slice(signature).map_err(|_| anyhow!("signature is malformed"))?;
+    key.verify(&signed_message(header, payload), &signature)
+        .map_err(|_| anyhow!("signature does not match this public key, or the file was modified after signing"))
+}
@@ -1 +1,154 @@
+//! parsing and formatting of sizes, durations, throughput and ratios, so every command reports them the same way.
+use core::fmt::{self, Display, Write};
+use core::time::Duration;
+
+pub const KIBIBYTES: usize = 1024;
 pub const MEBIBYTES: usize = 1024 * 1024;
+pub const GIBIBYTES: usize = 1024 * 1024 * 1024;
+
+/// Parses a byte count with an optional binary or decimal suffix, e.g. `4096`, `64KiB`, `10MB` or `1g`.
+pub fn parse_byte_size(raw: &str) -> Result<u64, String> {
+    let trimmed = raw.trim();
+    let split = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+    let (digits, suffix) = trimmed.split_at(split);
+    let value: u64 = digits.parse().map_err(|err| format!("failed to parse size '{raw}': {err}"))?;
+    let multiplier: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => KIBIBYTES as u64,
+        "m" | "mib" => MEBIBYTES as u64,
+        "g" | "gib" => GIBIBYTES as u64,
+        "kb" => 1000,
+        "mb" => 1000 * 1000,
+        "gb" => 1000 * 1000 * 1000,
+        other => return Err(format!("unknown size suffix '{other}' in '{raw}'")),
+    };
+    value.checked_mul(multiplier).ok_or_else(|| format!("size '{raw}' is too large"))
+}
+
+/// Parses a duration with an optional unit, e.g. `500ms`, `90s`, `10m` or `2h`. a bare number is in seconds.
+pub fn parse_duration(raw: &str) -> Result<Duration, String> {
+    let trimmed = raw.trim();
+    let split = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+    let (digits, suffix) = trimmed.split_at(split);
+    let value: u64 = digits.parse().map_err(|err| format!("failed to parse duration '{raw}': {err}"))?;
+    let millis: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "ms" => 1,
+        "" | "s" => 1000,
+        "m" | "min" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        other => return Err(format!("unknown duration unit '{other}' in '{raw}'")),
+    };
+    value
+        .checked_mul(millis)
+        .map(Duration::from_millis)
+        .ok_or_else(|| format!("duration '{raw}' is too long"))
+}
+
+/// A byte count in the largest binary unit that keeps it at or above 1, e.g. `512 B` or `1.5 MiB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+        if self.0 < KIBIBYTES as u64 {
+            return write!(f, "{} B", self.0);
+        }
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while value >= KIBIBYTES as f64 && unit < UNITS.len() - 1 {
+            value /= KIBIBYTES as f64;
+            unit += 1;
+        }
+        write!(f, "{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// An exact count with its digits grouped in threes, e.g. `1,048,576`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grouped(pub u64);
+
+impl Display for Grouped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.0.to_string();
+        for (index, digit) in digits.chars().enumerate() {
+            if index != 0 && (digits.len() - index).is_multiple_of(3) {
+                f.write_char(',')?;
+            }
+            f.write_char(digit)?;
+        }
+        Ok(())
+    }
+}
+
+/// A duration with a precision that suits its magnitude, e.g. `850 µs`, `12.3 ms`, `1.25 s` or `2m 03s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed(pub Duration);
+
+impl Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let duration = self.0;
+        if duration < Duration::from_millis(1) {
+            write!(f, "{} µs", duration.as_micros())
+        } else if duration < Duration::from_secs(1) {
+            write!(f, "{:.1} ms", duration.as_secs_f64() * 1000.0)
+        } else if duration < Duration::from_secs(60) {
+            write!(f, "{:.2} s", duration.as_secs_f64())
+        } else {
+            write!(f, "{}m {:02}s", duration.as_secs() / 60, duration.as_secs() % 60)
+        }
+    }
+}
+
+/// How fast `bytes` were processed in `elapsed`, e.g. `85.2 MiB/s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Throughput {
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return f.write_str("-");
+        }
+        write!(f, "{}/s", ByteSize((self.bytes as f64 / seconds) as u64))
+    }
+}
+
+/// The compressed size as a fraction of the original size, shown as a percentage, e.g. `33.1%`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ratio {
+    pub original: u64,
+    pub compressed: u64,
+}
+
+impl Ratio {
+    pub fn new(original: usize, compressed: usize) -> Self {
+        Ratio {
+            original: original as u64,
+            compressed: compressed as u64,
+        }
+    }
+
+    /// `compressed / original`, where an empty original counts as not compressing at all.
+    pub fn value(&self) -> f64 {
+        if self.original == 0 {
+            1.0
+        } else {
+            self.compressed as f64 / self.original as f64
+        }
+    }
+
+    /// How much of the original was saved, in percent. negative when the output grew.
+    pub fn percent_saved(&self) -> f64 {
+        (1.0 - self.value()) * 100.0
+    }
+}
+
+impl Display for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1}%", self.value() * 100.0)
+    }
+}
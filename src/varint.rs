@@ -0,0 +1,32 @@
+//! LEB128 variable length integers used by stackpack framings.
+use anyhow::{Result, anyhow};
+
+/// Appends `value` as an unsigned LEB128 integer.
+pub fn write_u64(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+/// Reads an unsigned LEB128 integer from the front of `data`, advancing it past the integer.
+pub fn read_u64(data: &mut &[u8]) -> Result<u64> {
+    let mut value: u64 = 0;
+    for (index, &byte) in data.iter().enumerate() {
+        let shift = 7 * index as u32;
+        if shift >= u64::BITS || (shift == 63 && byte > 1) {
+            return Err(anyhow!("varint overflows u64"));
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            *data = &data[index + 1..];
+            return Ok(value);
+        }
+    }
+    Err(anyhow!("input too short: truncated varint"))
+}
@@ -0,0 +1,150 @@
+//! how much `enc`, `dec`, `test` and `corpus` report on stderr, set with the global `--verbose` (`-v`) and `--quiet`
+//! (`-q`) flags. this works the same in every build, the `tracing` logs come on top of it when the feature is on.
+//!
+//! | level     | reports                                                                                 |
+//! |-----------|-----------------------------------------------------------------------------------------|
+//! | quiet     | warnings, errors and failed round trips                                                 |
+//! | normal    | the pipeline in use and every round trip of `test` and `corpus`                         |
+//! | verbose   | a summary of every encode and decode with its ratio and timing, and of every stage run  |
+//!
+//! stages are also recorded for the documents `--format json` prints and the table `--stats` prints, whatever the
+//! level (see [`crate::cli::summary`] and [`crate::cli::stats`]).
+use core::{
+    mem,
+    sync::atomic::{AtomicU8, Ordering},
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::{
+    events::{self, Event},
+    units::{ByteSize, Elapsed, Throughput},
+};
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+static STAGES: Mutex<Option<Vec<StageStats>>> = Mutex::new(None);
+static TOTALS: Mutex<Option<Vec<StageStats>>> = Mutex::new(None);
+
+/// What one stage of a pipeline did over a run, summed over all blocks and frames it ran on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageStats {
+    pub direction: &'static str,
+    pub index: usize,
+    pub stage: String,
+    pub input_size: u64,
+    pub output_size: u64,
+    pub duration_ms: f64,
+}
+
+/// How much is reported on stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+/// Sets how much is reported for the rest of the run.
+pub fn set(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn get() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+pub fn is_quiet() -> bool {
+    get() == Verbosity::Quiet
+}
+
+pub fn is_verbose() -> bool {
+    get() == Verbosity::Verbose
+}
+
+/// Starts recording the stages that run, see [`take_stages`].
+pub fn record_stages() {
+    *STAGES.lock() = Some(Vec::new());
+}
+
+/// The stages run since the last call, in the order they first ran. empty unless [`record_stages`] was called.
+pub fn take_stages() -> Vec<StageStats> {
+    STAGES.lock().as_mut().map(mem::take).unwrap_or_default()
+}
+
+/// The stages run since the last [`take_stages`], left in place for it.
+pub fn peek_stages() -> Vec<StageStats> {
+    STAGES.lock().clone().unwrap_or_default()
+}
+
+/// Starts adding up the stages that run over the whole command, see [`take_totals`].
+pub fn record_totals() {
+    *TOTALS.lock() = Some(Vec::new());
+}
+
+/// The stages run over the whole command, `None` unless [`record_totals`] was called. unlike [`take_stages`] this
+/// isn't reset by every run, and stops recording.
+pub fn take_totals() -> Option<Vec<StageStats>> {
+    TOTALS.lock().take()
+}
+
+/// Adds a run of stage `index` to what `stages` recorded so far.
+fn add_stage(stages: &mut Vec<StageStats>, direction: &'static str, index: usize, name: &str, input: u64, output: u64, duration_ms: f64) {
+    match stages.iter_mut().find(|stats| stats.direction == direction && stats.index == index) {
+        Some(stats) => {
+            stats.input_size += input;
+            stats.output_size += output;
+            stats.duration_ms += duration_ms;
+        }
+        None => stages.push(StageStats {
+            direction,
+            index,
+            stage: name.to_string(),
+            input_size: input,
+            output_size: output,
+            duration_ms,
+        }),
+    }
+}
+
+/// Reports stage `index` of `count` going from `input` to `output` bytes in verbose mode, records it and sends it as a
+/// `stage_finished` event.
+pub fn stage(direction: &'static str, index: usize, count: usize, name: &str, input: usize, output: usize, elapsed: Duration) {
+    let duration_ms = elapsed.as_secs_f64() * 1000.0;
+    events::emit(Event::StageFinished {
+        direction,
+        index,
+        count,
+        stage: name,
+        input_size: input as u64,
+        output_size: output as u64,
+        duration_ms,
+    });
+    if let Some(stages) = STAGES.lock().as_mut() {
+        add_stage(stages, direction, index, name, input as u64, output as u64, duration_ms);
+    }
+    if let Some(totals) = TOTALS.lock().as_mut() {
+        add_stage(totals, direction, index, name, input as u64, output as u64, duration_ms);
+    }
+    if is_verbose() {
+        eprintln!(
+            "  {} stage {}/{} ({}): {} -> {} in {} ({})",
+            direction,
+            index + 1,
+            count,
+            name,
+            ByteSize(input as u64),
+            ByteSize(output as u64),
+            Elapsed(elapsed),
+            Throughput {
+                bytes: input as u64,
+                elapsed
+            }
+        );
+    }
+}
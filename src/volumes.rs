@@ -0,0 +1,68 @@
+//! multi-volume outputs written by `enc --split-size`. the output is cut into `{output}.001`, `{output}.002`, ...
+//! each holding at most the split size. the volumes are plain slices of the output, so concatenating them in order
+//! (`cat out.stpk.* > out.stpk`) gives back the single file, and that is all reading them does.
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::io::{IoPolicy, is_stdio, read_file, write_file};
+
+/// Digits in the volume suffix, more volumes than this still work but stop sorting by name.
+const SUFFIX_DIGITS: usize = 3;
+
+/// The path of volume `index`, counting from 1.
+pub fn volume_path(base: &Path, index: usize) -> PathBuf {
+    let mut name = base.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{:0width$}", index, width = SUFFIX_DIGITS));
+    base.with_file_name(name)
+}
+
+/// The output a volume belongs to, if `path` is named like a volume.
+pub fn volume_base(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let (base, suffix) = name.rsplit_once('.')?;
+    if base.is_empty() || suffix.len() < SUFFIX_DIGITS || !suffix.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(path.with_file_name(OsString::from(base)))
+}
+
+/// Writes `data` as volumes of at most `split_size` bytes and returns their paths. Volumes left over from an
+/// earlier, longer output are removed so they aren't read back as part of this one.
+pub fn write_volumes(base: &Path, data: &[u8], split_size: usize, policy: IoPolicy) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    // an empty output still gets its one, empty volume
+    for (index, volume) in data.chunks(split_size.max(1)).chain(data.is_empty().then_some(&[][..])).enumerate() {
+        let path = volume_path(base, index + 1);
+        write_file(&path, volume, policy)?;
+        paths.push(path);
+    }
+    let mut stale = paths.len() + 1;
+    while volume_path(base, stale).is_file() {
+        fs::remove_file(volume_path(base, stale))?;
+        stale += 1;
+    }
+    Ok(paths)
+}
+
+/// Reads an input that may have been split into volumes. `path` can be the output name given to `enc`, when no
+/// file by that name exists, or any of its volumes. Volumes are read from the first one until one is missing.
+pub fn read_input(path: &Path, policy: IoPolicy) -> io::Result<Vec<u8>> {
+    if is_stdio(path) {
+        return read_file(path, policy);
+    }
+    let base = match volume_base(path) {
+        Some(base) if volume_path(&base, 1).is_file() => base,
+        _ if !path.exists() && volume_path(path, 1).is_file() => path.to_path_buf(),
+        _ => return read_file(path, policy),
+    };
+    let mut data = Vec::new();
+    let mut index = 1;
+    while volume_path(&base, index).is_file() {
+        data.extend_from_slice(&read_file(&volume_path(&base, index), policy)?);
+        index += 1;
+    }
+    Ok(data)
+}
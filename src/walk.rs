@@ -0,0 +1,70 @@
+//! walking directory inputs with the `--include` and `--exclude` globs of `enc`, `test` and `corpus`. globs are
+//! matched against paths relative to the directory given, with `/` as the separator. a glob without a `/` matches
+//! the name of an entry at any depth, so `--exclude target` skips every `target` directory and `--exclude '*.bin'`
+//! every `.bin` file, while `src/*.rs` only matches files directly inside `src`. a trailing `/` is ignored.
+//!
+//! an excluded directory is skipped with everything below it. includes only apply to files, directories are walked
+//! anyway to find the files in them. a file is kept when it matches an include, or there are none, and matches no
+//! exclude. the directory given is never filtered out, and neither are files given directly.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use walkdir::{DirEntry, WalkDir};
+
+/// Which entries of a directory walk are kept.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        Ok(PathFilter {
+            include: glob_set(include)?,
+            exclude: glob_set(exclude)?,
+        })
+    }
+
+    /// Whether the entry at `relative`, a path below the walked directory, is kept.
+    pub fn keeps(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.exclude.as_ref().is_some_and(|exclude| exclude.is_match(relative)) {
+            return false;
+        }
+        is_dir || self.include.as_ref().is_none_or(|include| include.is_match(relative))
+    }
+
+    /// Walks `walk` in file name order, leaving out the entries this filter doesn't keep.
+    pub fn walk(&self, walk: WalkDir) -> impl Iterator<Item = walkdir::Result<DirEntry>> {
+        walk.sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || self.keeps(&relative_path(entry), entry.file_type().is_dir()))
+    }
+}
+
+/// The path of `entry` below the root of its walk.
+fn relative_path(entry: &DirEntry) -> PathBuf {
+    let components = entry.path().components().collect::<Vec<_>>();
+    components[components.len() - entry.depth()..].iter().collect()
+}
+
+fn glob_set(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let trimmed = pattern.trim_end_matches('/');
+        let anchored = match trimmed.contains('/') {
+            true => trimmed.to_string(),
+            false => format!("**/{}", trimmed),
+        };
+        let glob = GlobBuilder::new(&anchored)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| anyhow!("invalid glob {:?}: {}", pattern, e.kind()))?;
+        builder.add(glob);
+    }
+    Ok(Some(builder.build()?))
+}